@@ -0,0 +1,133 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use anyhow::Context;
+
+pub const ARCHIVE_METADATA_KEY: &str = "archive_metadata";
+
+// bump when a change makes older versions unable to read the manifest/blobs
+// 2: files record their ciphertext size, 3: files can be stored in chunks, 4: entries record when they were pushed,
+// 5: manifests record how names are compared, 6: manifests record the host that wrote them
+pub const MANIFEST_FORMAT_VERSION: u32 = 6;
+pub const BLOB_FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArchiveMetadata {
+    pub archive_uuid: String,
+    pub creation_time: u64, // seconds since unix epoch
+    pub manifest_format_version: u32,
+    pub blob_format_version: u32,
+    pub chunk_size: Option<u64>, // none means files are stored as a single blob
+    pub storage_mode: String,
+    pub description: String,
+    pub extra: BTreeMap<String, String>, // free form key/values
+    #[serde(default)]
+    pub signing_public_key: Option<String>, // hex ed25519 key, when set the manifest must be signed (see keys)
+}
+
+impl ArchiveMetadata {
+    pub fn new(description: &str) -> Self {
+        let creation_time = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        Self {
+            archive_uuid: new_uuid(),
+            creation_time,
+            manifest_format_version: MANIFEST_FORMAT_VERSION,
+            blob_format_version: BLOB_FORMAT_VERSION,
+            chunk_size: None,
+            storage_mode: "encrypted".to_string(),
+            description: description.to_string(),
+            extra: BTreeMap::new(),
+            signing_public_key: None,
+        }
+    }
+
+    pub fn to_bytes(&self) -> anyhow::Result<bytes::Bytes> {
+        let serialized = rmp_serde::encode::to_vec_named(&self).context("Serialize archive metadata")?;
+        Ok(bytes::Bytes::from(serialized))
+    }
+
+    pub fn from_bytes(bytes: bytes::Bytes) -> anyhow::Result<Self> {
+        let metadata: Self = rmp_serde::decode::from_slice(&bytes).context("Deserialize archive metadata")?;
+        Ok(metadata)
+    }
+
+    // fails if the archive was written by a version of har_backup that this one cannot read
+    pub fn check_compatible(&self) -> anyhow::Result<()> {
+        if self.manifest_format_version > MANIFEST_FORMAT_VERSION {
+            anyhow::bail!("Remote manifest format version is {} but this version of har only supports up to {}",
+                self.manifest_format_version, MANIFEST_FORMAT_VERSION);
+        }
+        if self.blob_format_version > BLOB_FORMAT_VERSION {
+            anyhow::bail!("Remote blob format version is {} but this version of har only supports up to {}",
+                self.blob_format_version, BLOB_FORMAT_VERSION);
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ArchiveMetadata {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "archive uuid: {}", self.archive_uuid)?;
+        writeln!(f, "creation time: {}", self.creation_time)?;
+        writeln!(f, "manifest format version: {}", self.manifest_format_version)?;
+        writeln!(f, "blob format version: {}", self.blob_format_version)?;
+        match self.chunk_size {
+            Some(chunk_size) => writeln!(f, "chunk size: {}", chunk_size)?,
+            None => writeln!(f, "chunk size: none")?,
+        };
+        writeln!(f, "storage mode: {}", self.storage_mode)?;
+        writeln!(f, "description: {}", self.description)?;
+        match &self.signing_public_key {
+            Some(public_key) => writeln!(f, "signing public key: {}", public_key)?,
+            None => writeln!(f, "signing public key: none (manifest not signed)")?,
+        };
+        for (key, value) in &self.extra {
+            writeln!(f, "{}: {}", key, value)?;
+        }
+        Ok(())
+    }
+}
+
+// random (version 4) uuid
+pub(crate) fn new_uuid() -> String {
+    use chacha20poly1305::aead::{OsRng, rand_core::RngCore};
+    let mut bytes = [0u8; 16];
+    OsRng.fill_bytes(&mut bytes);
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serialize_deserialize() -> anyhow::Result<()> {
+        let mut metadata = ArchiveMetadata::new("my photos");
+        metadata.extra.insert("host".to_string(), "kek".to_string());
+        let metadata_b = ArchiveMetadata::from_bytes(metadata.to_bytes()?)?;
+        assert_eq!(metadata, metadata_b);
+        Ok(())
+    }
+
+    #[test]
+    fn uuid_format() {
+        let uuid = new_uuid();
+        assert_eq!(uuid.len(), 36);
+        assert_eq!(&uuid[14..15], "4");
+        assert_ne!(uuid, new_uuid());
+    }
+
+    #[test]
+    fn newer_format_is_incompatible() {
+        let mut metadata = ArchiveMetadata::new("");
+        assert!(metadata.check_compatible().is_ok());
+        metadata.manifest_format_version = MANIFEST_FORMAT_VERSION + 1;
+        assert!(metadata.check_compatible().is_err());
+    }
+}
@@ -0,0 +1,101 @@
+use anyhow::Context;
+
+// zstd compression of blob payloads, done before encryption (see blob_encryption)
+// payloads that don't get smaller are stored as is
+
+pub const DEFAULT_LEVEL: i32 = 3;
+const MIN_SIZE: usize = 128; // the zstd frame overhead eats what could be saved
+const SAMPLE_SIZE: usize = 128 * 1024;
+const MAX_SAMPLE_RATIO_PERCENT: usize = 95; // sample must shrink at least this much
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Compression {
+    level: Option<i32>, // None is no compression
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self::with_level(DEFAULT_LEVEL)
+    }
+}
+
+impl Compression {
+    pub fn off() -> Self {
+        Self { level: None }
+    }
+
+    pub fn with_level(level: i32) -> Self {
+        Self { level: Some(level) }
+    }
+
+    pub fn level(&self) -> Option<i32> {
+        self.level
+    }
+
+    // None if data should be stored uncompressed
+    pub fn compress(&self, data: &[u8]) -> Option<Vec<u8>> {
+        let level = self.level?;
+        if data.len() < MIN_SIZE || is_compressed_format(data) {
+            return None;
+        }
+        // big incompressible payloads without a known signature: find out on a sample first
+        if data.len() > 4 * SAMPLE_SIZE {
+            let sample = &data[..SAMPLE_SIZE];
+            let compressed_sample = zstd::bulk::compress(sample, level).ok()?;
+            if compressed_sample.len() * 100 > sample.len() * MAX_SAMPLE_RATIO_PERCENT {
+                return None;
+            }
+        }
+        let compressed = zstd::bulk::compress(data, level).ok()?;
+        (compressed.len() < data.len()).then_some(compressed)
+    }
+}
+
+pub fn decompress(data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    zstd::stream::decode_all(data).context("Decompressing blob")
+}
+
+// (offset, signature) of formats that are already compressed
+const COMPRESSED_SIGNATURES: &[(usize, &[u8])] = &[
+    (0, b"\x28\xb5\x2f\xfd"), // zstd
+    (0, b"\x1f\x8b"), // gzip
+    (0, b"PK\x03\x04"), // zip, and docx/xlsx/odt/jar/apk...
+    (0, b"\x37\x7a\xbc\xaf\x27\x1c"), // 7z
+    (0, b"\xfd7zXZ\x00"), // xz
+    (0, b"BZh"), // bzip2
+    (0, b"Rar!\x1a\x07"), // rar
+    (0, b"\x04\x22\x4d\x18"), // lz4
+    (0, b"\x89PNG"), // png
+    (0, b"\xff\xd8\xff"), // jpeg
+    (0, b"GIF8"), // gif
+    (8, b"WEBP"), // webp (RIFF container)
+    (4, b"ftyp"), // mp4/mov/heic
+    (0, b"\x1a\x45\xdf\xa3"), // mkv/webm
+    (0, b"ID3"), // mp3
+    (0, b"OggS"), // ogg/opus
+    (0, b"fLaC"), // flac
+];
+
+fn is_compressed_format(data: &[u8]) -> bool {
+    COMPRESSED_SIGNATURES.iter()
+        .any(|(offset, signature)| data.get(*offset..offset + signature.len()) == Some(*signature))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compress_only_when_worth_it() -> anyhow::Result<()> {
+        let text = "hello world, hello blobs. ".repeat(100);
+        let compressed = Compression::default().compress(text.as_bytes()).expect("text compresses");
+        assert!(compressed.len() < text.len());
+        assert_eq!(decompress(&compressed)?, text.as_bytes());
+
+        assert!(Compression::off().compress(text.as_bytes()).is_none());
+        assert!(Compression::default().compress(b"tiny").is_none());
+        let already_compressed = [b"\x89PNG".as_slice(), text.as_bytes()].concat();
+        assert!(Compression::default().compress(&already_compressed).is_none());
+        Ok(())
+    }
+}
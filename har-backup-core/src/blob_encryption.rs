@@ -0,0 +1,331 @@
+use bytes::Bytes;
+use std::path::Path;
+use chacha20poly1305::{
+    aead::{generic_array::GenericArray, Aead, AeadCore, KeyInit, OsRng, Payload}, ChaCha20Poly1305, KeySizeUser, Nonce,
+    XChaCha20Poly1305, XNonce
+};
+use chacha20poly1305::aead::generic_array::typenum::Unsigned;
+use anyhow::anyhow;
+use crate::keys::{KeyFile, KeyId, KEY_ID_SIZE};
+use crate::blob_compression::{self, Compression};
+use log::debug;
+
+// blob envelope: ENVELOPE_MAGIC, version, algorithm, compression, key id, nonce, ciphertext
+// the header (everything before the nonce) is authenticated with the ciphertext
+//
+// still readable:
+// version 0: ENVELOPE_MAGIC, b'K' (b'Z' if zstd), key id, 12 bytes nonce, ChaCha20-Poly1305 ciphertext
+// from before key ids: 12 bytes nonce, ChaCha20-Poly1305 ciphertext
+const ENVELOPE_MAGIC: &[u8; 3] = b"HAR";
+const ENVELOPE_VERSION: u8 = 1;
+const ALGORITHM_XCHACHA20_POLY1305: u8 = 1;
+const COMPRESSION_NONE: u8 = 0;
+const COMPRESSION_ZSTD: u8 = 1;
+const HEADER_SIZE: usize = ENVELOPE_MAGIC.len() + 3 + KEY_ID_SIZE;
+
+const V0_PAYLOAD_RAW: u8 = b'K';
+const V0_PAYLOAD_ZSTD: u8 = b'Z';
+const V0_HEADER_SIZE: usize = ENVELOPE_MAGIC.len() + 1 + KEY_ID_SIZE;
+
+const NONCE_SIZE: usize = <ChaCha20Poly1305 as AeadCore>::NonceSize::USIZE;
+const XNONCE_SIZE: usize = <XChaCha20Poly1305 as AeadCore>::NonceSize::USIZE;
+
+struct Header<'a> {
+    size: usize,
+    key_id: &'a [u8],
+    extended_nonce: bool, // XChaCha20, version 0 is ChaCha20
+    compressed: bool,
+}
+
+impl<'a> Header<'a> {
+    // None if data does not start with an envelope header (legacy blob)
+    fn parse(data: &'a [u8]) -> anyhow::Result<Option<Self>> {
+        if !data.starts_with(ENVELOPE_MAGIC) || data.len() <= V0_HEADER_SIZE {
+            return Ok(None);
+        }
+        let header = match data[ENVELOPE_MAGIC.len()] {
+            ENVELOPE_VERSION if data.len() > HEADER_SIZE => {
+                let algorithm = data[ENVELOPE_MAGIC.len() + 1];
+                let compression = data[ENVELOPE_MAGIC.len() + 2];
+                if algorithm != ALGORITHM_XCHACHA20_POLY1305 {
+                    anyhow::bail!("Blob envelope has unknown algorithm {}", algorithm);
+                }
+                if compression != COMPRESSION_NONE && compression != COMPRESSION_ZSTD {
+                    anyhow::bail!("Blob envelope has unknown compression {}", compression);
+                }
+                Header {
+                    size: HEADER_SIZE,
+                    key_id: &data[HEADER_SIZE - KEY_ID_SIZE..HEADER_SIZE],
+                    extended_nonce: true,
+                    compressed: compression == COMPRESSION_ZSTD,
+                }
+            },
+            kind @ (V0_PAYLOAD_RAW | V0_PAYLOAD_ZSTD) => Header {
+                size: V0_HEADER_SIZE,
+                key_id: &data[V0_HEADER_SIZE - KEY_ID_SIZE..V0_HEADER_SIZE],
+                extended_nonce: false,
+                compressed: kind == V0_PAYLOAD_ZSTD,
+            },
+            _ => return Ok(None),
+        };
+        Ok(Some(header))
+    }
+}
+
+// a keyring: blobs are encrypted with the first key, decrypted with the key their id points to
+#[derive(Clone)]
+pub struct EncryptWithChacha {
+    keys: Vec<(KeyId, chacha20poly1305::Key)>,
+    compression: Compression,
+}
+
+impl EncryptWithChacha {
+    pub fn new_with_key_from_file(path: &Path) -> anyhow::Result<Self> {
+        let key_file = KeyFile::from_file(path)?;
+        Ok(Self::new(&key_file))
+    }
+
+    pub fn new(key_file: &KeyFile) -> Self {
+        Self::new_keyring(key_file, &[])
+    }
+
+    // old_keys are only used to decrypt, for blobs written before a key rotation
+    pub fn new_keyring(key_file: &KeyFile, old_keys: &[KeyFile]) -> Self {
+        let keys = std::iter::once(key_file).chain(old_keys)
+            .map(|key_file| (key_file.key_id(), *GenericArray::from_slice(key_file.read_key())))
+            .collect();
+        Self {
+            keys,
+            compression: Compression::off(),
+        }
+    }
+
+    // payloads are compressed before being encrypted, decompression is automatic
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    pub fn encrypt_blob(&self, data: Bytes) -> anyhow::Result<Bytes> {
+        let (key_id, key) = &self.keys[0];
+        let (compression, plain_text) = match self.compression.compress(&data) {
+            Some(compressed) => (COMPRESSION_ZSTD, Bytes::from(compressed)),
+            None => (COMPRESSION_NONE, data),
+        };
+
+        use bytes::BufMut;
+        let mut header: Vec<u8> = Vec::with_capacity(HEADER_SIZE);
+        header.put_slice(ENVELOPE_MAGIC);
+        header.put_u8(ENVELOPE_VERSION);
+        header.put_u8(ALGORITHM_XCHACHA20_POLY1305);
+        header.put_u8(compression);
+        header.put_slice(key_id);
+
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let cipher = XChaCha20Poly1305::new(key);
+        let cipher_text = cipher.encrypt(&nonce, Payload { msg: plain_text.as_ref(), aad: &header })
+            .map_err(|err| anyhow!("cipher.encrypt error: {}", err))?;
+
+        let mut blob = header;
+        blob.reserve(nonce.len() + cipher_text.len());
+        blob.put_slice(nonce.as_ref());
+        blob.put_slice(cipher_text.as_ref());
+
+        Ok(Bytes::from(blob))
+    }
+
+    pub fn decrypt_blob(&self, data: Bytes) -> anyhow::Result<Bytes> {
+        // reported only if the blob does not decrypt as a legacy blob either
+        let mut envelope_err = None;
+        let header = Header::parse(&data).unwrap_or_else(|err| {
+            envelope_err = Some(err);
+            None
+        });
+        if let Some(header) = header {
+            let key = self.keys.iter().find(|(id, _)| id == header.key_id).map(|(_, key)| key);
+            match key {
+                Some(key) => {
+                    let body = data.slice(header.size..);
+                    let decrypted = if header.extended_nonce {
+                        decrypt_xchacha(key, &data[..header.size], body)
+                    } else {
+                        decrypt_with_key(key, body)
+                    };
+                    if let Ok(plain_text) = decrypted {
+                        if header.compressed {
+                            return Ok(Bytes::from(blob_compression::decompress(&plain_text)?));
+                        }
+                        return Ok(plain_text);
+                    }
+                },
+                None => {
+                    debug!("Blob key id {:x?} is not in the keyring", header.key_id);
+                    envelope_err = Some(anyhow!("Blob is encrypted with key id {}, which is not in the keyring",
+                        crate::keys::to_hex(header.key_id)));
+                },
+            }
+        }
+        // a legacy blob can start with the magic by chance
+
+        let mut last_err = anyhow!("decrypt_blob no key to try");
+        for (_, key) in &self.keys {
+            match decrypt_with_key(key, data.clone()) {
+                Ok(plain_text) => return Ok(plain_text),
+                Err(err) => last_err = err,
+            }
+        }
+        Err(envelope_err.unwrap_or(last_err))
+    }
+}
+
+// data is nonce, ciphertext, header is the associated data
+fn decrypt_xchacha(key: &chacha20poly1305::Key, header: &[u8], mut data: Bytes) -> anyhow::Result<Bytes> {
+    if data.len() <= XNONCE_SIZE {
+        anyhow::bail!("decrypt_blob not enough bytes in data to contain a nonce and ciphertext")
+    }
+
+    let nonce = *XNonce::from_slice(&data.slice(0..XNONCE_SIZE));
+    let cipher_text = data.split_off(XNONCE_SIZE);
+
+    let cipher = XChaCha20Poly1305::new(key);
+    let plain_text = cipher.decrypt(&nonce, Payload { msg: cipher_text.as_ref(), aad: header })
+        .map_err(|err| anyhow!("cipher.decrypt error: {}", err))?;
+
+    Ok(bytes::Bytes::from(plain_text))
+}
+
+// data is nonce, ciphertext
+fn decrypt_with_key(key: &chacha20poly1305::Key, mut data: Bytes) -> anyhow::Result<Bytes> {
+
+    if data.len() < NONCE_SIZE {
+        anyhow::bail!("decrypt_blob not enough bytes in data to contain a nonce")
+    }
+    else if data.len() < NONCE_SIZE + 1 {
+        anyhow::bail!("decrypt_blob data is just the nonce?")
+    }
+
+    let nonce = *Nonce::from_slice(&data.slice(0..NONCE_SIZE));
+    let cipher_text = data.split_off(NONCE_SIZE);
+
+    let cipher = ChaCha20Poly1305::new(key);
+    let plain_text = cipher.decrypt(&nonce, cipher_text.as_ref())
+        .map_err(|err| anyhow!("cipher.decrypt error: {}", err))?;
+
+    Ok(bytes::Bytes::from(plain_text))
+}
+
+const CHACHA_KEY_SIZE: usize = <ChaCha20Poly1305 as KeySizeUser>::KeySize::USIZE;
+pub fn create_key() -> [u8; CHACHA_KEY_SIZE] {
+    let key = ChaCha20Poly1305::generate_key(OsRng);
+    key.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use super::*;
+
+    #[test]
+    fn encrypt_and_decrypt() {
+        let stuffing: [u8; 8] = [1, 2, 3, 4, 5, 6, 7, 8];
+        let key = [&stuffing[..], &stuffing[..], &stuffing[..], &stuffing[..]].concat();
+        let mut key_file = tempfile::NamedTempFile::new().expect("create a tempfile");
+        key_file.write_all(key.as_ref()).expect("write key file content");
+
+        let encrypt = EncryptWithChacha::new_with_key_from_file(key_file.path()).expect("create encrypt");
+
+        let plain_text = bytes::Bytes::from("Hello world");
+
+        let blob = encrypt.encrypt_blob(plain_text.clone()).expect("encrypt blob");
+
+        println!("plain_text: {:x?}", plain_text.as_ref());
+        println!("encrypt_blob out: {:x?}", blob.as_ref());
+
+        let plain_text_bis = encrypt.decrypt_blob(blob).expect("decrypt blob");
+
+        assert_eq!(plain_text, plain_text_bis);
+    }
+
+    // nonce, ciphertext as written before the versioned envelope
+    fn chacha_blob(key_file: &KeyFile, plain_text: &[u8]) -> Vec<u8> {
+        let cipher = ChaCha20Poly1305::new(GenericArray::from_slice(key_file.read_key()));
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let cipher_text = cipher.encrypt(&nonce, plain_text).expect("encrypt");
+        [nonce.as_slice(), &cipher_text].concat()
+    }
+
+    #[test]
+    fn keyring_decrypts_blobs_of_old_keys() -> anyhow::Result<()> {
+        let old_key = KeyFile::from_bytes(&[1; crate::keys::READ_KEY_SIZE])?;
+        let new_key = KeyFile::from_bytes(&[2; crate::keys::READ_KEY_SIZE])?;
+        let plain_text = bytes::Bytes::from("Hello world");
+
+        let old_blob = EncryptWithChacha::new(&old_key).encrypt_blob(plain_text.clone())?;
+        let legacy_blob = Bytes::from(chacha_blob(&old_key, &plain_text)); // as written before key ids
+
+        let old_key_id = old_key.key_id();
+        let keyring = EncryptWithChacha::new_keyring(&new_key, &[old_key]);
+        assert_eq!(keyring.decrypt_blob(old_blob.clone())?, plain_text);
+        assert_eq!(keyring.decrypt_blob(legacy_blob)?, plain_text);
+        let new_blob = keyring.encrypt_blob(plain_text.clone())?;
+        assert_eq!(&new_blob[HEADER_SIZE - KEY_ID_SIZE..HEADER_SIZE], new_key.key_id().as_slice());
+
+        let err = EncryptWithChacha::new(&new_key).decrypt_blob(old_blob).unwrap_err();
+        assert!(err.to_string().contains(&crate::keys::to_hex(&old_key_id)));
+        Ok(())
+    }
+
+    #[test]
+    fn compressed_payload() -> anyhow::Result<()> {
+        let key = KeyFile::from_bytes(&[3; crate::keys::READ_KEY_SIZE])?;
+        let plain_text = bytes::Bytes::from("compress me ".repeat(1000));
+        let encrypt = EncryptWithChacha::new(&key).with_compression(Compression::default());
+
+        let blob = encrypt.encrypt_blob(plain_text.clone())?;
+        assert_eq!(blob[ENVELOPE_MAGIC.len() + 2], COMPRESSION_ZSTD);
+        assert!(blob.len() < plain_text.len());
+        // decompression does not depend on the compression setting
+        assert_eq!(EncryptWithChacha::new(&key).decrypt_blob(blob)?, plain_text);
+        Ok(())
+    }
+
+    #[test]
+    fn versioned_envelope() -> anyhow::Result<()> {
+        let key = KeyFile::from_bytes(&[4; crate::keys::READ_KEY_SIZE])?;
+        let encrypt = EncryptWithChacha::new(&key);
+        let plain_text = bytes::Bytes::from("Hello world");
+
+        let blob = encrypt.encrypt_blob(plain_text.clone())?;
+        assert_eq!(&blob[..HEADER_SIZE - KEY_ID_SIZE], b"HAR\x01\x01\x00");
+        assert_eq!(blob.len(), HEADER_SIZE + XNONCE_SIZE + plain_text.len() + 16);
+
+        // the header is authenticated
+        let mut tampered = blob.to_vec();
+        tampered[ENVELOPE_MAGIC.len() + 2] = COMPRESSION_ZSTD;
+        assert!(encrypt.decrypt_blob(Bytes::from(tampered)).is_err());
+
+        let mut unknown_algorithm = blob.to_vec();
+        unknown_algorithm[ENVELOPE_MAGIC.len() + 1] = 42;
+        let err = encrypt.decrypt_blob(Bytes::from(unknown_algorithm)).unwrap_err();
+        assert!(err.to_string().contains("unknown algorithm"));
+
+        // version 0 envelope, ChaCha20 with a 12 bytes nonce
+        let v0_blob = [ENVELOPE_MAGIC.as_slice(), &[V0_PAYLOAD_RAW], &key.key_id(), &chacha_blob(&key, &plain_text)].concat();
+        assert_eq!(encrypt.decrypt_blob(Bytes::from(v0_blob))?, plain_text);
+        Ok(())
+    }
+
+    #[test]
+    fn legacy_blob_starting_with_the_magic() -> anyhow::Result<()> {
+        let key = KeyFile::from_bytes(&[5; crate::keys::READ_KEY_SIZE])?;
+        let plain_text = bytes::Bytes::from("Hello world");
+        let cipher = ChaCha20Poly1305::new(GenericArray::from_slice(key.read_key()));
+
+        // parses as a version 1 header with an unknown algorithm
+        let nonce = *Nonce::from_slice(b"HAR\x01\x2a\x00abcdef");
+        let cipher_text = cipher.encrypt(&nonce, plain_text.as_ref()).expect("encrypt");
+        let legacy_blob = [nonce.as_slice(), &cipher_text].concat();
+        assert_eq!(EncryptWithChacha::new(&key).decrypt_blob(Bytes::from(legacy_blob))?, plain_text);
+        Ok(())
+    }
+}
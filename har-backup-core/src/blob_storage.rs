@@ -0,0 +1,208 @@
+use super::thread_sync::Receiver;
+use bytes::Bytes;
+use std::time::Duration;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TaskId {
+    id: u64
+}
+
+impl std::fmt::Debug for TaskId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(f, "TaskId:{}", self.id)
+    }
+}
+
+impl TaskId {
+    pub fn to_u64(&self) -> u64 {
+        self.id
+    }
+
+    pub fn from_u64(val: u64) -> Self {
+        Self { id: val }
+    }
+}
+
+// what went wrong, for callers to tell a missing blob from refused credentials or a key that does not decrypt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    NotFound,
+    Auth, // credentials refused or missing
+    Network, // the request did not go through, or the storage failed
+    Crypto, // encryption, or decryption with the wrong key or of altered data
+    Io, // of local files
+    Changed, // a conditional upload found the object at another version
+    Other,
+}
+
+#[derive(Debug, Clone)]
+pub struct Error {
+    pub kind: ErrorKind,
+    pub msg: String
+}
+
+impl Error {
+    pub fn new(kind: ErrorKind, msg: String) -> Self {
+        Self { kind, msg }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(f, "{}", self.msg)
+    }
+}
+
+impl std::error::Error for Error {
+
+}
+
+#[derive(Debug, Clone)]
+pub struct Progress {
+    // transmitted_bytes: u64
+}
+
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub content: EventContent,
+    pub id: TaskId
+}
+
+impl std::fmt::Display for Event {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[task:{}] {:?}", self.id.id, self.content)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct UploadInfo {
+    pub key: String, // blob name/key
+    pub plaintext_size: u64,
+    pub ciphertext_size: u64,
+    pub ciphertext_checksum: String, // blake3 hex of what was stored
+    pub duration: Duration,
+}
+
+#[derive(Clone)]
+pub struct DownloadInfo {
+    pub data: Bytes, // decrypted data
+    pub ciphertext_size: u64,
+    pub ciphertext_checksum: String, // blake3 hex of what was fetched
+    pub duration: Duration,
+    pub version: Option<String>, // to condition an upload on (the ETag for s3), None if the storage has no conditional upload
+}
+
+impl std::fmt::Debug for DownloadInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DownloadInfo")
+            .field("data", &format_args!("({} bytes)", self.data.len()))
+            .field("ciphertext_size", &self.ciphertext_size)
+            .field("ciphertext_checksum", &self.ciphertext_checksum)
+            .field("duration", &self.duration)
+            .field("version", &self.version)
+            .finish()
+    }
+}
+
+#[derive(Clone)]
+pub enum EventContent {
+    UploadSuccess(UploadInfo),
+    DownloadSuccess(DownloadInfo),
+    Error(Error),
+    Progress(Progress),
+    ExistsSuccess(bool),
+}
+
+// an object as listed by the storage, without downloading it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectInfo {
+    pub key: String,
+    pub size: u64, // of the ciphertext
+}
+
+// what the storage tells of an object without downloading it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectMetadata {
+    pub size: u64, // of the ciphertext
+    pub last_modified: Option<u64>, // unix seconds, if the storage tells
+}
+
+pub type UploadResult = Result<UploadInfo, Error>;
+pub type DownloadResult = Result<DownloadInfo, Error>;
+pub type ExistsResult = Result<bool, Error>;
+pub type ListResult = Result<Vec<ObjectInfo>, Error>;
+pub type MetadataResult = Result<Option<ObjectMetadata>, Error>;
+
+impl std::fmt::Debug for EventContent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EventContent::DownloadSuccess(a) => write!(f, "DownloadSuccess({:?})", a),
+            EventContent::UploadSuccess(a) => write!(f, "UploadSuccess({:?})", a),
+            EventContent::Error(a) => write!(f, "Error({:?})", a),
+            EventContent::Progress(a) => write!(f, "Progress({:?})", a),
+            EventContent::ExistsSuccess(a) => write!(f, "ExistsSuccess({:?})", a),
+        }
+    }
+}
+
+// the error to report instead of what a task should not have answered, rather than panicking over a storage
+pub fn unexpected_event(content: &EventContent, expected: &str) -> Error {
+    Error::new(ErrorKind::Other, format!("Expected {} from the blob storage, got {:?}", expected, content))
+}
+
+pub trait BlobStorage {
+    fn upload(&mut self, data: Bytes, key: Option<&str>) -> TaskId;
+    fn download(&mut self, key: &str) -> TaskId;
+    fn exists(&mut self, key: &str) -> TaskId;
+    fn events(&mut self) -> Receiver<Event>;
+
+    fn upload_blocking(&mut self, data: Bytes, key: Option<&str>) -> UploadResult;
+    fn download_blocking(&mut self, key: &str) -> DownloadResult;
+    fn exists_blocking(&mut self, key: &str) -> ExistsResult;
+    fn list_blocking(&mut self) -> ListResult; // every object of the storage
+    fn metadata_blocking(&mut self, key: &str) -> MetadataResult; // None if there is no object with key
+
+    // uploads to key only if the object there is still at version (as downloaded), fails with ErrorKind::Changed
+    // otherwise. Only called with a version the storage gave
+    fn upload_if_version_blocking(&mut self, _data: Bytes, key: &str, _version: &str) -> UploadResult {
+        Err(Error::new(ErrorKind::Other, format!("Conditional upload of {} on a storage without them", key)))
+    }
+}
+
+pub(crate) fn get_hash_name(bucket_name: &str, data: Bytes) -> String {
+    let mut hasher = hash_name_hasher(bucket_name);
+    hasher.update(data.as_ref());
+    let hash = hasher.finalize();
+    let hash_hex = hash.to_hex();
+    hash_hex.to_string()
+}
+
+// get_hash_name of the content of a file, without having all of it in memory
+pub fn get_hash_name_of_file(bucket_name: &str, path: &std::path::Path) -> std::io::Result<String> {
+    let mut hasher = hash_name_hasher(bucket_name);
+    std::io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+// get_hash_name is the hex of this hasher updated with the content
+pub(crate) fn hash_name_hasher(bucket_name: &str) -> blake3::Hasher {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update("har_backup".as_bytes());
+    hasher.update(bucket_name.as_bytes());
+    hasher
+}
+
+pub(crate) fn get_checksum(data: &[u8]) -> String {
+    blake3::hash(data).to_hex().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EventContent;
+
+    #[test]
+    fn print_debug_event_content() {
+        let event = EventContent::ExistsSuccess(false);
+        println!("{:?}", event);
+    }
+}
\ No newline at end of file
@@ -3,12 +3,21 @@ use bytes::Bytes;
 use log::debug;
 use anyhow::Context;
 use super::blob_storage::{
-    self, Event, EventContent, get_hash_name, BlobStorage};
+    self, ErrorKind, Event, EventContent, get_hash_name, get_checksum, BlobStorage, UploadInfo, DownloadInfo, ObjectInfo, ObjectMetadata};
 use super::blob_encryption::EncryptWithChacha;
+use super::keys::KeyFile;
 use super::blob_storage_tasks::{
     Comm, Task, TaskHelper, TaskProvider};
 use delegate::delegate;
 
+// the storage is local files: a blob that is not there is missing, other errors are of the files
+fn io_error_kind(err: &std::io::Error) -> ErrorKind {
+    match err.kind() {
+        std::io::ErrorKind::NotFound => ErrorKind::NotFound,
+        _ => ErrorKind::Io,
+    }
+}
+
 struct BlobStorageLocalDirectoryImpl {
     local_dir_path: PathBuf,
     encrypt: EncryptWithChacha,
@@ -34,6 +43,7 @@ struct ExistsTask {
 impl Task for UploadTask {
     fn run<T: Comm>(&mut self, mut comm: T) {
         debug!("Running UploadTask id:{}", comm.task_id().to_u64());
+        let start = std::time::Instant::now();
 
         let key = match &self.key {
             Some(key) => key.clone(),
@@ -45,18 +55,25 @@ impl Task for UploadTask {
             Ok(data) => data,
             Err(err) => {
                 let err_msg = format!("Error while encrypting ({})", err);
-                comm.send_error_event(err_msg);
+                comm.send_error_event(ErrorKind::Crypto, err_msg);
                 return;
             }
         };
 
         match std::fs::write(path, data.as_ref()) {
             Ok(_) => {
-                comm.send_event_content(EventContent::UploadSuccess(key));
+                let info = UploadInfo {
+                    key,
+                    plaintext_size: self.data.len() as u64,
+                    ciphertext_size: data.len() as u64,
+                    ciphertext_checksum: get_checksum(data.as_ref()),
+                    duration: start.elapsed(),
+                };
+                comm.send_event_content(EventContent::UploadSuccess(info));
             },
             Err(err) => {
                 let err_msg = format!("Error while opening file ({})", err);
-                comm.send_error_event(err_msg);
+                comm.send_error_event(io_error_kind(&err), err_msg);
             }
         };
     }
@@ -65,27 +82,38 @@ impl Task for UploadTask {
 impl Task for DownloadTask {
     fn run<T: Comm>(&mut self, mut comm: T) {
         debug!("Running DownloadTask id:{}", comm.task_id().to_u64());
+        let start = std::time::Instant::now();
 
         let blob = match std::fs::read(&self.blob_path) {
             Ok(data) => data,
             Err(err) => {
                 let err_msg = format!("Error while opening/reading {:?} ({})", self.blob_path.to_str(), err);
-                comm.send_error_event(err_msg);
+                comm.send_error_event(io_error_kind(&err), err_msg);
                 return;
             }
         };
 
+        let ciphertext_size = blob.len() as u64;
+        let ciphertext_checksum = get_checksum(&blob);
+
         let decrypted = match self.encrypt.decrypt_blob(bytes::Bytes::from(blob)) {
             Ok(data) => data,
             Err(err) => {
                 let err_msg = format!("Error while decrypting ({})", err);
-                comm.send_error_event(err_msg);
+                comm.send_error_event(ErrorKind::Crypto, err_msg);
                 return;
             }
         };
 
         debug!("Success in task {}", comm.task_id().to_u64());
-        let content = EventContent::DownloadSuccess(decrypted);
+        let info = DownloadInfo {
+            data: decrypted,
+            ciphertext_size,
+            ciphertext_checksum,
+            duration: start.elapsed(),
+            version: None,
+        };
+        let content = EventContent::DownloadSuccess(info);
         comm.send_event_content(content);
     }
 }
@@ -99,11 +127,10 @@ impl Task for ExistsTask {
 }
 
 impl BlobStorageLocalDirectoryImpl {
-    pub fn new(local_dir_path: &Path, encryption_key_file: &Path) -> anyhow::Result<Self> {
+    pub fn new(local_dir_path: &Path, encrypt: EncryptWithChacha) -> anyhow::Result<Self> {
         if !local_dir_path.exists() {
             anyhow::bail!("BlobStorageLocalDirectory::new Directory does not exist")
         }
-        let encrypt = EncryptWithChacha::new_with_key_from_file(encryption_key_file).context("Opening key file")?;
         let me = Self {
             local_dir_path: local_dir_path.to_path_buf(),
             encrypt,
@@ -144,6 +171,31 @@ impl TaskProvider for BlobStorageLocalDirectoryImpl {
             blob_path: self.local_dir_path.join(key),
         }
     }
+
+    fn list_objects(&self) -> blob_storage::ListResult {
+        let to_error = |e: std::io::Error| blob_storage::Error::new(io_error_kind(&e), format!("Error while listing ({})", e));
+        let mut objects = Vec::new();
+        for dir_entry in std::fs::read_dir(&self.local_dir_path).map_err(to_error)? {
+            let dir_entry = dir_entry.map_err(to_error)?;
+            let metadata = dir_entry.metadata().map_err(to_error)?;
+            if metadata.is_file() {
+                objects.push(ObjectInfo { key: dir_entry.file_name().to_string_lossy().into_owned(), size: metadata.len() });
+            }
+        }
+        Ok(objects)
+    }
+
+    fn object_metadata(&self, key: &str) -> blob_storage::MetadataResult {
+        let metadata = match std::fs::metadata(self.local_dir_path.join(key)) {
+            Ok(metadata) => metadata,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(blob_storage::Error::new(ErrorKind::Io, format!("Error while reading metadata of {} ({})", key, err))),
+        };
+        let last_modified = metadata.modified().ok()
+            .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|since_epoch| since_epoch.as_secs());
+        Ok(Some(ObjectMetadata { size: metadata.len(), last_modified }))
+    }
 }
 
 pub struct BlobStorageLocalDirectory {
@@ -152,8 +204,17 @@ pub struct BlobStorageLocalDirectory {
 
 impl BlobStorageLocalDirectory {
     pub fn new(local_dir_path: &Path, encryption_key_file: &Path) -> anyhow::Result<Self> {
+        let key = KeyFile::from_file(encryption_key_file).context("Opening key file")?;
+        Self::new_with_key(local_dir_path, &key)
+    }
+
+    pub fn new_with_key(local_dir_path: &Path, key: &KeyFile) -> anyhow::Result<Self> {
+        Self::new_with_encryption(local_dir_path, EncryptWithChacha::new(key))
+    }
+
+    pub fn new_with_encryption(local_dir_path: &Path, encrypt: EncryptWithChacha) -> anyhow::Result<Self> {
         Ok(Self {
-            inner: BlobStorageLocalDirectoryImpl::new(local_dir_path, encryption_key_file)?
+            inner: BlobStorageLocalDirectoryImpl::new(local_dir_path, encrypt)?
         })
     }
 }
@@ -169,6 +230,8 @@ impl BlobStorage for BlobStorageLocalDirectory {
             fn upload_blocking(&mut self, data: Bytes, key: Option<&str>) -> blob_storage::UploadResult;
             fn download_blocking(&mut self, key: &str) -> blob_storage::DownloadResult;
             fn exists_blocking(&mut self, key: &str) -> blob_storage::ExistsResult;
+            fn list_blocking(&mut self) -> blob_storage::ListResult;
+            fn metadata_blocking(&mut self, key: &str) -> blob_storage::MetadataResult;
         }
     }
 }
\ No newline at end of file
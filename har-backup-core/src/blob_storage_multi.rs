@@ -0,0 +1,313 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, Weak};
+use bytes::Bytes;
+use log::debug;
+use super::blob_storage::{
+    self, BlobStorage, Event, EventContent, TaskId, UploadInfo, Error, get_hash_name};
+use super::thread_sync::{self, Receiver, Sender};
+
+// writes go to every storage, reads (download/exists) are served by the first one that does not fail
+// an upload succeeds only if it succeeded everywhere
+pub struct BlobStorageMulti {
+    state: Arc<Mutex<MultiState>>,
+    bucket_name: String, // keys of blobs uploaded without a key are derived from it
+}
+
+pub type ChildStorage = Box<dyn BlobStorage + Send>;
+
+struct MultiState {
+    children: Vec<ChildStorage>,
+    ops: HashMap<TaskId, Op>,
+    child_tasks: HashMap<(usize, TaskId), TaskId>, // (child index, child task) to our task
+    senders: Vec<Sender<Event>>,
+    next_task_id: u64,
+}
+
+enum Op {
+    Upload { remaining: usize, info: Option<UploadInfo>, error: Option<Error> },
+    Read { kind: ReadKind, key: String, child_index: usize },
+}
+
+#[derive(Clone, Copy)]
+enum ReadKind {
+    Download,
+    Exists,
+}
+
+impl BlobStorageMulti {
+    pub fn new(mut children: Vec<ChildStorage>, bucket_name: &str) -> anyhow::Result<Self> {
+        if children.is_empty() {
+            anyhow::bail!("BlobStorageMulti::new needs at least one blob storage");
+        }
+        let receivers: Vec<Receiver<Event>> = children.iter_mut().map(|child| child.events()).collect();
+        let state = Arc::new(Mutex::new(MultiState {
+            children,
+            ops: HashMap::new(),
+            child_tasks: HashMap::new(),
+            senders: Vec::new(),
+            next_task_id: 0,
+        }));
+        for (child_index, receiver) in receivers.into_iter().enumerate() {
+            let state = Arc::downgrade(&state);
+            std::thread::spawn(move || listen_child(child_index, receiver, state));
+        }
+        Ok(Self {
+            state,
+            bucket_name: bucket_name.to_string(),
+        })
+    }
+
+    fn read(&mut self, kind: ReadKind, key: &str) -> TaskId {
+        let mut state = self.state.lock().unwrap();
+        let task_id = state.new_task_id();
+        state.start_read(task_id, kind, key.to_string(), 0);
+        task_id
+    }
+}
+
+// forward events of one child as events of ours; stops when the child or us are gone
+fn listen_child(child_index: usize, receiver: Receiver<Event>, state: Weak<Mutex<MultiState>>) {
+    while let Ok(event) = receiver.recv() {
+        let Some(state) = state.upgrade() else {
+            return;
+        };
+        state.lock().unwrap().on_child_event(child_index, event);
+    }
+}
+
+impl MultiState {
+    fn new_task_id(&mut self) -> TaskId {
+        let task_id = TaskId::from_u64(self.next_task_id);
+        self.next_task_id += 1;
+        task_id
+    }
+
+    fn send(&mut self, id: TaskId, content: EventContent) {
+        let event = Event { id, content };
+        for sender in &self.senders {
+            // it's ok if it's disconnected
+            let _ = sender.send(event.clone());
+        }
+    }
+
+    fn start_read(&mut self, task_id: TaskId, kind: ReadKind, key: String, child_index: usize) {
+        let child = &mut self.children[child_index];
+        let child_task = match kind {
+            ReadKind::Download => child.download(&key),
+            ReadKind::Exists => child.exists(&key),
+        };
+        self.child_tasks.insert((child_index, child_task), task_id);
+        self.ops.insert(task_id, Op::Read { kind, key, child_index });
+    }
+
+    fn on_child_event(&mut self, child_index: usize, event: Event) {
+        if let EventContent::Progress(_) = event.content {
+            return;
+        }
+        let Some(task_id) = self.child_tasks.remove(&(child_index, event.id)) else {
+            debug!("Event for unknown task {} of blob storage {}", event.id.to_u64(), child_index);
+            return;
+        };
+        match self.ops.remove(&task_id).unwrap() {
+            Op::Upload { remaining, mut info, mut error } => {
+                match event.content {
+                    EventContent::UploadSuccess(child_info) => { info.get_or_insert(child_info); },
+                    EventContent::Error(child_error) => {
+                        error = Some(Error::new(child_error.kind, format!("Blob storage {}: {}", child_index, child_error)));
+                    },
+                    other => error = Some(blob_storage::unexpected_event(&other, "an upload result")),
+                }
+                if remaining > 1 {
+                    self.ops.insert(task_id, Op::Upload { remaining: remaining - 1, info, error });
+                } else {
+                    match error {
+                        Some(error) => self.send(task_id, EventContent::Error(error)),
+                        None => self.send(task_id, EventContent::UploadSuccess(info.unwrap())),
+                    }
+                }
+            },
+            Op::Read { kind, key, child_index } => {
+                let failed = matches!(event.content, EventContent::Error(_));
+                if failed && child_index + 1 < self.children.len() {
+                    debug!("Blob storage {} failed to read {}, trying the next one", child_index, key);
+                    self.start_read(task_id, kind, key, child_index + 1);
+                } else {
+                    self.send(task_id, event.content);
+                }
+            },
+        }
+    }
+}
+
+impl BlobStorage for BlobStorageMulti {
+    fn upload(&mut self, data: Bytes, key: Option<&str>) -> TaskId {
+        let key = match key {
+            Some(key) => key.to_string(),
+            None => get_hash_name(&self.bucket_name, data.clone()),
+        };
+        let mut state = self.state.lock().unwrap();
+        let task_id = state.new_task_id();
+        let num_children = state.children.len();
+        for child_index in 0..num_children {
+            let child_task = state.children[child_index].upload(data.clone(), Some(&key));
+            state.child_tasks.insert((child_index, child_task), task_id);
+        }
+        state.ops.insert(task_id, Op::Upload { remaining: num_children, info: None, error: None });
+        task_id
+    }
+
+    fn download(&mut self, key: &str) -> TaskId {
+        self.read(ReadKind::Download, key)
+    }
+
+    fn exists(&mut self, key: &str) -> TaskId {
+        self.read(ReadKind::Exists, key)
+    }
+
+    fn events(&mut self) -> Receiver<Event> {
+        let mut state = self.state.lock().unwrap();
+        state.senders.retain(|sender| !sender.disconnected());
+        let (sender, receiver) = thread_sync::channel::<Event>();
+        state.senders.push(sender);
+        receiver
+    }
+
+    fn upload_blocking(&mut self, data: Bytes, key: Option<&str>) -> blob_storage::UploadResult {
+        let key = match key {
+            Some(key) => key.to_string(),
+            None => get_hash_name(&self.bucket_name, data.clone()),
+        };
+        let mut state = self.state.lock().unwrap();
+        let mut first_info = None;
+        for (child_index, child) in state.children.iter_mut().enumerate() {
+            let info = child.upload_blocking(data.clone(), Some(&key))
+                .map_err(|error| Error::new(error.kind, format!("Blob storage {}: {}", child_index, error)))?;
+            first_info.get_or_insert(info);
+        }
+        Ok(first_info.unwrap())
+    }
+
+    // the version is only kept from the first storage, which conditional uploads go to
+    fn download_blocking(&mut self, key: &str) -> blob_storage::DownloadResult {
+        let mut state = self.state.lock().unwrap();
+        let mut result = None;
+        for (child_index, child) in state.children.iter_mut().enumerate() {
+            match child.download_blocking(key) {
+                Ok(mut info) => {
+                    if child_index > 0 {
+                        info.version = None;
+                    }
+                    return Ok(info);
+                },
+                child_result => result = Some(child_result),
+            }
+        }
+        result.unwrap()
+    }
+
+    fn exists_blocking(&mut self, key: &str) -> blob_storage::ExistsResult {
+        let mut state = self.state.lock().unwrap();
+        let mut result = None;
+        for child in state.children.iter_mut() {
+            let child_result = child.exists_blocking(key);
+            if child_result.is_ok() {
+                return child_result;
+            }
+            result = Some(child_result);
+        }
+        result.unwrap()
+    }
+
+    fn list_blocking(&mut self) -> blob_storage::ListResult {
+        let mut state = self.state.lock().unwrap();
+        let mut result = None;
+        for child in state.children.iter_mut() {
+            let child_result = child.list_blocking();
+            if child_result.is_ok() {
+                return child_result;
+            }
+            result = Some(child_result);
+        }
+        result.unwrap()
+    }
+
+    fn metadata_blocking(&mut self, key: &str) -> blob_storage::MetadataResult {
+        let mut state = self.state.lock().unwrap();
+        let mut result = None;
+        for child in state.children.iter_mut() {
+            let child_result = child.metadata_blocking(key);
+            if child_result.is_ok() {
+                return child_result;
+            }
+            result = Some(child_result);
+        }
+        result.unwrap()
+    }
+
+    // conditional on the first storage, the others are written to after it
+    fn upload_if_version_blocking(&mut self, data: Bytes, key: &str, version: &str) -> blob_storage::UploadResult {
+        let mut state = self.state.lock().unwrap();
+        let info = state.children[0].upload_if_version_blocking(data.clone(), key, version)
+            .map_err(|error| Error::new(error.kind, format!("Blob storage 0: {}", error)))?;
+        for (child_index, child) in state.children.iter_mut().enumerate().skip(1) {
+            child.upload_blocking(data.clone(), Some(key))
+                .map_err(|error| Error::new(error.kind, format!("Blob storage {}: {}", child_index, error)))?;
+        }
+        Ok(info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blob_storage_local_directory::BlobStorageLocalDirectory;
+    use crate::keys::KeyFile;
+
+    fn make_local(key: &KeyFile) -> (tempfile::TempDir, ChildStorage) {
+        let tempdir = tempfile::tempdir().expect("create tempdir for local blob storage");
+        let blob_storage = BlobStorageLocalDirectory::new_with_key(tempdir.path(), key).expect("create blob storage");
+        (tempdir, Box::new(blob_storage))
+    }
+
+    #[test]
+    fn write_to_all_read_from_first_available() -> anyhow::Result<()> {
+        let key = KeyFile::from_bytes(&[7; crate::keys::READ_KEY_SIZE])?;
+        let (dir_a, storage_a) = make_local(&key);
+        let (dir_b, storage_b) = make_local(&key);
+        let mut multi = BlobStorageMulti::new(vec![storage_a, storage_b], dir_a.path().to_str().unwrap())?;
+
+        let events = multi.events();
+        let task_id = multi.upload(Bytes::from("kek"), None);
+        let event = events.recv()?;
+        assert_eq!(event.id, task_id);
+        let EventContent::UploadSuccess(info) = event.content else {
+            panic!("Expected upload success, got {:?}", event.content);
+        };
+        assert!(dir_a.path().join(&info.key).exists());
+        assert!(dir_b.path().join(&info.key).exists());
+
+        std::fs::remove_file(dir_a.path().join(&info.key))?;
+        let task_id = multi.download(&info.key);
+        let event = events.recv()?;
+        assert_eq!(event.id, task_id);
+        let EventContent::DownloadSuccess(download_info) = event.content else {
+            panic!("Expected download success, got {:?}", event.content);
+        };
+        assert_eq!(download_info.data, Bytes::from("kek"));
+        assert_eq!(multi.download_blocking(&info.key)?.data, Bytes::from("kek"));
+        Ok(())
+    }
+
+    #[test]
+    fn metadata_of_an_object() -> anyhow::Result<()> {
+        let key = KeyFile::from_bytes(&[7; crate::keys::READ_KEY_SIZE])?;
+        let (dir, storage) = make_local(&key);
+        let mut multi = BlobStorageMulti::new(vec![storage], dir.path().to_str().unwrap())?;
+        let info = multi.upload_blocking(Bytes::from("kek"), None)?;
+        let metadata = multi.metadata_blocking(&info.key)?.unwrap();
+        assert_eq!(metadata.size, info.ciphertext_size);
+        assert!(metadata.last_modified.is_some_and(|modified| modified + 60 > crate::clock::unix_now()));
+        assert_eq!(multi.metadata_blocking("chuchu")?, None);
+        Ok(())
+    }
+}
@@ -0,0 +1,330 @@
+// blobs in any remote rclone has (rclone://REMOTE:PATH), transferred by running the rclone binary of PATH with its
+// own config: rcat to upload, cat to download, lsf and lsjson to find objects. Encryption is ours as for the others
+use std::io::Write;
+use std::process::{Command, Stdio};
+use bytes::Bytes;
+use log::debug;
+use serde::Deserialize;
+use super::blob_storage::{
+    self, ErrorKind, Event, EventContent, get_hash_name, get_checksum, BlobStorage, UploadInfo, DownloadInfo, ObjectInfo, ObjectMetadata};
+use super::blob_encryption::EncryptWithChacha;
+use super::blob_storage_tasks::{
+    Comm, Task, TaskHelper, TaskProvider};
+use delegate::delegate;
+
+const RCLONE: &str = "rclone";
+// what rclone exits with when the directory of the remote does not exist (yet)
+const DIRECTORY_NOT_FOUND: i32 = 3;
+const FILE_NOT_FOUND: i32 = 4;
+
+#[derive(Debug)]
+enum RcloneError {
+    Exit(i32, String), // exit code and stderr
+    Spawn(std::io::Error),
+}
+
+impl RcloneError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            RcloneError::Exit(DIRECTORY_NOT_FOUND | FILE_NOT_FOUND, _) => ErrorKind::NotFound,
+            RcloneError::Exit(..) => ErrorKind::Network,
+            RcloneError::Spawn(_) => ErrorKind::Other,
+        }
+    }
+}
+
+impl std::fmt::Display for RcloneError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RcloneError::Exit(code, stderr) => write!(f, "rclone exited with {}: {}", code, stderr.trim()),
+            RcloneError::Spawn(err) => write!(f, "Running rclone: {}", err),
+        }
+    }
+}
+
+// stdout of rclone with args, input written to its stdin
+fn run_rclone(args: &[&str], input: Option<&[u8]>) -> Result<Vec<u8>, RcloneError> {
+    debug!("rclone {}", args.join(" "));
+    let mut child = Command::new(RCLONE)
+        .args(args)
+        .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(RcloneError::Spawn)?;
+    // stdin is dropped (closed) for rclone to see the end of it, a write fails if rclone exited: its error tells more
+    let written = match input {
+        Some(input) => child.stdin.take().unwrap().write_all(input),
+        None => Ok(()),
+    };
+    let output = child.wait_with_output().map_err(RcloneError::Spawn)?;
+    match output.status.code() {
+        Some(0) => written.map(|_| output.stdout).map_err(RcloneError::Spawn),
+        code => Err(RcloneError::Exit(code.unwrap_or(-1), String::from_utf8_lossy(&output.stderr).into_owned())),
+    }
+}
+
+// REMOTE:PATH/key, or REMOTE:key at the root of a remote
+fn object_path(remote: &str, key: &str) -> String {
+    match remote.ends_with([':', '/']) {
+        true => format!("{}{}", remote, key),
+        false => format!("{}/{}", remote, key),
+    }
+}
+
+struct BlobStorageRcloneImpl {
+    remote: String,
+    encrypt: EncryptWithChacha,
+    task_helper: TaskHelper
+}
+
+struct UploadTask {
+    remote: String,
+    key: Option<String>,
+    data: Bytes,
+    encrypt: EncryptWithChacha
+}
+
+struct DownloadTask {
+    object_path: String,
+    encrypt: EncryptWithChacha
+}
+
+struct ExistsTask {
+    remote: String,
+    key: String,
+}
+
+impl Task for UploadTask {
+    fn run<T: Comm>(&mut self, mut comm: T) {
+        debug!("Running UploadTask id:{}", comm.task_id().to_u64());
+        let start = std::time::Instant::now();
+
+        let key = match &self.key {
+            Some(key) => key.clone(),
+            None => get_hash_name(&self.remote, self.data.clone())
+        };
+
+        let data = match self.encrypt.encrypt_blob(self.data.clone()) {
+            Ok(data) => data,
+            Err(err) => {
+                let err_msg = format!("Error while encrypting ({})", err);
+                comm.send_error_event(ErrorKind::Crypto, err_msg);
+                return;
+            }
+        };
+
+        match run_rclone(&["rcat", &object_path(&self.remote, &key)], Some(data.as_ref())) {
+            Ok(_) => {
+                let info = UploadInfo {
+                    key,
+                    plaintext_size: self.data.len() as u64,
+                    ciphertext_size: data.len() as u64,
+                    ciphertext_checksum: get_checksum(data.as_ref()),
+                    duration: start.elapsed(),
+                };
+                comm.send_event_content(EventContent::UploadSuccess(info));
+            },
+            Err(err) => comm.send_error_event(err.kind(), format!("Error while uploading ({})", err)),
+        };
+    }
+}
+
+impl Task for DownloadTask {
+    fn run<T: Comm>(&mut self, mut comm: T) {
+        debug!("Running DownloadTask id:{}", comm.task_id().to_u64());
+        let start = std::time::Instant::now();
+
+        let blob = match run_rclone(&["cat", &self.object_path], None) {
+            Ok(data) => data,
+            Err(err) => {
+                let err_msg = format!("Error while downloading {} ({})", self.object_path, err);
+                comm.send_error_event(err.kind(), err_msg);
+                return;
+            }
+        };
+
+        let ciphertext_size = blob.len() as u64;
+        let ciphertext_checksum = get_checksum(&blob);
+
+        let decrypted = match self.encrypt.decrypt_blob(Bytes::from(blob)) {
+            Ok(data) => data,
+            Err(err) => {
+                let err_msg = format!("Error while decrypting ({})", err);
+                comm.send_error_event(ErrorKind::Crypto, err_msg);
+                return;
+            }
+        };
+
+        let info = DownloadInfo {
+            data: decrypted,
+            ciphertext_size,
+            ciphertext_checksum,
+            duration: start.elapsed(),
+            version: None,
+        };
+        comm.send_event_content(EventContent::DownloadSuccess(info));
+    }
+}
+
+impl Task for ExistsTask {
+    fn run<T: Comm>(&mut self, mut comm: T) {
+        // keys have no characters that filters give a meaning to
+        let include = format!("/{}", self.key);
+        match run_rclone(&["lsf", "--files-only", "--include", &include, &self.remote], None) {
+            Ok(listed) => {
+                let exists = String::from_utf8_lossy(&listed).lines().any(|name| name == self.key);
+                comm.send_event_content(EventContent::ExistsSuccess(exists));
+            },
+            Err(RcloneError::Exit(DIRECTORY_NOT_FOUND, _)) => comm.send_event_content(EventContent::ExistsSuccess(false)),
+            Err(err) => comm.send_error_event(err.kind(), format!("Error while checking {} ({})", self.key, err)),
+        }
+    }
+}
+
+// an item of rclone lsjson
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ListedObject {
+    name: String,
+    size: i64, // -1 if the remote does not know
+    #[serde(default)]
+    mod_time: Option<String>, // with the offset of the remote, see clock::parse_timestamp_with_offset
+}
+
+impl BlobStorageRcloneImpl {
+    pub fn new(remote: &str, encrypt: EncryptWithChacha) -> anyhow::Result<Self> {
+        if !remote.contains(':') {
+            anyhow::bail!("rclone remote {} is not REMOTE:PATH", remote)
+        }
+        let me = Self {
+            remote: remote.to_string(),
+            encrypt,
+            task_helper: TaskHelper::new()
+        };
+        Ok(me)
+    }
+}
+
+impl TaskProvider for BlobStorageRcloneImpl {
+
+    type UploadTask = UploadTask;
+    type DownloadTask = DownloadTask;
+    type ExistsTask = ExistsTask;
+
+    fn task_helper(&mut self) -> &mut TaskHelper {
+        &mut self.task_helper
+    }
+
+    fn new_upload_task(&self, data: Bytes, key: Option<&str>) -> UploadTask {
+        UploadTask {
+            remote: self.remote.clone(),
+            key: key.map(String::from),
+            data,
+            encrypt: self.encrypt.clone()
+        }
+    }
+
+    fn new_download_task(&self, key: &str) -> DownloadTask {
+        DownloadTask {
+            object_path: object_path(&self.remote, key),
+            encrypt: self.encrypt.clone()
+        }
+    }
+
+    fn new_exists_task(&self, key: &str) -> ExistsTask {
+        ExistsTask {
+            remote: self.remote.clone(),
+            key: key.to_string(),
+        }
+    }
+
+    fn list_objects(&self) -> blob_storage::ListResult {
+        let to_error = |kind, msg: String| blob_storage::Error::new(kind, format!("Error while listing ({})", msg));
+        let listed = match run_rclone(&["lsjson", "--files-only", &self.remote], None) {
+            Ok(listed) => listed,
+            Err(RcloneError::Exit(DIRECTORY_NOT_FOUND, _)) => return Ok(Vec::new()),
+            Err(err) => return Err(to_error(err.kind(), err.to_string())),
+        };
+        let listed: Vec<ListedObject> = serde_json::from_slice(&listed).map_err(|e| to_error(ErrorKind::Other, e.to_string()))?;
+        Ok(listed.into_iter().map(|object| ObjectInfo { key: object.name, size: object.size.max(0) as u64 }).collect())
+    }
+
+    // lsjson of the one object, as ExistsTask filters lsf
+    fn object_metadata(&self, key: &str) -> blob_storage::MetadataResult {
+        let to_error = |kind, msg: String| blob_storage::Error::new(kind, format!("Error while reading metadata of {} ({})", key, msg));
+        let include = format!("/{}", key);
+        let listed = match run_rclone(&["lsjson", "--files-only", "--include", &include, &self.remote], None) {
+            Ok(listed) => listed,
+            Err(RcloneError::Exit(DIRECTORY_NOT_FOUND, _)) => return Ok(None),
+            Err(err) => return Err(to_error(err.kind(), err.to_string())),
+        };
+        let listed: Vec<ListedObject> = serde_json::from_slice(&listed).map_err(|e| to_error(ErrorKind::Other, e.to_string()))?;
+        Ok(listed.into_iter().find(|object| object.name == key).map(|object| {
+            let last_modified = object.mod_time
+                .and_then(|mod_time| crate::clock::parse_timestamp_with_offset(&mod_time).ok())
+                .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|since_epoch| since_epoch.as_secs());
+            ObjectMetadata { size: object.size.max(0) as u64, last_modified }
+        }))
+    }
+}
+
+pub struct BlobStorageRclone {
+    inner: BlobStorageRcloneImpl
+}
+
+impl BlobStorageRclone {
+    // remote as rclone takes it: a remote of its config and a path in it (REMOTE:PATH)
+    pub fn new_with_encryption(remote: &str, encrypt: EncryptWithChacha) -> anyhow::Result<Self> {
+        Ok(Self {
+            inner: BlobStorageRcloneImpl::new(remote, encrypt)?
+        })
+    }
+}
+
+impl BlobStorage for BlobStorageRclone {
+    delegate! {
+        to self.inner {
+            fn upload(&mut self, data: Bytes, key: Option<&str>) -> blob_storage::TaskId;
+            fn download(&mut self, key: &str) -> blob_storage::TaskId;
+            fn exists(&mut self, key: &str) -> blob_storage::TaskId;
+            fn events(&mut self) -> crate::thread_sync::Receiver<Event>;
+
+            fn upload_blocking(&mut self, data: Bytes, key: Option<&str>) -> blob_storage::UploadResult;
+            fn download_blocking(&mut self, key: &str) -> blob_storage::DownloadResult;
+            fn exists_blocking(&mut self, key: &str) -> blob_storage::ExistsResult;
+            fn list_blocking(&mut self) -> blob_storage::ListResult;
+            fn metadata_blocking(&mut self, key: &str) -> blob_storage::MetadataResult;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::KeyFile;
+
+    #[test]
+    fn object_paths() {
+        assert_eq!(object_path("gdrive:", "manifest"), "gdrive:manifest");
+        assert_eq!(object_path("gdrive:backups/har", "manifest"), "gdrive:backups/har/manifest");
+        assert_eq!(object_path("gdrive:backups/", "manifest"), "gdrive:backups/manifest");
+    }
+
+    #[test]
+    #[ignore] // needs rclone in PATH, a local path is a remote without config
+    fn upload_download_through_rclone() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let remote = format!(":local:{}", dir.path().to_str().unwrap());
+        let mut storage = BlobStorageRclone::new_with_encryption(&remote, EncryptWithChacha::new(&KeyFile::from_bytes(&[1; crate::keys::READ_KEY_SIZE])?))?;
+        let info = storage.upload_blocking(Bytes::from("kek"), None)?;
+        assert!(storage.exists_blocking(&info.key)?);
+        assert!(!storage.exists_blocking("chuchu")?);
+        assert_eq!(storage.download_blocking(&info.key)?.data, Bytes::from("kek"));
+        assert_eq!(storage.metadata_blocking(&info.key)?.map(|metadata| metadata.size), Some(info.ciphertext_size));
+        assert_eq!(storage.metadata_blocking("chuchu")?, None);
+        assert_eq!(storage.list_blocking()?, vec![ObjectInfo { key: info.key, size: info.ciphertext_size }]);
+        Ok(())
+    }
+}
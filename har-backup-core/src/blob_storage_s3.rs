@@ -0,0 +1,382 @@
+use crate::blob_storage::{self, BlobStorage, ErrorKind, Event, EventContent, get_hash_name, get_checksum, UploadInfo, DownloadInfo, ObjectInfo, ObjectMetadata};
+use crate::blob_storage_tasks::{run_blocking, Comm, Task, TaskHelper, TaskProvider};
+use crate::blob_encryption::EncryptWithChacha;
+use crate::keys::KeyFile;
+use std::path::Path;
+use std::io::Read;
+use std::sync::Arc;
+use rusty_s3::{Bucket, Credentials, UrlStyle, S3Action};
+use rusty_s3::actions::ListObjectsV2;
+use url::Url;
+use bytes::Bytes;
+use anyhow::Context;
+use log::debug;
+use delegate::delegate;
+
+mod credentials;
+pub use credentials::{CredentialsProvider, FetchedCredentials, ProfileCredentials, provider_from_spec};
+use credentials::SharedCredentials;
+
+const PRESIGNED_URL_DURATION: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+struct BlobStorageS3Impl {
+    task_helper: TaskHelper,
+    bucket: Bucket,
+    credentials: Arc<SharedCredentials>,
+    encrypt: EncryptWithChacha,
+}
+
+impl BlobStorageS3Impl {
+    pub fn new(endpoint: &str, bucket: &str, credentials: Box<dyn CredentialsProvider>, encrypt: EncryptWithChacha) -> anyhow::Result<Self> {
+        let endpoint = endpoint.parse().context("parsing endpoint")?;
+        let bucket = bucket.to_string();
+        let bucket = Bucket::new(endpoint, UrlStyle::VirtualHost, bucket, "toto").expect("Create rusty_s3 bucket");
+        debug!("Init s3 bucket: {:?}", bucket);
+        Ok(Self {
+            task_helper: TaskHelper::new(),
+            bucket,
+            credentials: Arc::new(SharedCredentials::new(credentials)),
+            encrypt,
+        })
+    }
+}
+
+enum SendError {
+    Credentials(anyhow::Error),
+    Request(Box<ureq::Error>), // boxed, ureq errors are large
+}
+
+impl SendError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            SendError::Credentials(_) => ErrorKind::Auth,
+            SendError::Request(err) => match **err {
+                ureq::Error::Status(401 | 403, _) => ErrorKind::Auth,
+                ureq::Error::Status(404, _) => ErrorKind::NotFound,
+                ureq::Error::Status(412, _) => ErrorKind::Changed, // If-Match of a conditional upload
+                _ => ErrorKind::Network,
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for SendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SendError::Credentials(err) => write!(f, "{:#}", err),
+            SendError::Request(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+// urls are signed when the request is sent, with the current credentials
+// temporary credentials that expired are refused with 403 (or 400 ExpiredToken): then the request is sent once more
+// with credentials fetched again, if their source can give new ones
+fn send_signed(
+    bucket: &Bucket,
+    credentials: &SharedCredentials,
+    sign: impl Fn(&Bucket, &Credentials) -> Url,
+    send: impl Fn(&Url) -> Result<ureq::Response, Box<ureq::Error>>,
+) -> Result<ureq::Response, SendError> {
+    let (current, generation) = credentials.get().map_err(SendError::Credentials)?;
+    match send(&sign(bucket, &current)) {
+        Err(err) if matches!(*err, ureq::Error::Status(400 | 403, _)) => {
+            let refreshed = credentials.refresh(generation).map_err(SendError::Credentials)?;
+            match refreshed {
+                Some((refreshed, _)) => {
+                    debug!("Credentials refused, sending again with refreshed ones");
+                    send(&sign(bucket, &refreshed)).map_err(SendError::Request)
+                },
+                None => Err(SendError::Request(err)),
+            }
+        },
+        result => result.map_err(SendError::Request),
+    }
+}
+
+struct UploadTask {
+    bucket: Bucket,
+    credentials: Arc<SharedCredentials>,
+    key: Option<String>,
+    data: Bytes,
+    encrypt: EncryptWithChacha,
+    if_match: Option<String>, // ETag the object must still have, for a conditional upload
+}
+
+struct DownloadTask {
+    bucket: Bucket,
+    credentials: Arc<SharedCredentials>,
+    key: String,
+    encrypt: EncryptWithChacha,
+}
+
+struct ExistsTask {
+    bucket: Bucket,
+    credentials: Arc<SharedCredentials>,
+    key: String,
+}
+
+impl Task for UploadTask {
+    fn run<T: Comm>(&mut self, mut comm: T) {
+        let start = std::time::Instant::now();
+
+        let key = match &self.key {
+            Some(key) => key.clone(),
+            None => get_hash_name(self.bucket.name(), self.data.clone())
+        };
+
+        let data = match self.encrypt.encrypt_blob(self.data.clone()) {
+            Ok(data) => data,
+            Err(err) => {
+                let err_msg = format!("Error while encrypting ({})", err);
+                comm.send_error_event(ErrorKind::Crypto, err_msg);
+                return;
+            }
+        };
+
+        let response = send_signed(&self.bucket, &self.credentials,
+            |bucket, credentials| bucket.put_object(Some(credentials), key.as_str()).sign(PRESIGNED_URL_DURATION),
+            |url| {
+                let mut request = ureq::request_url("PUT", url);
+                if let Some(etag) = &self.if_match {
+                    request = request.set("If-Match", etag);
+                }
+                request.send_bytes(data.as_ref()).map_err(Box::new)
+            });
+        match response {
+            Err(err) => {
+                let err_msg = format!("Error while uploading ({})", err);
+                comm.send_error_event(err.kind(), err_msg);
+                return;
+            },
+            Ok(_) => (),
+        };
+
+        let info = UploadInfo {
+            key,
+            plaintext_size: self.data.len() as u64,
+            ciphertext_size: data.len() as u64,
+            ciphertext_checksum: get_checksum(data.as_ref()),
+            duration: start.elapsed(),
+        };
+        comm.send_event_content(EventContent::UploadSuccess(info));
+    }
+}
+
+impl Task for DownloadTask {
+    fn run<T: Comm>(&mut self, mut comm: T) {
+        let start = std::time::Instant::now();
+        let response = send_signed(&self.bucket, &self.credentials,
+            |bucket, credentials| bucket.get_object(Some(credentials), self.key.as_str()).sign(PRESIGNED_URL_DURATION),
+            |url| ureq::request_url("GET", url).call().map_err(Box::new));
+        let response = match response {
+            Err(err) => {
+                let err_msg = format!("Error while downloading ({})", err);
+                comm.send_error_event(err.kind(), err_msg);
+                return;
+            },
+            Ok(v) => v,
+        };
+        let version = response.header("ETag").map(String::from);
+
+        let mut buf = Vec::new();
+        match response.into_reader().read_to_end(&mut buf) {
+            Ok(_) => (),
+            Err(err) => {
+                let err_msg = format!("Error while reading response content ({})", err);
+                comm.send_error_event(ErrorKind::Network, err_msg);
+                return;
+            },
+        };
+        let blob = Bytes::from(buf);
+        let ciphertext_size = blob.len() as u64;
+        let ciphertext_checksum = get_checksum(blob.as_ref());
+
+        let decrypted = match self.encrypt.decrypt_blob(bytes::Bytes::from(blob)) {
+            Ok(data) => data,
+            Err(err) => {
+                let err_msg = format!("Error while decrypting ({})", err);
+                comm.send_error_event(ErrorKind::Crypto, err_msg);
+                return;
+            }
+        };
+
+        debug!("Success in task {}", comm.task_id().to_u64());
+        let info = DownloadInfo {
+            data: decrypted,
+            ciphertext_size,
+            ciphertext_checksum,
+            duration: start.elapsed(),
+            version,
+        };
+        let content = EventContent::DownloadSuccess(info);
+        comm.send_event_content(content);
+    }
+}
+
+impl Task for ExistsTask {
+    fn run<T: Comm>(&mut self, mut comm: T) {
+        let response = send_signed(&self.bucket, &self.credentials,
+            |bucket, credentials| bucket.head_object(Some(credentials), self.key.as_str()).sign(PRESIGNED_URL_DURATION),
+            |url| ureq::request_url("HEAD", url).call().map_err(Box::new));
+        match response {
+            Err(err) => {
+                match err {
+                    SendError::Request(err) if matches!(*err, ureq::Error::Status(404, _)) => {
+                        let content = EventContent::ExistsSuccess(false);
+                        comm.send_event_content(content);
+                    },
+                    err => {
+                        let err_msg = format!("Error while head'ing ({})", err);
+                        comm.send_error_event(err.kind(), err_msg);
+                    },
+                };
+                return;
+            },
+            Ok(_) => {
+                let content = EventContent::ExistsSuccess(true);
+                comm.send_event_content(content);
+            },
+        };
+    }
+}
+
+impl TaskProvider for BlobStorageS3Impl {
+
+    type UploadTask = UploadTask;
+    type DownloadTask = DownloadTask;
+    type ExistsTask = ExistsTask;
+
+    fn task_helper(&mut self) -> &mut TaskHelper {
+        &mut self.task_helper
+    }
+
+    fn new_upload_task(&self, data: bytes::Bytes, key: Option<&str>) -> UploadTask {
+        UploadTask {
+            bucket: self.bucket.clone(),
+            credentials: self.credentials.clone(),
+            data,
+            encrypt: self.encrypt.clone(),
+            key: key.map(String::from),
+            if_match: None,
+        }
+    }
+
+    fn new_download_task(&self, key: &str) -> DownloadTask {
+        DownloadTask {
+            bucket: self.bucket.clone(),
+            credentials: self.credentials.clone(),
+            key: key.to_string(),
+            encrypt: self.encrypt.clone(),
+        }
+    }
+
+    fn new_exists_task(&self, key: &str) -> ExistsTask {
+        ExistsTask {
+            bucket: self.bucket.clone(),
+            credentials: self.credentials.clone(),
+            key: key.to_string(),
+        }
+    }
+
+    // one request per page of (up to) 1000 objects
+    fn list_objects(&self) -> blob_storage::ListResult {
+        let mut objects = Vec::new();
+        let mut continuation_token: Option<String> = None;
+        loop {
+            let sign = |bucket: &Bucket, credentials: &Credentials| {
+                let mut action = bucket.list_objects_v2(Some(credentials));
+                if let Some(token) = &continuation_token {
+                    action.with_continuation_token(token.as_str());
+                }
+                action.sign(PRESIGNED_URL_DURATION)
+            };
+            let to_error = |kind, err: &dyn std::fmt::Display| blob_storage::Error::new(kind, format!("Error while listing ({})", err));
+            let response = send_signed(&self.bucket, &self.credentials, sign, |url| ureq::request_url("GET", url).call().map_err(Box::new))
+                .map_err(|err| to_error(err.kind(), &err))?;
+            let body = response.into_string().map_err(|err| to_error(ErrorKind::Network, &err))?;
+            let page = ListObjectsV2::parse_response(body.as_str())
+                .map_err(|err| blob_storage::Error::new(ErrorKind::Network, format!("Error while parsing listing ({})", err)))?;
+            objects.extend(page.contents.into_iter().map(|content| ObjectInfo { key: content.key, size: content.size }));
+            continuation_token = page.next_continuation_token;
+            if continuation_token.is_none() {
+                return Ok(objects);
+            }
+        }
+    }
+
+    // a HEAD request, as exists
+    fn object_metadata(&self, key: &str) -> blob_storage::MetadataResult {
+        let to_error = |kind, err: &dyn std::fmt::Display| blob_storage::Error::new(kind, format!("Error while head'ing {} ({})", key, err));
+        let response = send_signed(&self.bucket, &self.credentials,
+            |bucket, credentials| bucket.head_object(Some(credentials), key).sign(PRESIGNED_URL_DURATION),
+            |url| ureq::request_url("HEAD", url).call().map_err(Box::new));
+        let response = match response {
+            Ok(response) => response,
+            Err(SendError::Request(err)) if matches!(*err, ureq::Error::Status(404, _)) => return Ok(None),
+            Err(err) => return Err(to_error(err.kind(), &err)),
+        };
+        let size = response.header("Content-Length").and_then(|length| length.parse().ok())
+            .ok_or_else(|| to_error(ErrorKind::Network, &"no Content-Length"))?;
+        let last_modified = response.header("Last-Modified")
+            .and_then(|date| crate::clock::parse_http_date(date).ok())
+            .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|since_epoch| since_epoch.as_secs());
+        Ok(Some(ObjectMetadata { size, last_modified }))
+    }
+}
+
+pub struct BlobStorageS3 {
+    inner: BlobStorageS3Impl
+}
+
+impl BlobStorageS3 {
+    pub fn new(endpoint: &str, bucket: &str, key: &str, secret: &str, encryption_key_file: &Path) -> anyhow::Result<Self> {
+        let encryption_key = KeyFile::from_file(encryption_key_file).context("Opening key file")?;
+        Self::new_with_key(endpoint, bucket, key, secret, &encryption_key)
+    }
+
+    pub fn new_with_key(endpoint: &str, bucket: &str, key: &str, secret: &str, encryption_key: &KeyFile) -> anyhow::Result<Self> {
+        Self::new_with_encryption(endpoint, bucket, key, secret, EncryptWithChacha::new(encryption_key))
+    }
+
+    // key and secret as in the remote spec, see credentials for the sources of temporary credentials
+    pub fn new_with_encryption(endpoint: &str, bucket: &str, key: &str, secret: &str, encrypt: EncryptWithChacha) -> anyhow::Result<Self> {
+        Self::new_with_provider(endpoint, bucket, provider_from_spec(key, secret)?, encrypt)
+    }
+
+    pub fn new_with_provider(endpoint: &str, bucket: &str, credentials: Box<dyn CredentialsProvider>, encrypt: EncryptWithChacha) -> anyhow::Result<Self> {
+        Ok(Self {
+            inner: BlobStorageS3Impl::new(endpoint, bucket, credentials, encrypt)?
+        })
+    }
+}
+
+impl BlobStorage for BlobStorageS3 {
+    // the version is the ETag of the download, the upload is sent with If-Match and refused with 412 if it changed
+    fn upload_if_version_blocking(&mut self, data: Bytes, key: &str, version: &str) -> blob_storage::UploadResult {
+        let task = UploadTask {
+            if_match: Some(version.to_string()),
+            ..self.inner.new_upload_task(data, Some(key))
+        };
+        run_blocking(task, "an upload result", |content| match content {
+            EventContent::UploadSuccess(result) => Ok(result),
+            other => Err(other),
+        })
+    }
+
+    delegate! {
+        to self.inner {
+            fn upload(&mut self, data: Bytes, key: Option<&str>) -> blob_storage::TaskId;
+            fn download(&mut self, key: &str) -> blob_storage::TaskId;
+            fn exists(&mut self, key: &str) -> blob_storage::TaskId;
+            fn events(&mut self) -> crate::thread_sync::Receiver<Event>;
+
+            fn upload_blocking(&mut self, data: Bytes, key: Option<&str>) -> blob_storage::UploadResult;
+            fn download_blocking(&mut self, key: &str) -> blob_storage::DownloadResult;
+            fn exists_blocking(&mut self, key: &str) -> blob_storage::ExistsResult;
+            fn list_blocking(&mut self) -> blob_storage::ListResult;
+            fn metadata_blocking(&mut self, key: &str) -> blob_storage::MetadataResult;
+        }
+    }
+}
\ No newline at end of file
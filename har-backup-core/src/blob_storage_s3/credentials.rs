@@ -0,0 +1,284 @@
+// where the credentials of s3 requests come from
+//
+// the key line of an s3 remote spec is either an access key (and the next line its secret) or a source:
+// profile:<name> (the shared credentials file, that other tools keep fresh), process:<command> (prints the
+// json of aws credential_process) or imds (the role of the ec2 instance). temporary credentials from a source
+// are fetched again before they expire, and when the storage refuses them.
+
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use anyhow::Context;
+use log::debug;
+use rusty_s3::Credentials;
+use serde::Deserialize;
+use crate::clock::parse_utc_timestamp;
+
+// fetched again when they expire in less than that
+const EXPIRATION_MARGIN: Duration = Duration::from_secs(5 * 60);
+const IMDS_ENDPOINT: &str = "http://169.254.169.254";
+const IMDS_TOKEN_TTL_SECONDS: &str = "300";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FetchedCredentials {
+    pub key: String,
+    pub secret: String,
+    pub token: Option<String>, // of temporary credentials
+    pub expiration: Option<SystemTime>,
+}
+
+impl FetchedCredentials {
+    fn to_credentials(&self) -> Credentials {
+        match &self.token {
+            Some(token) => Credentials::new_with_token(&self.key, &self.secret, token),
+            None => Credentials::new(&self.key, &self.secret),
+        }
+    }
+}
+
+pub trait CredentialsProvider: Send + Sync {
+    fn fetch(&self) -> anyhow::Result<FetchedCredentials>;
+
+    // false if fetching again can't give anything new
+    fn can_refresh(&self) -> bool {
+        true
+    }
+}
+
+pub struct StaticCredentials {
+    key: String,
+    secret: String,
+}
+
+impl CredentialsProvider for StaticCredentials {
+    fn fetch(&self) -> anyhow::Result<FetchedCredentials> {
+        Ok(FetchedCredentials { key: self.key.clone(), secret: self.secret.clone(), token: None, expiration: None })
+    }
+
+    fn can_refresh(&self) -> bool {
+        false
+    }
+}
+
+// a profile of the shared credentials file, read again on every fetch
+pub struct ProfileCredentials {
+    path: PathBuf,
+    profile: String,
+}
+
+impl ProfileCredentials {
+    pub fn new(profile: &str) -> anyhow::Result<Self> {
+        let path = match std::env::var_os("AWS_SHARED_CREDENTIALS_FILE") {
+            Some(path) => PathBuf::from(path),
+            None => {
+                let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE")).context("No home directory for the aws credentials file")?;
+                PathBuf::from(home).join(".aws").join("credentials")
+            },
+        };
+        Ok(Self { path, profile: profile.to_string() })
+    }
+}
+
+impl CredentialsProvider for ProfileCredentials {
+    fn fetch(&self) -> anyhow::Result<FetchedCredentials> {
+        let content = std::fs::read_to_string(&self.path).with_context(|| format!("Reading {}", self.path.to_str().unwrap()))?;
+        parse_profile(&content, &self.profile).with_context(|| format!("Profile {} of {}", self.profile, self.path.to_str().unwrap()))
+    }
+}
+
+// values of [profile] in an ini file
+fn parse_profile(content: &str, profile: &str) -> anyhow::Result<FetchedCredentials> {
+    let mut in_profile = false;
+    let (mut key, mut secret, mut token) = (None, None, None);
+    for line in content.lines().map(str::trim) {
+        if let Some(section) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+            in_profile = section.trim() == profile;
+        } else if let (true, Some((name, value))) = (in_profile, line.split_once('=')) {
+            let value = Some(value.trim().to_string());
+            match name.trim() {
+                "aws_access_key_id" => key = value,
+                "aws_secret_access_key" => secret = value,
+                "aws_session_token" => token = value,
+                _ => (),
+            }
+        }
+    }
+    Ok(FetchedCredentials {
+        key: key.context("No aws_access_key_id")?,
+        secret: secret.context("No aws_secret_access_key")?,
+        token,
+        expiration: None,
+    })
+}
+
+// output of a credential_process, and of imds with Token instead of SessionToken
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ProcessOutput {
+    access_key_id: String,
+    secret_access_key: String,
+    #[serde(alias = "Token")]
+    session_token: Option<String>,
+    expiration: Option<String>,
+}
+
+impl ProcessOutput {
+    fn into_fetched(self) -> anyhow::Result<FetchedCredentials> {
+        let expiration = self.expiration.as_deref().map(parse_utc_timestamp).transpose()?;
+        Ok(FetchedCredentials { key: self.access_key_id, secret: self.secret_access_key, token: self.session_token, expiration })
+    }
+}
+
+pub struct ProcessCredentials {
+    command: String,
+}
+
+impl CredentialsProvider for ProcessCredentials {
+    fn fetch(&self) -> anyhow::Result<FetchedCredentials> {
+        let output = std::process::Command::new("sh").arg("-c").arg(&self.command).output()
+            .with_context(|| format!("Running credentials process {}", self.command))?;
+        if !output.status.success() {
+            anyhow::bail!("Credentials process {} failed: {}", self.command, String::from_utf8_lossy(&output.stderr).trim());
+        }
+        let parsed: ProcessOutput = serde_json::from_slice(&output.stdout).context("Parsing output of credentials process")?;
+        parsed.into_fetched()
+    }
+}
+
+// instance metadata service, v2 (session token)
+pub struct ImdsCredentials {
+    endpoint: String,
+}
+
+impl CredentialsProvider for ImdsCredentials {
+    fn fetch(&self) -> anyhow::Result<FetchedCredentials> {
+        let token = ureq::put(&format!("{}/latest/api/token", self.endpoint))
+            .set("X-aws-ec2-metadata-token-ttl-seconds", IMDS_TOKEN_TTL_SECONDS)
+            .call().context("Getting imds token")?
+            .into_string()?;
+        let get = |path: &str| -> anyhow::Result<String> {
+            Ok(ureq::get(&format!("{}/latest/meta-data/iam/security-credentials/{}", self.endpoint, path))
+                .set("X-aws-ec2-metadata-token", &token)
+                .call().with_context(|| format!("Getting imds security-credentials/{}", path))?
+                .into_string()?)
+        };
+        let role = get("")?;
+        let role = role.lines().next().context("Instance has no role")?;
+        let parsed: ProcessOutput = serde_json::from_str(&get(role)?).context("Parsing imds credentials")?;
+        parsed.into_fetched()
+    }
+}
+
+// the key and secret lines of an s3 spec, see the top of this file
+pub fn provider_from_spec(key: &str, secret: &str) -> anyhow::Result<Box<dyn CredentialsProvider>> {
+    let provider: Box<dyn CredentialsProvider> = if let Some(profile) = key.strip_prefix("profile:") {
+        Box::new(ProfileCredentials::new(profile)?)
+    } else if let Some(command) = key.strip_prefix("process:") {
+        Box::new(ProcessCredentials { command: command.to_string() })
+    } else if key == "imds" {
+        Box::new(ImdsCredentials { endpoint: IMDS_ENDPOINT.to_string() })
+    } else {
+        Box::new(StaticCredentials { key: key.to_string(), secret: secret.to_string() })
+    };
+    Ok(provider)
+}
+
+struct Current {
+    credentials: Credentials,
+    expiration: Option<SystemTime>,
+    generation: u64, // bumped on every fetch, so that concurrent refreshes of the same credentials fetch once
+}
+
+// credentials shared by the tasks of a storage
+pub struct SharedCredentials {
+    provider: Box<dyn CredentialsProvider>,
+    current: Mutex<Option<Current>>,
+}
+
+impl SharedCredentials {
+    pub fn new(provider: Box<dyn CredentialsProvider>) -> Self {
+        Self { provider, current: Mutex::new(None) }
+    }
+
+    // (credentials, generation), fetched if there are none or they are about to expire
+    pub fn get(&self) -> anyhow::Result<(Credentials, u64)> {
+        let mut current = self.current.lock().unwrap();
+        let expiring = |current: &Current| current.expiration.is_some_and(|expiration| expiration <= SystemTime::now() + EXPIRATION_MARGIN);
+        match current.as_ref() {
+            Some(fresh) if !expiring(fresh) => Ok((fresh.credentials.clone(), fresh.generation)),
+            _ => self.fetch(&mut current),
+        }
+    }
+
+    // after the storage refused credentials of generation, None if fetching them again won't help
+    pub fn refresh(&self, generation: u64) -> anyhow::Result<Option<(Credentials, u64)>> {
+        if !self.provider.can_refresh() {
+            return Ok(None);
+        }
+        let mut current = self.current.lock().unwrap();
+        match current.as_ref() {
+            // another task got new ones meanwhile
+            Some(newer) if newer.generation != generation => Ok(Some((newer.credentials.clone(), newer.generation))),
+            _ => self.fetch(&mut current).map(Some),
+        }
+    }
+
+    fn fetch(&self, current: &mut Option<Current>) -> anyhow::Result<(Credentials, u64)> {
+        let fetched = self.provider.fetch().context("Fetching s3 credentials")?;
+        let generation = current.as_ref().map_or(0, |current| current.generation + 1);
+        debug!("Fetched s3 credentials, generation {}, expiration {:?}", generation, fetched.expiration);
+        let credentials = fetched.to_credentials();
+        *current = Some(Current { credentials: credentials.clone(), expiration: fetched.expiration, generation });
+        Ok((credentials, generation))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn profiles() -> anyhow::Result<()> {
+        let content = "[default]\naws_access_key_id = AKDEFAULT\naws_secret_access_key = s\n\n[backup]\naws_access_key_id=AKBACKUP\naws_secret_access_key=secret\naws_session_token=tok\n";
+        let fetched = parse_profile(content, "backup")?;
+        assert_eq!((fetched.key.as_str(), fetched.secret.as_str(), fetched.token.as_deref()), ("AKBACKUP", "secret", Some("tok")));
+        assert!(parse_profile(content, "missing").is_err());
+        Ok(())
+    }
+
+    struct Counting(Arc<AtomicU64>, Option<SystemTime>);
+
+    impl CredentialsProvider for Counting {
+        fn fetch(&self) -> anyhow::Result<FetchedCredentials> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(FetchedCredentials { key: "k".to_string(), secret: "s".to_string(), token: Some("t".to_string()), expiration: self.1 })
+        }
+    }
+
+    #[test]
+    fn refresh_once_per_generation() -> anyhow::Result<()> {
+        let fetches = Arc::new(AtomicU64::new(0));
+        let shared = SharedCredentials::new(Box::new(Counting(fetches.clone(), Some(SystemTime::now() + Duration::from_secs(3600)))));
+        let (_, generation) = shared.get()?;
+        shared.get()?;
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+
+        // two tasks refused with the same credentials
+        assert_eq!(shared.refresh(generation)?.unwrap().1, generation + 1);
+        assert_eq!(shared.refresh(generation)?.unwrap().1, generation + 1);
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+
+        // about to expire
+        let expiring = SharedCredentials::new(Box::new(Counting(fetches.clone(), Some(SystemTime::now() + Duration::from_secs(60)))));
+        expiring.get()?;
+        expiring.get()?;
+        assert_eq!(fetches.load(Ordering::SeqCst), 4);
+
+        let fixed = SharedCredentials::new(provider_from_spec("AKID", "secret")?);
+        let (_, generation) = fixed.get()?;
+        assert!(fixed.refresh(generation)?.is_none());
+        Ok(())
+    }
+}
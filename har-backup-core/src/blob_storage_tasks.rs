@@ -2,7 +2,7 @@ use crate::blob_storage::BlobStorage;
 
 use super::thread_sync::Sender;
 use log::debug;
-use super::blob_storage::{Event, EventContent, TaskId, Error};
+use super::blob_storage::{Event, EventContent, TaskId, Error, ErrorKind};
 
 pub struct AsyncComm {
     pub senders: Vec<Sender<Event>>,
@@ -22,9 +22,9 @@ pub trait Comm {
         self.send_event(&event);
     }
 
-    fn send_error_event(&mut self, err_msg: String) {
+    fn send_error_event(&mut self, kind: ErrorKind, err_msg: String) {
         debug!("Error in task {}: {}", self.task_id().to_u64(), err_msg);
-        let event = Event { id: self.task_id(), content: EventContent::Error(Error { msg: err_msg })};
+        let event = Event { id: self.task_id(), content: EventContent::Error(Error::new(kind, err_msg))};
         self.send_event(&event);
     }
 }
@@ -108,6 +108,20 @@ impl TaskHelper {
     }
 }
 
+// runs task on this thread, the first event that is not progress is its result
+pub(crate) fn run_blocking<T: Task, R>(mut task: T, expected: &str, result_of: impl Fn(EventContent) -> Result<R, EventContent>) -> Result<R, Error> {
+    let mut events = Vec::new();
+    task.run(SyncComm { events: &mut events });
+    let content = events.into_iter()
+        .map(|event| event.content)
+        .find(|content| !matches!(content, EventContent::Progress(_)))
+        .ok_or_else(|| Error::new(ErrorKind::Other, format!("Expected {} from the blob storage, the task ended without one", expected)))?;
+    match content {
+        EventContent::Error(err) => Err(err),
+        content => result_of(content).map_err(|other| crate::blob_storage::unexpected_event(&other, expected)),
+    }
+}
+
 pub trait TaskProvider {
     type UploadTask: Task + 'static;
     type DownloadTask: Task + 'static;
@@ -115,6 +129,8 @@ pub trait TaskProvider {
     fn new_upload_task(&self, data: bytes::Bytes, key: Option<&str>) -> Self::UploadTask;
     fn new_download_task(&self, key: &str) -> Self::DownloadTask;
     fn new_exists_task(&self, key: &str) -> Self::ExistsTask;
+    fn list_objects(&self) -> crate::blob_storage::ListResult;
+    fn object_metadata(&self, key: &str) -> crate::blob_storage::MetadataResult;
     fn task_helper(&mut self) -> &mut TaskHelper;
 }
 
@@ -139,56 +155,72 @@ impl<T: TaskProvider> BlobStorage for T {
     }
 
     fn upload_blocking(&mut self, data: bytes::Bytes, key: Option<&str>) -> crate::blob_storage::UploadResult {
-
-        let mut task = self.new_upload_task(data, key);
-
-        let mut events = Vec::new();
-        task.run(SyncComm { events: &mut events });
-
-        for event in &events {
-            match &event.content {
-                EventContent::UploadSuccess(result) => return Ok(result.clone()),
-                EventContent::Error(err) => return Err(err.clone()),
-                _ => todo!()
-            };
-        }
-
-        panic!("Did not find event");
+        run_blocking(self.new_upload_task(data, key), "an upload result", |content| match content {
+            EventContent::UploadSuccess(result) => Ok(result),
+            other => Err(other),
+        })
     }
 
     fn download_blocking(&mut self, key: &str) -> crate::blob_storage::DownloadResult {
+        run_blocking(self.new_download_task(key), "a download result", |content| match content {
+            EventContent::DownloadSuccess(result) => Ok(result),
+            other => Err(other),
+        })
+    }
 
-        let mut task = self.new_download_task(key);
-
-        let mut events = Vec::new();
-        task.run(SyncComm { events: &mut events });
+    fn exists_blocking(&mut self, key: &str) -> crate::blob_storage::ExistsResult {
+        run_blocking(self.new_exists_task(key), "an exists result", |content| match content {
+            EventContent::ExistsSuccess(result) => Ok(result),
+            other => Err(other),
+        })
+    }
 
-        for event in &events {
-            match &event.content {
-                EventContent::DownloadSuccess(result) => return Ok(result.clone()),
-                EventContent::Error(err) => return Err(err.clone()),
-                _ => todo!()
-            };
-        }
+    fn list_blocking(&mut self) -> crate::blob_storage::ListResult {
+        self.list_objects()
+    }
 
-        panic!("Did not find event");
+    fn metadata_blocking(&mut self, key: &str) -> crate::blob_storage::MetadataResult {
+        self.object_metadata(key)
     }
+}
 
-    fn exists_blocking(&mut self, key: &str) -> crate::blob_storage::ExistsResult {
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        let mut task = self.new_exists_task(key);
+    struct SendsTask(Vec<EventContent>);
 
-        let mut events = Vec::new();
-        task.run(SyncComm { events: &mut events });
+    impl Task for SendsTask {
+        fn run<T: Comm>(&mut self, mut comm: T) {
+            for content in self.0.drain(..) {
+                comm.send_event_content(content);
+            }
+        }
+    }
 
-        for event in &events {
-            match &event.content {
-                EventContent::ExistsSuccess(result) => return Ok(*result),
-                EventContent::Error(err) => return Err(err.clone()),
-                _ => todo!()
-            };
+    fn exists_result(content: EventContent) -> Result<bool, EventContent> {
+        match content {
+            EventContent::ExistsSuccess(result) => Ok(result),
+            other => Err(other),
         }
+    }
 
-        panic!("Did not find event");
+    #[test]
+    fn odd_events_are_errors() {
+        let progress = || EventContent::Progress(crate::blob_storage::Progress {});
+        let task = SendsTask(vec![progress(), EventContent::ExistsSuccess(true)]);
+        assert!(run_blocking(task, "an exists result", exists_result).unwrap());
+        let task = SendsTask(vec![EventContent::Error(Error::new(ErrorKind::NotFound, "kek".to_string()))]);
+        assert_eq!(run_blocking(task, "an exists result", exists_result).unwrap_err().kind, ErrorKind::NotFound);
+
+        let task = SendsTask(vec![progress()]);
+        let err = run_blocking(task, "an exists result", exists_result).unwrap_err();
+        assert!(err.msg.contains("ended without one"), "{}", err);
+        let task = SendsTask(vec![EventContent::ExistsSuccess(true)]);
+        let err = run_blocking(task, "an upload result", |content| match content {
+            EventContent::UploadSuccess(info) => Ok(info),
+            other => Err(other),
+        }).unwrap_err();
+        assert!(err.msg.contains("got ExistsSuccess(true)"), "{}", err);
     }
 }
@@ -0,0 +1,247 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use anyhow::Context;
+
+// time and randomness of the transfer schedulers (retries, status lines), injectable so that tests are reproducible
+// nonces, keys and uuids are not concerned: they keep using the OS generator
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+    fn sleep(&self, duration: Duration);
+    // how long to wait for an event that may come before deadline, None to wait for the event whatever the time
+    fn wait_limit(&self, deadline: Instant) -> Option<Duration>;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+
+    fn wait_limit(&self, deadline: Instant) -> Option<Duration> {
+        Some(deadline.saturating_duration_since(Instant::now()))
+    }
+}
+
+// time only passes when slept, so schedules don't depend on how fast the machine is
+// clones share the same time, keep one to look at it
+#[derive(Debug, Clone)]
+pub struct VirtualClock {
+    start: Instant,
+    now: Arc<Mutex<Instant>>,
+}
+
+impl Default for VirtualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VirtualClock {
+    pub fn new() -> Self {
+        let start = Instant::now();
+        Self { start, now: Arc::new(Mutex::new(start)) }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap() += duration;
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.now() - self.start
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+
+    // events come from real threads, waiting for them takes no virtual time
+    fn wait_limit(&self, _deadline: Instant) -> Option<Duration> {
+        None
+    }
+}
+
+// spreads retries of items that failed together (splitmix64, no need for more)
+#[derive(Debug, Clone)]
+pub struct Jitter {
+    state: u64,
+}
+
+impl Jitter {
+    pub fn from_seed(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn from_os() -> Self {
+        use chacha20poly1305::aead::{OsRng, rand_core::RngCore};
+        Self::from_seed(OsRng.next_u64())
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // another generator, seeded from this one
+    pub fn fork(&mut self) -> Self {
+        Self::from_seed(self.next_u64())
+    }
+
+    // below bound (> 0), for sampling
+    pub fn below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound
+    }
+
+    // between 3/4 of duration and duration
+    pub fn apply(&mut self, duration: Duration) -> Duration {
+        let quarter = duration / 4;
+        let nanos = quarter.as_nanos() as u64;
+        let cut = if nanos == 0 { 0 } else { self.next_u64() % (nanos + 1) };
+        duration - Duration::from_nanos(cut)
+    }
+}
+
+// wall clock time, in seconds since the unix epoch, as recorded in manifests
+pub fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since_epoch| since_epoch.as_secs())
+}
+
+// 2024-05-01T12:00:00Z, with optional fractional seconds and +00:00 for utc, or a date alone (midnight utc)
+pub fn parse_utc_timestamp(timestamp: &str) -> anyhow::Result<SystemTime> {
+    let invalid = || format!("Invalid timestamp {}", timestamp);
+    let trimmed = timestamp.trim_end_matches('Z').trim_end_matches("+00:00");
+    let (date, time) = trimmed.split_once('T').unwrap_or((trimmed, "00:00:00"));
+    let time = time.split('.').next().unwrap();
+    let numbers = |text: &str, separator| text.split(separator).map(str::parse::<i64>).collect::<Result<Vec<_>, _>>();
+    let (date, time) = (numbers(date, '-').with_context(invalid)?, numbers(time, ':').with_context(invalid)?);
+    let (&[year, month, day], &[hours, minutes, seconds]) = (date.as_slice(), time.as_slice()) else {
+        anyhow::bail!(invalid());
+    };
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        anyhow::bail!(invalid());
+    }
+    // days from civil, see http://howardhinnant.github.io/date_algorithms.html
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+    let seconds = days * 86400 + hours * 3600 + minutes * 60 + seconds;
+    Ok(UNIX_EPOCH + Duration::from_secs(u64::try_from(seconds).with_context(invalid)?))
+}
+
+// 2024-05-01T14:00:00.5+02:00 as storages list times (rclone lsjson gives them in the local time of the remote)
+pub fn parse_timestamp_with_offset(timestamp: &str) -> anyhow::Result<SystemTime> {
+    let invalid = || format!("Invalid timestamp {}", timestamp);
+    let offset_at = timestamp.rfind(['+', '-']).filter(|&at| timestamp[..at].contains('T') && timestamp.len() - at == 6);
+    let Some(offset_at) = offset_at else {
+        return parse_utc_timestamp(timestamp);
+    };
+    let (hours, minutes) = timestamp[offset_at + 1..].split_once(':').with_context(invalid)?;
+    let offset = Duration::from_secs(hours.parse::<u64>().with_context(invalid)? * 3600 + minutes.parse::<u64>().with_context(invalid)? * 60);
+    let local = parse_utc_timestamp(&timestamp[..offset_at])?;
+    match &timestamp[offset_at..offset_at + 1] {
+        "+" => local.checked_sub(offset).with_context(invalid),
+        _ => Ok(local + offset),
+    }
+}
+
+// Wed, 01 May 2024 12:00:00 GMT, the date of http headers (Last-Modified...)
+pub fn parse_http_date(date: &str) -> anyhow::Result<SystemTime> {
+    let invalid = || format!("Invalid date {}", date);
+    let months = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+    let [_, day, month, year, time, "GMT"] = date.split_whitespace().collect::<Vec<_>>()[..] else {
+        anyhow::bail!(invalid());
+    };
+    let month = months.iter().position(|name| *name == month).with_context(invalid)? + 1;
+    parse_utc_timestamp(&format!("{}-{:02}-{}T{}Z", year, month, day, time))
+}
+
+// seconds since the unix epoch as 2024-05-01T12:00:00Z
+pub fn format_utc_timestamp(unix_seconds: u64) -> String {
+    let (days, seconds) = ((unix_seconds / 86400) as i64, unix_seconds % 86400);
+    // civil from days, the inverse of the above
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seeded_jitter_is_reproducible() {
+        let backoff = Duration::from_millis(1000);
+        let delays = |seed| {
+            let mut jitter = Jitter::from_seed(seed);
+            (0..16).map(|_| jitter.apply(backoff)).collect::<Vec<_>>()
+        };
+        assert_eq!(delays(7), delays(7));
+        assert_ne!(delays(7), delays(8));
+        assert!(delays(7).iter().all(|delay| *delay >= backoff * 3 / 4 && *delay <= backoff));
+        assert_eq!(Jitter::from_seed(7).apply(Duration::ZERO), Duration::ZERO);
+    }
+
+    #[test]
+    fn virtual_clock_passes_when_slept() {
+        let clock = VirtualClock::new();
+        let handle = clock.clone();
+        let start = clock.now();
+        clock.sleep(Duration::from_secs(30));
+        assert_eq!(handle.now() - start, Duration::from_secs(30));
+        assert_eq!(handle.elapsed(), Duration::from_secs(30));
+        assert_eq!(clock.wait_limit(start + Duration::from_secs(60)), None);
+    }
+
+    #[test]
+    fn utc_timestamps() -> anyhow::Result<()> {
+        assert_eq!(parse_utc_timestamp("1970-01-02T00:00:01Z")?, UNIX_EPOCH + Duration::from_secs(86401));
+        assert_eq!(parse_utc_timestamp("2024-03-01T00:00:00.000+00:00")?, UNIX_EPOCH + Duration::from_secs(1709251200));
+        assert_eq!(parse_utc_timestamp("2024-03-01")?, UNIX_EPOCH + Duration::from_secs(1709251200));
+        assert!(parse_utc_timestamp("yesterday").is_err());
+        assert!(parse_utc_timestamp("2024-13-01").is_err());
+
+        assert_eq!(format_utc_timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_utc_timestamp(1709251200 + 3723), "2024-03-01T01:02:03Z");
+        assert_eq!(format_utc_timestamp(951782400), "2000-02-29T00:00:00Z");
+        let unix_seconds = |timestamp| parse_utc_timestamp(timestamp).map(|time| time.duration_since(UNIX_EPOCH).unwrap().as_secs());
+        assert_eq!(format_utc_timestamp(unix_seconds("2099-12-31T23:59:59Z")?), "2099-12-31T23:59:59Z");
+        Ok(())
+    }
+
+    #[test]
+    fn timestamps_of_storages() -> anyhow::Result<()> {
+        let may_first = UNIX_EPOCH + Duration::from_secs(1714564800);
+        assert_eq!(parse_timestamp_with_offset("2024-05-01T14:00:00.034468261+02:00")?, may_first);
+        assert_eq!(parse_timestamp_with_offset("2024-05-01T07:30:00-04:30")?, may_first);
+        assert_eq!(parse_timestamp_with_offset("2024-05-01T12:00:00Z")?, may_first);
+        assert_eq!(parse_http_date("Wed, 01 May 2024 12:00:00 GMT")?, may_first);
+        assert!(parse_http_date("Wed, 01 Mai 2024 12:00:00 GMT").is_err());
+        assert!(parse_http_date("2024-05-01T12:00:00Z").is_err());
+        Ok(())
+    }
+}
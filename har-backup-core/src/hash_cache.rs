@@ -0,0 +1,118 @@
+use serde::{Deserialize, Serialize};
+use anyhow::Context;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use log::debug;
+use crate::blob_storage;
+
+// files modified this recently may change again within the resolution of their modification time, unseen
+const RACY_WINDOW_NS: u64 = 2_000_000_000;
+
+// blob keys of local files by path, valid while the size and modification time of the file are the same
+// so that hash checks (see manifest::DiffManifests::with_hash_cache) only read the files changed since the last one
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct HashCache {
+    bucket_name: String, // keys are derived from it
+    entries: HashMap<PathBuf, CachedHash>,
+    #[serde(skip)]
+    num_hashed: usize,
+    #[serde(skip)]
+    num_cached: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct CachedHash {
+    size: u64,
+    modified_ns: u64,
+    key: String,
+}
+
+impl HashCache {
+    pub fn new(bucket_name: &str) -> Self {
+        Self { bucket_name: bucket_name.to_string(), ..Default::default() }
+    }
+
+    // empty if bytes are not a cache for bucket_name, it is only a cache
+    pub fn from_bytes(bytes: &[u8], bucket_name: &str) -> Self {
+        match serde_json::from_slice::<Self>(bytes) {
+            Ok(cache) if cache.bucket_name == bucket_name => cache,
+            Ok(_) => Self::new(bucket_name),
+            Err(e) => {
+                debug!("Ignoring the hash cache: {}", e);
+                Self::new(bucket_name)
+            },
+        }
+    }
+
+    pub fn to_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        serde_json::to_vec(self).context("Serialize hash cache")
+    }
+
+    // blob key of the file at full_path, path in the archive is what it is cached by
+    pub fn key_of(&mut self, path: &Path, full_path: &Path) -> std::io::Result<String> {
+        let metadata = std::fs::metadata(full_path)?;
+        let modified_ns = metadata.modified()?.duration_since(std::time::UNIX_EPOCH).map_or(0, |modified| modified.as_nanos() as u64);
+        if let Some(cached) = self.entries.get(path) {
+            if cached.size == metadata.len() && cached.modified_ns == modified_ns {
+                self.num_cached += 1;
+                return Ok(cached.key.clone());
+            }
+        }
+        let key = blob_storage::get_hash_name_of_file(&self.bucket_name, full_path)?;
+        self.num_hashed += 1;
+        let now_ns = crate::clock::unix_now().saturating_mul(1_000_000_000);
+        if modified_ns + RACY_WINDOW_NS < now_ns {
+            self.entries.insert(path.to_path_buf(), CachedHash { size: metadata.len(), modified_ns, key: key.clone() });
+        } else {
+            self.entries.remove(path);
+        }
+        Ok(key)
+    }
+
+    // forgets the files that are not in the archive anymore
+    pub fn retain(&mut self, mut keep: impl FnMut(&Path) -> bool) {
+        self.entries.retain(|path, _| keep(path));
+    }
+
+    // since loaded: files read and hashed, files whose key was cached
+    pub fn counts(&self) -> (usize, usize) {
+        (self.num_hashed, self.num_cached)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_changed_files_only() -> anyhow::Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let full_path = dir.path().join("felt");
+        let an_hour_ago = std::time::SystemTime::now() - std::time::Duration::from_secs(3600);
+        let write = |content: &str, modified: std::time::SystemTime| -> std::io::Result<()> {
+            std::fs::write(&full_path, content)?;
+            std::fs::File::options().write(true).open(&full_path)?.set_modified(modified)
+        };
+        write("kek", an_hour_ago)?;
+
+        let mut cache = HashCache::new("bucket");
+        let key = cache.key_of(Path::new("felt"), &full_path)?;
+        assert_eq!(key, blob_storage::get_hash_name_of_file("bucket", &full_path)?);
+        let mut cache = HashCache::from_bytes(&cache.to_bytes()?, "bucket");
+        assert_eq!(cache.key_of(Path::new("felt"), &full_path)?, key);
+        assert_eq!(cache.counts(), (0, 1));
+
+        write("kok", an_hour_ago + std::time::Duration::from_secs(1))?;
+        assert_ne!(cache.key_of(Path::new("felt"), &full_path)?, key);
+        // just modified, hashed every time until it is old enough
+        write("kak", std::time::SystemTime::now())?;
+        cache.key_of(Path::new("felt"), &full_path)?;
+        cache.key_of(Path::new("felt"), &full_path)?;
+        assert_eq!(cache.counts(), (3, 1));
+
+        // keys of another bucket are other keys
+        let cache = HashCache::from_bytes(&cache.to_bytes()?, "other");
+        assert!(cache.entries.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,120 @@
+use std::time::{Duration, Instant};
+
+// reachability of the remote as seen by a probe (an exists on the manifest, a HEAD for s3)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RemoteHealth {
+    Reachable,
+    Unreachable(String),
+    AuthExpired(String), // credentials refused, retrying won't help until they are renewed
+}
+
+impl RemoteHealth {
+    // from the error of a probe, storages only give us a message
+    pub fn from_probe_error(msg: &str) -> Self {
+        if msg.contains("status code 401") || msg.contains("status code 403") {
+            RemoteHealth::AuthExpired(msg.to_string())
+        } else {
+            RemoteHealth::Unreachable(msg.to_string())
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HealthMetrics {
+    pub probes: u64,
+    pub failed_probes: u64,
+    pub transitions: u64,
+    pub unreachable_time: Duration, // total, counting the current outage up to the last probe
+}
+
+// periodic probes of the remote for long running modes (watch)
+// scheduled pushes are paused while the remote is not reachable and resume on the next good probe
+pub struct HealthMonitor {
+    interval: Duration,
+    state: Option<RemoteHealth>, // None before the first probe
+    last_probe: Option<Instant>,
+    metrics: HealthMetrics,
+}
+
+impl HealthMonitor {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            state: None,
+            last_probe: None,
+            metrics: HealthMetrics::default(),
+        }
+    }
+
+    pub fn is_probe_due(&self, now: Instant) -> bool {
+        match self.last_probe {
+            Some(last_probe) => now.duration_since(last_probe) >= self.interval,
+            None => true,
+        }
+    }
+
+    // returns the new state on a transition, for the caller to report
+    pub fn record(&mut self, health: RemoteHealth, now: Instant) -> Option<&RemoteHealth> {
+        self.metrics.probes += 1;
+        if health != RemoteHealth::Reachable {
+            self.metrics.failed_probes += 1;
+        }
+        if let (Some(last_probe), Some(state)) = (self.last_probe, &self.state) {
+            if *state != RemoteHealth::Reachable {
+                self.metrics.unreachable_time += now.duration_since(last_probe);
+            }
+        }
+        self.last_probe = Some(now);
+
+        let transition = match &self.state {
+            // the first probe only reports bad news
+            None => health != RemoteHealth::Reachable,
+            Some(state) => std::mem::discriminant(state) != std::mem::discriminant(&health),
+        };
+        if transition {
+            self.metrics.transitions += 1;
+        }
+        let state = self.state.insert(health);
+        transition.then_some(&*state)
+    }
+
+    pub fn pushes_paused(&self) -> bool {
+        matches!(self.state, Some(RemoteHealth::Unreachable(_)) | Some(RemoteHealth::AuthExpired(_)))
+    }
+
+    pub fn metrics(&self) -> &HealthMetrics {
+        &self.metrics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transitions_pause_and_resume() {
+        let mut monitor = HealthMonitor::new(Duration::from_secs(60));
+        let start = Instant::now();
+        assert!(monitor.is_probe_due(start));
+
+        assert_eq!(monitor.record(RemoteHealth::Reachable, start), None);
+        assert!(!monitor.is_probe_due(start + Duration::from_secs(30)));
+        assert_eq!(monitor.record(RemoteHealth::from_probe_error("connection refused"), start + Duration::from_secs(60)),
+            Some(&RemoteHealth::Unreachable("connection refused".to_string())));
+        assert!(monitor.pushes_paused());
+        // same state with another reason is not a transition
+        assert_eq!(monitor.record(RemoteHealth::Unreachable("timeout".to_string()), start + Duration::from_secs(120)), None);
+        let refused = "https://s3/manifest: status code 403";
+        assert_eq!(monitor.record(RemoteHealth::from_probe_error(refused), start + Duration::from_secs(180)),
+            Some(&RemoteHealth::AuthExpired(refused.to_string())));
+        assert_eq!(monitor.record(RemoteHealth::Reachable, start + Duration::from_secs(240)), Some(&RemoteHealth::Reachable));
+        assert!(!monitor.pushes_paused());
+
+        assert_eq!(monitor.metrics(), &HealthMetrics {
+            probes: 5,
+            failed_probes: 3,
+            transitions: 3,
+            unreachable_time: Duration::from_secs(180),
+        });
+    }
+}
@@ -0,0 +1,169 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use anyhow::Context;
+use log::debug;
+
+// failures of push/pull per archive path, kept across runs in .har
+// an item that failed max_attempts times is quarantined: it is not retried until the journal is cleared
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TransferJournal {
+    failures: BTreeMap<PathBuf, FailureRecord>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FailureRecord {
+    pub attempts: u32,
+    pub last_error: String,
+}
+
+impl TransferJournal {
+    // returns the number of failed attempts so far for that path
+    pub fn record_failure(&mut self, path: &Path, error: &str) -> u32 {
+        let record = self.failures.entry(path.to_path_buf()).or_insert(FailureRecord { attempts: 0, last_error: String::new() });
+        record.attempts += 1;
+        record.last_error = error.to_string();
+        record.attempts
+    }
+
+    pub fn record_success(&mut self, path: &Path) {
+        self.failures.remove(path);
+    }
+
+    pub fn attempts(&self, path: &Path) -> u32 {
+        self.failures.get(path).map(|record| record.attempts).unwrap_or(0)
+    }
+
+    pub fn is_quarantined(&self, path: &Path, max_attempts: u32) -> bool {
+        self.attempts(path) >= max_attempts
+    }
+
+    pub fn quarantined(&self, max_attempts: u32) -> Vec<(&Path, &FailureRecord)> {
+        self.failures.iter()
+            .filter(|(_, record)| record.attempts >= max_attempts)
+            .map(|(path, record)| (path.as_path(), record))
+            .collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.failures.clear();
+    }
+
+    pub fn to_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        serde_json::to_vec_pretty(self).context("Serialize transfer journal")
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        serde_json::from_slice(bytes).context("Deserialize transfer journal")
+    }
+}
+
+// blobs uploaded by a push that has not updated the remote manifest yet, one json line per upload
+// lines are appended as uploads complete so that they survive the process dying, the next push reuses them
+#[derive(Debug, Default)]
+pub struct UploadJournal {
+    records: HashMap<PathBuf, UploadRecord>,
+    file: Option<std::fs::File>, // None when not persisted
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UploadRecord {
+    pub path: PathBuf, // in the archive
+    pub size: u64,
+    pub modified_ns: u64, // of the file when it was read, to know if it changed since
+    pub blob_key: String,
+    pub ciphertext_size: u64,
+    pub ciphertext_checksum: String,
+}
+
+impl UploadJournal {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let mut records = HashMap::new();
+        let mut torn = false;
+        if path.exists() {
+            let content = std::fs::read_to_string(path).context("Read upload journal")?;
+            for line in content.lines() {
+                // the last line is torn if the process died while writing it
+                match serde_json::from_str::<UploadRecord>(line) {
+                    Ok(record) => { records.insert(record.path.clone(), record); },
+                    Err(e) => debug!("Skipping upload journal line: {}", e),
+                }
+            }
+            torn = !content.is_empty() && !content.ends_with('\n');
+        }
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(path).context("Open upload journal")?;
+        if torn {
+            file.write_all(b"\n").context("Append to upload journal")?;
+        }
+        Ok(Self { records, file: Some(file) })
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&UploadRecord> {
+        self.records.get(path)
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    pub fn record(&mut self, record: UploadRecord) -> anyhow::Result<()> {
+        if let Some(file) = self.file.as_mut() {
+            let mut line = serde_json::to_vec(&record).context("Serialize upload record")?;
+            line.push(b'\n');
+            file.write_all(&line).context("Append to upload journal")?;
+        }
+        self.records.insert(record.path.clone(), record);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quarantine_after_max_attempts() -> anyhow::Result<()> {
+        let mut journal = TransferJournal::default();
+        let path = Path::new("a/b");
+        assert_eq!(journal.record_failure(path, "permission denied"), 1);
+        assert!(!journal.is_quarantined(path, 2));
+        assert_eq!(journal.record_failure(path, "permission denied"), 2);
+        assert!(journal.is_quarantined(path, 2));
+        assert_eq!(journal.quarantined(2).len(), 1);
+
+        let journal_b = TransferJournal::from_bytes(&journal.to_bytes()?)?;
+        assert_eq!(journal, journal_b);
+
+        journal.record_success(path);
+        assert_eq!(journal.attempts(path), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn upload_journal_survives_reopen() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("upload_journal");
+        let record = UploadRecord {
+            path: PathBuf::from("a/b"),
+            size: 4,
+            modified_ns: 42,
+            blob_key: "kek".to_string(),
+            ciphertext_size: 40,
+            ciphertext_checksum: "00".to_string(),
+        };
+        UploadJournal::open(&path)?.record(record.clone())?;
+        std::fs::OpenOptions::new().append(true).open(&path)?.write_all(b"{\"path\":\"a/c\",\"si")?;
+
+        let mut journal = UploadJournal::open(&path)?;
+        assert_eq!(journal.len(), 1);
+        assert_eq!(journal.get(Path::new("a/b")), Some(&record));
+        journal.record(UploadRecord { path: PathBuf::from("a/c"), ..record })?;
+        assert_eq!(UploadJournal::open(&path)?.len(), 2);
+        Ok(())
+    }
+}
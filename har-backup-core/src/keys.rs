@@ -0,0 +1,282 @@
+use std::path::Path;
+use anyhow::Context;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+
+// a key file is either
+// - a read key: the 32 bytes chacha key, enough to decrypt/encrypt blobs and manifests
+// - a full key: the read key followed by a 32 bytes ed25519 seed used to sign manifests
+// the read key of a full key can be handed out for restores, it cannot produce a valid manifest signature
+pub const READ_KEY_SIZE: usize = 32;
+pub const FULL_KEY_SIZE: usize = READ_KEY_SIZE + ed25519_dalek::SECRET_KEY_LENGTH;
+
+// short identifier of a read key, stored in front of encrypted blobs
+pub const KEY_ID_SIZE: usize = 8;
+pub type KeyId = [u8; KEY_ID_SIZE];
+
+// a key file can also be wrapped with a passphrase:
+// PASSPHRASE_MAGIC, argon2id m_cost, t_cost, p_cost (u32 le), salt, nonce, ChaCha20-Poly1305 ciphertext of the key
+// everything before the nonce is authenticated with the ciphertext
+const PASSPHRASE_MAGIC: &[u8; 8] = b"HARKEYPW";
+const SALT_SIZE: usize = 16;
+const WRAP_HEADER_SIZE: usize = PASSPHRASE_MAGIC.len() + 3 * 4 + SALT_SIZE;
+const WRAP_NONCE_SIZE: usize = 12;
+// argon2id, 64 MiB, 3 passes: unlocking a key file happens once per command
+const ARGON2_M_COST: u32 = 64 * 1024;
+const ARGON2_T_COST: u32 = 3;
+const ARGON2_P_COST: u32 = 1;
+const ARGON2_MAX_M_COST: u32 = 4 * 1024 * 1024; // don't let a key file ask for more than 4 GiB
+const ARGON2_MAX_T_COST: u32 = 64; // or for passes that take hours
+const ARGON2_MAX_P_COST: u32 = 64;
+
+pub struct KeyFile {
+    read_key: [u8; READ_KEY_SIZE],
+    signing_key: Option<SigningKey>,
+}
+
+impl KeyFile {
+    // prompts for the passphrase if the key file is passphrase protected
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let file_content = std::fs::read(path).with_context(|| format!("Read key file {}", path.to_str().unwrap()))?;
+        Self::from_content(&file_content, path.to_str().unwrap())
+    }
+
+    // content of a key file, name is what the passphrase prompt refers to
+    pub fn from_content(content: &[u8], name: &str) -> anyhow::Result<Self> {
+        if !is_passphrase_protected(content) {
+            return Self::from_bytes(content);
+        }
+        let passphrase = prompt_passphrase(&crate::messages::render(
+            crate::messages::MessageKey::PassphrasePrompt, &[name.to_string()]))?;
+        Self::from_bytes(&unwrap_with_passphrase(content, &passphrase)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        let signing_key = match bytes.len() {
+            READ_KEY_SIZE => None,
+            FULL_KEY_SIZE => {
+                let seed: [u8; ed25519_dalek::SECRET_KEY_LENGTH] = bytes[READ_KEY_SIZE..].try_into().unwrap();
+                Some(SigningKey::from_bytes(&seed))
+            },
+            _ => anyhow::bail!("Key file content does not have the right length for a key"),
+        };
+        Ok(Self {
+            read_key: bytes[..READ_KEY_SIZE].try_into().unwrap(),
+            signing_key,
+        })
+    }
+
+    // content of a new full key file
+    pub fn create_full() -> Vec<u8> {
+        use chacha20poly1305::aead::{OsRng, rand_core::RngCore};
+        let mut seed = [0u8; ed25519_dalek::SECRET_KEY_LENGTH];
+        OsRng.fill_bytes(&mut seed);
+        [&crate::blob_encryption::create_key()[..], &seed[..]].concat()
+    }
+
+    pub fn read_key(&self) -> &[u8; READ_KEY_SIZE] {
+        &self.read_key
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.signing_key.is_none()
+    }
+
+    pub fn public_key_hex(&self) -> Option<String> {
+        self.signing_key.as_ref().map(|key| to_hex(key.verifying_key().as_bytes()))
+    }
+
+    // identifies the read key without revealing it, stored in the remote to detect a wrong key
+    pub fn fingerprint(&self) -> String {
+        let hash = blake3::derive_key("har_backup key fingerprint", &self.read_key);
+        to_hex(&hash)
+    }
+
+    pub fn key_id(&self) -> KeyId {
+        let hash = blake3::derive_key("har_backup key id", &self.read_key);
+        hash[..KEY_ID_SIZE].try_into().unwrap()
+    }
+
+    pub fn sign_manifest(&self, manifest_bytes: &[u8]) -> anyhow::Result<bytes::Bytes> {
+        let signing_key = self.signing_key.as_ref().context("Read only key cannot sign manifests")?;
+        let signature = signing_key.sign(manifest_bytes);
+        Ok(bytes::Bytes::copy_from_slice(&signature.to_bytes()))
+    }
+}
+
+// error of a manifest whose signature is not of the key of the archive (altered, or signed by another key)
+#[derive(Debug)]
+pub struct SignatureMismatch;
+
+impl std::fmt::Display for SignatureMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Manifest signature does not match archive public key")
+    }
+}
+
+impl std::error::Error for SignatureMismatch {}
+
+pub fn verify_manifest(public_key_hex: &str, manifest_bytes: &[u8], signature: &[u8]) -> anyhow::Result<()> {
+    let public_key: [u8; ed25519_dalek::PUBLIC_KEY_LENGTH] = from_hex(public_key_hex)?
+        .try_into().map_err(|_| anyhow::anyhow!("Public key has wrong length"))?;
+    let public_key = VerifyingKey::from_bytes(&public_key).context("Invalid public key")?;
+    let signature = Signature::from_slice(signature).context("Invalid manifest signature")?;
+    public_key.verify(manifest_bytes, &signature).context(SignatureMismatch)
+}
+
+pub fn is_passphrase_protected(content: &[u8]) -> bool {
+    content.starts_with(PASSPHRASE_MAGIC) && content.len() > WRAP_HEADER_SIZE + WRAP_NONCE_SIZE
+}
+
+// content of a passphrase protected key file
+pub fn wrap_with_passphrase(key_bytes: &[u8], passphrase: &str) -> anyhow::Result<Vec<u8>> {
+    wrap_with_params(key_bytes, passphrase, ARGON2_M_COST, ARGON2_T_COST, ARGON2_P_COST)
+}
+
+fn wrap_with_params(key_bytes: &[u8], passphrase: &str, m_cost: u32, t_cost: u32, p_cost: u32) -> anyhow::Result<Vec<u8>> {
+    use chacha20poly1305::aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng, Payload};
+    use chacha20poly1305::ChaCha20Poly1305;
+
+    let mut salt = [0u8; SALT_SIZE];
+    OsRng.fill_bytes(&mut salt);
+    let mut content = Vec::with_capacity(WRAP_HEADER_SIZE + WRAP_NONCE_SIZE + key_bytes.len() + 16);
+    content.extend_from_slice(PASSPHRASE_MAGIC);
+    for cost in [m_cost, t_cost, p_cost] {
+        content.extend_from_slice(&cost.to_le_bytes());
+    }
+    content.extend_from_slice(&salt);
+
+    let wrapping_key = derive_wrapping_key(passphrase, &salt, m_cost, t_cost, p_cost)?;
+    let cipher = ChaCha20Poly1305::new(&wrapping_key.into());
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let cipher_text = cipher.encrypt(&nonce, Payload { msg: key_bytes, aad: &content })
+        .map_err(|err| anyhow::anyhow!("Wrapping key: {}", err))?;
+    content.extend_from_slice(&nonce);
+    content.extend_from_slice(&cipher_text);
+    Ok(content)
+}
+
+// key bytes of a passphrase protected key file
+pub fn unwrap_with_passphrase(content: &[u8], passphrase: &str) -> anyhow::Result<Vec<u8>> {
+    use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+    use chacha20poly1305::{ChaCha20Poly1305, Nonce};
+
+    if !is_passphrase_protected(content) {
+        anyhow::bail!("Key file is not passphrase protected");
+    }
+    let (header, wrapped) = content.split_at(WRAP_HEADER_SIZE);
+    let cost = |index: usize| {
+        let start = PASSPHRASE_MAGIC.len() + 4 * index;
+        u32::from_le_bytes(header[start..start + 4].try_into().unwrap())
+    };
+    let (m_cost, t_cost, p_cost) = (cost(0), cost(1), cost(2));
+    if m_cost > ARGON2_MAX_M_COST {
+        anyhow::bail!("Key file asks for {} KiB of memory to unlock, refusing", m_cost);
+    }
+    if t_cost > ARGON2_MAX_T_COST {
+        anyhow::bail!("Key file asks for {} argon2 passes to unlock, refusing", t_cost);
+    }
+    if p_cost > ARGON2_MAX_P_COST {
+        anyhow::bail!("Key file asks for {} argon2 lanes to unlock, refusing", p_cost);
+    }
+    let salt = &header[WRAP_HEADER_SIZE - SALT_SIZE..];
+
+    let wrapping_key = derive_wrapping_key(passphrase, salt, m_cost, t_cost, p_cost)?;
+    let cipher = ChaCha20Poly1305::new(&wrapping_key.into());
+    let (nonce, cipher_text) = wrapped.split_at(WRAP_NONCE_SIZE);
+    cipher.decrypt(Nonce::from_slice(nonce), Payload { msg: cipher_text, aad: header })
+        .map_err(|_| anyhow::anyhow!("Wrong passphrase for key file (or key file is damaged)"))
+}
+
+fn derive_wrapping_key(passphrase: &str, salt: &[u8], m_cost: u32, t_cost: u32, p_cost: u32) -> anyhow::Result<[u8; 32]> {
+    use argon2::{Algorithm, Argon2, Params, Version};
+    let params = Params::new(m_cost, t_cost, p_cost, Some(32))
+        .map_err(|err| anyhow::anyhow!("Invalid key file argon2 parameters: {}", err))?;
+    let mut key = [0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|err| anyhow::anyhow!("Deriving key from passphrase: {}", err))?;
+    Ok(key)
+}
+
+pub fn prompt_passphrase(prompt: &str) -> anyhow::Result<String> {
+    rpassword::prompt_password(prompt).context("Reading passphrase")
+}
+
+// asks twice, for a passphrase to protect a new key file with
+pub fn prompt_new_passphrase() -> anyhow::Result<String> {
+    use crate::messages::{render, MessageKey};
+    let passphrase = prompt_passphrase(&render(MessageKey::NewPassphrasePrompt, &[]))?;
+    if passphrase.is_empty() {
+        anyhow::bail!("Passphrase is empty");
+    }
+    if prompt_passphrase(&render(MessageKey::ConfirmPassphrasePrompt, &[]))? != passphrase {
+        anyhow::bail!("Passphrases do not match");
+    }
+    Ok(passphrase)
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> anyhow::Result<Vec<u8>> {
+    hex.as_bytes().chunks(2)
+        .map(|pair| {
+            let pair = std::str::from_utf8(pair).ok().filter(|pair| pair.len() == 2).context("Invalid hex string")?;
+            u8::from_str_radix(pair, 16).context("Invalid hex string")
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_key_cannot_sign() -> anyhow::Result<()> {
+        let full_bytes = KeyFile::create_full();
+        let full = KeyFile::from_bytes(&full_bytes)?;
+        let read_only = KeyFile::from_bytes(full.read_key())?;
+        assert!(!full.is_read_only());
+        assert!(read_only.is_read_only());
+        assert_eq!(full.read_key(), read_only.read_key());
+
+        let manifest_bytes = b"kek";
+        let signature = full.sign_manifest(manifest_bytes)?;
+        let public_key = full.public_key_hex().unwrap();
+        verify_manifest(&public_key, manifest_bytes, &signature)?;
+        assert!(verify_manifest(&public_key, b"forged", &signature).is_err());
+        assert!(read_only.sign_manifest(manifest_bytes).is_err());
+        assert_eq!(full.fingerprint(), read_only.fingerprint());
+        assert_ne!(full.fingerprint(), KeyFile::from_bytes(&KeyFile::create_full())?.fingerprint());
+        Ok(())
+    }
+
+    #[test]
+    fn passphrase_protected() -> anyhow::Result<()> {
+        let key_bytes = KeyFile::create_full();
+        // cheap argon2 parameters, the defaults are slow in debug builds
+        let content = wrap_with_params(&key_bytes, "correct horse", 64, 1, 1)?;
+        assert!(is_passphrase_protected(&content));
+        assert!(!is_passphrase_protected(&key_bytes));
+        assert!(KeyFile::from_bytes(&content).is_err());
+
+        assert_eq!(unwrap_with_passphrase(&content, "correct horse")?, key_bytes);
+        let err = unwrap_with_passphrase(&content, "battery staple").unwrap_err();
+        assert!(err.to_string().contains("Wrong passphrase"));
+
+        let mut tampered = content.clone();
+        tampered[PASSPHRASE_MAGIC.len()] ^= 1; // m_cost is authenticated
+        assert!(unwrap_with_passphrase(&tampered, "correct horse").is_err());
+
+        // refused before deriving anything, a damaged file would hang the prompt
+        for (index, refused) in [(1, "passes"), (2, "lanes")] {
+            let mut costly = content.clone();
+            let start = PASSPHRASE_MAGIC.len() + 4 * index;
+            costly[start..start + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+            let err = unwrap_with_passphrase(&costly, "correct horse").unwrap_err();
+            assert!(err.to_string().contains(refused));
+        }
+        Ok(())
+    }
+}
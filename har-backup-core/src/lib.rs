@@ -0,0 +1,25 @@
+pub mod blob_storage;
+pub mod blob_storage_local_directory;
+pub mod blob_encryption;
+pub mod blob_compression;
+pub mod manifest;
+pub mod thread_sync;
+pub mod mirror;
+pub mod blob_storage_tasks;
+#[cfg(feature = "s3")]
+pub mod blob_storage_s3;
+#[cfg(feature = "rclone")]
+pub mod blob_storage_rclone;
+pub mod blob_storage_multi;
+pub mod archive_metadata;
+pub mod messages;
+pub mod scan;
+pub mod timings;
+pub mod journal;
+pub mod keys;
+pub mod health;
+pub mod clock;
+pub mod rate_limit;
+pub mod read_ahead;
+pub mod remote_lock;
+pub mod hash_cache;
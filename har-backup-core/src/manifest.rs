@@ -0,0 +1,2374 @@
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use std::path::Component;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use anyhow::Context;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::blob_storage;
+use crate::hash_cache::HashCache;
+use crate::scan::{self, PlaceholderPolicy, ScanOptions, ScanReport};
+
+mod dir_entries;
+mod fsck;
+mod names;
+mod recovery;
+mod repr;
+use dir_entries::DirEntries;
+use names::{NameId, Names};
+pub use fsck::{FsckIssue, FsckReport};
+pub use recovery::{RecoveryReport, LOST_AND_FOUND};
+
+// error of manifest bytes that are not a manifest (damaged, or not decrypted with the right key)
+#[derive(Debug)]
+pub struct UndecodableManifest;
+
+impl fmt::Display for UndecodableManifest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Manifest could not be decoded")
+    }
+}
+
+impl std::error::Error for UndecodableManifest {}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize, Hash)]
+pub struct EntryId {
+    id: usize
+}
+
+impl EntryId {
+    pub fn to_usize(&self) -> usize {
+        self.id
+    }
+
+    pub fn from_usize(val: usize) -> Self {
+        Self { id: val }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Directory {
+    name: NameId,
+    entries: DirEntries,
+    times: Option<EntryTimes>,
+}
+
+// when an entry of a remote manifest first appeared in a snapshot, and when its blob key last changed (the same for
+// directories), in seconds since the unix epoch. None in manifests made from the fs and in those written before it was recorded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EntryTimes {
+    pub created: u64,
+    pub modified: u64,
+}
+
+impl EntryTimes {
+    pub fn new(now: u64) -> Self {
+        Self { created: now, modified: now }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+struct BlobKey {
+    key: blake3::Hash
+}
+
+// what BlobKey used to derive, kept as is for the binary (msgpack) format
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "BlobKey")]
+struct BlobKeyRepr {
+    key: blake3::Hash
+}
+
+// human readable formats (json) get the hex string instead of an array of bytes
+impl Serialize for BlobKey {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(self.key.to_hex().as_str())
+        }
+        else {
+            BlobKeyRepr { key: self.key }.serialize(serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for BlobKey {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            let hex = String::deserialize(deserializer)?;
+            BlobKey::try_from(hex.as_str()).map_err(serde::de::Error::custom)
+        }
+        else {
+            let repr = BlobKeyRepr::deserialize(deserializer)?;
+            Ok(Self { key: repr.key })
+        }
+    }
+}
+
+impl TryFrom<&str> for BlobKey {
+    type Error = anyhow::Error;
+
+    fn try_from(hex: &str) -> Result<Self, Self::Error> {
+        let key = blake3::Hash::from_hex(hex).context("Could not convert hex str to hash/blobkey")?;
+        Ok(Self {key})
+    }
+}
+
+impl BlobKey {
+    fn to_string(&self) -> String {
+        self.key.to_hex().to_string()
+    }
+}
+
+impl fmt::Debug for BlobKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hash_hex = self.key.to_hex();
+        write!(f, "{}", hash_hex.as_str())
+    }
+}
+
+impl Default for BlobKey {
+    fn default() -> Self {
+        let all_zero = [0; blake3::OUT_LEN];
+        Self {
+            key: blake3::Hash::from_bytes(all_zero)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct File {
+    name: NameId,
+    blob_key: BlobKey,
+    size: u64,
+    ciphertext_size: Option<u64>, // as stored, None for manifests written before it was recorded
+    chunks: Vec<Chunk>, // when stored in chunks, in order; blob_key is then the key the content would have as one blob
+    times: Option<EntryTimes>,
+}
+
+// part of a file stored in its own blob, see mirror::TransferConfig::chunk_threshold
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Chunk {
+    blob_key: BlobKey,
+    size: u64,
+    ciphertext_size: u64,
+}
+
+#[derive(Debug, Clone)]
+enum Entry {
+    Directory(Directory),
+    File(File)
+}
+
+impl Entry {
+
+    fn try_file_ref(&self) -> anyhow::Result<&File> {
+        if let Entry::File(x) = self { Ok(x) } else { anyhow::bail!("Tried to force enum type but it's the wrong one") }
+    }
+
+    fn try_file_ref_mut(&mut self) -> anyhow::Result<&mut File> {
+        if let Entry::File(x) = self { Ok(x) } else { anyhow::bail!("Tried to force enum type but it's the wrong one") }
+    }
+
+    fn try_directory_ref(&self) -> anyhow::Result<&Directory> {
+        if let Entry::Directory(x) = self { Ok(x) } else { anyhow::bail!("Tried to force enum type but it's the wrong one") }
+    }
+
+    fn try_directory_ref_mut(&mut self) -> anyhow::Result<&mut Directory> {
+        if let Entry::Directory(x) = self { Ok(x) } else { anyhow::bail!("Tried to force enum type but it's the wrong one") }
+    }
+
+    fn name(&self) -> NameId {
+        match self {
+            Entry::Directory(dir) => dir.name,
+            Entry::File(file) => file.name,
+        }
+    }
+
+    fn set_name(&mut self, name: NameId) {
+        match self {
+            Entry::Directory(dir) => dir.name = name,
+            Entry::File(file) => file.name = name,
+        }
+    }
+
+    fn times(&self) -> Option<EntryTimes> {
+        match self {
+            Entry::Directory(dir) => dir.times,
+            Entry::File(file) => file.times,
+        }
+    }
+
+    fn set_times(&mut self, times: Option<EntryTimes>) {
+        match self {
+            Entry::Directory(dir) => dir.times = times,
+            Entry::File(file) => file.times = times,
+        }
+    }
+}
+
+// how the names of a manifest are compared with the names looked up in it (diff, merge, paths given by the user)
+// names are stored with the bytes they had on the fs they were pushed from, pull writes them back as such where
+// the fs keeps them (macOS makes them NFD, Linux keeps what it is given)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NameNormalization {
+    #[default]
+    Exact, // manifests written before the policy was recorded
+    Nfc, // names that are the same once composed (é as one char or as e and a combining accent) are the same
+}
+
+impl NameNormalization {
+    pub fn is_exact(&self) -> bool {
+        *self == NameNormalization::Exact
+    }
+
+    pub fn compared_form<'a>(self, name: &'a str) -> Cow<'a, str> {
+        match self {
+            NameNormalization::Nfc if !name.is_ascii() => Cow::Owned(name.nfc().collect()),
+            _ => Cow::Borrowed(name),
+        }
+    }
+}
+
+// who wrote a version of the manifest, for har log
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestOrigin {
+    pub host: String,
+    pub written: u64, // unix seconds
+}
+
+// serialized as if entries owned their names, see repr.rs
+#[derive(Clone)]
+pub struct Manifest {
+    root: EntryId,
+    entries: Vec<Entry>,
+    names: Names,
+    name_normalization: NameNormalization,
+    origin: Option<ManifestOrigin>, // None for manifests written before it was recorded
+}
+
+const NUM_LARGEST_FILES_IN_STATS: usize = 10;
+const MAX_SUGGESTIONS: usize = 3;
+
+// user input to a path relative to the archive root: ./docs/ is docs, docs//file is docs/file
+pub fn normalize_path(path: &Path) -> anyhow::Result<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(component) => normalized.push(component),
+            Component::CurDir => (),
+            Component::ParentDir => anyhow::bail!("{} goes up with .., paths are relative to the archive root", path.to_str().unwrap()),
+            Component::RootDir | Component::Prefix(_) => anyhow::bail!("{} is absolute, paths are relative to the archive root", path.to_str().unwrap()),
+        }
+    }
+    Ok(normalized)
+}
+
+// levenshtein, on chars
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, char_a) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, &char_b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(char_a != char_b);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DirTotals {
+    pub num_dirs: usize,
+    pub num_files: usize,
+    pub size: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct Stats {
+    pub num_dirs: usize,
+    pub num_files: usize,
+    pub total_size: u64, // sum of file sizes, before encryption
+    pub largest_files: Vec<(PathBuf, u64)>, // biggest first
+    pub deepest_path: PathBuf,
+    pub max_depth: usize,
+    pub top_level_dirs: BTreeMap<String, DirTotals>, // recursive totals of each dir in root
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Files: {}, dirs: {}, total size: {} bytes", self.num_files, self.num_dirs, self.total_size)?;
+        writeln!(f, "Deepest path (depth {}): {}", self.max_depth, self.deepest_path.to_str().unwrap())?;
+        writeln!(f, "Largest files:")?;
+        for (path, size) in &self.largest_files {
+            writeln!(f, "  {} {}", size, path.to_str().unwrap())?;
+        }
+        writeln!(f, "Top level directories:")?;
+        for (name, totals) in &self.top_level_dirs {
+            writeln!(f, "  {} files: {} dirs: {} size: {}", name, totals.num_files, totals.num_dirs, totals.size)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeConflictKind {
+    DifferentBlobKey,
+    DifferentEntryType, // file in one manifest, directory in the other
+}
+
+#[derive(Debug, Clone)]
+pub struct MergeConflict {
+    pub path: PathBuf,
+    pub kind: MergeConflictKind,
+}
+
+#[derive(Debug, Default)]
+pub struct MergeReport {
+    pub added_files: usize,
+    pub added_dirs: usize,
+    pub conflicts: Vec<MergeConflict>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DuplicateGroup {
+    pub blob_key: String,
+    pub size: u64, // size of one of the files
+    pub paths: Vec<PathBuf>, // sorted
+}
+
+// recursive totals of a directory, see Manifest::disk_usage
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DirUsage {
+    pub path: PathBuf,
+    pub num_files: usize,
+    pub size: u64, // sum of file sizes
+    pub unique_size: u64, // files with the same content counted once, as the remote stores them
+}
+
+// what the upload of a file left in the storage
+#[derive(Debug, Clone)]
+pub struct StoredBlob {
+    pub key: String,
+    pub ciphertext_size: Option<u64>,
+    pub chunks: Vec<StoredChunk>, // empty unless the file was stored in chunks
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredChunk {
+    pub key: String,
+    pub size: u64,
+    pub ciphertext_size: u64,
+}
+
+impl From<&Chunk> for StoredChunk {
+    fn from(chunk: &Chunk) -> Self {
+        Self { key: chunk.blob_key.to_string(), size: chunk.size, ciphertext_size: chunk.ciphertext_size }
+    }
+}
+
+impl TryFrom<&StoredChunk> for Chunk {
+    type Error = anyhow::Error;
+
+    fn try_from(chunk: &StoredChunk) -> anyhow::Result<Self> {
+        Ok(Self { blob_key: BlobKey::try_from(chunk.key.as_str())?, size: chunk.size, ciphertext_size: chunk.ciphertext_size })
+    }
+}
+
+// an entry met by Manifest::walk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WalkEntry {
+    pub path: PathBuf,
+    pub kind: WalkKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WalkKind {
+    Directory,
+    File { size: u64, blob_key: String },
+}
+
+// depth first, the children of a directory by name right after it
+pub struct Walk<'a> {
+    manifest: &'a Manifest,
+    to_visit: Vec<(EntryId, PathBuf)>,
+}
+
+impl<'a> Walk<'a> {
+    fn push_children(&mut self, dir: &'a Directory, dir_path: &Path) {
+        let mut children: Vec<(&str, EntryId)> = dir.entries.iter().map(|(name, entry_id)| (self.manifest.names.get(name), entry_id)).collect();
+        children.sort_by_key(|&(name, _)| std::cmp::Reverse(name));
+        self.to_visit.extend(children.into_iter().map(|(name, entry_id)| (entry_id, dir_path.join(name))));
+    }
+}
+
+impl Iterator for Walk<'_> {
+    type Item = WalkEntry;
+
+    fn next(&mut self) -> Option<WalkEntry> {
+        let (entry_id, path) = self.to_visit.pop()?;
+        let kind = match self.manifest.get_entry(entry_id) {
+            Entry::File(file) => WalkKind::File { size: file.size, blob_key: file.blob_key.to_string() },
+            Entry::Directory(dir) => {
+                self.push_children(dir, &path);
+                WalkKind::Directory
+            },
+        };
+        Some(WalkEntry { path, kind })
+    }
+}
+
+impl DuplicateGroup {
+    // what would be saved locally if there was only one copy
+    pub fn wasted_size(&self) -> u64 {
+        self.size * (self.paths.len() as u64 - 1)
+    }
+}
+
+impl Default for Manifest {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Manifest {
+    pub fn new() -> Self {
+        let mut names = Names::default();
+        let root_entry = Entry::Directory(Directory { name: names.intern("ROOT"), entries: DirEntries::new(), times: None });
+        Self {
+            root: EntryId::from_usize(0),
+            entries: vec![root_entry],
+            names,
+            name_normalization: NameNormalization::Nfc,
+            origin: None,
+        }
+    }
+
+    pub fn root(&self) -> EntryId {
+        self.root
+    }
+
+    pub fn name_normalization(&self) -> NameNormalization {
+        self.name_normalization
+    }
+
+    pub fn origin(&self) -> Option<&ManifestOrigin> {
+        self.origin.as_ref()
+    }
+
+    // by whatever pushes the manifest
+    pub fn set_origin(&mut self, origin: ManifestOrigin) {
+        self.origin = Some(origin);
+    }
+
+    // for manifests written before the policy was recorded, see push
+    pub fn set_name_normalization(&mut self, name_normalization: NameNormalization) {
+        self.name_normalization = name_normalization;
+    }
+
+    fn get_entry(&self, id: EntryId) -> &Entry {
+        &self.entries[id.to_usize()]
+    }
+
+    fn name(&self, entry: &Entry) -> &str {
+        self.names.get(entry.name())
+    }
+
+    // the entry named name exactly if there is one, else one with the same name once normalized
+    fn get_child(&self, dir: &Directory, name: &str) -> Option<EntryId> {
+        let exact = self.names.find(name).and_then(|name| dir.entries.get(name));
+        if exact.is_some() || self.name_normalization == NameNormalization::Exact || name.is_ascii() {
+            return exact;
+        }
+        let name = self.name_normalization.compared_form(name);
+        dir.entries.iter()
+            .find(|&(child_name, _)| self.name_normalization.compared_form(self.names.get(child_name)) == name)
+            .map(|(_, entry_id)| entry_id)
+    }
+
+    fn join_and_get_entry_id(&self, base: EntryId, path_add: &Path) -> anyhow::Result<EntryId> {
+        let path_add = normalize_path(path_add)?;
+        let mut cd = self.entries[base.to_usize()].try_directory_ref()?;
+        let mut last_entry_id = None;
+        for component in path_add.components() {
+            let component_str = component.as_os_str().to_str().expect("Why would component be None here");
+            let entry_id = match self.get_child(cd, component_str) {
+                Some(entry_id) => entry_id,
+                None => {
+                    let suggestions = self.closest_names(cd, component_str);
+                    if suggestions.is_empty() {
+                        anyhow::bail!("Entry {} not found in cd {}", component_str, self.names.get(cd.name));
+                    }
+                    anyhow::bail!("Entry {} not found in cd {}, did you mean {}?", component_str, self.names.get(cd.name), suggestions.join(", "));
+                },
+            };
+            let entry = &self.entries[entry_id.to_usize()];
+            last_entry_id = Some(entry_id);
+            if let Entry::Directory(directory) = entry {
+                cd = &directory;
+            }
+        }
+        if path_add.components().count() == 0 {
+            return Ok(base);
+        }
+        last_entry_id.context("last_entry is none?")
+    }
+
+    // names in dir close to name (typos, case), closest first
+    fn closest_names(&self, dir: &Directory, name: &str) -> Vec<&str> {
+        let max_distance = (name.chars().count() / 3).max(2);
+        let mut close: Vec<(usize, &str)> = dir.entries.iter()
+            .map(|(child_name, _)| self.names.get(child_name))
+            .map(|child_name| (edit_distance(&name.to_lowercase(), &child_name.to_lowercase()), child_name))
+            .filter(|&(distance, _)| distance <= max_distance)
+            .collect();
+        close.sort();
+        close.into_iter().take(MAX_SUGGESTIONS).map(|(_, child_name)| child_name).collect()
+    }
+
+    fn add(&mut self, entry: Entry, parent_dir: EntryId) -> anyhow::Result<EntryId> {
+        {
+            let parent_dir = self.entries[parent_dir.to_usize()].try_directory_ref()?;
+            let maybe_exists = parent_dir.entries.get(entry.name());
+            if maybe_exists.is_some() {
+                anyhow::bail!("Entry with same name exists")
+            }
+        }
+        let entry_name = entry.name();
+        let entry_id = EntryId::from_usize(self.entries.len());
+        self.entries.push(entry);
+        let parent_dir = self.entries[parent_dir.to_usize()].try_directory_ref_mut()?;
+        parent_dir.entries.insert(entry_name, entry_id);
+        Ok(entry_id)
+    }
+
+    fn add_file(&mut self, name: &str, blob_key: BlobKey, size: u64, parent_dir: EntryId) -> anyhow::Result<EntryId> {
+        self.add_stored_file(name, blob_key, size, None, Vec::new(), parent_dir)
+    }
+
+    fn add_stored_file(&mut self, name: &str, blob_key: BlobKey, size: u64, ciphertext_size: Option<u64>, chunks: Vec<Chunk>, parent_dir: EntryId) -> anyhow::Result<EntryId> {
+        let name = self.names.intern(name);
+        self.add(Entry::File(File { name, blob_key, size, ciphertext_size, chunks, times: None }), parent_dir)
+    }
+
+    fn add_dir(&mut self, name: &str, parent_dir: EntryId) -> anyhow::Result<EntryId> {
+        let name = self.names.intern(name);
+        self.add(Entry::Directory(Directory { name, entries: DirEntries::new(), times: None }), parent_dir)
+    }
+
+    fn set_times(&mut self, entry_id: EntryId, times: Option<EntryTimes>) {
+        self.entries[entry_id.to_usize()].set_times(times);
+    }
+
+    // cloud placeholders are taken as regular files
+    pub fn from_fs(fs_dir: &Path) -> anyhow::Result<Self> {
+        let options = ScanOptions { placeholder_policy: PlaceholderPolicy::Hydrate, ..Default::default() };
+        let (me, _) = Self::from_fs_with_options(fs_dir, &options)?;
+        Ok(me)
+    }
+
+    pub fn from_fs_with_options(fs_dir: &Path, options: &ScanOptions) -> anyhow::Result<(Self, ScanReport)> {
+        let mut me = Self::new();
+        let mut report = ScanReport::default();
+        me.add_dir_from_fs(me.root, fs_dir, options, &mut report)?;
+        Ok((me, report))
+    }
+
+    // from (path relative to the archive root, size) of files, as listed by an index (see scan::ScanSource)
+    // directories are made from the paths, so empty directories are not in it
+    pub fn from_listing(files: impl IntoIterator<Item = (PathBuf, u64)>) -> anyhow::Result<Self> {
+        let mut files = files.into_iter()
+            .map(|(path, size)| Ok((normalize_path(&path)?, size)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        // children are kept sorted, adding them in order is cheaper
+        files.sort();
+
+        let mut me = Self::new();
+        let mut dirs: HashMap<PathBuf, EntryId> = HashMap::from([(PathBuf::new(), me.root)]);
+        for (path, size) in files {
+            let file_name = path.file_name().with_context(|| format!("Listed path {:?} has no file name", path))?;
+            let file_name = file_name.to_str().context("Convert osstr to string")?;
+            let parent_path = path.parent().unwrap_or(Path::new(""));
+            let mut dir = me.root;
+            let mut dir_path = PathBuf::new();
+            for component in parent_path.components() {
+                dir_path.push(component);
+                dir = match dirs.get(&dir_path) {
+                    Some(&dir) => dir,
+                    None => {
+                        let component_str = component.as_os_str().to_str().context("Convert osstr to string")?;
+                        let new_dir = me.add_dir(component_str, dir)
+                            .with_context(|| format!("Listed path {:?} is both a file and a directory", dir_path))?;
+                        dirs.insert(dir_path.clone(), new_dir);
+                        new_dir
+                    },
+                };
+            }
+            me.add_file(file_name, BlobKey::default(), size, dir)
+                .with_context(|| format!("Listed path {:?} is listed twice or is also a directory", path))?;
+        }
+        Ok(me)
+    }
+
+    fn add_dir_from_fs(&mut self, dir: EntryId, fs_dir: &Path, options: &ScanOptions, report: &mut ScanReport) -> anyhow::Result<()>  {
+        let fs_dir_content = std::fs::read_dir(fs_dir).context("Reading fs_dir")
+            .and_then(|read_dir| read_dir.collect::<std::io::Result<Vec<_>>>().context("Reading fs_dir entry"));
+        let mut fs_dir_content = match fs_dir_content {
+            Ok(fs_dir_content) => fs_dir_content,
+            Err(e) => return report.keep_going(options, fs_dir, e), // left as an empty directory
+        };
+        // children are kept sorted, adding them in order is cheaper
+        fs_dir_content.sort_by_key(|fs_dir_entry| fs_dir_entry.file_name());
+        for fs_dir_entry in fs_dir_content {
+            let file_type = match fs_dir_entry.file_type().context("Getting file type") {
+                Ok(file_type) => file_type,
+                Err(e) => {
+                    report.keep_going(options, &fs_dir_entry.path(), e)?;
+                    continue;
+                },
+            };
+            let entry_name = fs_dir_entry.file_name().into_string().expect("Convert osstr to string");
+
+            if file_type.is_dir() {
+                let new_dir = self.add_dir(&entry_name, dir)?;
+                self.add_dir_from_fs(new_dir, &fs_dir_entry.path(), options, report)?;
+            }
+            else if file_type.is_file() {
+                if entry_name.ends_with(crate::mirror::PARTIAL_SUFFIX) {
+                    continue; // of a pull in progress
+                }
+                let metadata = match fs_dir_entry.metadata().context("Getting file metadata") {
+                    Ok(metadata) => metadata,
+                    Err(e) => {
+                        report.keep_going(options, &fs_dir_entry.path(), e)?;
+                        continue;
+                    },
+                };
+                if scan::is_cloud_placeholder(&metadata) {
+                    match options.placeholder_policy {
+                        PlaceholderPolicy::Skip => {
+                            debug!("Skipping cloud placeholder {:?}", fs_dir_entry.path());
+                            report.skipped_placeholders.push(fs_dir_entry.path());
+                            continue;
+                        },
+                        PlaceholderPolicy::Error => anyhow::bail!("{} is a cloud placeholder file", fs_dir_entry.path().to_str().unwrap()),
+                        PlaceholderPolicy::Hydrate => (),
+                    }
+                }
+                let size = metadata.len();
+                self.add_file(&entry_name, BlobKey::default(), size, dir)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get_stats(&self) -> Stats {
+        use std::collections::BinaryHeap;
+        use std::cmp::Reverse;
+
+        let mut stats = Stats::default();
+        // min-heap so that the smallest of the largest files is the one popped
+        let mut largest_files: BinaryHeap<Reverse<(u64, PathBuf)>> = BinaryHeap::new();
+
+        // (entry, path, depth, name of top level dir it's in)
+        let mut to_visit: Vec<(EntryId, PathBuf, usize, Option<&str>)> = vec![(self.root, PathBuf::new(), 0, None)];
+
+        while let Some((entry_id, path, depth, top_level_dir)) = to_visit.pop() {
+            if depth > stats.max_depth {
+                stats.max_depth = depth;
+                stats.deepest_path = path.clone();
+            }
+
+            match self.get_entry(entry_id) {
+                Entry::Directory(dir) => {
+                    stats.num_dirs += 1;
+                    if let Some(name) = top_level_dir {
+                        let totals = stats.top_level_dirs.entry(name.to_string()).or_default();
+                        if depth > 1 {
+                            totals.num_dirs += 1;
+                        }
+                    }
+                    for (name, sub_entry_id) in &dir.entries {
+                        let name = self.names.get(name);
+                        let top_level_dir = match top_level_dir {
+                            None if depth == 0 && matches!(self.get_entry(sub_entry_id), Entry::Directory(_)) => Some(name),
+                            other => other,
+                        };
+                        to_visit.push((sub_entry_id, path.join(name), depth + 1, top_level_dir));
+                    }
+                },
+                Entry::File(file) => {
+                    stats.num_files += 1;
+                    stats.total_size += file.size;
+                    if let Some(name) = top_level_dir {
+                        let totals = stats.top_level_dirs.entry(name.to_string()).or_default();
+                        totals.num_files += 1;
+                        totals.size += file.size;
+                    }
+                    largest_files.push(Reverse((file.size, path)));
+                    if largest_files.len() > NUM_LARGEST_FILES_IN_STATS {
+                        largest_files.pop();
+                    }
+                }
+            }
+        }
+
+        stats.largest_files = largest_files.into_sorted_vec().into_iter()
+            .map(|Reverse((size, path))| (path, size))
+            .collect();
+
+        stats
+    }
+
+    pub fn save_as_file(&self, path: &Path) -> anyhow::Result<()> {
+        let mut file = std::fs::File::create(path).context("Create/open file for saving manifest")?;
+        rmp_serde::encode::write(&mut file, &self).context("Serialize/write manifest into file")?;
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> anyhow::Result<bytes::Bytes> {
+        let serialized = rmp_serde::encode::to_vec(&self).context("Serialize manifest into bytes")?;
+        Ok(bytes::Bytes::from(serialized))
+    }
+
+    pub fn from_bytes(bytes: bytes::Bytes) -> anyhow::Result<Self> {
+        let manifest: Self = rmp_serde::decode::from_slice(&bytes).context(UndecodableManifest)?;
+        Ok(manifest)
+    }
+
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        serde_json::to_string_pretty(&self).context("Serialize manifest into json")
+    }
+
+    pub fn from_json(json: &str) -> anyhow::Result<Self> {
+        let manifest: Self = serde_json::from_str(json).context("Deserialize manifest from json")?;
+        Ok(manifest)
+    }
+
+    // map each entry to its parent
+    fn get_map_parent(&self) -> HashMap<EntryId, EntryId> {
+
+        let mut map = HashMap::new();
+        let mut dirs_to_visit = vec![self.root];
+
+        while let Some(dir_entry_id) = dirs_to_visit.pop() {
+            let dir = self.get_entry(dir_entry_id).try_directory_ref().unwrap();
+
+            for sub_entry_id in dir.entries.values().cloned() {
+                let sub_entry = self.get_entry(sub_entry_id);
+                map.insert(sub_entry_id, dir_entry_id);
+                match sub_entry {
+                    Entry::File(_) => {},
+                    Entry::Directory(_) => {
+                        dirs_to_visit.push(sub_entry_id)
+                    }
+                }
+            }
+        }
+
+        map
+    }
+
+    fn get_full_path(&self, entry_id: EntryId, map_parent: &HashMap<EntryId, EntryId>) -> PathBuf {
+        if entry_id == self.root {
+            return PathBuf::from("");
+        }
+
+        let mut components = vec![self.name(self.get_entry(entry_id))];
+        let mut parent_id = map_parent.get(&entry_id).unwrap();
+        while parent_id != &self.root {
+            components.push(self.name(self.get_entry(*parent_id)));
+            parent_id = map_parent.get(parent_id).unwrap();
+        }
+        PathBuf::from_iter(components.iter().rev())
+    }
+
+    // allows to use get_full_path above, without the user having to build/pass map_parent
+    // the lifetime/borrow check should also prevent manifest from changing without map_parent changing
+    pub fn get_full_path_getter<'a>(&'a self) -> impl Fn(EntryId) -> PathBuf + 'a {
+        let map_parent = self.get_map_parent();
+        move |entry_id: EntryId| -> PathBuf {
+            self.get_full_path(entry_id, &map_parent)
+        }
+    }
+
+    pub fn get_child_files_recurs(&self, entry_id: EntryId) -> Vec<EntryId> {
+        let entry = self.get_entry(entry_id);
+        if let Entry::File(_) = entry {
+            return vec![entry_id];
+        }
+
+        let mut to_visit: Vec<EntryId> = entry.try_directory_ref().unwrap().entries.values().cloned().collect();
+        let mut child_files = Vec::new();
+
+        while let Some(entry_id) = to_visit.pop() {
+            let entry = self.get_entry(entry_id);
+            match entry {
+                Entry::File(_) => child_files.push(entry_id),
+                Entry::Directory(dir) => to_visit.extend(dir.entries.values().clone()),
+            }
+        }
+
+        child_files
+    }
+
+    pub fn get_child_dirs_recurs(&self, entry_id: EntryId) -> Vec<EntryId> {
+        let entry = self.get_entry(entry_id);
+        if let Entry::File(_) = entry {
+            return Vec::new();
+        }
+
+        let mut to_visit: Vec<EntryId> = entry.try_directory_ref().unwrap().entries.values().cloned().collect();
+        let mut child_dirs = vec![entry_id];
+
+        while let Some(entry_id) = to_visit.pop() {
+            let entry = self.get_entry(entry_id);
+            match entry {
+                Entry::File(_) => (),
+                Entry::Directory(dir) => {
+                    child_dirs.push(entry_id);
+                    to_visit.extend(dir.entries.values().clone())
+                },
+            }
+        }
+
+        child_dirs
+    }
+
+    // deep copy of src_id (and its children if it's a dir) from src into self under parent_dir
+    // returns the number of (files, dirs) added
+    fn add_copy_of(&mut self, src: &Manifest, src_id: EntryId, parent_dir: EntryId) -> anyhow::Result<(usize, usize)> {
+        let mut num_added = (0, 0);
+        let mut to_visit: Vec<(EntryId, EntryId)> = Vec::new();
+
+        let mut add_one = |dest: &mut Manifest, src_id: EntryId, dest_dir: EntryId, to_visit: &mut Vec<(EntryId, EntryId)>| -> anyhow::Result<()> {
+            match src.get_entry(src_id) {
+                Entry::File(file) => {
+                    let new_file = dest.add_stored_file(src.names.get(file.name), file.blob_key.clone(), file.size, file.ciphertext_size, file.chunks.clone(), dest_dir)?;
+                    dest.set_times(new_file, file.times);
+                    num_added.0 += 1;
+                },
+                Entry::Directory(dir) => {
+                    let new_dir = dest.add_dir(src.names.get(dir.name), dest_dir)?;
+                    dest.set_times(new_dir, dir.times);
+                    to_visit.push((src_id, new_dir));
+                    num_added.1 += 1;
+                }
+            }
+            Ok(())
+        };
+
+        add_one(self, src_id, parent_dir, &mut to_visit)?;
+        while let Some((src_dir_id, dest_dir_id)) = to_visit.pop() {
+            let src_dir = src.get_entry(src_dir_id).try_directory_ref()?;
+            for &sub_entry_id in src_dir.entries.values() {
+                add_one(self, sub_entry_id, dest_dir_id, &mut to_visit)?;
+            }
+        }
+
+        Ok(num_added)
+    }
+
+    // new manifest which root is the directory at path
+    pub fn subtree(&self, path: &Path) -> anyhow::Result<Manifest> {
+        let dir_id = self.join_and_get_entry_id(self.root, path)?;
+        let dir = self.get_entry(dir_id).try_directory_ref()
+            .with_context(|| format!("Subtree path {} is not a directory", path.to_str().unwrap()))?;
+        let mut subtree = Manifest::new();
+        for &entry_id in dir.entries.values() {
+            subtree.add_copy_of(self, entry_id, subtree.root)?;
+        }
+        Ok(subtree)
+    }
+
+    // new manifest with only the entry at path (and its children if it's a dir), under the same dirs as in self
+    // unlike subtree, paths in it are the same as in self
+    pub fn scoped(&self, path: &Path) -> anyhow::Result<Manifest> {
+        let entry_id = self.join_and_get_entry_id(self.root, path)?;
+        let mut scoped = Manifest::new();
+        scoped.name_normalization = self.name_normalization;
+        if entry_id == self.root {
+            scoped.merge(self)?;
+            return Ok(scoped);
+        }
+        let path = normalize_path(path)?;
+        let (mut src_dir, mut dest_dir) = (self.root, scoped.root);
+        for component in path.parent().unwrap_or(Path::new("")).components() {
+            let name = component.as_os_str().to_str().unwrap();
+            src_dir = self.get_child(self.get_entry(src_dir).try_directory_ref()?, name).context("Scope path not found")?;
+            let dir = self.get_entry(src_dir).try_directory_ref()?;
+            dest_dir = scoped.add_dir(self.names.get(dir.name), dest_dir)?;
+            scoped.set_times(dest_dir, dir.times);
+        }
+        scoped.add_copy_of(self, entry_id, dest_dir)?;
+        Ok(scoped)
+    }
+
+    // removes the entry at path (and its children if it's a dir), returns what was removed as scoped does
+    // the entries left are copied to a new manifest, removed ones would otherwise stay in entries, listed nowhere
+    pub fn remove(&mut self, path: &Path) -> anyhow::Result<Manifest> {
+        let entry_id = self.join_and_get_entry_id(self.root, path)?;
+        if entry_id == self.root {
+            anyhow::bail!("Can't remove the archive root");
+        }
+        let removed = self.scoped(path)?;
+        let parent = self.get_map_parent()[&entry_id];
+        let name = self.get_entry(entry_id).name();
+        self.entries[parent.to_usize()].try_directory_ref_mut()?.entries.remove(name);
+
+        let mut kept = Manifest::new();
+        kept.name_normalization = self.name_normalization;
+        let root = kept.root;
+        kept.set_times(root, self.get_entry(self.root).times());
+        for &child_id in self.get_entry(self.root).try_directory_ref()?.entries.values() {
+            kept.add_copy_of(self, child_id, root)?;
+        }
+        *self = kept;
+        Ok(removed)
+    }
+
+    // moves the entry at from to to, like mv: into to if it is a directory, missing parent dirs of to are added
+    // returns the path of the entry now
+    pub fn rename(&mut self, from: &Path, to: &Path) -> anyhow::Result<PathBuf> {
+        let from = normalize_path(from)?;
+        let mut to = normalize_path(to)?;
+        let entry_id = self.join_and_get_entry_id(self.root, &from)?;
+        if entry_id == self.root {
+            anyhow::bail!("Can't move the archive root");
+        }
+        if let Ok(to_id) = self.join_and_get_entry_id(self.root, &to) {
+            if self.get_entry(to_id).try_directory_ref().is_ok() && to_id != entry_id {
+                to = to.join(from.file_name().unwrap());
+            }
+        }
+        if to.starts_with(&from) && to != from {
+            anyhow::bail!("Can't move {} into itself", from.to_str().unwrap());
+        }
+        let name = to.file_name().context("Destination has no name")?.to_str().unwrap().to_string();
+
+        let mut dest_dir = self.root;
+        for component in to.parent().unwrap_or(Path::new("")).components() {
+            let component = component.as_os_str().to_str().unwrap();
+            let dir = self.get_entry(dest_dir).try_directory_ref()?;
+            dest_dir = match self.get_child(dir, component) {
+                Some(child_id) => {
+                    self.get_entry(child_id).try_directory_ref().with_context(|| format!("{} is a file", component))?;
+                    child_id
+                },
+                None => self.add_dir(component, dest_dir)?,
+            };
+        }
+        // the same entry under the same name once normalized is a rename of its bytes only
+        match self.get_child(self.get_entry(dest_dir).try_directory_ref()?, &name) {
+            Some(existing) if existing != entry_id => anyhow::bail!("{} already exists", to.to_str().unwrap()),
+            _ => (),
+        }
+
+        let parent = self.get_map_parent()[&entry_id];
+        let old_name = self.get_entry(entry_id).name();
+        self.entries[parent.to_usize()].try_directory_ref_mut()?.entries.remove(old_name);
+        let name = self.names.intern(&name);
+        self.entries[entry_id.to_usize()].set_name(name);
+        self.entries[dest_dir.to_usize()].try_directory_ref_mut()?.entries.insert(name, entry_id);
+        Ok(to)
+    }
+
+    // scoped for each of paths together, paths that are not in self are left out
+    pub fn scoped_to_paths(&self, paths: &[PathBuf]) -> anyhow::Result<Manifest> {
+        let mut scoped = Manifest::new();
+        scoped.name_normalization = self.name_normalization;
+        for path in paths {
+            if self.join_and_get_entry_id(self.root, path).is_ok() {
+                scoped.merge(&self.scoped(path)?)?;
+            }
+        }
+        Ok(scoped)
+    }
+
+    // union of self and other, entries only in other are added to self
+    // on conflict (same path, different blob key or different entry type) self is kept as is
+    pub fn merge(&mut self, other: &Manifest) -> anyhow::Result<MergeReport> {
+        let mut report = MergeReport::default();
+        let mut to_visit_dirs: Vec<(EntryId, EntryId, PathBuf)> = vec![(self.root, other.root, PathBuf::new())];
+
+        while let Some((dir_id_self, dir_id_other, dir_path)) = to_visit_dirs.pop() {
+            let dir_other = other.get_entry(dir_id_other).try_directory_ref()?;
+            for (name, entry_id_other) in &dir_other.entries {
+                let name = other.names.get(name);
+                let path = dir_path.join(name);
+                let maybe_entry_id_self = self.get_child(self.get_entry(dir_id_self).try_directory_ref()?, name);
+
+                let Some(entry_id_self) = maybe_entry_id_self else {
+                    let num_added = self.add_copy_of(other, entry_id_other, dir_id_self)?;
+                    report.added_files += num_added.0;
+                    report.added_dirs += num_added.1;
+                    continue;
+                };
+
+                match (self.get_entry(entry_id_self), other.get_entry(entry_id_other)) {
+                    (Entry::Directory(_), Entry::Directory(_)) => {
+                        to_visit_dirs.push((entry_id_self, entry_id_other, path));
+                    },
+                    (Entry::File(file_self), Entry::File(file_other)) => {
+                        if file_self.blob_key != file_other.blob_key {
+                            report.conflicts.push(MergeConflict { path, kind: MergeConflictKind::DifferentBlobKey });
+                        }
+                    },
+                    _ => {
+                        report.conflicts.push(MergeConflict { path, kind: MergeConflictKind::DifferentEntryType });
+                    }
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    // (path, blob key, size) of every file, sorted by path
+    pub fn list_files(&self) -> Vec<(PathBuf, String, u64)> {
+        let path_getter = self.get_full_path_getter();
+        let mut files: Vec<(PathBuf, String, u64)> = self.get_child_files_recurs(self.root).into_iter()
+            .map(|entry_id| {
+                let file = self.get_entry(entry_id).try_file_ref().unwrap();
+                (path_getter(entry_id), file.blob_key.to_string(), file.size)
+            })
+            .collect();
+        files.sort();
+        files
+    }
+
+    // (path, blob key, ciphertext size if recorded) of every blob of the files that have some, sorted by path
+    // a file stored in chunks has one item per chunk
+    pub fn list_blobs(&self) -> Vec<(PathBuf, String, Option<u64>)> {
+        let path_getter = self.get_full_path_getter();
+        let mut blobs: Vec<(PathBuf, String, Option<u64>)> = Vec::new();
+        for entry_id in self.get_child_files_recurs(self.root) {
+            let file = self.get_entry(entry_id).try_file_ref().unwrap();
+            if !file.chunks.is_empty() {
+                let path = path_getter(entry_id);
+                blobs.extend(file.chunks.iter().map(|chunk| (path.clone(), chunk.blob_key.to_string(), Some(chunk.ciphertext_size))));
+            } else if file.blob_key != BlobKey::default() {
+                blobs.push((path_getter(entry_id), file.blob_key.to_string(), file.ciphertext_size));
+            }
+        }
+        blobs.sort();
+        blobs
+    }
+
+    // path -> chunks of every file stored in chunks
+    pub fn list_chunked_files(&self) -> HashMap<PathBuf, Vec<StoredChunk>> {
+        let path_getter = self.get_full_path_getter();
+        self.get_child_files_recurs(self.root).into_iter()
+            .filter_map(|entry_id| {
+                let file = self.get_entry(entry_id).try_file_ref().unwrap();
+                (!file.chunks.is_empty()).then(|| (path_getter(entry_id), file.chunks.iter().map(StoredChunk::from).collect()))
+            })
+            .collect()
+    }
+
+    // blob key of the whole content -> how it is stored, of every file that has a blob
+    // files with the same content were stored the same way, any of them does
+    pub fn list_stored_blobs(&self) -> HashMap<String, StoredBlob> {
+        self.get_child_files_recurs(self.root).into_iter()
+            .map(|entry_id| self.get_entry(entry_id).try_file_ref().unwrap())
+            .filter(|file| file.blob_key != BlobKey::default())
+            .map(|file| {
+                let stored = StoredBlob {
+                    key: file.blob_key.to_string(),
+                    ciphertext_size: file.ciphertext_size,
+                    chunks: file.chunks.iter().map(StoredChunk::from).collect(),
+                };
+                (stored.key.clone(), stored)
+            })
+            .collect()
+    }
+
+    // path of every directory except root, sorted
+    pub fn list_dirs(&self) -> Vec<PathBuf> {
+        let path_getter = self.get_full_path_getter();
+        let mut dirs: Vec<PathBuf> = self.get_child_dirs_recurs(self.root).into_iter()
+            .filter(|&entry_id| entry_id != self.root)
+            .map(path_getter)
+            .collect();
+        dirs.sort();
+        dirs
+    }
+
+    // names of the entries of the directory at path, sorted, and whether each is a directory
+    pub fn list_dir(&self, path: &Path) -> anyhow::Result<Vec<(String, bool)>> {
+        let dir_id = self.join_and_get_entry_id(self.root, path)?;
+        let dir = self.get_entry(dir_id).try_directory_ref()
+            .with_context(|| format!("{} is not a directory", path.to_str().unwrap()))?;
+        let mut children: Vec<(String, bool)> = dir.entries.iter()
+            .map(|(name, entry_id)| (self.names.get(name).to_string(), matches!(self.get_entry(entry_id), Entry::Directory(_))))
+            .collect();
+        children.sort();
+        Ok(children)
+    }
+
+    // every entry but root, see Walk
+    pub fn walk(&self) -> Walk<'_> {
+        let mut walk = Walk { manifest: self, to_visit: Vec::new() };
+        if let Ok(root) = self.get_entry(self.root).try_directory_ref() {
+            walk.push_children(root, Path::new(""));
+        }
+        walk
+    }
+
+    // (path, times) of the files whose blob key changed at or after since (unix seconds), oldest change first
+    // files without recorded times are left out, they were pushed before times were recorded
+    pub fn files_changed_since(&self, since: u64) -> Vec<(PathBuf, EntryTimes)> {
+        let path_getter = self.get_full_path_getter();
+        let mut files: Vec<(PathBuf, EntryTimes)> = self.get_child_files_recurs(self.root).into_iter()
+            .filter_map(|entry_id| {
+                let times = self.get_entry(entry_id).times().filter(|times| times.modified >= since)?;
+                Some((path_getter(entry_id), times))
+            })
+            .collect();
+        files.sort_by(|a, b| a.1.modified.cmp(&b.1.modified).then_with(|| a.0.cmp(&b.0)));
+        files
+    }
+
+    // groups of files sharing the same blob key, most wasted space first
+    // files without a blob key (eg manifest made from fs) are ignored
+    pub fn duplicates(&self) -> Vec<DuplicateGroup> {
+        let no_key = BlobKey::default().to_string();
+        let mut by_key: HashMap<String, DuplicateGroup> = HashMap::new();
+        for (path, blob_key, size) in self.list_files() {
+            if blob_key == no_key {
+                continue;
+            }
+            let group = by_key.entry(blob_key.clone()).or_insert_with(|| DuplicateGroup { blob_key, size, paths: Vec::new() });
+            group.paths.push(path);
+        }
+
+        let mut groups: Vec<DuplicateGroup> = by_key.into_values().filter(|group| group.paths.len() > 1).collect();
+        groups.sort_by(|a, b| b.wasted_size().cmp(&a.wasted_size()).then_with(|| a.paths.cmp(&b.paths)));
+        groups
+    }
+
+    // usage of the directory at path and of the directories under it down to depth (0 for path only), sorted by path
+    // the unique size is an estimate: chunks that files with different content share are counted for each file
+    pub fn disk_usage(&self, path: &Path, depth: usize) -> anyhow::Result<Vec<DirUsage>> {
+        let path = normalize_path(path)?;
+        let subtree = self.subtree(&path)?;
+        let no_key = BlobKey::default().to_string();
+        let mut usages: BTreeMap<PathBuf, (DirUsage, HashSet<String>)> = BTreeMap::new();
+        usages.insert(PathBuf::new(), Default::default());
+        for entry in subtree.walk() {
+            match entry.kind {
+                WalkKind::Directory if entry.path.components().count() <= depth => {
+                    usages.insert(entry.path, Default::default());
+                },
+                WalkKind::Directory => (),
+                WalkKind::File { size, blob_key } => {
+                    let mut dir = entry.path.parent().unwrap().to_path_buf();
+                    while dir.components().count() > depth {
+                        dir.pop();
+                    }
+                    // and each of its parents
+                    for ancestor in dir.ancestors() {
+                        let (usage, keys) = usages.get_mut(ancestor).unwrap();
+                        usage.num_files += 1;
+                        usage.size += size;
+                        if blob_key == no_key || keys.insert(blob_key.clone()) {
+                            usage.unique_size += size;
+                        }
+                    }
+                },
+            }
+        }
+        Ok(usages.into_iter()
+            .map(|(dir, (usage, _))| DirUsage { path: if dir.as_os_str().is_empty() { path.clone() } else { path.join(dir) }, ..usage })
+            .collect())
+    }
+
+    // this method does not really make sense
+    // but should we make entry, file, directory pub instead?
+    pub fn get_file_key_and_size(&self, entry_id: EntryId) -> anyhow::Result<(String, u64)> {
+        let entry = self.get_entry(entry_id);
+        let file = entry.try_file_ref()?;
+        Ok((file.blob_key.to_string(), file.size))
+    }
+}
+
+pub struct TreeFormat {
+    pub show_size: bool,
+    pub show_hash: bool,
+    pub max_depth: Option<usize>, // root is depth 0
+}
+
+impl Default for TreeFormat {
+    fn default() -> Self {
+        Self {
+            show_size: true,
+            show_hash: false,
+            max_depth: None,
+        }
+    }
+}
+
+const SIZE_COLUMN_WIDTH: usize = 14;
+const HASH_COLUMN_WIDTH: usize = 2 * blake3::OUT_LEN;
+
+fn write_entry<W: std::io::Write>(manifest: &Manifest, entry: &Entry, depth: usize, format: &TreeFormat, out: &mut W) -> std::io::Result<()> {
+    if format.max_depth.is_some_and(|max_depth| depth > max_depth) {
+        return Ok(());
+    }
+
+    match entry {
+        Entry::File(file) => {
+            if format.show_size {
+                write!(out, "{:>width$} ", file.size, width = SIZE_COLUMN_WIDTH)?;
+            }
+            if format.show_hash {
+                write!(out, "{} ", file.blob_key.to_string())?;
+            }
+            writeln!(out, "{}{}", " ".repeat(2 * depth), manifest.names.get(file.name))?;
+        },
+        Entry::Directory(dir) => {
+            if format.show_size {
+                write!(out, "{:>width$} ", "", width = SIZE_COLUMN_WIDTH)?;
+            }
+            if format.show_hash {
+                write!(out, "{:width$} ", "", width = HASH_COLUMN_WIDTH)?;
+            }
+            writeln!(out, "{}{}/", " ".repeat(2 * depth), manifest.names.get(dir.name))?;
+
+            let mut children: Vec<(&str, EntryId)> = dir.entries.iter().map(|(name, entry_id)| (manifest.names.get(name), entry_id)).collect();
+            children.sort_by_key(|&(name, _)| name);
+            for (_, entry_id) in children {
+                let entry = manifest.get_entry(entry_id);
+                write_entry(manifest, entry, depth + 1, format, out)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// children are sorted by name so that the output is the same on every run
+pub fn write_tree<W: std::io::Write>(manifest: &Manifest, format: &TreeFormat, out: &mut W) -> std::io::Result<()> {
+    write_entry(manifest, manifest.get_entry(manifest.root), 0, format, out)
+}
+
+// the entries of the directory at path (or the file at path) sorted by name, directories with a trailing /
+// long adds the type (d or -), the size (of everything in it for a directory) and the blob key of files
+pub fn write_listing<W: std::io::Write>(manifest: &Manifest, path: &Path, long: bool, out: &mut W) -> anyhow::Result<()> {
+    let entry_id = manifest.join_and_get_entry_id(manifest.root, path)?;
+    let entries: Vec<(&str, EntryId)> = match manifest.get_entry(entry_id) {
+        Entry::Directory(dir) => {
+            let mut children: Vec<(&str, EntryId)> = dir.entries.iter().map(|(name, entry_id)| (manifest.names.get(name), entry_id)).collect();
+            children.sort_by_key(|&(name, _)| name);
+            children
+        },
+        file => vec![(manifest.name(file), entry_id)],
+    };
+    for (name, entry_id) in entries {
+        let entry = manifest.get_entry(entry_id);
+        if long {
+            match entry {
+                Entry::File(file) => write!(out, "- {:>size_width$} {} ", file.size, file.blob_key.to_string(), size_width = SIZE_COLUMN_WIDTH)?,
+                Entry::Directory(_) => {
+                    let size: u64 = manifest.get_child_files_recurs(entry_id).into_iter()
+                        .map(|file_id| manifest.get_entry(file_id).try_file_ref().unwrap().size)
+                        .sum();
+                    write!(out, "d {:>size_width$} {:hash_width$} ", size, "", size_width = SIZE_COLUMN_WIDTH, hash_width = HASH_COLUMN_WIDTH)?;
+                },
+            }
+        }
+        match entry {
+            Entry::File(_) => writeln!(out, "{}", name)?,
+            Entry::Directory(_) => writeln!(out, "{}/", name)?,
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListingFormat {
+    Mtree, // a full path per line, and the keywords of BSD mtree and libarchive
+    Csv, // path,size,hash,mode,mtime of the files, with a header line
+}
+
+// modes are not in the manifest, these are the modes export-tar gives
+const LISTED_FILE_MODE: &str = "0644";
+const LISTED_DIR_MODE: &str = "0755";
+
+// every entry under path (a dir with it, or a file) sorted by path, returns the number of entries written
+// hash is the blob key (keyed by the bucket name, see blob_storage::get_hash_name), mtime when the blob of the file
+// last changed, 0 for entries pushed before times were recorded
+pub fn write_full_listing<W: std::io::Write>(manifest: &Manifest, path: &Path, format: ListingFormat, out: &mut W) -> anyhow::Result<usize> {
+    let entry_id = manifest.join_and_get_entry_id(manifest.root, path)?;
+    let path_getter = manifest.get_full_path_getter();
+    let mut entries: Vec<(PathBuf, EntryId)> = match format {
+        ListingFormat::Mtree => manifest.get_child_dirs_recurs(entry_id),
+        ListingFormat::Csv => Vec::new(),
+    }.into_iter()
+        .chain(manifest.get_child_files_recurs(entry_id))
+        .map(|entry_id| (path_getter(entry_id), entry_id))
+        .collect();
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    match format {
+        ListingFormat::Mtree => writeln!(out, "#mtree")?,
+        ListingFormat::Csv => writeln!(out, "path,size,hash,mode,mtime")?,
+    }
+    for (path, entry_id) in &entries {
+        let entry = manifest.get_entry(*entry_id);
+        let mtime = entry.times().map_or(0, |times| times.modified);
+        match (format, entry) {
+            (ListingFormat::Mtree, Entry::Directory(_)) => {
+                writeln!(out, "{} type=dir mode={} time={}.0", mtree_path(path), LISTED_DIR_MODE, mtime)?;
+            },
+            (ListingFormat::Mtree, Entry::File(file)) => {
+                writeln!(out, "{} type=file mode={} size={} time={}.0 harkey={}", mtree_path(path), LISTED_FILE_MODE, file.size, mtime,
+                    file.blob_key.to_string())?;
+            },
+            (ListingFormat::Csv, Entry::File(file)) => {
+                writeln!(out, "{},{},{},{},{}", csv_field(path.to_str().unwrap()), file.size, file.blob_key.to_string(), LISTED_FILE_MODE, mtime)?;
+            },
+            (ListingFormat::Csv, Entry::Directory(_)) => unreachable!(),
+        }
+    }
+    Ok(entries.len())
+}
+
+// ./ and the path with spaces, backslashes and what is not printable ascii as \ooo (octal bytes), as mtree(5) encodes names
+fn mtree_path(path: &Path) -> String {
+    let mut encoded = String::from(".");
+    for component in path.iter() {
+        encoded.push('/');
+        for byte in component.to_str().unwrap().bytes() {
+            if byte.is_ascii_graphic() && byte != b'\\' && byte != b'#' {
+                encoded.push(byte as char);
+            } else {
+                encoded += &format!("\\{:03o}", byte);
+            }
+        }
+    }
+    encoded
+}
+
+// quoted (with doubled quotes) when it has a separator, a quote or a line break, as RFC 4180 has it
+pub fn csv_field(text: &str) -> Cow<'_, str> {
+    match text.contains([',', '"', '\n', '\r']) {
+        true => Cow::Owned(format!("\"{}\"", text.replace('"', "\"\""))),
+        false => Cow::Borrowed(text),
+    }
+}
+
+pub fn print_tree(manifest: &Manifest) {
+    print_tree_with_format(manifest, &TreeFormat::default());
+}
+
+pub fn print_tree_with_format(manifest: &Manifest, format: &TreeFormat) {
+    let stdout = std::io::stdout();
+    let _ = write_tree(manifest, format, &mut stdout.lock());
+}
+
+#[derive(Default)]
+pub struct DiffManifests {
+    // top means non recursive, in other words not total
+    // if not mentioned, it is recursive/total
+    pub top_extra_ids_in_a: Vec<EntryId>,
+    pub paths_of_top_extra_in_a: Vec<PathBuf>,
+    pub extra_files_in_a: usize,
+    pub extra_dirs_in_a: usize,
+    pub paths_of_different_files: Vec<PathBuf>,
+    pub hashing_duration: std::time::Duration, // time spent rehashing local files for the hash check
+    dirs_num_files_dirs: HashMap<EntryId, (usize, usize)>, // recursive number of (files, dirs) in a dir
+    archive_root: PathBuf,
+    bucket_name: String,
+    hash_check: bool,
+    hash_cache: Option<HashCache>,
+    already_called: bool,
+}
+
+impl fmt::Display for DiffManifests {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        writeln!(f, "top_extra_ids_in_a:{:?}", self.top_extra_ids_in_a)?;
+        writeln!(f, "paths_of_top_extra_in_a:{:?}", self.paths_of_top_extra_in_a)?;
+        writeln!(f, "extra_files_in_a:{:?}", self.extra_files_in_a)?;
+        writeln!(f, "extra_dirs_in_a:{:?}", self.extra_dirs_in_a)
+    }
+}
+
+impl DiffManifests {
+    pub fn with_hash_check(mut self, archive_root: PathBuf, bucket_name: String) -> Self {
+        self.hash_check = true;
+        self.archive_root = archive_root;
+        self.bucket_name = bucket_name;
+        self
+    }
+
+    // the hash check takes the keys of files that did not change since they were cached, see take_hash_cache
+    pub fn with_hash_cache(mut self, hash_cache: HashCache) -> Self {
+        self.hash_cache = Some(hash_cache);
+        self
+    }
+
+    // with the files hashed by diff_manifests, to be stored for the next hash check
+    pub fn take_hash_cache(&mut self) -> Option<HashCache> {
+        self.hash_cache.take()
+    }
+
+    pub fn diff_manifests(mut self, manifest_a: &Manifest, manifest_b: &Manifest) -> Self {
+
+        assert!(!self.already_called);
+        self.already_called = true;
+
+        let root_dir_a = manifest_a.get_entry(manifest_a.root).try_directory_ref().unwrap();
+        let root_dir_b = manifest_b.get_entry(manifest_b.root).try_directory_ref().unwrap();
+        let path_getter = manifest_a.get_full_path_getter();
+
+        let mut to_visit_dirs: Vec<(&Directory, &Directory)> = vec![(root_dir_a, root_dir_b)];
+
+        while let Some((dir_a, dir_b)) = to_visit_dirs.pop() {
+
+            for entry_id_a in dir_a.entries.values().cloned() {
+
+                // exclude stuff
+                // todo: move to from_fs()
+                let full_path = path_getter(entry_id_a);
+                if full_path == Path::new(".har") {
+                    continue;
+                }
+
+                let entry_a = manifest_a.get_entry(entry_id_a);
+                match entry_a {
+                    Entry::File(file) => {
+                        if let Some(entry_id_b) = manifest_b.get_child(dir_b, manifest_a.names.get(file.name)) {
+                            if self.hash_check {
+                                let hashing_start = std::time::Instant::now();
+                                let file_path = self.archive_root.join(&full_path);
+                                let hash_name = match &mut self.hash_cache {
+                                    Some(hash_cache) => hash_cache.key_of(&full_path, &file_path),
+                                    None => std::fs::read(&file_path).map(|file_bytes| blob_storage::get_hash_name(self.bucket_name.as_str(), bytes::Bytes::from(file_bytes))),
+                                };
+                                let hash_name = match hash_name {
+                                    Ok(hash_name) => hash_name,
+                                    Err(e) => {
+                                        debug!("Not checking the hash of {}: {}", file_path.to_str().unwrap(), e);
+                                        continue;
+                                    },
+                                };
+
+                                let remote_entry = manifest_b.get_entry(entry_id_b);
+                                let remote_entry_hash_name = remote_entry.try_file_ref().unwrap().blob_key.to_string();
+
+                                if hash_name != remote_entry_hash_name {
+                                    self.paths_of_different_files.push(full_path);
+                                }
+                                self.hashing_duration += hashing_start.elapsed();
+                            }
+                        }
+                        else {
+                            self.extra_files_in_a += 1;
+                            self.top_extra_ids_in_a.push(entry_id_a);
+                        }
+                    },
+                    Entry::Directory(subdir_a) => {
+                        if let Some(entry_id_b) = manifest_b.get_child(dir_b, manifest_a.names.get(subdir_a.name)) {
+                            let subdir_b = manifest_b.get_entry(entry_id_b).try_directory_ref().unwrap(); // todo handle error of mismatch entry type
+                            to_visit_dirs.push((subdir_a, subdir_b));
+                        }
+                        else {
+                            self.extra_dirs_in_a += 1;
+                            self.top_extra_ids_in_a.push(entry_id_a);
+                            let num_children = get_num_child_in_dir_recurs(&mut self.dirs_num_files_dirs, manifest_a, entry_id_a);
+                            self.extra_files_in_a += num_children.0;
+                            self.extra_dirs_in_a += num_children.1;
+                        }
+                    },
+                }
+            }
+        }
+
+        for &entry_id in &self.top_extra_ids_in_a {
+            let full_path = path_getter(entry_id);
+            self.paths_of_top_extra_in_a.push(full_path);
+        }
+
+        self
+    }
+}
+
+pub fn diff_manifests(manifest_a: &Manifest, manifest_b: &Manifest) -> DiffManifests {
+    let diff = DiffManifests::default();
+    diff.diff_manifests(manifest_a, manifest_b)
+}
+
+// file level (recursive) differences, from old to new
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct FileChanges {
+    pub added: Vec<PathBuf>,
+    pub changed: Vec<PathBuf>, // same path but different blob key
+    pub deleted: Vec<PathBuf>,
+}
+
+// paths are compared as the old manifest compares names, see NameNormalization
+pub fn diff_files(new: &Manifest, old: &Manifest) -> FileChanges {
+    let mut changes = FileChanges::default();
+    let compared = |path: &Path| PathBuf::from(old.name_normalization.compared_form(path.to_str().unwrap()).into_owned());
+    let old_files: HashMap<PathBuf, (PathBuf, String)> = old.list_files().into_iter()
+        .map(|(path, key, _)| (compared(&path), (path, key)))
+        .collect();
+    let new_files = new.list_files();
+
+    for (path, key, _) in &new_files {
+        match old_files.get(&compared(path)) {
+            None => changes.added.push(path.clone()),
+            Some((_, old_key)) if old_key != key => changes.changed.push(path.clone()),
+            Some(_) => (),
+        }
+    }
+
+    let new_paths: HashSet<PathBuf> = new_files.iter().map(|(path, _, _)| compared(path)).collect();
+    changes.deleted = old_files.into_iter().filter(|(compared, _)| !new_paths.contains(compared)).map(|(_, (path, _))| path).collect();
+    changes.deleted.sort();
+
+    changes
+}
+
+pub fn add_new_entries_to_manifest(
+    src: &Manifest,
+    dest: &mut Manifest,
+    diff: &DiffManifests,
+    blob_keys: &HashMap<PathBuf, StoredBlob>,
+    skipped: &HashSet<PathBuf>, // files left out of dest, for example because their upload failed
+    now: u64, // unix seconds, the times of the added entries
+) -> anyhow::Result<()> {
+
+    let map_parent_src = src.get_map_parent();
+
+    let mut dirs_to_visit: Vec<(EntryId, EntryId)> = Vec::new();
+
+    let add_entry_src_to_dest = |entry_id_src, entry_src: &Entry, dest_dir, dir_path: &Path, dest_manifest: &mut Manifest, dirs_to_visit: &mut Vec<(EntryId, EntryId)>|
+        -> anyhow::Result<()> {
+        match entry_src {
+            Entry::File(file) => {
+                let name = src.names.get(file.name);
+                let path = dir_path.join(name);
+                if skipped.contains(&path) {
+                    return Ok(());
+                }
+                let stored = blob_keys.get(&path).with_context(|| format!("Did not find path-key entry in map path:{}", path.to_str().unwrap()))?;
+                let blob_key = BlobKey::try_from(stored.key.as_str())?;
+                let chunks = stored.chunks.iter().map(Chunk::try_from).collect::<anyhow::Result<Vec<Chunk>>>()?;
+                let new_file = dest_manifest.add_stored_file(name, blob_key, file.size, stored.ciphertext_size, chunks, dest_dir)
+                    .context("Add file from src/dest diff in dest")?;
+                dest_manifest.set_times(new_file, Some(EntryTimes::new(now)));
+            },
+            Entry::Directory(dir) => {
+                let new_dir_b = dest_manifest.add_dir(src.names.get(dir.name), dest_dir).context("Add dir from src/dest diff in dest")?;
+                dest_manifest.set_times(new_dir_b, Some(EntryTimes::new(now)));
+                dirs_to_visit.push((entry_id_src, new_dir_b));
+            }
+        }
+        Ok(())
+    };
+
+    debug!("add_new_entries_to_manifest step 1");
+
+    for &entry_id_a in &diff.top_extra_ids_in_a {
+        let entry_a = src.get_entry(entry_id_a);
+        let parent_a = map_parent_src[&entry_id_a];
+        let parent_path = src.get_full_path(parent_a, &map_parent_src);
+        let parent_b = dest.join_and_get_entry_id(dest.root, &parent_path)?;
+
+        add_entry_src_to_dest(entry_id_a, entry_a, parent_b, &parent_path, dest, &mut dirs_to_visit)?;
+    }
+
+    debug!("add_new_entries_to_manifest step 2");
+
+    while let Some((dir_entry_id_a, dir_entry_id_b)) = dirs_to_visit.pop() {
+        let dir_entry_a = src.get_entry(dir_entry_id_a);
+        let dir_a = dir_entry_a.try_directory_ref().unwrap();
+        let parent_path = src.get_full_path(dir_entry_id_a, &map_parent_src);
+        for &sub_entry_id in dir_a.entries.values() {
+            let sub_entry = src.get_entry(sub_entry_id);
+            add_entry_src_to_dest(sub_entry_id, sub_entry, dir_entry_id_b, &parent_path, dest, &mut dirs_to_visit)?;
+        }
+    }
+
+    Ok(())
+}
+
+// files of dest whose content differs in src (see DiffManifests::with_hash_check) get the blobs their new content was
+// pushed to, they keep the time they were created
+pub fn update_changed_entries_in_manifest(
+    src: &Manifest,
+    dest: &mut Manifest,
+    diff: &DiffManifests,
+    blob_keys: &HashMap<PathBuf, StoredBlob>,
+    skipped: &HashSet<PathBuf>, // files left as they were in dest
+    now: u64,
+) -> anyhow::Result<()> {
+    for path in &diff.paths_of_different_files {
+        if skipped.contains(path) {
+            continue;
+        }
+        let stored = blob_keys.get(path).with_context(|| format!("Did not find path-key entry in map path:{}", path.to_str().unwrap()))?;
+        let size = src.get_entry(src.join_and_get_entry_id(src.root, path)?).try_file_ref()?.size;
+        let dest_id = dest.join_and_get_entry_id(dest.root, path)?;
+        let file = dest.entries[dest_id.to_usize()].try_file_ref_mut()?;
+        file.blob_key = BlobKey::try_from(stored.key.as_str())?;
+        file.size = size;
+        file.ciphertext_size = stored.ciphertext_size;
+        file.chunks = stored.chunks.iter().map(Chunk::try_from).collect::<anyhow::Result<Vec<Chunk>>>()?;
+        file.times = Some(EntryTimes { created: file.times.map_or(now, |times| times.created), modified: now });
+    }
+    Ok(())
+}
+
+fn add_tuples(t0: (usize, usize), t1: (usize, usize)) -> (usize, usize) {
+    (t0.0 + t1.0, t0.1 + t1.1)
+}
+
+fn get_num_child_in_dir_recurs(dirs_num_child: &mut HashMap<EntryId, (usize, usize)>, manifest: &Manifest, entry_id: EntryId) -> (usize, usize) {
+
+    let entry = manifest.get_entry(entry_id);
+    if let Entry::File(_) = entry {
+        return (1, 0);
+    }
+
+    let maybe_known_size = dirs_num_child.get(&entry_id);
+    if let Some(&size) = maybe_known_size {
+        return size;
+    }
+
+    let Entry::Directory(dir) = entry else {
+        panic!("What? we already tested if it's a file, it can't be")
+    };
+
+    let mut size = (0, 0);
+    for &entry_id in dir.entries.values() {
+        let entry = manifest.get_entry(entry_id);
+        let sub_size = match entry {
+            Entry::File(_) => (1, 0),
+            Entry::Directory(_) => add_tuples(get_num_child_in_dir_recurs(dirs_num_child, manifest, entry_id), (0, 1)),
+        };
+        size = add_tuples(size, sub_size);
+    }
+
+    dirs_num_child.insert(entry_id, size);
+
+    size
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn dummy_blob_key() -> BlobKey {
+        let stuffing: [u8; 32] = [1, 2, 3, 4, 5, 6, 7, 8, 1, 2, 3, 4, 5, 6, 7, 8, 1, 2, 3, 4, 5, 6, 7, 8, 1, 2, 3, 4, 5, 6, 7, 8];
+        BlobKey {
+            key: blake3::Hash::from_bytes(stuffing)
+        }
+    }
+
+    fn add_dummy_file(manifest: &mut Manifest, parent_dir: EntryId) -> anyhow::Result<EntryId> {
+        manifest.add_file("imafile", dummy_blob_key(), 42, parent_dir)
+    }
+
+    fn assert_is_dummy_file(manifest: &Manifest, entry_id: EntryId) {
+        let file = manifest.get_entry(entry_id).try_file_ref().unwrap();
+        assert_eq!(manifest.names.get(file.name), "imafile");
+        assert_eq!(file.blob_key, dummy_blob_key());
+        assert_eq!(file.size, 42);
+    }
+
+    fn dummy_manifest() -> Manifest {
+        let mut manifest = Manifest::new();
+        let root = manifest.root;
+        add_dummy_file(&mut manifest, root).expect("Add entry");
+        manifest.join_and_get_entry_id(manifest.root, Path::new("imafile")).expect("join and get entry id");
+        manifest
+    }
+
+    #[test]
+    fn create_file() {
+        let mut manifest = Manifest::new();
+        let root = manifest.root;
+        add_dummy_file(&mut manifest, root).expect("Add entry");
+        let entry_id = manifest.join_and_get_entry_id(manifest.root, Path::new("imafile")).expect("join and get entry id");
+        assert_is_dummy_file(&manifest, entry_id);
+    }
+
+    #[test]
+    fn create_dir_and_file() {
+        let mut manifest = Manifest::new();
+        manifest.add_dir("imadir", manifest.root).expect("Add dir");
+        let dir = manifest.join_and_get_entry_id(manifest.root, Path::new("imadir")).expect("Get dir");
+        add_dummy_file(&mut manifest, dir).expect("Add file in dir");
+
+        let file_a = manifest.join_and_get_entry_id(manifest.root, Path::new("imadir/imafile")).expect("Get file");
+        let file_b = manifest.join_and_get_entry_id(dir, Path::new("imafile")).expect("Get file");
+
+        assert_eq!(file_a, file_b);
+        assert_is_dummy_file(&manifest, file_a);
+        assert_eq!(manifest.entries.len(), 3);
+
+        print_tree(&manifest);
+    }
+
+    #[test]
+    fn seialize_deserialize() -> anyhow::Result<()> {
+        let manifest = dummy_manifest();
+        let bytes = manifest.to_bytes().context("serializing")?;
+        let manifest_b = Manifest::from_bytes(bytes).context("deserializing")?;
+
+        assert_eq!(manifest.get_stats().num_files, manifest_b.get_stats().num_files);
+
+        Ok(())
+    }
+
+    #[test]
+    fn json_roundtrip() -> anyhow::Result<()> {
+        let manifest = dummy_manifest();
+        let json = manifest.to_json()?;
+        assert!(json.contains(&dummy_blob_key().to_string()));
+
+        let manifest_b = Manifest::from_json(&json)?;
+        let file = manifest_b.join_and_get_entry_id(manifest_b.root, Path::new("imafile"))?;
+        assert_is_dummy_file(&manifest_b, file);
+
+        // binary format is not affected by the json representation of blob keys
+        let manifest_c = Manifest::from_bytes(manifest_b.to_bytes()?)?;
+        assert_eq!(manifest_c.to_json()?, json);
+
+        Ok(())
+    }
+
+    struct ManifestBuilder {
+        manifest: Manifest,
+        cwd: EntryId,
+        previous_cwd: EntryId
+    }
+
+    impl ManifestBuilder {
+        fn new(manifest: Manifest) -> Self {
+            let cwd = manifest.root;
+            ManifestBuilder {
+                manifest,
+                cwd,
+                previous_cwd: EntryId::from_usize(0),
+            }
+        }
+        fn get_manifest(self) -> Manifest {
+            self.manifest
+        }
+        fn file(mut self, name: &str) -> Self {
+            self.manifest.add_file(name, BlobKey::default(), 42, self.cwd).unwrap();
+            self
+        }
+        fn start_dir(mut self, name: &str) -> Self {
+            self.previous_cwd = self.cwd;
+            self.cwd = self.manifest.add_dir(name, self.cwd).unwrap();
+            self
+        }
+        fn cd_dir(mut self, name: &str) -> Self {
+            self.previous_cwd = self.cwd;
+            let dir = self.manifest.get_entry(self.previous_cwd).try_directory_ref().unwrap();
+            self.cwd = self.manifest.get_child(dir, name).unwrap();
+            self
+        }
+        fn end_dir(mut self) -> Self {
+            self.cwd = self.previous_cwd;
+            self
+        }
+    }
+
+    #[test]
+    fn diff_0() -> anyhow::Result<()> {
+
+        let manifest = ManifestBuilder::new(Manifest::new())
+            .file("felt")
+            .start_dir("dango")
+                .file("fetch")
+            .end_dir()
+            .start_dir("dog")
+                .file("fault")
+                .start_dir("deal")
+                .end_dir()
+            .end_dir()
+            .get_manifest();
+
+        let other = ManifestBuilder::new(manifest.clone())
+            .cd_dir("dango")
+                .file("voice")
+            .end_dir()
+            .get_manifest();
+
+        let diff = diff_manifests(&other, &manifest);
+
+        print!("{}", diff);
+        assert_eq!(diff.extra_dirs_in_a, 0);
+        assert_eq!(diff.extra_files_in_a, 1);
+        assert_eq!(diff.top_extra_ids_in_a.len(), 1);
+        assert_eq!(diff.paths_of_top_extra_in_a, vec![PathBuf::from("dango/voice")]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn diff_1() -> anyhow::Result<()> {
+
+        let manifest = ManifestBuilder::new(Manifest::new())
+            .file("felt")
+            .start_dir("dango")
+                .file("fetch")
+            .end_dir()
+            .start_dir("dog")
+                .file("fault")
+                .start_dir("deal")
+                .end_dir()
+            .end_dir()
+            .get_manifest();
+
+        let other = ManifestBuilder::new(manifest.clone())
+            .cd_dir("dango")
+                .start_dir("cab")
+                    .start_dir("choco")
+                        .file("vault")
+                    .end_dir()
+                .end_dir()
+            .end_dir()
+            .get_manifest();
+
+        let diff = diff_manifests(&other, &manifest);
+
+        print!("{}", diff);
+        assert_eq!(diff.extra_dirs_in_a, 2);
+        assert_eq!(diff.extra_files_in_a, 1);
+        assert_eq!(diff.top_extra_ids_in_a.len(), 1);
+        assert_eq!(diff.paths_of_top_extra_in_a, vec![PathBuf::from("dango/cab")]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn merge_disjoint() -> anyhow::Result<()> {
+        let mut manifest = ManifestBuilder::new(Manifest::new())
+            .file("felt")
+            .start_dir("dango")
+                .file("fetch")
+            .end_dir()
+            .get_manifest();
+
+        let other = ManifestBuilder::new(Manifest::new())
+            .file("felt")
+            .start_dir("dango")
+                .file("voice")
+            .end_dir()
+            .start_dir("dog")
+                .start_dir("deal")
+                    .file("fault")
+                .end_dir()
+            .end_dir()
+            .get_manifest();
+
+        let report = manifest.merge(&other)?;
+
+        assert!(report.conflicts.is_empty());
+        assert_eq!(report.added_files, 2);
+        assert_eq!(report.added_dirs, 2);
+        manifest.join_and_get_entry_id(manifest.root, Path::new("dango/fetch"))?;
+        manifest.join_and_get_entry_id(manifest.root, Path::new("dango/voice"))?;
+        manifest.join_and_get_entry_id(manifest.root, Path::new("dog/deal/fault"))?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn merge_conflicts() -> anyhow::Result<()> {
+        let mut manifest = ManifestBuilder::new(Manifest::new())
+            .file("felt")
+            .file("dango")
+            .get_manifest();
+
+        let mut other = ManifestBuilder::new(Manifest::new())
+            .start_dir("dango")
+            .end_dir()
+            .get_manifest();
+        other.add_file("felt", BlobKey::default(), 42, other.root)?;
+        let felt = other.join_and_get_entry_id(other.root, Path::new("felt"))?;
+        if let Entry::File(file) = &mut other.entries[felt.to_usize()] {
+            file.blob_key = dummy_blob_key();
+        }
+
+        let report = manifest.merge(&other)?;
+
+        assert_eq!(report.added_files, 0);
+        assert_eq!(report.conflicts.len(), 2);
+        let conflict_felt = report.conflicts.iter().find(|c| c.path == Path::new("felt")).unwrap();
+        assert_eq!(conflict_felt.kind, MergeConflictKind::DifferentBlobKey);
+        let conflict_dango = report.conflicts.iter().find(|c| c.path == Path::new("dango")).unwrap();
+        assert_eq!(conflict_dango.kind, MergeConflictKind::DifferentEntryType);
+
+        Ok(())
+    }
+
+    #[test]
+    fn stats() -> anyhow::Result<()> {
+        let manifest = ManifestBuilder::new(Manifest::new())
+            .file("felt")
+            .start_dir("dango")
+            .end_dir()
+            .start_dir("dog")
+                .file("fault")
+                .start_dir("deal")
+                    .file("fetch")
+                .end_dir()
+            .end_dir()
+            .get_manifest();
+
+        let stats = manifest.get_stats();
+        assert_eq!(stats.num_files, 3);
+        assert_eq!(stats.num_dirs, 4);
+        assert_eq!(stats.total_size, 3 * 42);
+        assert_eq!(stats.max_depth, 3);
+        assert_eq!(stats.deepest_path, PathBuf::from("dog/deal/fetch"));
+        assert_eq!(stats.largest_files.len(), 3);
+        assert_eq!(stats.top_level_dirs["dog"], DirTotals { num_dirs: 1, num_files: 2, size: 2 * 42 });
+        assert_eq!(stats.top_level_dirs["dango"], DirTotals::default());
+        assert!(!stats.top_level_dirs.contains_key("felt"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn diff_files_0() -> anyhow::Result<()> {
+        let old = ManifestBuilder::new(Manifest::new())
+            .file("felt")
+            .file("fetch")
+            .start_dir("dog")
+                .file("fault")
+            .end_dir()
+            .get_manifest();
+
+        let mut new = ManifestBuilder::new(Manifest::new())
+            .file("felt")
+            .file("fetch")
+            .start_dir("dog")
+                .file("deal")
+            .end_dir()
+            .get_manifest();
+        let fetch = new.join_and_get_entry_id(new.root, Path::new("fetch"))?;
+        if let Entry::File(file) = &mut new.entries[fetch.to_usize()] {
+            file.blob_key = dummy_blob_key();
+        }
+
+        let changes = diff_files(&new, &old);
+        assert_eq!(changes.added, vec![PathBuf::from("dog/deal")]);
+        assert_eq!(changes.changed, vec![PathBuf::from("fetch")]);
+        assert_eq!(changes.deleted, vec![PathBuf::from("dog/fault")]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn names_compared_in_nfc() -> anyhow::Result<()> {
+        let (nfc, nfd) = ("caf\u{e9}", "cafe\u{301}");
+        // pushed from macOS
+        let mut remote = ManifestBuilder::new(Manifest::new())
+            .start_dir(nfd)
+                .file(nfd)
+            .end_dir()
+            .get_manifest();
+        let local = ManifestBuilder::new(Manifest::new())
+            .start_dir(nfc)
+                .file(nfc)
+                .file("new")
+            .end_dir()
+            .get_manifest();
+
+        let diff = diff_manifests(&local, &remote);
+        assert_eq!(diff.paths_of_top_extra_in_a, vec![Path::new(nfc).join("new")]);
+        assert_eq!(diff_manifests(&remote, &local).extra_files_in_a, 0);
+        let changes = diff_files(&local, &remote);
+        assert_eq!((changes.added.len(), changes.deleted.len()), (1, 0));
+
+        // the names pushed first are kept as they are
+        assert!(remote.merge(&local)?.conflicts.is_empty());
+        let nfd_path = Path::new(nfd).join(nfd);
+        assert_eq!(remote.list_files().iter().map(|(path, _, _)| path.clone()).collect::<Vec<_>>(), vec![nfd_path, Path::new(nfd).join("new")]);
+
+        // manifests from before the policy compare names exactly
+        remote.set_name_normalization(NameNormalization::Exact);
+        let remote = Manifest::from_bytes(remote.to_bytes()?)?;
+        assert_eq!(remote.name_normalization(), NameNormalization::Exact);
+        assert_eq!(diff_manifests(&local, &remote).paths_of_top_extra_in_a, vec![PathBuf::from(nfc)]);
+        Ok(())
+    }
+
+    #[test]
+    fn write_tree_sorted() -> anyhow::Result<()> {
+        let manifest = ManifestBuilder::new(Manifest::new())
+            .file("felt")
+            .start_dir("dog")
+                .file("fault")
+                .file("deal")
+                .file("aaa")
+            .end_dir()
+            .file("cab")
+            .get_manifest();
+
+        let format = TreeFormat { show_size: false, show_hash: false, max_depth: None };
+        let mut out = Vec::new();
+        write_tree(&manifest, &format, &mut out)?;
+        assert_eq!(String::from_utf8(out)?, "ROOT/\n  cab\n  dog/\n    aaa\n    deal\n    fault\n  felt\n");
+
+        let format = TreeFormat { show_size: false, show_hash: false, max_depth: Some(1) };
+        let mut out = Vec::new();
+        write_tree(&manifest, &format, &mut out)?;
+        assert_eq!(String::from_utf8(out)?, "ROOT/\n  cab\n  dog/\n  felt\n");
+
+        let format = TreeFormat { show_size: true, show_hash: true, max_depth: Some(1) };
+        let mut out = Vec::new();
+        write_tree(&manifest, &format, &mut out)?;
+        let out = String::from_utf8(out)?;
+        assert!(out.contains(&format!("{:>14} {} {}", 42, BlobKey::default().to_string(), "  cab")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn write_listing_of_a_dir() -> anyhow::Result<()> {
+        let manifest = ManifestBuilder::new(Manifest::new())
+            .file("felt")
+            .start_dir("dog")
+                .file("fault")
+                .start_dir("bone")
+                    .file("aaa")
+                .end_dir()
+            .end_dir()
+            .get_manifest();
+
+        let listing = |path: &str, long: bool| -> anyhow::Result<String> {
+            let mut out = Vec::new();
+            write_listing(&manifest, Path::new(path), long, &mut out)?;
+            Ok(String::from_utf8(out)?)
+        };
+        assert_eq!(listing("", false)?, "dog/\nfelt\n");
+        assert_eq!(listing("dog", false)?, "bone/\nfault\n");
+        assert_eq!(listing("dog/fault", false)?, "fault\n");
+        let long = listing("dog", true)?;
+        assert_eq!(long.lines().next().unwrap(), format!("d {:>14} {:64} bone/", 42, ""));
+        assert_eq!(long.lines().nth(1).unwrap(), format!("- {:>14} {} fault", 42, BlobKey::default().to_string()));
+        assert!(listing("cat", false).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn full_listing_formats() -> anyhow::Result<()> {
+        let mut manifest = ManifestBuilder::new(Manifest::new())
+            .start_dir("dog")
+                .file("fault")
+                .file("bone, #1")
+            .end_dir()
+            .file("felt")
+            .get_manifest();
+        let fault = manifest.join_and_get_entry_id(manifest.root, Path::new("dog/fault"))?;
+        manifest.set_times(fault, Some(EntryTimes::new(1700000000)));
+
+        let listing = |path: &str, format: ListingFormat| -> anyhow::Result<String> {
+            let mut out = Vec::new();
+            write_full_listing(&manifest, Path::new(path), format, &mut out)?;
+            Ok(String::from_utf8(out)?)
+        };
+        let key = BlobKey::default().to_string();
+        assert_eq!(listing("dog", ListingFormat::Mtree)?, format!("#mtree\n\
+            ./dog type=dir mode=0755 time=0.0\n\
+            ./dog/bone,\\040\\0431 type=file mode=0644 size=42 time=0.0 harkey={key}\n\
+            ./dog/fault type=file mode=0644 size=42 time=1700000000.0 harkey={key}\n"));
+        assert_eq!(listing("", ListingFormat::Csv)?, format!("path,size,hash,mode,mtime\n\
+            \"dog/bone, #1\",42,{key},0644,0\n\
+            dog/fault,42,{key},0644,1700000000\n\
+            felt,42,{key},0644,0\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn duplicates() -> anyhow::Result<()> {
+        let mut manifest = ManifestBuilder::new(Manifest::new())
+            .file("felt")
+            .file("fault")
+            .start_dir("dog")
+            .end_dir()
+            .get_manifest();
+        let dog = manifest.join_and_get_entry_id(manifest.root, Path::new("dog"))?;
+        let root = manifest.root;
+        add_dummy_file(&mut manifest, root)?;
+        add_dummy_file(&mut manifest, dog)?;
+
+        let groups = manifest.duplicates();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].paths, vec![PathBuf::from("dog/imafile"), PathBuf::from("imafile")]);
+        assert_eq!(groups[0].wasted_size(), 42);
+
+        Ok(())
+    }
+
+    #[test]
+    fn subtree() -> anyhow::Result<()> {
+        let manifest = ManifestBuilder::new(Manifest::new())
+            .file("felt")
+            .start_dir("dog")
+                .file("fault")
+                .start_dir("deal")
+                    .file("fetch")
+                .end_dir()
+            .end_dir()
+            .get_manifest();
+
+        let subtree = manifest.subtree(Path::new("dog"))?;
+        assert_eq!(subtree.get_stats().num_files, 2);
+        subtree.join_and_get_entry_id(subtree.root, Path::new("fault"))?;
+        subtree.join_and_get_entry_id(subtree.root, Path::new("deal/fetch"))?;
+        assert!(subtree.join_and_get_entry_id(subtree.root, Path::new("felt")).is_err());
+
+        assert!(manifest.subtree(Path::new("felt")).is_err());
+        assert!(manifest.subtree(Path::new("nope")).is_err());
+
+        let scoped_paths = |path| -> anyhow::Result<Vec<PathBuf>> {
+            Ok(manifest.scoped(Path::new(path))?.list_files().into_iter().map(|(path, _, _)| path).collect())
+        };
+        assert_eq!(scoped_paths("dog/deal")?, vec![PathBuf::from("dog/deal/fetch")]);
+        assert_eq!(scoped_paths("./dog/fault")?, vec![PathBuf::from("dog/fault")]);
+        assert_eq!(scoped_paths("")?.len(), 3);
+        assert!(manifest.scoped(Path::new("dog/nope")).is_err());
+        let together = manifest.scoped_to_paths(&[PathBuf::from("dog/deal/fetch"), PathBuf::from("felt"), PathBuf::from("dog/nope")])?;
+        let mut paths: Vec<PathBuf> = together.list_files().into_iter().map(|(path, _, _)| path).collect();
+        paths.sort();
+        assert_eq!(paths, vec![PathBuf::from("dog/deal/fetch"), PathBuf::from("felt")]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn remove_entries() -> anyhow::Result<()> {
+        let mut manifest = ManifestBuilder::new(Manifest::new())
+            .file("felt")
+            .start_dir("dog")
+                .file("fault")
+                .start_dir("deal")
+                    .file("fetch")
+                .end_dir()
+            .end_dir()
+            .get_manifest();
+
+        let removed = manifest.remove(Path::new("dog/deal"))?;
+        assert_eq!(removed.list_files().into_iter().map(|(path, _, _)| path).collect::<Vec<_>>(), vec![PathBuf::from("dog/deal/fetch")]);
+        assert_eq!(manifest.list_files().into_iter().map(|(path, _, _)| path).collect::<Vec<_>>(),
+            vec![PathBuf::from("dog/fault"), PathBuf::from("felt")]);
+        // nothing left unlisted
+        assert_eq!(manifest.entries.len(), 4);
+
+        manifest.remove(Path::new("felt"))?;
+        assert_eq!(manifest.list_dirs(), vec![PathBuf::from("dog")]);
+        assert!(manifest.remove(Path::new("felt")).is_err());
+        assert!(manifest.remove(Path::new("")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn rename_entries() -> anyhow::Result<()> {
+        let mut manifest = ManifestBuilder::new(Manifest::new())
+            .file("felt")
+            .start_dir("dog")
+                .file("fault")
+            .end_dir()
+            .get_manifest();
+        let paths = |manifest: &Manifest| -> Vec<PathBuf> { manifest.list_files().into_iter().map(|(path, _, _)| path).collect() };
+
+        assert_eq!(manifest.rename(Path::new("felt"), Path::new("dog"))?, PathBuf::from("dog/felt"));
+        assert_eq!(manifest.rename(Path::new("dog"), Path::new("pets/dogs"))?, PathBuf::from("pets/dogs"));
+        assert_eq!(paths(&manifest), vec![PathBuf::from("pets/dogs/fault"), PathBuf::from("pets/dogs/felt")]);
+        assert_eq!(manifest.rename(Path::new("pets/dogs/felt"), Path::new(""))?, PathBuf::from("felt"));
+        assert_eq!(manifest.entries.len(), 5);
+
+        assert!(manifest.rename(Path::new("pets"), Path::new("pets/dogs")).is_err());
+        assert!(manifest.rename(Path::new("pets/dogs/fault"), Path::new("felt")).is_err());
+        assert!(manifest.rename(Path::new("felt"), Path::new("felt/inside")).is_err());
+        assert!(manifest.rename(Path::new("nope"), Path::new("felt2")).is_err());
+        assert_eq!(paths(&manifest), vec![PathBuf::from("felt"), PathBuf::from("pets/dogs/fault")]);
+        Ok(())
+    }
+
+    #[test]
+    fn walk_in_name_order() {
+        let manifest = ManifestBuilder::new(Manifest::new())
+            .start_dir("dog")
+                .file("fault")
+            .end_dir()
+            .file("felt")
+            .start_dir("deal")
+            .end_dir()
+            .get_manifest();
+        let walked: Vec<(String, bool)> = manifest.walk()
+            .map(|entry| (entry.path.to_str().unwrap().to_string(), matches!(entry.kind, WalkKind::File { .. })))
+            .collect();
+        assert_eq!(walked, vec![("deal".to_string(), false), ("dog".to_string(), false), ("dog/fault".to_string(), true), ("felt".to_string(), true)]);
+        assert_eq!(Manifest::new().walk().count(), 0);
+    }
+
+    #[test]
+    fn disk_usage_of_dirs() -> anyhow::Result<()> {
+        let mut manifest = Manifest::new();
+        let root = manifest.root;
+        let key = |byte: u8| BlobKey { key: blake3::Hash::from_bytes([byte; 32]) };
+        let photos = manifest.add_dir("photos", root)?;
+        manifest.add_file("cat", key(1), 100, photos)?;
+        let year = manifest.add_dir("2024", photos)?;
+        manifest.add_file("cat copy", key(1), 100, year)?;
+        manifest.add_file("dog", key(2), 10, year)?;
+        manifest.add_dir("empty", year)?;
+        manifest.add_file("felt", key(2), 10, root)?;
+
+        let usage = |path: &str, depth| -> anyhow::Result<Vec<(String, usize, u64, u64)>> {
+            Ok(manifest.disk_usage(Path::new(path), depth)?.into_iter()
+                .map(|usage| (usage.path.to_str().unwrap().to_string(), usage.num_files, usage.size, usage.unique_size))
+                .collect())
+        };
+        assert_eq!(usage("", 0)?, vec![("".to_string(), 4, 220, 110)]);
+        assert_eq!(usage("", 1)?, vec![("".to_string(), 4, 220, 110), ("photos".to_string(), 3, 210, 110)]);
+        assert_eq!(usage("photos", 5)?, vec![
+            ("photos".to_string(), 3, 210, 110),
+            ("photos/2024".to_string(), 2, 110, 110),
+            ("photos/2024/empty".to_string(), 0, 0, 0),
+        ]);
+        assert!(manifest.disk_usage(Path::new("felt"), 1).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn get_child_recurs() -> anyhow::Result<()> {
+        let manifest = ManifestBuilder::new(Manifest::new())
+            .file("felt")
+            .start_dir("dango")
+                .file("fetch")
+            .end_dir()
+            .start_dir("dog")
+                .file("fault")
+                .start_dir("deal")
+                .end_dir()
+            .end_dir()
+            .get_manifest();
+
+        let dango = manifest.join_and_get_entry_id(manifest.root, Path::new("dango"))?;
+        let fetch = manifest.join_and_get_entry_id(dango, Path::new("fetch"))?;
+        assert_eq!(manifest.get_child_files_recurs(dango), vec![fetch]);
+        assert_eq!(manifest.get_child_files_recurs(fetch), vec![fetch]);
+
+        let felt = manifest.join_and_get_entry_id(manifest.root, Path::new("felt"))?;
+        let fault = manifest.join_and_get_entry_id(manifest.root, Path::new("dog/fault"))?;
+        let child_recurs = manifest.get_child_files_recurs(manifest.root);
+        assert!(child_recurs.contains(&felt));
+        assert!(child_recurs.contains(&fault));
+        assert!(child_recurs.contains(&fetch));
+
+        Ok(())
+    }
+
+    // benchmark, run with: cargo test --release -- --ignored --nocapture million_children
+    #[test]
+    #[ignore]
+    fn million_children() -> anyhow::Result<()> {
+        use std::time::Instant;
+        const NUM_CHILDREN: usize = 1_000_000;
+        // shuffled names, lookups don't go in storage order
+        let names: Vec<String> = (0..NUM_CHILDREN).map(|i| format!("file_{:x}", i.wrapping_mul(2654435761) % NUM_CHILDREN)).collect();
+
+        let start = Instant::now();
+        let mut manifest = Manifest::new();
+        let dir = manifest.add_dir("imadir", manifest.root)?;
+        let mut sorted_names = names.clone();
+        sorted_names.sort();
+        sorted_names.dedup();
+        for name in &sorted_names {
+            manifest.add_file(name, BlobKey::default(), 42, dir)?;
+        }
+        println!("build {} children: {:?}", sorted_names.len(), start.elapsed());
+
+        let start = Instant::now();
+        let bytes = manifest.to_bytes()?;
+        println!("to_bytes ({} bytes): {:?}", bytes.len(), start.elapsed());
+
+        let start = Instant::now();
+        let manifest = Manifest::from_bytes(bytes)?;
+        println!("from_bytes: {:?}", start.elapsed());
+
+        let start = Instant::now();
+        for name in &names {
+            manifest.join_and_get_entry_id(dir, Path::new(name))?;
+        }
+        println!("{} lookups: {:?}", names.len(), start.elapsed());
+        Ok(())
+    }
+
+    // resident memory of the process in kB, linux only
+    fn resident_kb() -> Option<u64> {
+        let status = std::fs::read_to_string("/proc/self/status").ok()?;
+        let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+        line.split_whitespace().nth(1)?.parse().ok()
+    }
+
+    // benchmark, run with: cargo test --release -- --ignored --nocapture million_entries_memory
+    // names repeat across directories, as in real trees (src, README.md, ...)
+    #[test]
+    #[ignore]
+    fn million_entries_memory() -> anyhow::Result<()> {
+        const NUM_DIRS: usize = 1000;
+        const FILES_PER_DIR: usize = 1000;
+
+        let before = resident_kb();
+        let mut manifest = Manifest::new();
+        for dir_index in 0..NUM_DIRS {
+            let dir = manifest.add_dir(&format!("dir_{}", dir_index), manifest.root)?;
+            for file_index in 0..FILES_PER_DIR {
+                manifest.add_file(&format!("file_{}.txt", file_index), BlobKey::default(), 42, dir)?;
+            }
+        }
+        let after_build = resident_kb();
+        let bytes = manifest.to_bytes()?;
+        drop(manifest);
+        let manifest = Manifest::from_bytes(bytes)?;
+        let after_decode = resident_kb();
+
+        println!("{} entries", manifest.entries.len());
+        if let (Some(before), Some(after_build), Some(after_decode)) = (before, after_build, after_decode) {
+            println!("resident memory after build: +{} kB, after decode: +{} kB", after_build - before, after_decode - before);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn user_paths() -> anyhow::Result<()> {
+        let manifest = ManifestBuilder::new(Manifest::new())
+            .start_dir("docs")
+                .file("file")
+                .file("notes.txt")
+            .end_dir()
+            .get_manifest();
+
+        let file = manifest.join_and_get_entry_id(manifest.root, Path::new("docs/file"))?;
+        assert_eq!(manifest.join_and_get_entry_id(manifest.root, Path::new("./docs//file"))?, file);
+        let docs = manifest.join_and_get_entry_id(manifest.root, Path::new("docs"))?;
+        assert_eq!(manifest.join_and_get_entry_id(manifest.root, Path::new("./docs/"))?, docs);
+        assert_eq!(manifest.join_and_get_entry_id(manifest.root, Path::new("."))?, manifest.root);
+        assert!(manifest.join_and_get_entry_id(manifest.root, Path::new("docs/../docs")).is_err());
+
+        let err = manifest.join_and_get_entry_id(manifest.root, Path::new("Docs/note.txt")).unwrap_err();
+        assert!(err.to_string().contains("did you mean docs?"), "{}", err);
+        let err = manifest.join_and_get_entry_id(manifest.root, Path::new("docs/note.txt")).unwrap_err();
+        assert!(err.to_string().contains("did you mean notes.txt?"), "{}", err);
+        let err = manifest.join_and_get_entry_id(manifest.root, Path::new("nothing_like_it")).unwrap_err();
+        assert!(!err.to_string().contains("did you mean"), "{}", err);
+        Ok(())
+    }
+}
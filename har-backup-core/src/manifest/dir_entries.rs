@@ -0,0 +1,65 @@
+use super::EntryId;
+use super::names::NameId;
+
+// children of a directory, sorted by name id for binary search
+// a HashMap per directory was too heavy for directories with hundreds of thousands of children
+#[derive(Debug, Clone, Default)]
+pub(super) struct DirEntries {
+    children: Vec<(NameId, EntryId)>,
+}
+
+impl DirEntries {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn position(&self, name: NameId) -> Result<usize, usize> {
+        self.children.binary_search_by_key(&name, |&(child_name, _)| child_name)
+    }
+
+    pub fn get(&self, name: NameId) -> Option<EntryId> {
+        self.position(name).ok().map(|index| self.children[index].1)
+    }
+
+    // cheap when names come in order (new names get increasing ids), a memmove otherwise
+    pub fn insert(&mut self, name: NameId, entry_id: EntryId) -> Option<EntryId> {
+        match self.position(name) {
+            Ok(index) => Some(std::mem::replace(&mut self.children[index].1, entry_id)),
+            Err(index) => {
+                self.children.insert(index, (name, entry_id));
+                None
+            },
+        }
+    }
+
+    pub fn remove(&mut self, name: NameId) -> Option<EntryId> {
+        self.position(name).ok().map(|index| self.children.remove(index).1)
+    }
+
+    // in name id order, not alphabetical
+    pub fn iter(&self) -> std::iter::Cloned<std::slice::Iter<'_, (NameId, EntryId)>> {
+        self.children.iter().cloned()
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &EntryId> + Clone {
+        self.children.iter().map(|(_, entry_id)| entry_id)
+    }
+
+    pub fn from_unsorted(mut children: Vec<(NameId, EntryId)>) -> Self {
+        children.sort_by_key(|&(name, _)| name);
+        // a map would keep the last one
+        children.reverse();
+        children.dedup_by_key(|&mut (name, _)| name);
+        children.reverse();
+        Self { children }
+    }
+}
+
+impl<'a> IntoIterator for &'a DirEntries {
+    type Item = (NameId, EntryId);
+    type IntoIter = std::iter::Cloned<std::slice::Iter<'a, (NameId, EntryId)>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
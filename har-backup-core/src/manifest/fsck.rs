@@ -0,0 +1,283 @@
+// consistency of a manifest that decodes fine but is not what push writes, after a bug or an edit by hand
+//
+// every entry is reached once from the root, under its own name, and names of a directory are distinct under the
+// name normalization of the manifest. An entry listed twice (by two directories, or by a directory inside it) is
+// only taken where it is reached first. Exact duplicates of a name can't be decoded, the one dropped is an orphan.
+
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use super::{BlobKey, Entry, EntryId, Manifest, LOST_AND_FOUND};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FsckIssue {
+    RootNotDirectory, // everything is an orphan then
+    DanglingChild(PathBuf), // listed with an id past the entries, dropped by repair
+    NameMismatch(PathBuf, String), // listed under another name than its own (the second one), repair keeps the listed one
+    SharedEntry(PathBuf, PathBuf), // listed again at the first path, where it was reached first, dropped there by repair
+    DuplicateName(PathBuf, PathBuf), // same name as the second path once normalized, repair moves it to LOST_AND_FOUND
+    NoBlobKey(PathBuf), // a file without content in the remote, dropped by repair
+    Orphan(PathBuf), // listed by no directory reached from the root, where repair moves it in LOST_AND_FOUND
+}
+
+#[derive(Debug, Default)]
+pub struct FsckReport {
+    pub num_entries: usize,
+    pub issues: Vec<FsckIssue>,
+}
+
+impl FsckReport {
+    pub fn is_clean(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl Manifest {
+    pub fn fsck(&self) -> FsckReport {
+        Fsck::new(self, None).run()
+    }
+
+    // a copy with what fsck finds fixed, see FsckIssue for how
+    pub fn repaired(&self) -> anyhow::Result<(Manifest, FsckReport)> {
+        let mut repaired = Manifest::new();
+        repaired.name_normalization = self.name_normalization;
+        let report = Fsck::new(self, Some(&mut repaired)).run_repairing()?;
+        Ok((repaired, report))
+    }
+}
+
+struct Fsck<'a> {
+    manifest: &'a Manifest,
+    repaired: Option<&'a mut Manifest>,
+    reached: HashMap<usize, PathBuf>, // entry index to the path it was first reached at
+    lost_and_found: Option<EntryId>, // in repaired
+    listed_by_own_name: HashSet<usize>, // entry indexes
+    deferred: Vec<(&'a str, usize, Option<EntryId>, PathBuf)>, // see walk
+    report: FsckReport,
+}
+
+impl<'a> Fsck<'a> {
+    fn new(manifest: &'a Manifest, repaired: Option<&'a mut Manifest>) -> Self {
+        let report = FsckReport { num_entries: manifest.entries.len(), ..Default::default() };
+        let listed_by_own_name = manifest.entries.iter()
+            .filter_map(|entry| if let Entry::Directory(dir) = entry { Some(dir) } else { None })
+            .flat_map(|dir| dir.entries.iter())
+            .filter(|(name, entry_id)| manifest.entries.get(entry_id.to_usize()).is_some_and(|entry| entry.name() == *name))
+            .map(|(_, entry_id)| entry_id.to_usize())
+            .collect();
+        Self { manifest, repaired, reached: HashMap::new(), lost_and_found: None, listed_by_own_name, deferred: Vec::new(), report }
+    }
+
+    fn run(self) -> FsckReport {
+        // only repairing adds entries, which is what fails
+        self.run_repairing().unwrap()
+    }
+
+    fn run_repairing(mut self) -> anyhow::Result<FsckReport> {
+        let manifest = self.manifest;
+        let root = manifest.root.to_usize();
+        match manifest.entries.get(root) {
+            Some(Entry::Directory(_)) => {
+                self.reached.insert(root, PathBuf::new());
+                let new_root = self.repaired.as_ref().map(|repaired| repaired.root);
+                self.walk(root, new_root, PathBuf::new())?;
+                // what is not reached under its own name is taken under the other one
+                while let Some((name, child, new_dir, child_path)) = self.deferred.pop() {
+                    if let Some((index, new_dir, path)) = self.visit(name, child, new_dir, child_path, &mut HashMap::new())? {
+                        self.walk(index, new_dir, path)?;
+                    }
+                }
+            },
+            _ => self.report.issues.push(FsckIssue::RootNotDirectory),
+        }
+
+        // top level orphans first so that their children are found in them, then what is only listed by orphans
+        // in a cycle
+        let listed: HashSet<usize> = manifest.entries.iter()
+            .filter_map(|entry| if let Entry::Directory(dir) = entry { Some(dir) } else { None })
+            .flat_map(|dir| dir.entries.values().map(|entry_id| entry_id.to_usize()))
+            .collect();
+        for top_level_only in [true, false] {
+            for index in 0..manifest.entries.len() {
+                if self.reached.contains_key(&index) || (top_level_only && listed.contains(&index)) {
+                    continue;
+                }
+                let name = format!("{}_{}", index, manifest.name(&manifest.entries[index]));
+                let path = PathBuf::from(LOST_AND_FOUND).join(&name);
+                self.report.issues.push(FsckIssue::Orphan(path.clone()));
+                let lost_and_found = self.lost_and_found()?;
+                if let Some((index, new_dir, path)) = self.copy(index, &name, lost_and_found, path)? {
+                    self.walk(index, new_dir, path)?;
+                }
+            }
+        }
+        Ok(self.report)
+    }
+
+    // the children of the directory at index, copied in new_dir when repairing
+    fn walk(&mut self, index: usize, new_dir: Option<EntryId>, path: PathBuf) -> anyhow::Result<()> {
+        let manifest = self.manifest;
+        let mut to_visit = vec![(index, new_dir, path)];
+        while let Some((index, new_dir, dir_path)) = to_visit.pop() {
+            let Entry::Directory(dir) = &manifest.entries[index] else {
+                continue;
+            };
+            let mut children: Vec<(&str, EntryId)> = dir.entries.iter().map(|(name, entry_id)| (manifest.names.get(name), entry_id)).collect();
+            children.sort_by_key(|&(name, _)| name);
+            let mut compared_names: HashMap<String, PathBuf> = HashMap::new();
+            for (name, entry_id) in children {
+                let child = entry_id.to_usize();
+                let own_name = manifest.entries.get(child).map(|entry| manifest.name(entry));
+                // listed elsewhere under its own name, which is where it belongs if it is reached there
+                if own_name.is_some_and(|own_name| own_name != name) && self.listed_by_own_name.contains(&child) {
+                    self.deferred.push((name, child, new_dir, dir_path.join(name)));
+                    continue;
+                }
+                if let Some(to_walk) = self.visit(name, child, new_dir, dir_path.join(name), &mut compared_names)? {
+                    to_visit.push(to_walk);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // the child of new_dir listed as name, what to walk if it is a directory
+    fn visit(&mut self, name: &str, child: usize, new_dir: Option<EntryId>, child_path: PathBuf, compared_names: &mut HashMap<String, PathBuf>)
+            -> anyhow::Result<Option<(usize, Option<EntryId>, PathBuf)>> {
+        let manifest = self.manifest;
+        if child >= manifest.entries.len() {
+            self.report.issues.push(FsckIssue::DanglingChild(child_path));
+            return Ok(None);
+        }
+        if let Some(first_path) = self.reached.get(&child) {
+            self.report.issues.push(FsckIssue::SharedEntry(child_path, first_path.clone()));
+            return Ok(None);
+        }
+        let own_name = manifest.name(&manifest.entries[child]);
+        if own_name != name {
+            self.report.issues.push(FsckIssue::NameMismatch(child_path.clone(), own_name.to_string()));
+        }
+        let compared_name = manifest.name_normalization.compared_form(name).into_owned();
+        if let Some(other_path) = compared_names.get(&compared_name) {
+            self.report.issues.push(FsckIssue::DuplicateName(child_path.clone(), other_path.clone()));
+            let lost_and_found = self.lost_and_found()?;
+            return self.copy(child, &format!("{}_{}", child, name), lost_and_found, child_path);
+        }
+        compared_names.insert(compared_name, child_path.clone());
+        self.copy(child, name, new_dir, child_path)
+    }
+
+    // the entry at index reached at path, copied in new_parent when repairing, what to walk if it is a directory
+    fn copy(&mut self, index: usize, name: &str, new_parent: Option<EntryId>, path: PathBuf) -> anyhow::Result<Option<(usize, Option<EntryId>, PathBuf)>> {
+        self.reached.insert(index, path.clone());
+        let entry = &self.manifest.entries[index];
+        let new_entry = match entry {
+            Entry::File(file) if file.blob_key == BlobKey::default() && file.chunks.is_empty() => {
+                self.report.issues.push(FsckIssue::NoBlobKey(path));
+                return Ok(None);
+            },
+            Entry::File(file) => match (self.repaired.as_deref_mut(), new_parent) {
+                (Some(repaired), Some(new_parent)) => Some(repaired.add_stored_file(name, file.blob_key.clone(), file.size, file.ciphertext_size, file.chunks.clone(), new_parent)?),
+                _ => None,
+            },
+            Entry::Directory(_) => match (self.repaired.as_deref_mut(), new_parent) {
+                (Some(repaired), Some(new_parent)) => Some(repaired.add_dir(name, new_parent)?),
+                _ => None,
+            },
+        };
+        if let (Some(repaired), Some(new_entry)) = (self.repaired.as_deref_mut(), new_entry) {
+            repaired.set_times(new_entry, entry.times());
+        }
+        Ok(matches!(entry, Entry::Directory(_)).then_some((index, new_entry, path)))
+    }
+
+    // created in the root of repaired the first time, None when not repairing
+    fn lost_and_found(&mut self) -> anyhow::Result<Option<EntryId>> {
+        let Some(repaired) = self.repaired.as_deref_mut() else {
+            return Ok(None);
+        };
+        if self.lost_and_found.is_none() {
+            let root = repaired.root;
+            let existing = repaired.get_child(repaired.get_entry(root).try_directory_ref()?, LOST_AND_FOUND)
+                .filter(|&entry_id| matches!(repaired.get_entry(entry_id), Entry::Directory(_)));
+            self.lost_and_found = Some(match existing {
+                Some(entry_id) => entry_id,
+                None => repaired.add_dir(LOST_AND_FOUND, root)?,
+            });
+        }
+        Ok(self.lost_and_found)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn key(name: &str) -> BlobKey {
+        BlobKey::try_from(crate::blob_storage::get_hash_name("bucket", bytes::Bytes::from(name.to_string())).as_str()).unwrap()
+    }
+
+    fn list(manifest: &mut Manifest, dir: EntryId, name: &str, entry_id: EntryId) {
+        let name = manifest.names.intern(name);
+        manifest.entries[dir.to_usize()].try_directory_ref_mut().unwrap().entries.insert(name, entry_id);
+    }
+
+    #[test]
+    fn finds_and_repairs() -> anyhow::Result<()> {
+        let mut manifest = Manifest::new();
+        let dog = manifest.add_dir("dog", manifest.root)?;
+        let felt = manifest.add_file("felt", key("felt"), 3, dog)?;
+        manifest.add_file("cafe\u{301}", key("cafe1"), 5, manifest.root)?;
+        manifest.add_file("caf\u{e9}", key("cafe2"), 5, manifest.root)?;
+        manifest.add_file("empty", BlobKey::default(), 0, dog)?;
+        assert!(manifest.fsck().issues.iter().all(|issue| matches!(issue, FsckIssue::DuplicateName(..) | FsckIssue::NoBlobKey(_))));
+
+        let root = manifest.root;
+        list(&mut manifest, root, "also_felt", felt); // listed twice
+        list(&mut manifest, dog, "loop", dog); // listed by itself
+        list(&mut manifest, dog, "gone", EntryId::from_usize(100));
+        let orphan = EntryId::from_usize(manifest.entries.len());
+        manifest.entries.push(Entry::File(super::super::File {
+            name: manifest.names.intern("orphan"),
+            blob_key: key("orphan"),
+            size: 6,
+            ciphertext_size: None,
+            chunks: Vec::new(),
+            times: None,
+        }));
+
+        let report = manifest.fsck();
+        let path = |path: &str| Path::new(path).to_path_buf();
+        assert_eq!(report.num_entries, 7);
+        assert_eq!(report.issues, vec![
+            FsckIssue::DuplicateName(path("caf\u{e9}"), path("cafe\u{301}")),
+            FsckIssue::NoBlobKey(path("dog/empty")),
+            FsckIssue::DanglingChild(path("dog/gone")),
+            FsckIssue::SharedEntry(path("dog/loop"), path("dog")),
+            FsckIssue::SharedEntry(path("also_felt"), path("dog/felt")),
+            FsckIssue::Orphan(path(&format!("{}/{}_orphan", LOST_AND_FOUND, orphan.to_usize()))),
+        ]);
+
+        let (repaired, repair_report) = manifest.repaired()?;
+        assert_eq!(repair_report.issues, report.issues);
+        assert!(repaired.fsck().is_clean());
+        let files: Vec<PathBuf> = repaired.list_files().into_iter().map(|(path, _, _)| path).collect();
+        assert_eq!(files, vec![
+            path("cafe\u{301}"),
+            path("dog/felt"),
+            path("lost+found/4_caf\u{e9}"),
+            path(&format!("lost+found/{}_orphan", orphan.to_usize())),
+        ]);
+
+        // listed under another name only, it keeps that one
+        let mut manifest = Manifest::new();
+        let root = manifest.root;
+        let felt = manifest.add_file("felt", key("felt"), 3, root)?;
+        manifest.entries[root.to_usize()] = Entry::Directory(super::super::Directory { name: manifest.names.intern("ROOT"), entries: Default::default(), times: None });
+        list(&mut manifest, root, "kek", felt);
+        let (repaired, report) = manifest.repaired()?;
+        assert_eq!(report.issues, vec![FsckIssue::NameMismatch(path("kek"), "felt".to_string())]);
+        assert_eq!(repaired.list_files()[0].0, path("kek"));
+        Ok(())
+    }
+}
@@ -0,0 +1,36 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+// id of an interned entry name, only meaningful with the Names it comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(super) struct NameId(u32);
+
+// every distinct entry name of a manifest, stored once
+// directories and entries refer to names by NameId
+#[derive(Clone, Default)]
+pub(super) struct Names {
+    names: Vec<Arc<str>>,
+    ids: HashMap<Arc<str>, NameId>,
+}
+
+impl Names {
+    pub fn intern(&mut self, name: &str) -> NameId {
+        if let Some(&id) = self.ids.get(name) {
+            return id;
+        }
+        let id = NameId(u32::try_from(self.names.len()).expect("Less than 2^32 distinct names"));
+        let name: Arc<str> = Arc::from(name);
+        self.names.push(name.clone());
+        self.ids.insert(name, id);
+        id
+    }
+
+    // None if no entry has this name
+    pub fn find(&self, name: &str) -> Option<NameId> {
+        self.ids.get(name).cloned()
+    }
+
+    pub fn get(&self, id: NameId) -> &str {
+        &self.names[id.0 as usize]
+    }
+}
@@ -0,0 +1,316 @@
+// salvage what can be salvaged from a damaged (msgpack) manifest
+//
+// entries are decoded one by one, when one does not decode we skip bytes until two consecutive
+// entries decode again. We do not know how many entries were lost in a damaged region, so the
+// index of the entries after it is chosen so that most directories find their children by name.
+// Entries whose parent was lost end up in LOST_AND_FOUND.
+
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::path::PathBuf;
+use anyhow::Context;
+use serde::Deserialize;
+use super::{EntryId, Manifest, NameNormalization};
+use super::repr::{DirectoryRepr, EntryRepr};
+
+// entries are decoded in their serialized form, names are only interned when rebuilding
+type Entry = EntryRepr<String>;
+type Directory = DirectoryRepr<String>;
+
+pub const LOST_AND_FOUND: &str = "lost+found";
+
+#[derive(Debug, Default)]
+pub struct RecoveryReport {
+    pub damaged_regions: Vec<Range<usize>>, // byte ranges of the input that were skipped
+    pub expected_entries: usize,
+    pub recovered_entries: usize,
+    pub lost_paths: Vec<PathBuf>, // listed by a recovered directory but not recovered
+    pub orphans: Vec<PathBuf>, // recovered but their parent was not, path in the recovered manifest
+}
+
+impl Manifest {
+    // like from_bytes but keeps going on damaged entries
+    pub fn from_bytes_lenient(bytes: &[u8]) -> anyhow::Result<(Self, RecoveryReport)> {
+        if let Ok(manifest) = rmp_serde::decode::from_slice::<Manifest>(bytes) {
+            let num_entries = manifest.entries.len();
+            let report = RecoveryReport { expected_entries: num_entries, recovered_entries: num_entries, ..Default::default() };
+            return Ok((manifest, report));
+        }
+
+        let mut rest = bytes;
+        // root, entries and the name normalization when recorded
+        let header_len = read_array_len(&mut rest);
+        if !matches!(header_len, Some(2 | 3)) {
+            anyhow::bail!("Manifest header is damaged");
+        }
+        let mut name_normalization = NameNormalization::default();
+        let root = EntryId::deserialize(&mut rmp_serde::Deserializer::new(&mut rest)).context("Manifest root is damaged")?;
+        let expected_entries = read_array_len(&mut rest).context("Manifest entry list header is damaged")?;
+
+        let mut report = RecoveryReport { expected_entries, ..Default::default() };
+        let mut segments: Vec<Segment> = vec![Segment::default()];
+        let mut num_decoded = 0;
+
+        while !rest.is_empty() && num_decoded < expected_entries {
+            // what is left after the entries when some of them were damaged
+            if let Some(trailing) = decode_name_normalization(rest).filter(|_| header_len == Some(3)) {
+                name_normalization = trailing;
+                rest = &[];
+                break;
+            }
+            let offset = bytes.len() - rest.len();
+            if let Some(entry) = decode_entry(&mut rest) {
+                segments.last_mut().unwrap().entries.push(entry);
+                num_decoded += 1;
+                continue;
+            }
+            let resync = (offset + 1..bytes.len()).find(|&candidate| is_resync_point(&bytes[candidate..], expected_entries));
+            let end = resync.unwrap_or(bytes.len());
+            report.damaged_regions.push(offset..end);
+            segments.push(Segment { damaged_bytes: end - offset, ..Default::default() });
+            rest = &bytes[end..];
+        }
+
+        if header_len == Some(3) && !rest.is_empty() {
+            name_normalization = decode_name_normalization(rest).unwrap_or_default();
+        }
+
+        let recovered = place_segments(segments, expected_entries);
+        report.recovered_entries = recovered.len();
+        let mut manifest = rebuild(root, &recovered, &mut report)?;
+        manifest.name_normalization = name_normalization;
+        Ok((manifest, report))
+    }
+}
+
+#[derive(Default)]
+struct Segment {
+    damaged_bytes: usize, // size of the damaged region before the segment
+    entries: Vec<Entry>,
+}
+
+fn read_array_len(rest: &mut &[u8]) -> Option<usize> {
+    let (&marker, tail) = rest.split_first()?;
+    let (len, tail) = match marker {
+        0x90..=0x9f => ((marker & 0x0f) as usize, tail),
+        0xdc => (u16::from_be_bytes(tail.get(..2)?.try_into().ok()?) as usize, &tail[2..]),
+        0xdd => (u32::from_be_bytes(tail.get(..4)?.try_into().ok()?) as usize, &tail[4..]),
+        _ => return None,
+    };
+    *rest = tail;
+    Some(len)
+}
+
+// advances rest only on success
+fn decode_entry(rest: &mut &[u8]) -> Option<Entry> {
+    let mut candidate = *rest;
+    let entry = Entry::deserialize(&mut rmp_serde::Deserializer::new(&mut candidate)).ok()?;
+    *rest = candidate;
+    Some(entry)
+}
+
+// only if it is all that is left
+fn decode_name_normalization(rest: &[u8]) -> Option<NameNormalization> {
+    let mut candidate = rest;
+    let name_normalization = NameNormalization::deserialize(&mut rmp_serde::Deserializer::new(&mut candidate)).ok()?;
+    candidate.is_empty().then_some(name_normalization)
+}
+
+fn is_plausible(entry: &Entry, expected_entries: usize) -> bool {
+    let plausible_name = |name: &str| !name.is_empty() && !name.contains(['/', '\0']);
+    match entry {
+        EntryRepr::File(file) => plausible_name(&file.name),
+        EntryRepr::Directory(dir) => plausible_name(&dir.name)
+            && dir.entries.iter().all(|(name, id)| plausible_name(name) && id.to_usize() < expected_entries),
+    }
+}
+
+// two plausible entries in a row (or one at the very end)
+fn is_resync_point(mut candidate: &[u8], expected_entries: usize) -> bool {
+    for _ in 0..2 {
+        match decode_entry(&mut candidate) {
+            Some(entry) if is_plausible(&entry, expected_entries) => (),
+            _ => return false,
+        }
+        if candidate.is_empty() {
+            return true;
+        }
+    }
+    true
+}
+
+// give an index to every decoded entry
+fn place_segments(segments: Vec<Segment>, expected_entries: usize) -> HashMap<usize, Entry> {
+    // (name, id) of every child listed by any decoded directory
+    let references: Vec<(String, usize)> = segments.iter()
+        .flat_map(|segment| &segment.entries)
+        .filter_map(|entry| if let EntryRepr::Directory(dir) = entry { Some(dir) } else { None })
+        .flat_map(|dir| dir.entries.iter().map(|(name, id)| (name.clone(), id.to_usize())))
+        .collect();
+    let num_decoded: usize = segments.iter().map(|segment| segment.entries.len()).sum();
+    let total_bytes: usize = segments.iter().map(|segment| segment.damaged_bytes).sum::<usize>().max(1);
+    let num_lost = expected_entries.saturating_sub(num_decoded);
+
+    let mut recovered = HashMap::new();
+    let mut base = 0;
+    let mut num_placed = 0;
+    for (segment_index, segment) in segments.into_iter().enumerate() {
+        if segment_index > 0 {
+            // how many entries the damaged region before this segment held
+            let max_skip = expected_entries.saturating_sub(base + num_decoded - num_placed);
+            let estimate = (num_lost * segment.damaged_bytes / total_bytes).max(1);
+            let score = |skip: usize| {
+                let start = base + skip;
+                references.iter()
+                    .filter(|(name, id)| *id >= start && *id - start < segment.entries.len() && segment.entries[*id - start].name() == name)
+                    .count()
+            };
+            let skip = (1..=max_skip.max(1))
+                .max_by_key(|&skip| (score(skip), std::cmp::Reverse(skip.abs_diff(estimate))))
+                .unwrap();
+            base += skip;
+        }
+        num_placed += segment.entries.len();
+        for entry in segment.entries {
+            recovered.insert(base, entry);
+            base += 1;
+        }
+    }
+    recovered
+}
+
+fn rebuild(root: EntryId, recovered: &HashMap<usize, Entry>, report: &mut RecoveryReport) -> anyhow::Result<Manifest> {
+    let mut manifest = Manifest::new();
+    let mut visited: HashSet<usize> = HashSet::new();
+
+    let root_dir = match recovered.get(&root.to_usize()) {
+        Some(EntryRepr::Directory(dir)) => Some(dir),
+        _ => None,
+    };
+    if let Some(root_dir) = root_dir {
+        visited.insert(root.to_usize());
+        copy_children(root_dir, manifest.root, PathBuf::new(), recovered, &mut manifest, &mut visited, report)?;
+    }
+
+    // top level orphans: not reachable and not listed (with the right name) by any recovered directory
+    let listed: HashSet<usize> = recovered.values()
+        .filter_map(|entry| if let EntryRepr::Directory(dir) = entry { Some(dir) } else { None })
+        .flat_map(|dir| dir.entries.iter())
+        .filter(|(name, id)| recovered.get(&id.to_usize()).is_some_and(|entry| entry.name() == name.as_str()))
+        .map(|(_, id)| id.to_usize())
+        .collect();
+    let mut orphans: Vec<usize> = recovered.keys().cloned()
+        .filter(|index| !visited.contains(index) && !listed.contains(index) && *index != root.to_usize())
+        .collect();
+    orphans.sort();
+
+    let mut lost_and_found = None;
+    for index in orphans {
+        let lost_and_found = match lost_and_found {
+            Some(id) => id,
+            None => *lost_and_found.insert(manifest.add_dir(LOST_AND_FOUND, manifest.root)?),
+        };
+        let name = format!("{}_{}", index, recovered[&index].name());
+        let path = PathBuf::from(LOST_AND_FOUND).join(&name);
+        visited.insert(index);
+        match &recovered[&index] {
+            EntryRepr::File(file) => {
+                let new_file = manifest.add_stored_file(&name, file.blob_key.clone(), file.size, file.ciphertext_size, file.chunks.clone().unwrap_or_default(), lost_and_found)?;
+                manifest.set_times(new_file, file.times);
+            },
+            EntryRepr::Directory(dir) => {
+                let new_dir = manifest.add_dir(&name, lost_and_found)?;
+                manifest.set_times(new_dir, dir.times);
+                copy_children(dir, new_dir, path.clone(), recovered, &mut manifest, &mut visited, report)?;
+            },
+        }
+        report.orphans.push(path);
+    }
+
+    Ok(manifest)
+}
+
+fn copy_children(
+    dir: &Directory,
+    new_dir: EntryId,
+    dir_path: PathBuf,
+    recovered: &HashMap<usize, Entry>,
+    manifest: &mut Manifest,
+    visited: &mut HashSet<usize>,
+    report: &mut RecoveryReport,
+) -> anyhow::Result<()> {
+    let mut to_visit = vec![(dir, new_dir, dir_path)];
+    while let Some((dir, new_dir, dir_path)) = to_visit.pop() {
+        for (name, id) in dir.entries.iter() {
+            let child_path = dir_path.join(name);
+            let child = recovered.get(&id.to_usize()).filter(|child| child.name() == name.as_str());
+            let child = match child {
+                Some(child) if visited.insert(id.to_usize()) => child,
+                _ => {
+                    report.lost_paths.push(child_path);
+                    continue;
+                }
+            };
+            match child {
+                EntryRepr::File(file) => {
+                    let new_file = manifest.add_stored_file(name, file.blob_key.clone(), file.size, file.ciphertext_size, file.chunks.clone().unwrap_or_default(), new_dir)?;
+                    manifest.set_times(new_file, file.times);
+                },
+                EntryRepr::Directory(child_dir) => {
+                    let new_child = manifest.add_dir(name, new_dir)?;
+                    manifest.set_times(new_child, child_dir.times);
+                    to_visit.push((child_dir, new_child, child_path));
+                },
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::BlobKey;
+    use std::path::Path;
+
+    #[test]
+    fn recover_damaged_entry() -> anyhow::Result<()> {
+        let mut manifest = Manifest::new();
+        let dir_a = manifest.add_dir("aaaa", manifest.root)?;
+        manifest.add_file("xxxx", BlobKey::default(), 42, dir_a)?;
+        manifest.add_file("yyyy", BlobKey::default(), 42, dir_a)?;
+        let dir_b = manifest.add_dir("bbbb", manifest.root)?;
+        manifest.add_file("zzzz", BlobKey::default(), 42, dir_b)?;
+        manifest.add_file("top0", BlobKey::default(), 42, manifest.root)?;
+
+        let bytes = manifest.to_bytes()?;
+        let (same, report) = Manifest::from_bytes_lenient(&bytes)?;
+        assert_eq!(same.list_files(), manifest.list_files());
+        assert!(report.damaged_regions.is_empty());
+
+        // break the file entry yyyy (its name is last seen there, after the listing of aaaa)
+        let mut damaged = bytes.to_vec();
+        let name_offset = damaged.windows(4).rposition(|window| window == b"yyyy").unwrap();
+        damaged[name_offset - 1] = 0xc1; // never used msgpack marker
+        assert!(Manifest::from_bytes(bytes::Bytes::from(damaged.clone())).is_err());
+
+        let (recovered, report) = Manifest::from_bytes_lenient(&damaged)?;
+        let files: Vec<PathBuf> = recovered.list_files().into_iter().map(|(path, _, _)| path).collect();
+        assert_eq!(files, vec![PathBuf::from("aaaa/xxxx"), PathBuf::from("bbbb/zzzz"), PathBuf::from("top0")]);
+        assert_eq!(report.lost_paths, vec![Path::new("aaaa/yyyy").to_path_buf()]);
+        assert_eq!(report.damaged_regions.len(), 1);
+        assert_eq!(report.recovered_entries, report.expected_entries - 1);
+        assert!(report.orphans.is_empty());
+
+        // break the directory aaaa, its files are orphans
+        let mut damaged = bytes.to_vec();
+        let name_offset = damaged.windows(4).position(|window| window == b"yyyy").unwrap();
+        damaged[name_offset - 1] = 0xc1;
+        let (recovered, report) = Manifest::from_bytes_lenient(&damaged)?;
+        let files: Vec<PathBuf> = recovered.list_files().into_iter().map(|(path, _, _)| path).collect();
+        assert_eq!(files, vec![PathBuf::from("bbbb/zzzz"), PathBuf::from("lost+found/2_xxxx"), PathBuf::from("lost+found/3_yyyy"), PathBuf::from("top0")]);
+        assert_eq!(report.lost_paths, vec![Path::new("aaaa").to_path_buf()]);
+        assert_eq!(report.orphans.len(), 2);
+        Ok(())
+    }
+}
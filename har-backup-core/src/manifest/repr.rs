@@ -0,0 +1,362 @@
+// serialized form of a manifest
+//
+// names are interned in memory (see names.rs) but serialized as strings, directory children as a
+// map of name to entry id, the same as when entries owned their names.
+
+use std::fmt;
+use serde::de::{MapAccess, SeqAccess, Visitor};
+use serde::ser::{SerializeMap, SerializeSeq};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::dir_entries::DirEntries;
+use super::names::Names;
+use super::{BlobKey, Chunk, Directory, Entry, EntryId, EntryTimes, File, Manifest, ManifestOrigin, NameNormalization};
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename = "Directory")]
+pub(super) struct DirectoryRepr<N> {
+    pub name: N,
+    pub entries: Children<N>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub times: Option<EntryTimes>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename = "File")]
+pub(super) struct FileRepr<N> {
+    pub name: N,
+    pub blob_key: BlobKey,
+    pub size: u64,
+    #[serde(default)]
+    pub ciphertext_size: Option<u64>,
+    // left out when there is nothing after it, readers of format 2 can read files that are not chunked and have no times
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunks: Option<Vec<Chunk>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub times: Option<EntryTimes>, // fields are positional in msgpack, chunks (even empty) must come before
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename = "Entry")]
+pub(super) enum EntryRepr<N> {
+    Directory(DirectoryRepr<N>),
+    File(FileRepr<N>),
+}
+
+impl<N: AsRef<str>> EntryRepr<N> {
+    pub fn name(&self) -> &str {
+        match self {
+            EntryRepr::Directory(dir) => dir.name.as_ref(),
+            EntryRepr::File(file) => file.name.as_ref(),
+        }
+    }
+}
+
+// (name, entry id) of the children of a directory, a map once serialized
+#[derive(Debug)]
+pub(super) struct Children<N>(Vec<(N, EntryId)>);
+
+impl<N> Children<N> {
+    pub fn iter(&self) -> impl Iterator<Item = (&N, &EntryId)> {
+        self.0.iter().map(|(name, entry_id)| (name, entry_id))
+    }
+}
+
+impl<N: Serialize> Serialize for Children<N> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (name, entry_id) in &self.0 {
+            map.serialize_entry(name, entry_id)?;
+        }
+        map.end()
+    }
+}
+
+struct ChildrenVisitor<N>(std::marker::PhantomData<N>);
+
+impl<'de, N: Deserialize<'de>> Visitor<'de> for ChildrenVisitor<N> {
+    type Value = Children<N>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map of entry names to entry ids")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<Self::Value, A::Error> {
+        // the size hint comes from the data, don't trust it for huge allocations
+        let mut children = Vec::with_capacity(access.size_hint().unwrap_or(0).min(4096));
+        while let Some((name, entry_id)) = access.next_entry::<N, EntryId>()? {
+            children.push((name, entry_id));
+        }
+        Ok(Children(children))
+    }
+}
+
+impl<'de, N: Deserialize<'de>> Deserialize<'de> for Children<N> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(ChildrenVisitor(std::marker::PhantomData))
+    }
+}
+
+impl Manifest {
+    fn entry_repr<'a>(&'a self, entry: &'a Entry) -> EntryRepr<&'a str> {
+        match entry {
+            Entry::Directory(dir) => EntryRepr::Directory(DirectoryRepr {
+                name: self.names.get(dir.name),
+                entries: Children(dir.entries.iter().map(|(name, entry_id)| (self.names.get(name), entry_id)).collect()),
+                times: dir.times,
+            }),
+            Entry::File(file) => EntryRepr::File(FileRepr {
+                name: self.names.get(file.name),
+                blob_key: file.blob_key.clone(),
+                size: file.size,
+                ciphertext_size: file.ciphertext_size,
+                chunks: (!file.chunks.is_empty() || file.times.is_some()).then(|| file.chunks.clone()),
+                times: file.times,
+            }),
+        }
+    }
+}
+
+pub(super) fn intern_entry(names: &mut Names, entry: EntryRepr<String>) -> Entry {
+    match entry {
+        EntryRepr::Directory(dir) => {
+            let children = dir.entries.0.into_iter().map(|(name, entry_id)| (names.intern(&name), entry_id)).collect();
+            Entry::Directory(Directory { name: names.intern(&dir.name), entries: DirEntries::from_unsorted(children), times: dir.times })
+        },
+        EntryRepr::File(file) => Entry::File(File {
+            name: names.intern(&file.name),
+            blob_key: file.blob_key,
+            size: file.size,
+            ciphertext_size: file.ciphertext_size,
+            chunks: file.chunks.unwrap_or_default(),
+            times: file.times,
+        }),
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename = "Manifest")]
+struct ManifestRef<'a> {
+    root: EntryId,
+    entries: EntriesRef<'a>,
+    // left out when Exact, as in older manifests, unless origin (which comes after) is there
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name_normalization: Option<NameNormalization>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    origin: Option<&'a ManifestOrigin>,
+}
+
+struct EntriesRef<'a>(&'a Manifest);
+
+impl Serialize for EntriesRef<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let manifest = self.0;
+        let mut seq = serializer.serialize_seq(Some(manifest.entries.len()))?;
+        for entry in &manifest.entries {
+            seq.serialize_element(&manifest.entry_repr(entry))?;
+        }
+        seq.end()
+    }
+}
+
+impl Serialize for Manifest {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let name_normalization = Some(self.name_normalization).filter(|policy| !policy.is_exact() || self.origin.is_some());
+        ManifestRef { root: self.root, entries: EntriesRef(self), name_normalization, origin: self.origin.as_ref() }.serialize(serializer)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename = "Manifest")]
+struct ManifestOwned {
+    root: EntryId,
+    entries: EntriesOwned,
+    #[serde(default)]
+    name_normalization: NameNormalization, // Exact for manifests written before it was recorded
+    #[serde(default)]
+    origin: Option<ManifestOrigin>,
+}
+
+// names are interned as entries are decoded, one entry at a time
+struct EntriesOwned {
+    names: Names,
+    entries: Vec<Entry>,
+}
+
+struct EntriesVisitor;
+
+impl<'de> Visitor<'de> for EntriesVisitor {
+    type Value = EntriesOwned;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a sequence of manifest entries")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut access: A) -> Result<Self::Value, A::Error> {
+        let mut names = Names::default();
+        let mut entries = Vec::with_capacity(access.size_hint().unwrap_or(0).min(4096));
+        while let Some(entry) = access.next_element::<EntryRepr<String>>()? {
+            entries.push(intern_entry(&mut names, entry));
+        }
+        Ok(EntriesOwned { names, entries })
+    }
+}
+
+impl<'de> Deserialize<'de> for EntriesOwned {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_seq(EntriesVisitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for Manifest {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let ManifestOwned { root, entries: EntriesOwned { names, entries }, name_normalization, origin } = ManifestOwned::deserialize(deserializer)?;
+        Ok(Manifest { root, entries, names, name_normalization, origin })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::path::Path;
+
+    // the manifest types from before names were interned
+    #[derive(Serialize, Deserialize)]
+    #[serde(rename = "Directory")]
+    struct OldDirectory {
+        name: String,
+        entries: HashMap<String, EntryId>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(rename = "Entry")]
+    enum OldEntry {
+        Directory(OldDirectory),
+        File(FileRepr<String>),
+    }
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(rename = "Manifest")]
+    struct OldManifest {
+        root: EntryId,
+        entries: Vec<OldEntry>,
+    }
+
+    #[test]
+    fn same_format_as_owned_names() -> anyhow::Result<()> {
+        let mut manifest = Manifest::new();
+        manifest.set_name_normalization(NameNormalization::Exact); // not recorded by the old types
+        let dir = manifest.add_dir("dog", manifest.root)?;
+        manifest.add_file("felt", BlobKey::default(), 42, dir)?;
+        manifest.add_file("dog", BlobKey::default(), 7, manifest.root).unwrap_err(); // name taken
+        manifest.add_file("felt", BlobKey::default(), 7, manifest.root)?;
+
+        let old: OldManifest = rmp_serde::from_slice(&manifest.to_bytes()?)?;
+        let OldEntry::Directory(old_root) = &old.entries[old.root.to_usize()] else {
+            panic!("Root is not a directory");
+        };
+        assert_eq!(old_root.entries.len(), 2);
+        assert!(old_root.entries.contains_key("felt"));
+
+        let from_old = Manifest::from_bytes(bytes::Bytes::from(rmp_serde::to_vec(&old)?))?;
+        assert_eq!(from_old.list_files(), manifest.list_files());
+        from_old.join_and_get_entry_id(from_old.root, Path::new("dog/felt"))?;
+        assert_eq!(from_old.list_dirs(), manifest.list_dirs());
+        Ok(())
+    }
+
+    // files before the ciphertext size was recorded
+    #[derive(Serialize)]
+    #[serde(rename = "File")]
+    struct SizelessFile {
+        name: String,
+        blob_key: BlobKey,
+        size: u64,
+    }
+
+    #[test]
+    fn file_without_ciphertext_size() -> anyhow::Result<()> {
+        let sizeless = SizelessFile { name: "felt".to_string(), blob_key: BlobKey::default(), size: 42 };
+        let file: FileRepr<String> = rmp_serde::from_slice(&rmp_serde::to_vec(&sizeless)?)?;
+        assert_eq!(file.size, 42);
+        assert_eq!(file.ciphertext_size, None);
+        Ok(())
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename = "File")]
+    struct FormatTwoFile {
+        name: String,
+        #[allow(dead_code)]
+        blob_key: BlobKey,
+        size: u64,
+        ciphertext_size: Option<u64>,
+    }
+
+    #[test]
+    fn chunks_only_when_chunked() -> anyhow::Result<()> {
+        let chunks = vec![
+            Chunk { blob_key: BlobKey::default(), size: 1024, ciphertext_size: 1100 },
+            Chunk { blob_key: BlobKey::default(), size: 10, ciphertext_size: 86 },
+        ];
+        let mut manifest = Manifest::new();
+        manifest.add_stored_file("big", BlobKey::default(), 1034, Some(1186), chunks.clone(), manifest.root)?;
+        manifest.add_stored_file("small", BlobKey::default(), 7, Some(83), Vec::new(), manifest.root)?;
+
+        let decoded = Manifest::from_bytes(manifest.to_bytes()?)?;
+        let chunked = decoded.list_chunked_files();
+        assert_eq!(chunked.len(), 1);
+        assert_eq!(chunked[Path::new("big")], chunks.iter().map(super::super::StoredChunk::from).collect::<Vec<_>>());
+        assert_eq!(decoded.list_blobs().len(), 2); // the chunks of big, small has the default key
+
+        // files not in chunks are the same as before chunks existed
+        let small = manifest.entry_repr(manifest.get_entry(manifest.join_and_get_entry_id(manifest.root, Path::new("small"))?));
+        let EntryRepr::File(small) = small else {
+            panic!("Not a file");
+        };
+        let small: FormatTwoFile = rmp_serde::from_slice(&rmp_serde::to_vec(&small)?)?;
+        assert_eq!((small.name.as_str(), small.size, small.ciphertext_size), ("small", 7, Some(83)));
+        Ok(())
+    }
+
+    #[test]
+    fn times_come_after_chunks() -> anyhow::Result<()> {
+        let mut manifest = Manifest::new();
+        let dir = manifest.add_dir("dog", manifest.root)?;
+        manifest.set_times(dir, Some(EntryTimes::new(100)));
+        let felt = manifest.add_file("felt", BlobKey::default(), 42, dir)?;
+        manifest.set_times(felt, Some(EntryTimes { created: 100, modified: 200 }));
+        manifest.add_file("old", BlobKey::default(), 7, manifest.root)?;
+
+        for decoded in [Manifest::from_bytes(manifest.to_bytes()?)?, Manifest::from_json(&manifest.to_json()?)?] {
+            let changed = decoded.files_changed_since(150);
+            assert_eq!(changed, vec![(Path::new("dog/felt").to_path_buf(), EntryTimes { created: 100, modified: 200 })]);
+            assert_eq!(decoded.files_changed_since(0).len(), 1); // old has no times
+            assert_eq!(decoded.list_chunked_files().len(), 0);
+            assert_eq!(decoded.get_entry(decoded.join_and_get_entry_id(decoded.root, Path::new("dog"))?).times(), Some(EntryTimes::new(100)));
+        }
+
+        // copies keep the times
+        let subtree = manifest.subtree(Path::new("dog"))?;
+        assert_eq!(subtree.files_changed_since(200).len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn origin_after_name_normalization() -> anyhow::Result<()> {
+        let mut manifest = Manifest::new();
+        manifest.set_name_normalization(NameNormalization::Exact);
+        manifest.add_file("felt", BlobKey::default(), 42, manifest.root)?;
+        assert_eq!(Manifest::from_bytes(manifest.to_bytes()?)?.origin(), None);
+
+        // positional in msgpack, Exact is then written too
+        manifest.set_origin(ManifestOrigin { host: "kek".to_string(), written: 100 });
+        for decoded in [Manifest::from_bytes(manifest.to_bytes()?)?, Manifest::from_json(&manifest.to_json()?)?] {
+            assert_eq!(decoded.origin(), manifest.origin());
+            assert_eq!(decoded.name_normalization(), NameNormalization::Exact);
+            assert_eq!(decoded.list_files(), manifest.list_files());
+        }
+        Ok(())
+    }
+}
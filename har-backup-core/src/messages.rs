@@ -0,0 +1,409 @@
+// user facing messages go through here rather than inline println!
+// so that they can be translated (set_catalog) and so that tests can check keys instead of english text
+use std::cell::RefCell;
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MessageKey {
+    CreatingKey,
+    KeyStored,
+    ArchiveInitialized,
+    ManifestFetched,
+    ManifestGeneration,
+    RemoteInitialized,
+    RemoteAlreadyInitialized,
+    NoArchiveMetadata,
+    ArchiveMetadataUpdated,
+    DiffRemoteHasExtra,
+    DiffLocalHasExtra,
+    DiffTotals,
+    DiffHashChanged,
+    DuplicateGroup,
+    DuplicatesSummary,
+    ChangedFile,
+    ChangedFilesSummary,
+    NoRunHistory,
+    NothingToPush,
+    PushShrinkGuard,
+    PushResumed,
+    PushChangedFiles,
+    PushDeduplicated,
+    PushAdopted,
+    RemoteManifestUpdated,
+    NothingToPull,
+    PullOverwrote,
+    PullBackedUp,
+    PullDone,
+    TransferSummary,
+    FilesFailed,
+    Resuming,
+    NothingToResume,
+    LocalTrashed,
+    LocalDeleted,
+    PushStatus,
+    Interrupting,
+    Interrupted,
+    PullStatus,
+    Exported,
+    PlaceholdersSkipped,
+    PhaseTiming,
+    Quarantined,
+    QuarantineCleared,
+    SigningEnabled,
+    RemoteLockInfo,
+    RemoteNotLocked,
+    RemoteUnlocked,
+    ManifestBackupLine,
+    NoManifestBackups,
+    ManifestRestored,
+    RemoteManifestRestored,
+    ReadKeyStored,
+    KeyStoredInKeychain,
+    ManifestRecovered,
+    ManifestDamagedRegion,
+    ManifestLostPath,
+    ManifestOrphan,
+    CompressionSet,
+    CompressionOff,
+    RemoteReachable,
+    RemoteUnreachable,
+    RemoteAuthExpired,
+    PassphrasePrompt,
+    NewPassphrasePrompt,
+    ConfirmPassphrasePrompt,
+    VerifyMissingBlob,
+    VerifySizeMismatch,
+    VerifyRemoteSummary,
+    VerifyCorruptBlob,
+    VerifySummary,
+    FsckRootNotDirectory,
+    FsckDanglingChild,
+    FsckNameMismatch,
+    FsckSharedEntry,
+    FsckDuplicateName,
+    FsckNoBlobKey,
+    FsckOrphan,
+    FsckSummary,
+    FsckRepaired,
+    ManifestBackupPruned,
+    PruneSummary,
+    RestoreDone,
+    StatusArchiveRoot,
+    StatusRemote,
+    StatusKey,
+    StatusNoRemote,
+    StatusNoKey,
+    StatusNeverFetched,
+    StatusFetched,
+    StatusDelta,
+    StatusPendingRun,
+    StatusNothingPending,
+    RmRemoved,
+    RmMarkedForGc,
+    MvMoved,
+    FoundFile,
+    FoundFilesSummary,
+    DuLine,
+    LogVersion,
+    Mounted,
+    InitRemotePrompt,
+    InitS3BucketPrompt,
+    InitS3AccessKeyPrompt,
+    InitS3SecretPrompt,
+    InitKeyPrompt,
+    InitCreateKeyPrompt,
+    InitRemoteHasArchive,
+    InitRemoteEmpty,
+    RemoteSpecLine,
+    RemoteSet,
+    RemoteTestOk,
+    RemoteTestFailed,
+    KeyPathSet,
+    KeyFingerprint,
+    KeyCheckOk,
+    Watching,
+    WatchPushFailed,
+    WatchHealthMetrics,
+    WatchStopped,
+    BackupNothingChanged,
+    BackupScheduled,
+    BackupCatchingUp,
+    BackupFailed,
+    BackupNext,
+    BackupStopped,
+    TarExported,
+    ListingExported,
+    InventoryExported,
+    CopyDone,
+    TarImported,
+}
+
+impl MessageKey {
+    // printed even when quiet: things that went wrong and what inspection commands are run for
+    pub fn shown_when_quiet(self) -> bool {
+        use MessageKey::*;
+        matches!(self,
+            Quarantined | FilesFailed | PushShrinkGuard | Interrupting | Interrupted | RemoteUnreachable | RemoteAuthExpired | VerifyMissingBlob | VerifySizeMismatch | VerifyCorruptBlob
+            | ManifestDamagedRegion | ManifestLostPath | ManifestOrphan
+            | DiffRemoteHasExtra | DiffLocalHasExtra | DiffTotals | DiffHashChanged | DuplicateGroup | DuplicatesSummary | ChangedFile | ChangedFilesSummary | FoundFile | FoundFilesSummary | DuLine | LogVersion | NoRunHistory
+            | VerifyRemoteSummary | VerifySummary | ManifestGeneration | RemoteLockInfo | ManifestBackupLine | NoManifestBackups
+            | FsckRootNotDirectory | FsckDanglingChild | FsckNameMismatch | FsckSharedEntry | FsckDuplicateName | FsckNoBlobKey | FsckOrphan | FsckSummary
+            | StatusArchiveRoot | StatusRemote | StatusKey | StatusNoRemote | StatusNoKey | StatusNeverFetched | StatusFetched | StatusDelta | StatusPendingRun
+            | StatusNothingPending | RemoteSpecLine | RemoteTestFailed | KeyFingerprint | WatchPushFailed | BackupFailed)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub key: MessageKey,
+    pub args: Vec<String>,
+}
+
+// template for a key, {0} {1}... are replaced by the message args
+pub type Catalog = fn(MessageKey) -> &'static str;
+
+pub fn english(key: MessageKey) -> &'static str {
+    use MessageKey::*;
+    match key {
+        CreatingKey => "Creating key",
+        KeyStored => "key stored at {0}",
+        ArchiveInitialized => "Archive initialized.",
+        ManifestFetched => "Fetched manifest (generation {0}).",
+        ManifestGeneration => "Manifest generation: {0}",
+        RemoteInitialized => "Remote initialized. Archive uuid: {0}",
+        RemoteAlreadyInitialized => "Remote was already initialized by an earlier init-remote. Archive uuid: {0}",
+        NoArchiveMetadata => "Remote has no archive metadata (initialized with an older version?)",
+        ArchiveMetadataUpdated => "Archive metadata updated.",
+        DiffRemoteHasExtra => "Remote has the additional entries:",
+        DiffLocalHasExtra => "Local tree has the additional entries:",
+        DiffTotals => "Total extra files: {0}, total extra dirs: {1}",
+        DiffHashChanged => "There are some files which hash has changed:",
+        DuplicateGroup => "{0} bytes x {1} (blob {2})",
+        DuplicatesSummary => "Duplicate groups: {0}, wasted space: {1} bytes",
+        ChangedFile => "{0} {1}",
+        ChangedFilesSummary => "{0} files added or changed since {1}",
+        NoRunHistory => "No push or pull transferred anything yet, nothing to graph.",
+        NothingToPush => "Nothing to push.",
+        PushShrinkGuard => "Warning: the local tree had {0} files and the remote {1}, pushed anyway with --force.",
+        PushChangedFiles => "{0} files changed since they were pushed, their new content was pushed too.",
+        PushResumed => "{0} files were uploaded by an interrupted push, reusing their blobs.",
+        PushDeduplicated => "{0} files were not uploaded, the remote already has their content.",
+        PushAdopted => "{0} files adopted from blobs the remote has, {1} files it has no blob of are left for a push.",
+        RemoteManifestUpdated => "Remote manifest updated.",
+        NothingToPull => "Nothing to pull.",
+        PullOverwrote => "Overwrote {0} local files that differed from the remote:",
+        PullBackedUp => "Renamed {0} local files that differed from the remote to *{1} before pulling them:",
+        PullDone => "Pull done.",
+        Resuming => "Resuming the {0} of {1} files that stopped before the end.",
+        NothingToResume => "Nothing to resume, the last push and pull went to the end.",
+        FilesFailed => "{0} files failed, the others went on:",
+        TransferSummary => "{0} files transferred in {3}s, {1} bytes ({2} encrypted), {4} bytes/s. {5} retries, {6} files skipped.",
+        LocalTrashed => "Moved {0} local entries that the remote does not have to the trash:",
+        LocalDeleted => "Deleted {0} local entries that the remote does not have:",
+        Interrupting => "Stopping after the transfers in flight, Ctrl-C again to quit now.",
+        Interrupted => "Interrupted. Run the same command again to resume.",
+        PushStatus => "Push status: {0}/{1} num active: {2} transferred bytes: {3}",
+        PullStatus => "Pull status: {0}/{1} num active: {2} transferred bytes: {3}",
+        Exported => "Exported {0} added and {1} changed files ({2} deleted).",
+        PlaceholdersSkipped => "Skipped {0} cloud placeholder files (not downloaded locally):",
+        PhaseTiming => "{0}: {1}s",
+        Quarantined => "{0} files failed {1} times and are quarantined (not retried until har clear-quarantine):",
+        QuarantineCleared => "Cleared {0} quarantined files.",
+        SigningEnabled => "Manifest signing enabled, pushing now requires this key.",
+        RemoteLockInfo => "Remote is locked by {0} since {1}.",
+        RemoteNotLocked => "Remote is not locked.",
+        RemoteUnlocked => "Released the lock of {0}.",
+        ManifestBackupLine => "{0}: generation {1}, replaced {2}, {3} files",
+        NoManifestBackups => "No manifest backups, push makes them.",
+        ManifestRestored => "Fetched manifest is now backup {0} (generation {1}), push it with --push to roll the remote back too.",
+        RemoteManifestRestored => "Remote manifest replaced by the fetched manifest.",
+        ReadKeyStored => "read only key stored at {0}",
+        KeyStoredInKeychain => "Key stored in keychain as {0}, .har now refers to it.",
+        ManifestRecovered => "Recovered {0} of {1} entries ({2} damaged regions), written to {3}",
+        ManifestDamagedRegion => "damaged bytes {0}..{1}",
+        ManifestLostPath => "lost: {0}",
+        ManifestOrphan => "parent lost, moved to: {0}",
+        CompressionSet => "Blobs will be compressed with zstd level {0} before encryption.",
+        CompressionOff => "Blobs will not be compressed.",
+        RemoteReachable => "Remote is reachable again, resuming pushes.",
+        RemoteUnreachable => "Remote is unreachable, pausing pushes ({0})",
+        RemoteAuthExpired => "Remote refused the credentials, pausing pushes until they are renewed ({0})",
+        PassphrasePrompt => "Passphrase for key file {0}: ",
+        NewPassphrasePrompt => "Passphrase for the new key file: ",
+        ConfirmPassphrasePrompt => "Confirm passphrase: ",
+        VerifyMissingBlob => "missing blob {0} of {1}",
+        VerifySizeMismatch => "wrong size for blob {0} of {1}: {2} bytes stored, {3} expected",
+        VerifyRemoteSummary => "Checked {0} blobs against the remote listing: {1} missing, {2} of wrong size ({3} without a recorded size only checked for presence)",
+        VerifyCorruptBlob => "corrupt blob {0} of {1}: {2}",
+        VerifySummary => "Checked {0} blobs: {1} missing, {2} downloaded of which {3} corrupt",
+        FsckRootNotDirectory => "the root of the manifest is not a directory",
+        FsckDanglingChild => "{0} is listed but there is no such entry",
+        FsckNameMismatch => "{0} is an entry named {1}",
+        FsckSharedEntry => "{0} is the same entry as {1}",
+        FsckDuplicateName => "{0} has the same name as {1}",
+        FsckNoBlobKey => "{0} is a file without a blob",
+        FsckOrphan => "entry listed nowhere, {0} once repaired",
+        FsckSummary => "Checked {0} entries of the fetched manifest: {1} issues",
+        FsckRepaired => "Fetched manifest repaired, the previous one is kept with the manifest backups. Push it with --push.",
+        ManifestBackupPruned => "Removed the backup of generation {0}, replaced {1}",
+        PruneSummary => "Kept {0} manifest backups, removed {1}",
+        RestoreDone => "Restore done, {0} files restored.",
+        StatusArchiveRoot => "Archive root: {0}",
+        StatusRemote => "Remote: {0}",
+        StatusKey => "Key: {0}, fingerprint {1}",
+        StatusNoRemote => "Remote: unavailable ({0})",
+        StatusNoKey => "Key: unavailable ({0})",
+        StatusNeverFetched => "Fetched manifest: none, run fetch-manifest",
+        StatusFetched => "Fetched manifest: generation {0}, stored {1}",
+        StatusDelta => "Only local: {0} files ({1}), only in the remote: {2} files ({3})",
+        StatusPendingRun => "Pending: a {0} of {1} files, har resume continues it",
+        StatusNothingPending => "Pending: nothing",
+        RmRemoved => "Removed {0} from the archive ({1} files)",
+        RmMarkedForGc => "{0} blobs are not referred to anymore, marked for gc",
+        MvMoved => "Moved {0} to {1}",
+        FoundFile => "{0} {1} {2}",
+        FoundFilesSummary => "{0} files match ({1})",
+        DuLine => "{0} {1} {2}",
+        Mounted => "Serving the fetched manifest on {0}, read only, until it is unmounted (fusermount -u or Ctrl-C)",
+        LogVersion => "{0}: generation {1}, written {2} by {3}, {4} files ({5}), {6} added {7} changed {8} deleted",
+        InitRemotePrompt => "Remote (fs://PATH or s3://ENDPOINT): ",
+        InitS3BucketPrompt => "Bucket: ",
+        InitS3AccessKeyPrompt => "Access key: ",
+        InitS3SecretPrompt => "Secret key: ",
+        InitKeyPrompt => "Key file (or keychain://NAME): ",
+        InitCreateKeyPrompt => "{0} does not exist, create a new key there? [y/N] ",
+        InitRemoteHasArchive => "The remote has the archive {0}, fetch-manifest then pull to get its files.",
+        InitRemoteEmpty => "The remote is reachable and has no archive yet, init-remote creates it.",
+        RemoteSpecLine => "{0}",
+        RemoteSet => "Remote set to {0}, fetch-manifest before the next push if it is another archive.",
+        RemoteTestOk => "{0}: reachable ({1} ms), {2} objects listed ({3} ms)",
+        RemoteTestFailed => "{0}: unreachable ({1})",
+        KeyPathSet => "Key set to {0}, fingerprint {1}",
+        KeyFingerprint => "{0}: {1} ({2} key)",
+        KeyCheckOk => "The key decrypts the remote manifest ({0} files), it is a {1} key.",
+        Watching => "Watching {0}, changes are pushed {1} s after the last one. Ctrl-C to stop.",
+        WatchPushFailed => "Push failed: {0}. Trying again in {1} s or after the next change.",
+        WatchHealthMetrics => "Remote probed {0} times, {1} failed, {2} changes of state, unreachable for {3} s.",
+        WatchStopped => "Stopped watching.",
+        BackupNothingChanged => "Nothing new in the local tree, skipping this backup.",
+        BackupScheduled => "Backing up every {0}, first at {1}. Ctrl-C to stop.",
+        BackupCatchingUp => "{0} scheduled backups were missed (asleep or not running), catching up with one.",
+        BackupFailed => "Backup failed: {0}",
+        BackupNext => "Next backup at {0}.",
+        BackupStopped => "Stopped backing up.",
+        TarExported => "{0} files exported to {1}",
+        ListingExported => "{0} entries listed in {1}",
+        InventoryExported => "{0} rows for the files of {1} manifest versions written to {2}",
+        CopyDone => "{0} files copied to {1}",
+        TarImported => "{0} files imported into /{1}, {2} entries that are not files or directories left out",
+    }
+}
+
+static CATALOG: RwLock<Catalog> = RwLock::new(english);
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    static RECORDED: RefCell<Option<Vec<Message>>> = const { RefCell::new(None) };
+}
+
+pub fn set_catalog(catalog: Catalog) {
+    *CATALOG.write().unwrap() = catalog;
+}
+
+pub fn render(key: MessageKey, args: &[String]) -> String {
+    let template = (CATALOG.read().unwrap())(key);
+    // one pass over the template, an argument that contains "{1}" is left as it is
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        rendered.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        let arg = after.find('}').and_then(|close| {
+            let index: usize = after[..close].parse().ok()?;
+            Some((args.get(index)?, close))
+        });
+        match arg {
+            Some((arg, close)) => {
+                rendered.push_str(arg);
+                rest = &after[close + 1..];
+            }
+            None => {
+                rendered.push('{');
+                rest = after;
+            }
+        }
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+// --quiet: messages are still recorded, only printing is skipped
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+pub fn emit(key: MessageKey, args: Vec<String>) {
+    if !is_quiet() || key.shown_when_quiet() {
+        println!("{}", render(key, &args));
+    }
+    RECORDED.with(|recorded| {
+        if let Some(recorded) = recorded.borrow_mut().as_mut() {
+            recorded.push(Message { key, args });
+        }
+    });
+}
+
+// messages emitted by this thread are kept until take_recorded, for tests
+pub fn start_recording() {
+    RECORDED.with(|recorded| *recorded.borrow_mut() = Some(Vec::new()));
+}
+
+pub fn take_recorded() -> Vec<Message> {
+    RECORDED.with(|recorded| recorded.borrow_mut().take().unwrap_or_default())
+}
+
+// say!(ManifestFetched, generation) is emit(MessageKey::ManifestFetched, vec![generation.to_string()])
+#[macro_export]
+macro_rules! say {
+    ($key:ident $(, $arg:expr)* $(,)?) => {
+        $crate::messages::emit($crate::messages::MessageKey::$key, vec![$($arg.to_string()),*])
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_args() {
+        let rendered = render(MessageKey::DiffTotals, &["3".to_string(), "4".to_string()]);
+        assert_eq!(rendered, "Total extra files: 3, total extra dirs: 4");
+        let rendered = render(MessageKey::DiffTotals, &["{1}".to_string(), "4".to_string()]);
+        assert_eq!(rendered, "Total extra files: {1}, total extra dirs: 4");
+    }
+
+    #[test]
+    fn record() {
+        start_recording();
+        say!(ManifestFetched, 12);
+        say!(PullDone);
+        assert_eq!(take_recorded(), vec![
+            Message { key: MessageKey::ManifestFetched, args: vec!["12".to_string()] },
+            Message { key: MessageKey::PullDone, args: vec![] },
+        ]);
+        assert!(take_recorded().is_empty());
+    }
+
+    #[test]
+    fn quiet_keeps_warnings() {
+        assert!(!MessageKey::PushStatus.shown_when_quiet());
+        assert!(!MessageKey::PhaseTiming.shown_when_quiet());
+        assert!(MessageKey::Quarantined.shown_when_quiet());
+        assert!(MessageKey::DiffTotals.shown_when_quiet());
+    }
+}
@@ -0,0 +1,1893 @@
+use crate::blob_storage::{self, BlobStorage};
+use serde::{Deserialize, Serialize};
+use crate::manifest::{Manifest, StoredChunk};
+use crate::archive_metadata::{ArchiveMetadata, ARCHIVE_METADATA_KEY, MANIFEST_FORMAT_VERSION};
+use crate::health::RemoteHealth;
+use crate::clock::{Clock, Jitter, SystemClock};
+use crate::rate_limit::RateLimit;
+use crate::read_ahead::ReadAhead;
+use crate::remote_lock::{RemoteLock, RemoteLocked, REMOTE_LOCK_KEY};
+use crate::thread_sync::{CancelToken, Cancelled};
+use log::debug;
+use crate::timings::{Phase, Timings};
+use crate::journal::{TransferJournal, UploadJournal, UploadRecord};
+use anyhow::{Result, Context};
+use std::path::{Path, PathBuf};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::io::Read;
+
+pub struct Mirror {
+    blob_storage: Box<dyn BlobStorage>,
+    timings: Timings,
+    progress: Option<Box<dyn TransferProgress>>, // told what push and pull do, they print nothing of it themselves
+    dedup: Option<Dedup>,
+    bucket_name: Option<String>, // see set_bucket_name
+    clock: Arc<dyn Clock>, // of retries and status lines, see set_deterministic
+    jitter: Jitter,
+    cancel: CancelToken,
+    pull_conflict: PullConflict,
+    config: TransferConfig, // of push, pull and check_blobs
+}
+
+// the knobs of a Mirror, see Mirror::builder; those not given are the defaults of Mirror::new
+#[derive(Default)]
+pub struct MirrorBuilder {
+    storage: Option<Box<dyn BlobStorage>>,
+    config: TransferConfig,
+    retry: Option<(u32, std::time::Duration, std::time::Duration)>, // over the retries of config, whichever came first
+    progress: Option<Box<dyn TransferProgress>>,
+    dedup: Option<Dedup>,
+    bucket_name: Option<String>,
+    cancel: Option<CancelToken>,
+    pull_conflict: PullConflict,
+}
+
+impl MirrorBuilder {
+    pub fn storage(mut self, storage: Box<dyn BlobStorage>) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    pub fn transfer_config(mut self, config: TransferConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    // attempts of an item before it is quarantined, backoff before its first retry doubled up to max_backoff
+    pub fn retry(mut self, max_attempts: u32, backoff: std::time::Duration, max_backoff: std::time::Duration) -> Self {
+        self.retry = Some((max_attempts, backoff, max_backoff));
+        self
+    }
+
+    pub fn progress(mut self, progress: Box<dyn TransferProgress>) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    pub fn dedup(mut self, dedup: Dedup) -> Self {
+        self.dedup = Some(dedup);
+        self
+    }
+
+    pub fn bucket_name(mut self, bucket_name: String) -> Self {
+        self.bucket_name = Some(bucket_name);
+        self
+    }
+
+    pub fn cancel(mut self, cancel: CancelToken) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    pub fn pull_conflict(mut self, pull_conflict: PullConflict) -> Self {
+        self.pull_conflict = pull_conflict;
+        self
+    }
+
+    pub fn build(self) -> Result<Mirror> {
+        let Some(storage) = self.storage else {
+            anyhow::bail!("A mirror needs a blob storage");
+        };
+        let mut mirror = Mirror::new(storage);
+        mirror.config = self.config;
+        if let Some((max_attempts, backoff, max_backoff)) = self.retry {
+            mirror.config.max_attempts = max_attempts;
+            mirror.config.retry_backoff = backoff;
+            mirror.config.max_retry_backoff = max_backoff;
+        }
+        mirror.progress = self.progress;
+        mirror.dedup = self.dedup;
+        mirror.bucket_name = self.bucket_name;
+        if let Some(cancel) = self.cancel {
+            mirror.cancel = cancel;
+        }
+        mirror.pull_conflict = self.pull_conflict;
+        Ok(mirror)
+    }
+}
+
+// push skips files whose blob the remote already has, keys are computed locally like the storages do
+pub struct Dedup {
+    known: HashMap<String, u64>, // key -> ciphertext size of blobs known to exist (remote manifest), exists() otherwise
+}
+
+impl Dedup {
+    pub fn new(known: HashMap<String, u64>) -> Self {
+        Self { known }
+    }
+}
+
+// a file as push left it in the storage, for a file in chunks info is the key of the whole content and the sums of the chunks
+#[derive(Debug, Clone)]
+pub struct PushedFile {
+    pub info: blob_storage::UploadInfo,
+    pub chunks: Vec<blob_storage::UploadInfo>, // in order, empty unless the file is above TransferConfig::chunk_threshold
+}
+
+pub type PushResult = Result<PushedFile, blob_storage::Error>;
+
+// a file for pull, from its blob or from its chunks (see manifest::Manifest::list_chunked_files)
+#[derive(Debug, Clone)]
+pub struct PullFile {
+    pub path: PathBuf, // in the archive
+    pub key: String,
+    pub size: usize,
+    pub chunks: Vec<StoredChunk>,
+}
+
+// reported by push/pull as they go (see set_progress), the har cli prints them as progress bars or status lines
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransferEvent {
+    Resumed { num_files: usize }, // push: uploaded by an interrupted push, their blobs are reused
+    Started { num_files: usize, num_bytes: u64 }, // resumed and quarantined files are not counted
+    FileStarted { index: usize, path: PathBuf, size: u64 }, // again for every attempt (once for a file in chunks)
+    ChunkDone { index: usize, size: u64 },
+    FileDone { index: usize, size: u64 }, // size not already reported by ChunkDone
+    FileFailed { index: usize, size: u64, retrying: bool, reason: String },
+    // every TransferConfig::time_between_prints, files done (or quarantined) of all and parts in flight
+    Status { num_done: usize, num_files: usize, num_active: usize, transferred_bytes: usize },
+    Finished,
+    Deduplicated { num_files: usize }, // push: not uploaded, the remote already had their content
+}
+
+// what a push or pull did, returned with its results
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TransferReport {
+    pub num_files: usize, // transferred
+    pub plaintext_bytes: u64, // of the blobs transferred
+    pub ciphertext_bytes: u64,
+    pub elapsed: std::time::Duration, // of the transfer phase
+    pub num_retried: usize, // failed transfers of a file or chunk that were tried again
+    pub num_skipped: usize, // resumed or already in the remote for push, existing locally for pull
+    pub num_quarantined: usize,
+}
+
+impl TransferReport {
+    // plaintext bytes per second
+    pub fn throughput(&self) -> f64 {
+        self.plaintext_bytes as f64 / self.elapsed.as_secs_f64().max(0.001)
+    }
+}
+
+pub trait TransferProgress: Send {
+    fn on_event(&mut self, event: &TransferEvent);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InitOutcome {
+    Created,
+    AlreadyInitialized, // by an earlier init with the same metadata
+}
+
+// pulled files are written next to their destination under this suffix, then renamed once complete
+pub const PARTIAL_SUFFIX: &str = ".har-partial";
+// local files replaced by a pull with PullConflict::BackupExisting are kept under this suffix
+pub const BACKUP_SUFFIX: &str = ".har-backup";
+
+// what pull does with a file that exists locally, checked for each file as it is pulled
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PullConflict {
+    #[default]
+    SkipExisting, // the local file is kept, the remote one is not pulled
+    Overwrite,
+    BackupExisting, // the local file is renamed with BACKUP_SUFFIX, then the remote one is pulled
+}
+
+// what check_blobs found of a blob
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlobCheck {
+    Present, // not downloaded
+    Intact, // downloaded, decrypted and its content has its key
+    Missing,
+    Damaged(String),
+}
+
+// error of a manifest update over a remote manifest that is not the fetched one anymore
+#[derive(Debug)]
+pub struct RemoteManifestChanged;
+
+impl std::fmt::Display for RemoteManifestChanged {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Remote manifest changed since the last fetch (pushed from elsewhere?), run fetch-manifest and push again")
+    }
+}
+
+impl std::error::Error for RemoteManifestChanged {}
+
+const MANIFEST_KEY: &str = "manifest";
+const MANIFEST_SIGNATURE_KEY: &str = "manifest_signature";
+const KEY_FINGERPRINT_KEY: &str = "key_fingerprint";
+
+impl Mirror {
+    pub fn new(blob_storage: Box<dyn BlobStorage>) -> Self {
+        Self {
+            blob_storage,
+            timings: Timings::default(),
+            progress: None,
+            dedup: None,
+            bucket_name: None,
+            clock: Arc::new(SystemClock),
+            jitter: Jitter::from_os(),
+            cancel: CancelToken::new(),
+            pull_conflict: PullConflict::default(),
+            config: TransferConfig::default(),
+        }
+    }
+
+    pub fn builder() -> MirrorBuilder {
+        MirrorBuilder::default()
+    }
+
+    pub fn transfer_config(&self) -> &TransferConfig {
+        &self.config
+    }
+
+    // once cancelled, push and pull start nothing new, wait for the transfers in flight and return thread_sync::Cancelled
+    pub fn set_cancel(&mut self, cancel: CancelToken) {
+        self.cancel = cancel;
+    }
+
+    pub fn cancel(&self) -> &CancelToken {
+        &self.cancel
+    }
+
+    // what pull does when a file it pulls exists locally
+    pub fn set_pull_conflict(&mut self, pull_conflict: PullConflict) {
+        self.pull_conflict = pull_conflict;
+    }
+
+    // for tests: retries are spread with a seeded generator and scheduled on clock (a clock::VirtualClock)
+    pub fn set_deterministic(&mut self, seed: u64, clock: Arc<dyn Clock>) {
+        self.jitter = Jitter::from_seed(seed);
+        self.clock = clock;
+    }
+
+    // for random choices outside of transfers (har verify --sample), seeded in deterministic mode
+    pub fn fork_jitter(&mut self) -> Jitter {
+        self.jitter.fork()
+    }
+
+    pub fn set_dedup(&mut self, dedup: Option<Dedup>) {
+        self.dedup = dedup;
+    }
+
+    // what blob keys are derived from (see dot_har::RemoteSpec::bucket_name), dedup and chunks need it to compute keys
+    pub fn set_bucket_name(&mut self, bucket_name: String) {
+        self.bucket_name = Some(bucket_name);
+    }
+
+    // the upload the remote already has for data, if dedup is on
+    // a blob found with exists() has a ciphertext size of 0, it is not known
+    fn existing_upload(&mut self, data: &bytes::Bytes) -> Result<Option<blob_storage::UploadInfo>> {
+        let (Some(dedup), Some(bucket_name)) = (&self.dedup, &self.bucket_name) else {
+            return Ok(None);
+        };
+        let key = blob_storage::get_hash_name(bucket_name, data.clone());
+        let ciphertext_size = match dedup.known.get(&key) {
+            Some(&ciphertext_size) => ciphertext_size,
+            None if self.blob_storage.exists_blocking(&key)? => 0,
+            None => return Ok(None),
+        };
+        Ok(Some(blob_storage::UploadInfo {
+            key,
+            plaintext_size: data.len() as u64,
+            ciphertext_size,
+            ciphertext_checksum: String::new(),
+            duration: std::time::Duration::ZERO,
+        }))
+    }
+
+    pub fn set_progress(&mut self, progress: Option<Box<dyn TransferProgress>>) {
+        self.progress = progress;
+    }
+
+    fn report(&mut self, event: TransferEvent) {
+        if let Some(progress) = &mut self.progress {
+            progress.on_event(&event);
+        }
+    }
+
+    // like git init; create/upload an empty remote manifest
+    pub fn init(&mut self) -> anyhow::Result<()> {
+        self.init_with_metadata(&ArchiveMetadata::new(""))?;
+        Ok(())
+    }
+
+    // safe to retry with the same metadata: an init that died after uploading the manifest is AlreadyInitialized
+    pub fn init_with_metadata(&mut self, metadata: &ArchiveMetadata) -> anyhow::Result<InitOutcome> {
+
+        let exists = self.blob_storage.exists_blocking(MANIFEST_KEY)?;
+        if exists {
+            self.check_same_empty_archive(metadata)?;
+            return Ok(InitOutcome::AlreadyInitialized);
+        }
+
+        self.push_archive_metadata(metadata)?;
+
+        let manifest = Manifest::new();
+        let data = manifest.to_bytes()?;
+        self.blob_storage.upload_blocking(data, Some(MANIFEST_KEY))?;
+        Ok(InitOutcome::Created)
+    }
+
+    // the remote has a manifest, fails with what exists unless it is the empty archive of metadata
+    fn check_same_empty_archive(&mut self, metadata: &ArchiveMetadata) -> Result<()> {
+        const HOW_TO_PROCEED: &str = "use fetch to work with the existing archive, or point .har to another remote";
+        let remote_metadata = self.get_archive_metadata()
+            .with_context(|| format!("Remote already has a manifest but its archive metadata cannot be read (archive of another key?); {}", HOW_TO_PROCEED))?;
+        let remote_metadata = match remote_metadata {
+            Some(remote_metadata) => remote_metadata,
+            None => anyhow::bail!("Remote already has a manifest of an archive initialized by an older version (no archive metadata); {}", HOW_TO_PROCEED),
+        };
+        if remote_metadata.archive_uuid != metadata.archive_uuid {
+            anyhow::bail!("Remote already has archive {} (created at {}, description \"{}\"); {}",
+                remote_metadata.archive_uuid, remote_metadata.creation_time, remote_metadata.description, HOW_TO_PROCEED);
+        }
+        let manifest = Manifest::from_bytes(self.get_manifest_blob()?).context("Reading the existing remote manifest")?;
+        let stats = manifest.get_stats();
+        if stats.num_files > 0 || stats.num_dirs > 1 {
+            anyhow::bail!("Remote archive {} is already initialized and has {} files; {}",
+                remote_metadata.archive_uuid, stats.num_files, HOW_TO_PROCEED);
+        }
+        debug!("Remote has the empty manifest of archive {}, init already done", metadata.archive_uuid);
+        Ok(())
+    }
+
+    // cheap check that the remote answers, see health
+    pub fn probe(&mut self) -> RemoteHealth {
+        match self.blob_storage.exists_blocking(MANIFEST_KEY) {
+            Ok(_) => RemoteHealth::Reachable,
+            Err(error) => RemoteHealth::from_probe_error(&error.msg),
+        }
+    }
+
+    // none if the remote was initialized before archive metadata existed
+    pub fn get_archive_metadata(&mut self) -> Result<Option<ArchiveMetadata>> {
+        let exists = self.blob_storage.exists_blocking(ARCHIVE_METADATA_KEY)?;
+        if !exists {
+            return Ok(None);
+        }
+        let data = self.blob_storage.download_blocking(ARCHIVE_METADATA_KEY)?.data;
+        Ok(Some(ArchiveMetadata::from_bytes(data)?))
+    }
+
+    pub fn push_archive_metadata(&mut self, metadata: &ArchiveMetadata) -> Result<()> {
+        self.blob_storage.upload_blocking(metadata.to_bytes()?, Some(ARCHIVE_METADATA_KEY))?;
+        Ok(())
+    }
+
+    // before writing a manifest in the current format to an archive created by an older version
+    pub fn upgrade_manifest_format(&mut self) -> Result<()> {
+        if let Some(mut metadata) = self.get_archive_metadata()? {
+            if metadata.manifest_format_version < MANIFEST_FORMAT_VERSION {
+                debug!("Upgrade manifest format version from {} to {}", metadata.manifest_format_version, MANIFEST_FORMAT_VERSION);
+                metadata.manifest_format_version = MANIFEST_FORMAT_VERSION;
+                self.push_archive_metadata(&metadata)?;
+            }
+        }
+        Ok(())
+    }
+
+    // key -> size of every object of the remote, blobs and archive files alike
+    pub fn list_objects(&mut self) -> Result<HashMap<String, u64>> {
+        let objects = self.blob_storage.list_blocking()?;
+        Ok(objects.into_iter().map(|object| (object.key, object.size)).collect())
+    }
+
+    // to call when connecting to a remote, before reading/writing anything else
+    pub fn check_archive_metadata(&mut self) -> Result<()> {
+        match self.get_archive_metadata()? {
+            Some(metadata) => {
+                debug!("Archive uuid {}", metadata.archive_uuid);
+                metadata.check_compatible()
+            },
+            None => {
+                debug!("Remote has no archive metadata");
+                Ok(())
+            }
+        }
+    }
+
+    pub fn get_manifest_blob(&mut self) -> Result<bytes::Bytes> {
+        debug!("Download remote manifest...");
+        let remote_manifest_bytes = self.blob_storage.download_blocking(MANIFEST_KEY)?.data;
+        debug!("Download remote manifest done");
+        Ok(remote_manifest_bytes)
+    }
+
+    pub fn push_manifest_blob(&mut self, data: bytes::Bytes) -> Result<()> {
+        debug!("Upload remote manifest...");
+        self.blob_storage.upload_blocking(data, Some(MANIFEST_KEY))?;
+        debug!("Upload remote manifest done");
+        Ok(())
+    }
+
+    // compare-and-swap: fails with RemoteManifestChanged unless the remote manifest is still fetched (the one data was
+    // made from), so that a push from another machine since then is not overwritten. The upload is conditioned on the
+    // version of the manifest that was compared (If-Match on s3), a push in between makes it fail. Storages without
+    // conditional uploads (local directory, rclone) check then upload: callers hold the remote lock around it
+    pub fn push_manifest_blob_if_unchanged(&mut self, data: bytes::Bytes, fetched: &[u8]) -> Result<()> {
+        let current = self.blob_storage.download_blocking(MANIFEST_KEY)?;
+        if current.data.as_ref() != fetched {
+            return Err(RemoteManifestChanged.into());
+        }
+        let Some(version) = current.version else {
+            return self.push_manifest_blob(data);
+        };
+        debug!("Upload remote manifest over version {}...", version);
+        match self.blob_storage.upload_if_version_blocking(data, MANIFEST_KEY, &version) {
+            Err(err) if err.kind == blob_storage::ErrorKind::Changed => Err(RemoteManifestChanged.into()),
+            result => {
+                result?;
+                debug!("Upload remote manifest done");
+                Ok(())
+            },
+        }
+    }
+
+    // fails with RemoteManifestChanged if the remote manifest is not fetched anymore
+    pub fn check_manifest_unchanged(&mut self, fetched: &[u8]) -> Result<()> {
+        if self.get_manifest_blob()?.as_ref() != fetched {
+            return Err(RemoteManifestChanged.into());
+        }
+        Ok(())
+    }
+
+    // whether the remote has the blobs of keys, and for those to download whether their content is the content of
+    // their key (downloaded, decrypted, hashed), in the order of keys. Up to active_tasks_limit requests at once
+    pub fn check_blobs(&mut self, keys: &[(String, bool)]) -> Result<Vec<BlobCheck>> {
+        use blob_storage::EventContent;
+        let config = self.config.clone();
+        let mut checks = vec![BlobCheck::Present; keys.len()];
+        let mut exists_error = None;
+        self.run_tasks(keys.len(), config.active_tasks_limit, |storage, index| storage.exists(&keys[index].0), |index, content| match content {
+            EventContent::ExistsSuccess(true) => (),
+            EventContent::ExistsSuccess(false) => checks[index] = BlobCheck::Missing,
+            EventContent::Error(error) => exists_error = Some(error),
+            _ => (),
+        })?;
+        // the remote is not answering, that says nothing of the blobs
+        if let Some(error) = exists_error {
+            return Err(error).context("Checking that the remote has the blobs");
+        }
+        let to_download: Vec<usize> = (0..keys.len()).filter(|&index| keys[index].1 && checks[index] == BlobCheck::Present).collect();
+        let bucket_name = self.bucket_name.clone().context("Checking the content of blobs needs the bucket name")?;
+        self.run_tasks(to_download.len(), config.active_tasks_limit, |storage, i| storage.download(&keys[to_download[i]].0), |i, content| {
+            let index = to_download[i];
+            checks[index] = match content {
+                EventContent::DownloadSuccess(info) if blob_storage::get_hash_name(&bucket_name, info.data.clone()) == keys[index].0 => BlobCheck::Intact,
+                EventContent::DownloadSuccess(_) => BlobCheck::Damaged("content does not match its key".to_string()),
+                EventContent::Error(error) => BlobCheck::Damaged(error.msg),
+                _ => return,
+            };
+        })?;
+        Ok(checks)
+    }
+
+    // num tasks started by start, up to limit at once, done gets what each ended with
+    fn run_tasks(&mut self, num: usize, limit: usize, mut start: impl FnMut(&mut dyn BlobStorage, usize) -> blob_storage::TaskId,
+            mut done: impl FnMut(usize, blob_storage::EventContent)) -> Result<()> {
+        use blob_storage::EventContent;
+        let events = self.blob_storage.events();
+        let mut active_tasks = HashMap::new();
+        let mut next = 0;
+        while next < num || !active_tasks.is_empty() {
+            while next < num && active_tasks.len() < limit.max(1) && !self.cancel.is_cancelled() {
+                active_tasks.insert(start(self.blob_storage.as_mut(), next), next);
+                next += 1;
+            }
+            if active_tasks.is_empty() {
+                break; // cancelled
+            }
+            let event = events.recv().context("Blob storage stopped sending events")?;
+            if let EventContent::Progress(_) = event.content {
+                continue;
+            }
+            if let Some(index) = active_tasks.remove(&event.id) {
+                done(index, event.content);
+            }
+        }
+        if self.cancel.is_cancelled() {
+            return Err(Cancelled.into());
+        }
+        Ok(())
+    }
+
+    // the lock of the remote, none if it was released or never taken
+    pub fn get_lock(&mut self) -> Result<Option<RemoteLock>> {
+        if !self.blob_storage.exists_blocking(REMOTE_LOCK_KEY)? {
+            return Ok(None);
+        }
+        let lock = RemoteLock::from_bytes(&self.blob_storage.download_blocking(REMOTE_LOCK_KEY)?.data)?;
+        Ok((!lock.released).then_some(lock))
+    }
+
+    // fails with RemoteLocked if another client has it
+    pub fn lock(&mut self, holder: &str, now: u64) -> Result<RemoteLock> {
+        if let Some(lock) = self.get_lock()? {
+            return Err(RemoteLocked(lock).into());
+        }
+        let lock = RemoteLock::new(holder, now);
+        self.blob_storage.upload_blocking(lock.to_bytes()?, Some(REMOTE_LOCK_KEY))?;
+        // the last of clients taking it at once has it
+        match self.get_lock()? {
+            Some(current) if current.token == lock.token => Ok(lock),
+            Some(current) => Err(RemoteLocked(current).into()),
+            None => anyhow::bail!("Remote lock was released while taking it"),
+        }
+    }
+
+    // a lock that was forced meanwhile is left to whoever has it now
+    pub fn unlock(&mut self, lock: &RemoteLock) -> Result<()> {
+        match self.get_lock()? {
+            Some(current) if current.token == lock.token => self.release_lock(current),
+            _ => {
+                debug!("Remote lock of {} was forced, not releasing it", lock.holder);
+                Ok(())
+            },
+        }
+    }
+
+    // releases the lock whoever has it, for a client that died with it; returns the lock that was released
+    pub fn force_unlock(&mut self) -> Result<Option<RemoteLock>> {
+        let lock = self.get_lock()?;
+        if let Some(lock) = &lock {
+            self.release_lock(lock.clone())?;
+        }
+        Ok(lock)
+    }
+
+    fn release_lock(&mut self, mut lock: RemoteLock) -> Result<()> {
+        lock.released = true;
+        self.blob_storage.upload_blocking(lock.to_bytes()?, Some(REMOTE_LOCK_KEY))?;
+        Ok(())
+    }
+
+    pub fn push_key_fingerprint(&mut self, fingerprint: &str) -> Result<()> {
+        self.blob_storage.upload_blocking(bytes::Bytes::from(fingerprint.to_string()), Some(KEY_FINGERPRINT_KEY))?;
+        Ok(())
+    }
+
+    // ok if the remote has no fingerprint (initialized before fingerprints existed)
+    pub fn check_key_fingerprint(&mut self, fingerprint: &str) -> Result<()> {
+        let exists = self.blob_storage.exists_blocking(KEY_FINGERPRINT_KEY)?;
+        if !exists {
+            debug!("Remote has no key fingerprint");
+            return Ok(());
+        }
+        // with another key decryption fails before we get to compare
+        let remote_fingerprint = match self.blob_storage.download_blocking(KEY_FINGERPRINT_KEY) {
+            Ok(info) => info.data,
+            Err(e) => anyhow::bail!("Cannot read the key fingerprint of the remote, the local key is probably not the archive key ({})", e),
+        };
+        if remote_fingerprint.as_ref() != fingerprint.as_bytes() {
+            anyhow::bail!("Local key does not match the key of the archive (fingerprint mismatch)");
+        }
+        Ok(())
+    }
+
+    pub fn get_manifest_signature(&mut self) -> Result<Option<bytes::Bytes>> {
+        let exists = self.blob_storage.exists_blocking(MANIFEST_SIGNATURE_KEY)?;
+        if !exists {
+            return Ok(None);
+        }
+        Ok(Some(self.blob_storage.download_blocking(MANIFEST_SIGNATURE_KEY)?.data))
+    }
+
+    pub fn push_manifest_signature(&mut self, signature: bytes::Bytes) -> Result<()> {
+        self.blob_storage.upload_blocking(signature, Some(MANIFEST_SIGNATURE_KEY))?;
+        Ok(())
+    }
+
+    // time spent in push/pull since the last call
+    pub fn take_timings(&mut self) -> Timings {
+        std::mem::take(&mut self.timings)
+    }
+
+    pub fn get_blob(&mut self, key: &str) -> Result<bytes::Bytes> {
+        let info = self.blob_storage.download_blocking(key)?;
+        Ok(info.data)
+    }
+
+    pub fn blob_exists(&mut self, key: &str) -> Result<bool> {
+        Ok(self.blob_storage.exists_blocking(key)?)
+    }
+
+    // size and time of one object without listing them all, None if the remote does not have it
+    pub fn object_metadata(&mut self, key: &str) -> Result<Option<blob_storage::ObjectMetadata>> {
+        Ok(self.blob_storage.metadata_blocking(key)?)
+    }
+
+    // items failing config.max_attempts times (counting previous runs, as per journal) are quarantined:
+    // their result is an error and the rest of the transfer goes on
+    // uploads are recorded in the upload journal, files it has from an interrupted push are not uploaded again
+    // files above config.chunk_threshold are uploaded in chunks (needs the bucket name, see set_bucket_name), a chunk
+    // failing is tried again on its own; they are not in the upload journal but dedup finds the chunks already uploaded
+    pub fn push(&mut self, paths: &Vec<PathBuf>, prefix_path: &Path, journal: &mut TransferJournal, uploads: &mut UploadJournal)
+            -> Result<(Vec<Option<PushResult>>, TransferReport)> {
+
+        use blob_storage::{TaskId, EventContent};
+        let config = self.config.clone();
+
+        // map from taskid to part and its size
+        let mut active_tasks: HashMap<TaskId, (Part, usize)> = HashMap::new();
+        let mut active_size = 0; // sum of size of parts being transferred
+        let mut results: Vec<Option<PushResult>> = vec![None; paths.len()];
+        let mut stamps: Vec<Option<FileStamp>> = vec![None; paths.len()];
+        let mut chunked: HashMap<usize, ChunkedPush> = HashMap::new();
+        let mut uploaded: Vec<bool> = vec![false; paths.len()]; // some blob of the file was, it wasn't all deduplicated
+        let mut report = TransferReport::default();
+        let mut pending: VecDeque<Part> = VecDeque::with_capacity(paths.len());
+        let mut retries = RetryQueue::new(self.clock.clone(), self.jitter.fork());
+        let upload_limit = config.upload_rate_limit.map(|rate| RateLimit::new(rate, self.clock.clone()));
+        // in memory besides the uploads in flight, up to as much as them
+        let mut reads = ReadAhead::new(config.read_threads, config.active_tasks_limit, config.active_size_limit);
+        let reader = PartReader {
+            prefix_path: prefix_path.to_path_buf(),
+            bucket_name: self.bucket_name.clone(),
+            chunk_threshold: config.chunk_threshold,
+            chunk_size: config.chunk_size,
+        };
+        let mut num_resumed = 0;
+        let mut num_deduplicated = 0;
+        for (index, path) in paths.iter().enumerate() {
+            if journal.is_quarantined(path, config.max_attempts) {
+                results[index] = Some(Err(quarantined_error(path)));
+            } else if let Some(info) = already_uploaded(uploads, path, &prefix_path.join(path)) {
+                results[index] = Some(Ok(PushedFile { info, chunks: Vec::new() }));
+                num_resumed += 1;
+            } else {
+                pending.push_back(Part::whole(index));
+            }
+        }
+        if num_resumed > 0 {
+            self.report(TransferEvent::Resumed { num_files: num_resumed });
+        }
+        if self.progress.is_some() {
+            let num_bytes = pending.iter()
+                .filter_map(|part| std::fs::metadata(prefix_path.join(&paths[part.index])).ok())
+                .map(|metadata| metadata.len())
+                .sum();
+            self.report(TransferEvent::Started { num_files: pending.len(), num_bytes });
+        }
+        let events = self.blob_storage.events();
+        let mut time_of_last_print = self.clock.now();
+        let mut total_transferred = 0;
+        let transfer_start = std::time::Instant::now();
+
+        while !pending.is_empty() || !active_tasks.is_empty() || !retries.is_empty() {
+            if self.cancel.is_cancelled() {
+                pending.clear();
+                retries.clear();
+            }
+            retries.move_due(&mut pending, active_tasks.is_empty());
+            while !pending.is_empty()
+                    && (active_size < config.active_size_limit || active_tasks.is_empty())
+                    && active_tasks.len() < config.active_tasks_limit
+                    && !self.cancel.is_cancelled() {
+                reader.read_ahead(&mut reads, &pending, paths, &stamps);
+                let part = pending.pop_front().unwrap();
+                let index = part.index;
+                if results[index].is_some() {
+                    reads.forget(&part);
+                    continue; // chunk of a file quarantined meanwhile
+                }
+                if !reads.is_requested(&part) {
+                    reader.request(&mut reads, part, paths, &stamps, 0);
+                }
+                // only what the reader threads were late for
+                let read = self.timings.time(Phase::Read, || reads.take(&part)).context("Reader threads stopped")?;
+                if part.chunk.is_none() {
+                    stamps[index] = read.stamp;
+                }
+                let file_size = stamps[index].as_ref().map_or(0, |stamp| stamp.size);
+                let data = match read.content {
+                    Ok(PartContent::Data(data)) => data,
+                    Ok(PartContent::Chunked(key)) => {
+                        let num_chunks = file_size.div_ceil(config.chunk_size) as usize;
+                        chunked.insert(index, ChunkedPush { key, chunks: vec![None; num_chunks] });
+                        for chunk in (0..num_chunks).rev() {
+                            pending.push_front(Part { index, chunk: Some(chunk) });
+                        }
+                        debug!("Pushing {} in {} chunks", paths[index].to_str().unwrap(), num_chunks);
+                        self.report(TransferEvent::FileStarted { index, path: paths[index].clone(), size: file_size });
+                        continue;
+                    },
+                    Err(e) => {
+                        let file_path = prefix_path.join(&paths[index]);
+                        let error = blob_storage::Error::new(blob_storage::ErrorKind::Io, format!("Reading {}: {}", file_path.to_str().unwrap(), e));
+                        self.push_failed(part, error, &config, journal, &paths[index], &mut results, &mut chunked, &mut retries, file_size);
+                        continue;
+                    }
+                };
+                let data_size = data.len();
+                match self.existing_upload(&data) {
+                    Ok(Some(info)) => {
+                        debug!("Remote already has blob {} of {}", info.key, paths[index].to_str().unwrap());
+                        num_deduplicated += 1;
+                        self.push_done(part, info, data_size, journal, &paths[index], &mut results, &mut chunked);
+                        continue;
+                    },
+                    Ok(None) => (),
+                    // the upload will tell if the remote is really unreachable
+                    Err(error) => debug!("Could not check if the remote has the blob of {}: {}", paths[index].to_str().unwrap(), error),
+                }
+                if let Some(limit) = &upload_limit {
+                    limit.take(data_size as u64);
+                }
+                if part.chunk.is_none() {
+                    self.report(TransferEvent::FileStarted { index, path: paths[index].clone(), size: data_size as u64 });
+                }
+                let task_id = self.blob_storage.upload(data, None);
+                active_tasks.insert(task_id, (part, data_size));
+                active_size += data_size;
+                debug!("Started task {} for {:?}", task_id.to_u64(), part);
+            }
+
+            if !active_tasks.is_empty() {
+                let event = match retries.recv(&events)? {
+                    Some(event) => event,
+                    None => continue, // a retry is due
+                };
+                debug!("Got event {}", event);
+                let (part, size) = active_tasks.remove(&event.id).context("Got event for unknown task")?;
+                let index = part.index;
+                active_size -= size;
+                if results[index].is_some() {
+                    continue; // chunk of a file quarantined meanwhile
+                }
+                match event.content {
+                    EventContent::UploadSuccess(info) => {
+                        if let (None, Some(stamp)) = (part.chunk, &stamps[index]) {
+                            uploads.record(UploadRecord {
+                                path: paths[index].clone(),
+                                size: stamp.size,
+                                modified_ns: stamp.modified_ns,
+                                blob_key: info.key.clone(),
+                                ciphertext_size: info.ciphertext_size,
+                                ciphertext_checksum: info.ciphertext_checksum.clone(),
+                            })?;
+                        }
+                        if let Some(dedup) = &mut self.dedup {
+                            dedup.known.insert(info.key.clone(), info.ciphertext_size);
+                        }
+                        total_transferred += size;
+                        report.plaintext_bytes += info.plaintext_size;
+                        report.ciphertext_bytes += info.ciphertext_size;
+                        uploaded[index] = true;
+                        self.push_done(part, info, size, journal, &paths[index], &mut results, &mut chunked);
+                    },
+                    content => {
+                        let error = match content {
+                            EventContent::Error(error) => error,
+                            other => blob_storage::unexpected_event(&other, "an upload result"),
+                        };
+                        let file_size = stamps[index].as_ref().map_or(0, |stamp| stamp.size);
+                        self.push_failed(part, error, &config, journal, &paths[index], &mut results, &mut chunked, &mut retries, file_size);
+                    },
+                }
+            }
+
+            let elapsed_since_last_print = self.clock.now() - time_of_last_print;
+            if elapsed_since_last_print > config.time_between_prints {
+                let num_done = results.iter().filter(|result| result.is_some()).count();
+                let num_active = active_tasks.len();
+                self.report(TransferEvent::Status { num_done, num_files: results.len(), num_active, transferred_bytes: total_transferred });
+                time_of_last_print = self.clock.now();
+            }
+        }
+
+        self.timings.add(Phase::Transfer, transfer_start.elapsed());
+        self.report(TransferEvent::Finished);
+        // what was uploaded is in uploads, for the next push to resume from
+        if self.cancel.is_cancelled() {
+            return Err(Cancelled.into());
+        }
+        if num_deduplicated > 0 {
+            self.report(TransferEvent::Deduplicated { num_files: num_deduplicated });
+        }
+
+        report.elapsed = transfer_start.elapsed();
+        report.num_retried = retries.num_scheduled();
+        for (index, result) in results.iter().enumerate() {
+            match result {
+                Some(Ok(_)) if uploaded[index] => report.num_files += 1,
+                Some(Ok(_)) => report.num_skipped += 1,
+                Some(Err(_)) => report.num_quarantined += 1,
+                None => (),
+            }
+        }
+        Ok((results, report))
+    }
+
+    // uploads size bytes of reader as the content of one file, without more than a chunk of it in memory: above
+    // config.chunk_threshold in chunks as push does, the key of the whole content computed as they are read
+    // one blob at a time and without retries, for content that is not in a file (import-tar)
+    pub fn push_reader(&mut self, reader: &mut dyn Read, size: u64) -> Result<PushedFile> {
+        let config = self.config.clone();
+        let mut reader = reader.take(size);
+        let mut read_blob = |max_size: u64| -> Result<bytes::Bytes> {
+            let mut data = Vec::with_capacity(max_size.min(size) as usize);
+            (&mut reader).take(max_size).read_to_end(&mut data)?;
+            Ok(bytes::Bytes::from(data))
+        };
+        let bucket_name = match &self.bucket_name {
+            Some(bucket_name) if size > config.chunk_threshold => bucket_name.clone(),
+            _ => {
+                let data = read_blob(size)?;
+                if data.len() as u64 != size {
+                    anyhow::bail!("Read {} bytes out of {}", data.len(), size);
+                }
+                return Ok(PushedFile { info: self.push_blob(data)?, chunks: Vec::new() });
+            },
+        };
+        let mut hasher = blob_storage::hash_name_hasher(&bucket_name);
+        let mut chunks = Vec::new();
+        loop {
+            let data = read_blob(config.chunk_size)?;
+            if data.is_empty() {
+                break;
+            }
+            hasher.update(&data);
+            chunks.push(Some(self.push_blob(data)?));
+        }
+        let pushed = ChunkedPush { key: hasher.finalize().to_hex().to_string(), chunks }.into_pushed_file();
+        if pushed.info.plaintext_size != size {
+            anyhow::bail!("Read {} bytes out of {}", pushed.info.plaintext_size, size);
+        }
+        Ok(pushed)
+    }
+
+    fn push_blob(&mut self, data: bytes::Bytes) -> Result<blob_storage::UploadInfo> {
+        if let Some(info) = self.existing_upload(&data)? {
+            return Ok(info);
+        }
+        let info = self.blob_storage.upload_blocking(data, None)?;
+        if let Some(dedup) = &mut self.dedup {
+            dedup.known.insert(info.key.clone(), info.ciphertext_size);
+        }
+        Ok(info)
+    }
+
+    // part of the file at index (paths[index] is path) is uploaded, so is the file once all of its chunks are
+    #[allow(clippy::too_many_arguments)]
+    fn push_done(&mut self, part: Part, info: blob_storage::UploadInfo, size: usize, journal: &mut TransferJournal, path: &Path,
+            results: &mut [Option<PushResult>], chunked: &mut HashMap<usize, ChunkedPush>) {
+        let index = part.index;
+        let pushed = match part.chunk {
+            None => Some(PushedFile { info, chunks: Vec::new() }),
+            Some(chunk) => {
+                self.report(TransferEvent::ChunkDone { index, size: size as u64 });
+                let file = chunked.get_mut(&index).unwrap();
+                file.chunks[chunk] = Some(info);
+                file.chunks.iter().all(Option::is_some).then(|| chunked.remove(&index).unwrap().into_pushed_file())
+            },
+        };
+        if let Some(pushed) = pushed {
+            journal.record_success(path);
+            results[index] = Some(Ok(pushed));
+            let size = if part.chunk.is_some() { 0 } else { size as u64 };
+            self.report(TransferEvent::FileDone { index, size });
+        }
+    }
+
+    // a chunk is tried again on its own, but its failures count for the whole file
+    #[allow(clippy::too_many_arguments)]
+    fn push_failed(&mut self, part: Part, error: blob_storage::Error, config: &TransferConfig, journal: &mut TransferJournal, path: &Path,
+            results: &mut [Option<PushResult>], chunked: &mut HashMap<usize, ChunkedPush>, retries: &mut RetryQueue, file_size: u64) {
+        let index = part.index;
+        let retrying = retry_or_quarantine(journal, path, &error, config.max_attempts);
+        let reason = error.msg.clone();
+        if retrying {
+            retries.schedule(part, journal.attempts(path), config);
+        } else {
+            results[index] = Some(Err(error));
+        }
+        let size = match part.chunk {
+            None => file_size,
+            Some(_) if retrying => return, // the file goes on with its other chunks
+            Some(_) => file_size - chunked.remove(&index).unwrap().uploaded_size(),
+        };
+        self.report(TransferEvent::FileFailed { index, size, retrying, reason });
+    }
+
+    // returns the archive paths that were quarantined (see push), and the report
+    // files are written to their partial path (a file in chunks as they come) and renamed when complete, so that
+    // a crash doesn't leave truncated files that look pulled
+    pub fn pull(&mut self, files: &Vec<PullFile>, prefix_path: &Path, journal: &mut TransferJournal) -> Result<(Vec<PathBuf>, TransferReport)> {
+
+        use blob_storage::{TaskId, EventContent};
+        let config = self.config.clone();
+
+        // map from taskid to part and its size
+        let mut active_tasks: HashMap<TaskId, (Part, usize)> = HashMap::new();
+        let mut active_size = 0; // sum of size of parts being transferred
+        let mut quarantined = Vec::new();
+        let mut retries = RetryQueue::new(self.clock.clone(), self.jitter.fork());
+        let download_limit = config.download_rate_limit.map(|rate| RateLimit::new(rate, self.clock.clone()));
+        let mut pending: VecDeque<Part> = VecDeque::with_capacity(files.len());
+        let mut remaining_chunks: Vec<usize> = files.iter().map(|file| file.chunks.len()).collect();
+        let mut written_sizes: Vec<u64> = vec![0; files.len()]; // of the chunks of each file
+        let mut failed: Vec<bool> = vec![false; files.len()];
+        let mut report = TransferReport::default();
+        let mut num_bytes = 0;
+        let mut num_skipped = 0;
+        let pull_conflict = self.pull_conflict;
+        for (index, file) in files.iter().enumerate() {
+            if journal.is_quarantined(&file.path, config.max_attempts) {
+                quarantined.push(file.path.clone());
+                continue;
+            }
+            if pull_conflict == PullConflict::SkipExisting && prefix_path.join(&file.path).exists() {
+                debug!("Skipping {}, it exists locally", file.path.to_str().unwrap());
+                num_skipped += 1;
+                continue;
+            }
+            // left by a pull that crashed, chunks must not be mixed with it
+            let _ = std::fs::remove_file(partial_path(&prefix_path.join(&file.path)));
+            num_bytes += file.size as u64;
+            if file.chunks.is_empty() {
+                pending.push_back(Part::whole(index));
+            } else {
+                pending.extend((0..file.chunks.len()).map(|chunk| Part { index, chunk: Some(chunk) }));
+            }
+        }
+        self.report(TransferEvent::Started { num_files: files.len() - quarantined.len() - num_skipped, num_bytes });
+        let events = self.blob_storage.events();
+        let mut time_of_last_print = self.clock.now();
+        let mut num_done = num_skipped;
+        let mut total_transferred = 0;
+        let transfer_start = std::time::Instant::now();
+
+        while !pending.is_empty() || !active_tasks.is_empty() || !retries.is_empty() {
+            if self.cancel.is_cancelled() {
+                pending.clear();
+                retries.clear();
+            }
+            retries.move_due(&mut pending, active_tasks.is_empty());
+            while !pending.is_empty()
+                    && (active_size < config.active_size_limit || active_tasks.is_empty())
+                    && active_tasks.len() < config.active_tasks_limit
+                    && !self.cancel.is_cancelled() {
+                let part = pending.pop_front().unwrap();
+                let file = &files[part.index];
+                if failed[part.index] {
+                    continue;
+                }
+                let (key, data_size) = match part.chunk {
+                    None => (file.key.as_str(), file.size),
+                    Some(chunk) => (file.chunks[chunk].key.as_str(), file.chunks[chunk].size as usize),
+                };
+                if let Some(limit) = &download_limit {
+                    limit.take(data_size as u64);
+                }
+                let task_id = self.blob_storage.download(key);
+                active_tasks.insert(task_id, (part, data_size));
+                active_size += data_size;
+                if part.chunk.unwrap_or(0) == 0 {
+                    self.report(TransferEvent::FileStarted { index: part.index, path: file.path.clone(), size: file.size as u64 });
+                }
+                debug!("Started task {} for {:?}", task_id.to_u64(), part);
+            }
+
+            if !active_tasks.is_empty() {
+                let event = match retries.recv(&events)? {
+                    Some(event) => event,
+                    None => continue, // a retry is due
+                };
+                debug!("Got event {}", event);
+                let (part, size) = active_tasks.remove(&event.id).context("Got event for unknown task")?;
+                let index = part.index;
+                let file = &files[index];
+                active_size -= size;
+                if failed[index] {
+                    continue;
+                }
+
+                let file_path = prefix_path.join(&file.path);
+                let partial_file_path = partial_path(&file_path);
+                let mut downloaded = (0, 0); // plaintext and ciphertext sizes
+                let result = match event.content {
+                    EventContent::Error(error) => Err(error),
+                    EventContent::DownloadSuccess(info) => {
+                        downloaded = (info.data.len() as u64, info.ciphertext_size);
+                        let written = match part.chunk {
+                            None => self.timings.time(Phase::Write, || {
+                                std::fs::write(&partial_file_path, info.data)?;
+                                put_in_place(&partial_file_path, &file_path, pull_conflict)
+                            }),
+                            Some(chunk) => {
+                                let offset = file.chunks[..chunk].iter().map(|chunk| chunk.size).sum();
+                                self.timings.time(Phase::Write, || {
+                                    write_at(&partial_file_path, offset, &info.data)?;
+                                    if remaining_chunks[index] == 1 {
+                                        put_in_place(&partial_file_path, &file_path, pull_conflict)?;
+                                    }
+                                    Ok(())
+                                })
+                            },
+                        };
+                        written.map_err(|e| blob_storage::Error::new(blob_storage::ErrorKind::Io, format!("Writing {}: {}", file_path.to_str().unwrap(), e)))
+                    },
+                    other => Err(blob_storage::unexpected_event(&other, "a download result")),
+                };
+
+                match result {
+                    Ok(()) => {
+                        total_transferred += size;
+                        report.plaintext_bytes += downloaded.0;
+                        report.ciphertext_bytes += downloaded.1;
+                        let file_done = match part.chunk {
+                            None => true,
+                            Some(_) => {
+                                self.report(TransferEvent::ChunkDone { index, size: size as u64 });
+                                written_sizes[index] += size as u64;
+                                remaining_chunks[index] -= 1;
+                                remaining_chunks[index] == 0
+                            },
+                        };
+                        if file_done {
+                            journal.record_success(&file.path);
+                            num_done += 1;
+                            report.num_files += 1;
+                            let size = if part.chunk.is_some() { 0 } else { size as u64 };
+                            self.report(TransferEvent::FileDone { index, size });
+                        }
+                    },
+                    Err(error) => {
+                        let retrying = retry_or_quarantine(journal, &file.path, &error, config.max_attempts);
+                        if retrying {
+                            retries.schedule(part, journal.attempts(&file.path), &config);
+                        } else {
+                            num_done += 1;
+                            failed[index] = true;
+                            quarantined.push(file.path.clone());
+                        }
+                        let size = match part.chunk {
+                            None => size as u64,
+                            Some(_) if retrying => continue, // the file goes on with its other chunks
+                            Some(_) => {
+                                let _ = std::fs::remove_file(&partial_file_path);
+                                file.size as u64 - written_sizes[index]
+                            },
+                        };
+                        self.report(TransferEvent::FileFailed { index, size, retrying, reason: error.msg });
+                    }
+                }
+            }
+
+            let elapsed_since_last_print = self.clock.now() - time_of_last_print;
+            if elapsed_since_last_print > config.time_between_prints {
+                let num_active = active_tasks.len();
+                self.report(TransferEvent::Status { num_done, num_files: files.len(), num_active, transferred_bytes: total_transferred });
+                time_of_last_print = self.clock.now();
+            }
+        }
+
+        self.timings.add(Phase::Transfer, transfer_start.elapsed());
+        self.report(TransferEvent::Finished);
+        if self.cancel.is_cancelled() {
+            // files missing some chunks, the next pull gets them again
+            for (index, file) in files.iter().enumerate() {
+                if remaining_chunks[index] > 0 && written_sizes[index] > 0 && !failed[index] {
+                    let _ = std::fs::remove_file(partial_path(&prefix_path.join(&file.path)));
+                }
+            }
+            return Err(Cancelled.into());
+        }
+
+        report.elapsed = transfer_start.elapsed();
+        report.num_retried = retries.num_scheduled();
+        report.num_skipped = num_skipped;
+        report.num_quarantined = quarantined.len();
+        Ok((quarantined, report))
+    }
+}
+
+// records the failure, true if the item should be tried again
+fn retry_or_quarantine(journal: &mut TransferJournal, path: &Path, error: &blob_storage::Error, max_attempts: u32) -> bool {
+    let attempts = journal.record_failure(path, &error.msg);
+    debug!("Attempt {} failed for {}: {}", attempts, path.to_str().unwrap(), error);
+    attempts < max_attempts
+}
+
+// size and modification time of a file, to tell if it changed since it was uploaded
+#[derive(Clone)]
+struct FileStamp {
+    size: u64,
+    modified_ns: u64,
+}
+
+impl FileStamp {
+    fn of(path: &Path) -> Option<Self> {
+        let metadata = std::fs::metadata(path).ok()?;
+        let modified = metadata.modified().ok()?.duration_since(std::time::UNIX_EPOCH).ok()?;
+        Some(Self { size: metadata.len(), modified_ns: u64::try_from(modified.as_nanos()).ok()? })
+    }
+}
+
+// what a transfer is made of: a whole file, or one chunk of a file stored in chunks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct Part {
+    index: usize, // of the file
+    chunk: Option<usize>,
+}
+
+impl Part {
+    fn whole(index: usize) -> Self {
+        Self { index, chunk: None }
+    }
+}
+
+// what a reader thread of push got for a part
+struct PartRead {
+    stamp: Option<FileStamp>, // taken before reading, of whole parts only
+    content: std::io::Result<PartContent>,
+}
+
+enum PartContent {
+    Data(bytes::Bytes),
+    Chunked(String), // key of the content of a file too big for one blob, its chunks are read next
+}
+
+// reads the parts of push on the threads of a ReadAhead
+struct PartReader {
+    prefix_path: PathBuf,
+    bucket_name: Option<String>,
+    chunk_threshold: u64,
+    chunk_size: u64,
+}
+
+impl PartReader {
+    // requests the parts at the front of pending, as many as reads has room for
+    fn read_ahead(&self, reads: &mut ReadAhead<Part, PartRead>, pending: &VecDeque<Part>, paths: &[PathBuf], stamps: &[Option<FileStamp>]) {
+        for part in pending {
+            if reads.is_requested(part) {
+                continue;
+            }
+            let size = match part.chunk {
+                None => match std::fs::metadata(self.prefix_path.join(&paths[part.index])) {
+                    Ok(metadata) if self.is_chunked(metadata.len()) => 0, // only hashed
+                    Ok(metadata) => metadata.len() as usize,
+                    Err(_) => 0,
+                },
+                Some(chunk) => {
+                    let file_size = stamps[part.index].as_ref().map_or(0, |stamp| stamp.size);
+                    let range = chunk_range(chunk, file_size, self.chunk_size);
+                    (range.end - range.start) as usize
+                },
+            };
+            if !reads.has_room(size) {
+                break;
+            }
+            self.request(reads, *part, paths, stamps, size);
+        }
+    }
+
+    fn request(&self, reads: &mut ReadAhead<Part, PartRead>, part: Part, paths: &[PathBuf], stamps: &[Option<FileStamp>], size: usize) {
+        let file_path = self.prefix_path.join(&paths[part.index]);
+        match part.chunk {
+            None => {
+                let bucket_name = self.bucket_name.clone();
+                let chunk_threshold = self.chunk_threshold;
+                reads.request(part, size, move || {
+                    let stamp = FileStamp::of(&file_path);
+                    let file_size = stamp.as_ref().map_or(0, |stamp| stamp.size);
+                    let content = match bucket_name {
+                        Some(bucket_name) if file_size > chunk_threshold => blob_storage::get_hash_name_of_file(&bucket_name, &file_path).map(PartContent::Chunked),
+                        _ => std::fs::read(&file_path).map(|data| PartContent::Data(bytes::Bytes::from(data))),
+                    };
+                    PartRead { stamp, content }
+                });
+            },
+            Some(chunk) => {
+                let file_size = stamps[part.index].as_ref().map_or(0, |stamp| stamp.size);
+                let range = chunk_range(chunk, file_size, self.chunk_size);
+                reads.request(part, size, move || PartRead { stamp: None, content: read_range(&file_path, range).map(PartContent::Data) });
+            },
+        }
+    }
+
+    fn is_chunked(&self, file_size: u64) -> bool {
+        self.bucket_name.is_some() && file_size > self.chunk_threshold
+    }
+}
+
+// a file being pushed in chunks, done when all of them are
+struct ChunkedPush {
+    key: String, // of the whole content
+    chunks: Vec<Option<blob_storage::UploadInfo>>,
+}
+
+impl ChunkedPush {
+    fn uploaded_size(&self) -> u64 {
+        self.chunks.iter().flatten().map(|info| info.plaintext_size).sum()
+    }
+
+    fn into_pushed_file(self) -> PushedFile {
+        let chunks: Vec<blob_storage::UploadInfo> = self.chunks.into_iter().map(Option::unwrap).collect();
+        let info = blob_storage::UploadInfo {
+            key: self.key,
+            plaintext_size: chunks.iter().map(|chunk| chunk.plaintext_size).sum(),
+            ciphertext_size: chunks.iter().map(|chunk| chunk.ciphertext_size).sum(),
+            ciphertext_checksum: String::new(), // there is no such blob
+            duration: chunks.iter().map(|chunk| chunk.duration).sum(),
+        };
+        PushedFile { info, chunks }
+    }
+}
+
+fn chunk_range(chunk: usize, file_size: u64, chunk_size: u64) -> std::ops::Range<u64> {
+    let start = chunk as u64 * chunk_size;
+    start..(start + chunk_size).min(file_size)
+}
+
+fn read_range(path: &Path, range: std::ops::Range<u64>) -> std::io::Result<bytes::Bytes> {
+    use std::io::{Read, Seek};
+    let mut file = std::fs::File::open(path)?;
+    file.seek(std::io::SeekFrom::Start(range.start))?;
+    let mut data = vec![0; (range.end - range.start) as usize];
+    file.read_exact(&mut data)?;
+    Ok(bytes::Bytes::from(data))
+}
+
+fn partial_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(PARTIAL_SUFFIX);
+    path.with_file_name(name)
+}
+
+// renames a pulled file from its partial path to path, unless a local file there is to be kept
+fn put_in_place(partial_path: &Path, path: &Path, pull_conflict: PullConflict) -> std::io::Result<()> {
+    if path.exists() {
+        match pull_conflict {
+            PullConflict::SkipExisting => return std::fs::remove_file(partial_path), // made while it was pulled
+            PullConflict::Overwrite => (),
+            PullConflict::BackupExisting => std::fs::rename(path, backup_path(path))?,
+        }
+    }
+    std::fs::rename(partial_path, path)
+}
+
+// the first of name.har-backup, name.har-backup.1, name.har-backup.2... that is free
+fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(BACKUP_SUFFIX);
+    let mut backup = path.with_file_name(&name);
+    let mut num = 0;
+    while backup.exists() {
+        num += 1;
+        backup = path.with_file_name(format!("{}.{}", name.to_str().unwrap(), num));
+    }
+    backup
+}
+
+pub fn is_partial(path: &Path) -> bool {
+    path.file_name().and_then(|name| name.to_str()).is_some_and(|name| name.ends_with(PARTIAL_SUFFIX))
+}
+
+// partial files of pulls that crashed, anywhere under root (.har aside), returns how many were removed
+pub fn remove_stale_partials(root: &Path) -> Result<usize> {
+    let mut num_removed = 0;
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(&dir).with_context(|| format!("Reading {}", dir.to_str().unwrap()))? {
+            let entry = entry?;
+            let path = entry.path();
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() && path != root.join(crate::scan::DOT_HAR_NAME) {
+                dirs.push(path);
+            } else if file_type.is_file() && is_partial(&path) {
+                debug!("Removing stale {}", path.to_str().unwrap());
+                std::fs::remove_file(&path).with_context(|| format!("Removing {}", path.to_str().unwrap()))?;
+                num_removed += 1;
+            }
+        }
+    }
+    Ok(num_removed)
+}
+
+// chunks are written as they come, in any order
+fn write_at(path: &Path, offset: u64, data: &[u8]) -> std::io::Result<()> {
+    use std::io::{Seek, Write};
+    let mut file = std::fs::OpenOptions::new().write(true).create(true).truncate(false).open(path)?;
+    file.seek(std::io::SeekFrom::Start(offset))?;
+    file.write_all(data)
+}
+
+// upload of an interrupted push, if the file did not change since
+fn already_uploaded(uploads: &UploadJournal, path: &Path, file_path: &Path) -> Option<blob_storage::UploadInfo> {
+    let record = uploads.get(path)?;
+    let stamp = FileStamp::of(file_path)?;
+    if stamp.size != record.size || stamp.modified_ns != record.modified_ns {
+        debug!("{} changed since its upload, pushing it again", path.to_str().unwrap());
+        return None;
+    }
+    Some(blob_storage::UploadInfo {
+        key: record.blob_key.clone(),
+        plaintext_size: record.size,
+        ciphertext_size: record.ciphertext_size,
+        ciphertext_checksum: record.ciphertext_checksum.clone(),
+        duration: std::time::Duration::ZERO,
+    })
+}
+
+// items waiting to be tried again, each after its own backoff
+struct RetryQueue {
+    delayed: Vec<(std::time::Instant, Part)>,
+    clock: Arc<dyn Clock>,
+    jitter: Jitter,
+    num_scheduled: usize,
+}
+
+impl RetryQueue {
+    fn new(clock: Arc<dyn Clock>, jitter: Jitter) -> Self {
+        Self { delayed: Vec::new(), clock, jitter, num_scheduled: 0 }
+    }
+
+    // retries scheduled so far, including the ones cleared
+    fn num_scheduled(&self) -> usize {
+        self.num_scheduled
+    }
+
+    fn is_empty(&self) -> bool {
+        self.delayed.is_empty()
+    }
+
+    fn clear(&mut self) {
+        self.delayed.clear();
+    }
+
+    // the backoff doubles with every failed attempt, jittered so that items failing together don't retry together
+    fn schedule(&mut self, part: Part, attempts: u32, config: &TransferConfig) {
+        let backoff = config.retry_backoff.saturating_mul(1 << attempts.saturating_sub(1).min(16)).min(config.max_retry_backoff);
+        let backoff = self.jitter.apply(backoff);
+        debug!("Retrying {:?} in {:?}", part, backoff);
+        self.delayed.push((self.clock.now() + backoff, part));
+        self.num_scheduled += 1;
+    }
+
+    fn next_due(&self) -> Option<std::time::Instant> {
+        self.delayed.iter().map(|(due, _)| *due).min()
+    }
+
+    // with nothing else to wait for, sleeps until the first retry is due
+    fn move_due(&mut self, pending: &mut VecDeque<Part>, idle: bool) {
+        if idle && pending.is_empty() {
+            if let Some(due) = self.next_due() {
+                self.clock.sleep(due.saturating_duration_since(self.clock.now()));
+            }
+        }
+        let now = self.clock.now();
+        // in the order they are due, for the same order on every run
+        self.delayed.sort();
+        self.delayed.retain(|&(due, part)| {
+            if due <= now {
+                pending.push_back(part);
+            }
+            due > now
+        });
+    }
+
+    // next event of the storage, None if a retry became due first
+    fn recv(&self, events: &crate::thread_sync::Receiver<blob_storage::Event>) -> Result<Option<blob_storage::Event>> {
+        use std::sync::mpsc::RecvTimeoutError;
+        match self.next_due().and_then(|due| self.clock.wait_limit(due)) {
+            Some(timeout) => match events.recv_timeout(timeout) {
+                Ok(event) => Ok(Some(event)),
+                Err(RecvTimeoutError::Timeout) => Ok(None),
+                Err(RecvTimeoutError::Disconnected) => anyhow::bail!("Blob storage events disconnected"),
+            },
+            None => Ok(Some(events.recv()?)),
+        }
+    }
+}
+
+fn quarantined_error(path: &Path) -> blob_storage::Error {
+    blob_storage::Error::new(blob_storage::ErrorKind::Other, format!("{} is quarantined after failing too many times", path.to_str().unwrap()))
+}
+
+// the [transfer] table of the har settings, unset values keep the defaults of TransferConfig
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TransferSettings {
+    pub active_tasks_limit: Option<usize>,
+    pub active_size_limit: Option<usize>,
+    pub max_attempts: Option<u32>,
+    pub retry_backoff_ms: Option<u64>,
+    pub max_retry_backoff_ms: Option<u64>,
+    pub chunk_threshold: Option<u64>, // bytes
+    pub chunk_size: Option<u64>,
+    pub upload_rate_limit: Option<u64>, // bytes per second
+    pub download_rate_limit: Option<u64>,
+    pub status_interval_ms: Option<u64>, // between status lines, when there are no progress bars
+    pub read_threads: Option<usize>, // reading the files of push ahead of their upload
+}
+
+#[derive(Clone)]
+pub struct TransferConfig {
+    active_tasks_limit: usize,
+    active_size_limit: usize,
+    time_between_prints: std::time::Duration,
+    max_attempts: u32, // before an item is quarantined
+    retry_backoff: std::time::Duration, // before the first retry of an item, doubled for each next one
+    max_retry_backoff: std::time::Duration,
+    chunk_threshold: u64, // files above are transferred in chunks of chunk_size, each its own blob
+    chunk_size: u64,
+    upload_rate_limit: Option<u64>, // bytes per second, for all the uploads of a push together
+    download_rate_limit: Option<u64>,
+    read_threads: usize, // of push, see read_ahead::ReadAhead
+}
+
+impl TransferConfig {
+    pub fn from_settings(settings: &TransferSettings) -> Self {
+        let default = Self::default();
+        Self {
+            active_tasks_limit: settings.active_tasks_limit.unwrap_or(default.active_tasks_limit).max(1),
+            active_size_limit: settings.active_size_limit.unwrap_or(default.active_size_limit),
+            max_attempts: settings.max_attempts.unwrap_or(default.max_attempts),
+            retry_backoff: settings.retry_backoff_ms.map_or(default.retry_backoff, std::time::Duration::from_millis),
+            max_retry_backoff: settings.max_retry_backoff_ms.map_or(default.max_retry_backoff, std::time::Duration::from_millis),
+            chunk_threshold: settings.chunk_threshold.unwrap_or(default.chunk_threshold),
+            chunk_size: settings.chunk_size.unwrap_or(default.chunk_size).max(1),
+            upload_rate_limit: settings.upload_rate_limit.or(default.upload_rate_limit),
+            download_rate_limit: settings.download_rate_limit.or(default.download_rate_limit),
+            time_between_prints: settings.status_interval_ms.map_or(default.time_between_prints, std::time::Duration::from_millis),
+            read_threads: settings.read_threads.unwrap_or(default.read_threads).max(1),
+        }
+    }
+
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+}
+
+impl Default for TransferConfig {
+    fn default() -> Self {
+        Self {
+            active_size_limit: 10_000_000,
+            active_tasks_limit: 32,
+            time_between_prints: std::time::Duration::from_millis(800),
+            max_attempts: 3,
+            retry_backoff: std::time::Duration::from_secs(1),
+            max_retry_backoff: std::time::Duration::from_secs(30),
+            chunk_threshold: 64 * 1024 * 1024,
+            chunk_size: 8 * 1024 * 1024,
+            upload_rate_limit: None,
+            download_rate_limit: None,
+            read_threads: 4,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+    use crate::blob_storage_local_directory::BlobStorageLocalDirectory;
+    use crate::clock::VirtualClock;
+    use std::io::Write;
+    use std::time::Duration;
+
+    pub fn make_dummy_keyfile() -> NamedTempFile {
+        let mut keyfile = NamedTempFile::new().expect("create tempfile for dummy encryption key");
+        let key: [u8; 32] = [1, 2, 3, 4, 5, 6, 7, 8, 1, 2, 3, 4, 5, 6, 7, 8, 1, 2, 3, 4, 5, 6, 7, 8, 1, 2, 3, 4, 5, 6, 7, 8];
+        keyfile.write_all(&key).expect("write key file content");
+        keyfile
+    }
+    
+    pub fn make_dummy_blob_storage(dirpath: &Path) -> BlobStorageLocalDirectory {
+        let keyfile = make_dummy_keyfile();
+        BlobStorageLocalDirectory::new(dirpath, keyfile.path()).expect("create blob storage")
+    }
+
+    pub fn make_files(num_files: usize, file_size: usize) -> Vec<NamedTempFile> {
+        let mut files = Vec::new();
+        let big_data_buf: Vec<u8> = vec![42; file_size];
+        for _ in 0..num_files {
+            let mut file = NamedTempFile::new().expect("Create file to transfer");
+            file.write_all(&big_data_buf).expect("Write file to transfer");
+            files.push(file);
+        }
+        files
+    }
+
+    #[test]
+    fn push0() -> Result<()> {
+
+        let tempdir = tempfile::tempdir().expect("create tempdir for local blob storage");
+        let blob_storage = make_dummy_blob_storage(tempdir.path());
+
+        let config = TransferConfig { active_size_limit: 10_000_000, active_tasks_limit: 32, time_between_prints: Duration::from_millis(0), max_attempts: 3, ..TransferConfig::default() };
+        let mut mirror = Mirror::builder().storage(Box::new(blob_storage)).transfer_config(config).build()?;
+        let files = make_files(5, 1000);
+        let paths: Vec<PathBuf> = files.iter().map(|f| PathBuf::from(f.path())).collect();
+        mirror.push(&paths, Path::new(""), &mut TransferJournal::default(), &mut UploadJournal::default())?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn push1() -> Result<()> {
+
+        let tempdir = tempfile::tempdir().expect("create tempdir for local blob storage");
+        let blob_storage = make_dummy_blob_storage(tempdir.path());
+
+        let config = TransferConfig { active_size_limit: 100, active_tasks_limit: 32, time_between_prints: Duration::from_millis(0), max_attempts: 3, ..TransferConfig::default() };
+        let mut mirror = Mirror::builder().storage(Box::new(blob_storage)).transfer_config(config).build()?;
+        let files = make_files(5, 1000);
+        let paths: Vec<PathBuf> = files.iter().map(|f| PathBuf::from(f.path())).collect();
+        mirror.push(&paths, Path::new(""), &mut TransferJournal::default(), &mut UploadJournal::default())?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn config_from_settings() {
+        let settings = TransferSettings { active_tasks_limit: Some(0), active_size_limit: Some(1000), status_interval_ms: Some(2000), ..Default::default() };
+        let config = TransferConfig::from_settings(&settings);
+        assert_eq!((config.active_tasks_limit, config.active_size_limit), (1, 1000));
+        assert_eq!(config.time_between_prints, Duration::from_secs(2));
+        assert_eq!(config.max_attempts, TransferConfig::default().max_attempts);
+    }
+
+    #[test]
+    fn forked_jitter_follows_the_seed() {
+        let tempdir = tempfile::tempdir().expect("create tempdir for local blob storage");
+        let sample = |seed| {
+            let mut mirror = Mirror::new(Box::new(make_dummy_blob_storage(tempdir.path())));
+            mirror.set_deterministic(seed, Arc::new(VirtualClock::new()));
+            let mut jitter = mirror.fork_jitter();
+            (0..8).map(|_| jitter.below(1000)).collect::<Vec<_>>()
+        };
+        assert_eq!(sample(3), sample(3));
+        assert_ne!(sample(3), sample(4));
+    }
+
+    #[test]
+    fn builder_applies_retry_over_config() -> Result<()> {
+        assert!(Mirror::builder().build().is_err());
+        let tempdir = tempfile::tempdir()?;
+        let mirror = Mirror::builder()
+            .retry(5, Duration::from_millis(1), Duration::from_millis(2))
+            .transfer_config(TransferConfig { max_attempts: 1, active_tasks_limit: 4, ..TransferConfig::default() })
+            .storage(Box::new(make_dummy_blob_storage(tempdir.path())))
+            .build()?;
+        let config = mirror.transfer_config();
+        assert_eq!((config.max_attempts, config.active_tasks_limit), (5, 4));
+        assert_eq!(config.max_retry_backoff, Duration::from_millis(2));
+        Ok(())
+    }
+
+    #[test]
+    fn push_quarantines_failing_file() -> Result<()> {
+
+        let tempdir = tempfile::tempdir().expect("create tempdir for local blob storage");
+        let files = make_files(3, 1000);
+        let mut paths: Vec<PathBuf> = files.iter().map(|f| PathBuf::from(f.path())).collect();
+        let missing = tempdir.path().join("does_not_exist");
+        paths.insert(1, missing.clone());
+
+        let push = |seed| -> Result<Duration> {
+            let blob_storage = make_dummy_blob_storage(tempdir.path());
+            let config = TransferConfig { active_size_limit: 10_000_000, active_tasks_limit: 32, time_between_prints: Duration::from_millis(0), ..TransferConfig::default() };
+            let mut mirror = Mirror::builder()
+                .storage(Box::new(blob_storage))
+                .transfer_config(config)
+                .retry(3, Duration::from_millis(20), Duration::from_millis(30))
+                .build()?;
+            let clock = VirtualClock::new();
+            mirror.set_deterministic(seed, Arc::new(clock.clone()));
+
+            let mut journal = TransferJournal::default();
+            let (results, report) = mirror.push(&paths, Path::new(""), &mut journal, &mut UploadJournal::default())?;
+
+            assert!(results[1].as_ref().unwrap().is_err());
+            assert_eq!((report.num_files, report.num_retried, report.num_quarantined), (3, 2, 1));
+            assert_eq!(results.iter().filter(|result| result.as_ref().unwrap().is_ok()).count(), 3);
+            assert!(journal.is_quarantined(&missing, 3));
+            assert_eq!(journal.quarantined(3).len(), 1);
+            Ok(clock.elapsed())
+        };
+
+        // retried after 20ms, then after 40ms capped to 30ms, both cut by up to a quarter
+        let elapsed = push(1)?;
+        assert!(elapsed >= Duration::from_micros(37_500) && elapsed <= Duration::from_millis(50));
+        assert_eq!(push(1)?, elapsed);
+
+        Ok(())
+    }
+
+    #[test]
+    fn push_skips_existing_blobs() -> Result<()> {
+
+        let tempdir = tempfile::tempdir().expect("create tempdir for local blob storage");
+        let blob_storage = make_dummy_blob_storage(tempdir.path());
+        let config = TransferConfig { active_size_limit: 10_000_000, active_tasks_limit: 32, time_between_prints: Duration::from_secs(60), max_attempts: 3, ..TransferConfig::default() };
+        let mut mirror = Mirror::builder()
+            .storage(Box::new(blob_storage))
+            .transfer_config(config)
+            .bucket_name(tempdir.path().to_str().unwrap().to_string())
+            .build()?;
+
+        let files = make_files(2, 1000);
+        std::fs::write(files[1].path(), std::fs::read(files[0].path())?)?;
+        let paths: Vec<PathBuf> = files.iter().map(|f| PathBuf::from(f.path())).collect();
+        let (first, _) = mirror.push(&paths[..1].to_vec(), Path::new(""), &mut TransferJournal::default(), &mut UploadJournal::default())?;
+        let first = first[0].clone().unwrap().unwrap().info;
+
+        // found with exists(), the size is not known
+        mirror.set_dedup(Some(Dedup::new(HashMap::new())));
+        let events = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        mirror.set_progress(Some(Box::new(RecordProgress(events.clone()))));
+        let (second, report) = mirror.push(&paths[1..].to_vec(), Path::new(""), &mut TransferJournal::default(), &mut UploadJournal::default())?;
+        assert_eq!(events.lock().unwrap().last(), Some(&TransferEvent::Deduplicated { num_files: 1 }));
+        assert_eq!((report.num_files, report.num_skipped, report.ciphertext_bytes), (0, 1, 0));
+        let second = second[0].clone().unwrap().unwrap().info;
+        assert_eq!(second.key, first.key);
+        assert_eq!(second.ciphertext_size, 0);
+
+        // known from the remote manifest
+        mirror.set_dedup(Some(Dedup::new(HashMap::from([(first.key.clone(), first.ciphertext_size)]))));
+        let (third, _) = mirror.push(&paths[1..].to_vec(), Path::new(""), &mut TransferJournal::default(), &mut UploadJournal::default())?;
+        assert_eq!(third[0].clone().unwrap().unwrap().info.ciphertext_size, first.ciphertext_size);
+        Ok(())
+    }
+
+    #[test]
+    fn push_resumes_from_upload_journal() -> Result<()> {
+
+        let tempdir = tempfile::tempdir().expect("create tempdir for local blob storage");
+        let blob_storage = make_dummy_blob_storage(tempdir.path());
+        let journal_path = tempdir.path().join("upload_journal");
+
+        let config = TransferConfig { active_size_limit: 10_000_000, active_tasks_limit: 32, time_between_prints: Duration::from_secs(60), max_attempts: 3, ..TransferConfig::default() };
+        let mut mirror = Mirror::builder().storage(Box::new(blob_storage)).transfer_config(config).build()?;
+        let files = make_files(3, 1000);
+        let paths: Vec<PathBuf> = files.iter().map(|f| PathBuf::from(f.path())).collect();
+
+        let (first, _) = mirror.push(&paths, Path::new(""), &mut TransferJournal::default(), &mut UploadJournal::open(&journal_path)?)?;
+
+        // as if the push died before the manifest update, with one file changed since
+        std::fs::write(&paths[2], "changed")?;
+        let events = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        mirror.set_progress(Some(Box::new(RecordProgress(events.clone()))));
+        let (second, report) = mirror.push(&paths, Path::new(""), &mut TransferJournal::default(), &mut UploadJournal::open(&journal_path)?)?;
+        assert_eq!(events.lock().unwrap().first(), Some(&TransferEvent::Resumed { num_files: 2 }));
+        assert_eq!((report.num_files, report.num_skipped, report.plaintext_bytes), (1, 2, 7));
+
+        let key = |result: &Option<PushResult>| result.as_ref().unwrap().as_ref().unwrap().info.key.clone();
+        assert_eq!(key(&first[0]), key(&second[0]));
+        assert_ne!(key(&first[2]), key(&second[2]));
+
+        Ok(())
+    }
+
+    struct RecordProgress(std::sync::Arc<std::sync::Mutex<Vec<TransferEvent>>>);
+
+    impl TransferProgress for RecordProgress {
+        fn on_event(&mut self, event: &TransferEvent) {
+            self.0.lock().unwrap().push(event.clone());
+        }
+    }
+
+    #[test]
+    fn push_reports_progress() -> Result<()> {
+
+        let tempdir = tempfile::tempdir().expect("create tempdir for local blob storage");
+        let blob_storage = make_dummy_blob_storage(tempdir.path());
+        let events = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let config = TransferConfig { active_size_limit: 10_000_000, active_tasks_limit: 32, time_between_prints: Duration::ZERO, max_attempts: 3, ..TransferConfig::default() };
+        let mut mirror = Mirror::builder()
+            .storage(Box::new(blob_storage))
+            .transfer_config(config)
+            .progress(Box::new(RecordProgress(events.clone())))
+            .build()?;
+
+        let files = make_files(2, 1000);
+        let paths: Vec<PathBuf> = files.iter().map(|f| PathBuf::from(f.path())).collect();
+        crate::messages::start_recording();
+        mirror.push(&paths, Path::new(""), &mut TransferJournal::default(), &mut UploadJournal::default())?;
+        // the printing is left to the observer
+        assert!(crate::messages::take_recorded().is_empty());
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.first(), Some(&TransferEvent::Started { num_files: 2, num_bytes: 2000 }));
+        assert_eq!(events.last(), Some(&TransferEvent::Finished));
+        let num_done = events.iter().filter(|event| matches!(event, TransferEvent::FileDone { size: 1000, .. })).count();
+        assert_eq!(num_done, 2);
+        assert!(events.iter().any(|event| matches!(event, TransferEvent::Status { num_files: 2, .. })));
+        Ok(())
+    }
+
+    #[test]
+    fn pull() -> Result<()> {
+
+        let tempdir = tempfile::tempdir().expect("create tempdir for local blob storage");
+        let mut blob_storage = make_dummy_blob_storage(tempdir.path());
+        let num_dummy_blobs = 5;
+        let dummy_blob_size = 1000;
+        let big_data_buf: Vec<u8> = vec![42; dummy_blob_size];
+        let big_data_buf = bytes::Bytes::from(big_data_buf);
+
+        for i in 0..num_dummy_blobs {
+            let dummy_blob_key = format!("blob_{}", i);
+            blob_storage.upload_blocking(big_data_buf.clone(), Some(&dummy_blob_key)).expect("Putting dummy blob in blob storage");
+        }
+
+        let config = TransferConfig { active_size_limit: 10_000_000, active_tasks_limit: 32, time_between_prints: Duration::from_millis(0), max_attempts: 3, ..TransferConfig::default() };
+        let mut mirror = Mirror::builder().storage(Box::new(blob_storage)).transfer_config(config).build()?;
+
+        let mut files_arg_pull = Vec::new();
+        for i in 0..num_dummy_blobs {
+            let path = PathBuf::from(format!("kek_{}", i));
+            let key = format!("blob_{}", i);
+            files_arg_pull.push(PullFile { path, key, size: dummy_blob_size, chunks: Vec::new() });
+        }
+
+        let sink_dir = tempfile::tempdir()?;
+        mirror.pull(&files_arg_pull, sink_dir.path(), &mut TransferJournal::default())?;
+
+        Ok(())
+    }
+
+    // cancels once a file is done, as a Ctrl-C would
+    struct CancelOnFileDone(CancelToken);
+
+    impl TransferProgress for CancelOnFileDone {
+        fn on_event(&mut self, event: &TransferEvent) {
+            if let TransferEvent::FileDone { .. } = event {
+                self.0.cancel();
+            }
+        }
+    }
+
+    #[test]
+    fn cancelled_push_waits_for_transfers_in_flight() -> Result<()> {
+
+        let tempdir = tempfile::tempdir().expect("create tempdir for local blob storage");
+        let blob_storage = make_dummy_blob_storage(tempdir.path());
+        let cancel = CancelToken::new();
+        let config = TransferConfig { active_tasks_limit: 1, time_between_prints: Duration::from_secs(60), ..TransferConfig::default() };
+        let mut mirror = Mirror::builder()
+            .storage(Box::new(blob_storage))
+            .transfer_config(config)
+            .cancel(cancel.clone())
+            .progress(Box::new(CancelOnFileDone(cancel)))
+            .build()?;
+
+        let files = make_files(4, 1000);
+        let paths: Vec<PathBuf> = files.iter().map(|f| PathBuf::from(f.path())).collect();
+        let mut uploads = UploadJournal::default();
+        let pushed = mirror.push(&paths, Path::new(""), &mut TransferJournal::default(), &mut uploads);
+
+        assert!(pushed.unwrap_err().downcast_ref::<Cancelled>().is_some());
+        // one at a time: the first one was done, nothing else started
+        assert_eq!(uploads.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn push_and_pull_in_chunks() -> Result<()> {
+
+        let tempdir = tempfile::tempdir().expect("create tempdir for local blob storage");
+        let blob_storage = make_dummy_blob_storage(tempdir.path());
+        let bucket_name = tempdir.path().to_str().unwrap().to_string();
+        let config = TransferConfig { time_between_prints: Duration::from_secs(60), chunk_threshold: 1000, chunk_size: 1024, ..TransferConfig::default() };
+        let mut mirror = Mirror::builder().storage(Box::new(blob_storage)).transfer_config(config).bucket_name(bucket_name.clone()).build()?;
+
+        let content: Vec<u8> = (0..2500u32).map(|i| (i % 251) as u8).collect();
+        let files = make_files(2, 1000);
+        std::fs::write(files[1].path(), &content)?;
+        let paths: Vec<PathBuf> = files.iter().map(|f| PathBuf::from(f.path())).collect();
+        let (results, report) = mirror.push(&paths, Path::new(""), &mut TransferJournal::default(), &mut UploadJournal::default())?;
+        assert_eq!((report.num_files, report.plaintext_bytes), (2, 3500));
+
+        // not above the threshold
+        assert!(results[0].clone().unwrap().unwrap().chunks.is_empty());
+        let pushed = results[1].clone().unwrap().unwrap();
+        assert_eq!(pushed.info.key, blob_storage::get_hash_name(&bucket_name, bytes::Bytes::from(content.clone())));
+        let chunk_sizes: Vec<u64> = pushed.chunks.iter().map(|chunk| chunk.plaintext_size).collect();
+        assert_eq!(chunk_sizes, vec![1024, 1024, 452]);
+        assert_eq!(pushed.info.plaintext_size, 2500);
+        // the same blobs from a reader
+        let streamed = mirror.push_reader(&mut content.as_slice(), 2500)?;
+        assert_eq!(streamed.info.key, pushed.info.key);
+        let chunk_keys = |pushed: &PushedFile| pushed.chunks.iter().map(|chunk| chunk.key.clone()).collect::<Vec<_>>();
+        assert_eq!(chunk_keys(&streamed), chunk_keys(&pushed));
+        assert!(mirror.push_reader(&mut &content[..10], 20).is_err());
+
+        let chunks = pushed.chunks.iter()
+            .map(|chunk| StoredChunk { key: chunk.key.clone(), size: chunk.plaintext_size, ciphertext_size: chunk.ciphertext_size })
+            .collect();
+        let files_arg_pull = vec![PullFile { path: PathBuf::from("big"), key: pushed.info.key.clone(), size: 2500, chunks }];
+        let sink_dir = tempfile::tempdir()?;
+        // left by a pull that crashed, longer than the file
+        std::fs::write(sink_dir.path().join("big.har-partial"), vec![0; 4000])?;
+        let (quarantined, report) = mirror.pull(&files_arg_pull, sink_dir.path(), &mut TransferJournal::default())?;
+        assert!(quarantined.is_empty());
+        assert_eq!((report.num_files, report.plaintext_bytes, report.num_skipped), (1, 2500, 0));
+        assert_eq!(std::fs::read(sink_dir.path().join("big"))?, content);
+        assert!(!sink_dir.path().join("big.har-partial").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn stale_partials_are_removed() -> Result<()> {
+        let root = tempfile::tempdir()?;
+        std::fs::create_dir_all(root.path().join("docs"))?;
+        std::fs::create_dir_all(root.path().join(crate::scan::DOT_HAR_NAME))?;
+        std::fs::write(root.path().join("docs/taxes.har-partial"), "tax")?;
+        std::fs::write(root.path().join("docs/taxes"), "paid")?;
+        std::fs::write(root.path().join(".har/keep.har-partial"), "")?;
+
+        assert_eq!(remove_stale_partials(root.path())?, 1);
+        assert!(!root.path().join("docs/taxes.har-partial").exists());
+        assert!(root.path().join("docs/taxes").exists());
+        assert!(root.path().join(".har/keep.har-partial").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn pull_conflict_policies() -> Result<()> {
+        let tempdir = tempfile::tempdir().expect("create tempdir for local blob storage");
+        let blob_storage = make_dummy_blob_storage(tempdir.path());
+        let config = TransferConfig { time_between_prints: Duration::from_secs(60), ..TransferConfig::default() };
+        let mut mirror = Mirror::builder().storage(Box::new(blob_storage)).transfer_config(config).build()?;
+
+        let files = make_files(1, 100);
+        let paths = vec![PathBuf::from(files[0].path())];
+        let (results, _) = mirror.push(&paths, Path::new(""), &mut TransferJournal::default(), &mut UploadJournal::default())?;
+        let key = results[0].clone().unwrap().unwrap().info.key;
+        let files_arg_pull = vec![PullFile { path: PathBuf::from("taxes"), key, size: 100, chunks: Vec::new() }];
+        let pulled = vec![42; 100];
+
+        let sink_dir = tempfile::tempdir()?;
+        let local = sink_dir.path().join("taxes");
+        let mut pull_with = |pull_conflict| {
+            mirror.set_pull_conflict(pull_conflict);
+            mirror.pull(&files_arg_pull, sink_dir.path(), &mut TransferJournal::default())
+        };
+
+        std::fs::write(&local, "edited")?;
+        pull_with(PullConflict::SkipExisting)?;
+        assert_eq!(std::fs::read(&local)?, b"edited");
+
+        pull_with(PullConflict::BackupExisting)?;
+        assert_eq!(std::fs::read(&local)?, pulled);
+        assert_eq!(std::fs::read(sink_dir.path().join("taxes.har-backup"))?, b"edited");
+        // an earlier backup is not replaced
+        std::fs::write(&local, "edited again")?;
+        pull_with(PullConflict::BackupExisting)?;
+        assert_eq!(std::fs::read(sink_dir.path().join("taxes.har-backup"))?, b"edited");
+        assert_eq!(std::fs::read(sink_dir.path().join("taxes.har-backup.1"))?, b"edited again");
+
+        std::fs::write(&local, "edited")?;
+        pull_with(PullConflict::Overwrite)?;
+        assert_eq!(std::fs::read(&local)?, pulled);
+        assert!(!sink_dir.path().join("taxes.har-backup.2").exists());
+        Ok(())
+    }
+}
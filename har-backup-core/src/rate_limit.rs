@@ -0,0 +1,78 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use crate::clock::Clock;
+
+// token bucket of a bandwidth limit, shared by the transfers of a push or pull (clones share the tokens)
+// a transfer takes its whole size before it starts: it waits for the tokens it lacks, so the rate holds on average
+// over a few blobs (chunks keep big files from coming in one burst, see mirror::TransferConfig)
+#[derive(Clone)]
+pub struct RateLimit {
+    bytes_per_second: u64,
+    clock: Arc<dyn Clock>,
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+struct Bucket {
+    tokens: f64, // negative when a transfer took more than there was, the next ones wait for the debt
+    last_refill: Instant,
+}
+
+impl RateLimit {
+    // starts full, a second worth of transfer can go at once
+    pub fn new(bytes_per_second: u64, clock: Arc<dyn Clock>) -> Self {
+        let bytes_per_second = bytes_per_second.max(1);
+        let bucket = Bucket { tokens: bytes_per_second as f64, last_refill: clock.now() };
+        Self { bytes_per_second, clock, bucket: Arc::new(Mutex::new(bucket)) }
+    }
+
+    pub fn bytes_per_second(&self) -> u64 {
+        self.bytes_per_second
+    }
+
+    // returns how long it waited
+    pub fn take(&self, num_bytes: u64) -> Duration {
+        let rate = self.bytes_per_second as f64;
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap();
+            let now = self.clock.now();
+            let refill = now.duration_since(bucket.last_refill).as_secs_f64() * rate;
+            bucket.tokens = (bucket.tokens + refill).min(rate) - num_bytes as f64;
+            bucket.last_refill = now;
+            Duration::from_secs_f64((-bucket.tokens).max(0.0) / rate)
+        };
+        // not holding the lock, the tokens were already taken: others waiting after us wait for our debt too
+        if !wait.is_zero() {
+            self.clock.sleep(wait);
+        }
+        wait
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::VirtualClock;
+
+    #[test]
+    fn rate_holds_across_clones() {
+        let clock = VirtualClock::new();
+        let limit = RateLimit::new(1000, Arc::new(clock.clone()));
+        let other = limit.clone();
+
+        // the first second is in the bucket
+        assert_eq!(limit.take(600), Duration::ZERO);
+        assert_eq!(other.take(400), Duration::ZERO);
+        for _ in 0..4 {
+            limit.take(500);
+            other.take(500);
+        }
+        assert_eq!(clock.elapsed(), Duration::from_secs(4));
+
+        // a transfer bigger than the bucket waits for all of it
+        assert_eq!(other.take(3000), Duration::from_secs(3));
+        // idle time refills, up to a second worth
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(limit.take(1000), Duration::ZERO);
+        assert_eq!(limit.take(250), Duration::from_millis(250));
+    }
+}
@@ -0,0 +1,152 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::JoinHandle;
+use crate::blob_storage_tasks::set_thread_panic_hook;
+
+// reads of local files on reader threads, requested before they are needed, so that push has the data of the next
+// upload ready while the disk works on the ones after it (see mirror::Mirror::push)
+// bounded: what was requested and not taken yet counts against a number of reads and a number of bytes
+pub struct ReadAhead<K, T> {
+    jobs: Option<mpsc::Sender<(u64, Job<T>)>>,
+    done: mpsc::Receiver<(u64, T)>,
+    threads: Vec<JoinHandle<()>>,
+    requested: HashMap<K, (u64, usize)>, // id of the read and its size
+    ready: HashMap<u64, T>, // done, by id, until taken
+    forgotten: HashSet<u64>, // dropped when done
+    next_id: u64,
+    max_reads: usize,
+    max_bytes: usize,
+    bytes: usize, // of the reads requested
+}
+
+type Job<T> = Box<dyn FnOnce() -> T + Send>;
+
+impl<K: Eq + Hash, T: Send + 'static> ReadAhead<K, T> {
+    pub fn new(num_threads: usize, max_reads: usize, max_bytes: usize) -> Self {
+        let (jobs, job_receiver) = mpsc::channel::<(u64, Job<T>)>();
+        let (done_sender, done) = mpsc::channel();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        let threads = (0..num_threads.max(1)).map(|_| {
+            let job_receiver = job_receiver.clone();
+            let done_sender = done_sender.clone();
+            std::thread::spawn(move || {
+                set_thread_panic_hook();
+                loop {
+                    let received = job_receiver.lock().unwrap().recv();
+                    // stops once the ReadAhead is dropped and the reads it requested are done
+                    let Ok((id, job)) = received else { break };
+                    if done_sender.send((id, job())).is_err() {
+                        break;
+                    }
+                }
+            })
+        }).collect();
+        Self {
+            jobs: Some(jobs),
+            done,
+            threads,
+            requested: HashMap::new(),
+            ready: HashMap::new(),
+            forgotten: HashSet::new(),
+            next_id: 0,
+            max_reads: max_reads.max(1),
+            max_bytes,
+            bytes: 0,
+        }
+    }
+
+    // a read of size bytes can be requested, there is always room for one
+    pub fn has_room(&self, size: usize) -> bool {
+        self.requested.is_empty() || (self.requested.len() < self.max_reads && self.bytes + size <= self.max_bytes)
+    }
+
+    pub fn is_requested(&self, key: &K) -> bool {
+        self.requested.contains_key(key)
+    }
+
+    // size is what the result will hold in memory, for the bound
+    pub fn request(&mut self, key: K, size: usize, read: impl FnOnce() -> T + Send + 'static) {
+        let id = self.next_id;
+        self.next_id += 1;
+        if let Some((previous, previous_size)) = self.requested.insert(key, (id, size)) {
+            self.bytes -= previous_size;
+            self.forget_id(previous);
+        }
+        self.bytes += size;
+        // the threads only stop once jobs is dropped
+        let _ = self.jobs.as_ref().unwrap().send((id, Box::new(read)));
+    }
+
+    // the result of the read requested for key, waiting for it, None if it was not requested
+    pub fn take(&mut self, key: &K) -> Option<T> {
+        let (id, size) = self.requested.remove(key)?;
+        self.bytes -= size;
+        loop {
+            if let Some(result) = self.ready.remove(&id) {
+                return Some(result);
+            }
+            let (done_id, result) = self.done.recv().ok()?;
+            if !self.forgotten.remove(&done_id) {
+                self.ready.insert(done_id, result);
+            }
+        }
+    }
+
+    // the result of the read requested for key is not needed anymore
+    pub fn forget(&mut self, key: &K) {
+        if let Some((id, size)) = self.requested.remove(key) {
+            self.bytes -= size;
+            self.forget_id(id);
+        }
+    }
+
+    fn forget_id(&mut self, id: u64) {
+        if self.ready.remove(&id).is_none() {
+            self.forgotten.insert(id);
+        }
+    }
+}
+
+impl<K, T> Drop for ReadAhead<K, T> {
+    fn drop(&mut self) {
+        self.jobs = None;
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn reads_ahead_within_bounds() {
+        let mut reads: ReadAhead<usize, usize> = ReadAhead::new(2, 2, 100);
+        assert!(reads.has_room(1000));
+        assert!(reads.take(&0).is_none());
+
+        let num_done = Arc::new(AtomicUsize::new(0));
+        for key in 0..2 {
+            let num_done = num_done.clone();
+            reads.request(key, 50, move || {
+                num_done.fetch_add(1, Ordering::SeqCst);
+                key * 10
+            });
+        }
+        assert!(!reads.has_room(1));
+        assert!(reads.is_requested(&1));
+        assert_eq!(reads.take(&1), Some(10));
+        assert!(reads.has_room(50));
+        assert!(!reads.has_room(51));
+
+        reads.forget(&0);
+        assert!(!reads.is_requested(&0));
+        reads.request(0, 10, || 7);
+        assert_eq!(reads.take(&0), Some(7));
+        drop(reads);
+        assert_eq!(num_done.load(Ordering::SeqCst), 2);
+    }
+}
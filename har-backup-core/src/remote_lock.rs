@@ -0,0 +1,43 @@
+use serde::{Deserialize, Serialize};
+use anyhow::Context;
+use crate::clock::format_utc_timestamp;
+
+pub const REMOTE_LOCK_KEY: &str = "lock";
+
+// advisory lock of an archive, taken by push and init-remote so that clients don't update the remote at the same time
+// storages can neither create an object only if it is missing nor delete one: the lock is written over, then read
+// back to see who has it, and released by writing it again as released (see mirror::Mirror::lock)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RemoteLock {
+    pub holder: String, // for the user, host and process
+    pub token: String, // of this taking of the lock
+    pub acquired: u64, // unix seconds
+    pub released: bool,
+}
+
+impl RemoteLock {
+    pub fn new(holder: &str, acquired: u64) -> Self {
+        Self { holder: holder.to_string(), token: crate::archive_metadata::new_uuid(), acquired, released: false }
+    }
+
+    pub fn to_bytes(&self) -> anyhow::Result<bytes::Bytes> {
+        Ok(bytes::Bytes::from(serde_json::to_vec(self).context("Serialize remote lock")?))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        serde_json::from_slice(bytes).context("Deserialize remote lock")
+    }
+}
+
+// error of taking a lock that another client has
+#[derive(Debug)]
+pub struct RemoteLocked(pub RemoteLock);
+
+impl std::fmt::Display for RemoteLocked {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Remote is locked by {} since {}, if it is not pushing anymore run har unlock --force",
+            self.0.holder, format_utc_timestamp(self.0.acquired))
+    }
+}
+
+impl std::error::Error for RemoteLocked {}
@@ -0,0 +1,263 @@
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use anyhow::Context;
+use log::debug;
+use serde::Deserialize;
+use crate::manifest::Manifest;
+
+// metadata of the archive at its root, never part of the tree (see dot_har in the har crate)
+pub const DOT_HAR_NAME: &str = ".har";
+
+// what to do with files that are stubs for content stored in the cloud (OneDrive, Dropbox, iCloud...)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlaceholderPolicy {
+    #[default]
+    Skip, // leave them out of the local manifest
+    Hydrate, // treat them as regular files, reading them downloads the content
+    Error,
+}
+
+// where the local tree is taken from, walking it can take hours for gigantic trees that are already indexed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ScanBackend {
+    #[default]
+    Walk,
+    Listing(PathBuf), // see ListingFile
+    Watchman,
+}
+
+impl ScanBackend {
+    pub fn source(&self) -> Box<dyn ScanSource> {
+        match self {
+            ScanBackend::Walk => Box::new(FsWalker),
+            ScanBackend::Listing(path) => Box::new(ListingFile { path: path.clone() }),
+            ScanBackend::Watchman => Box::new(Watchman),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ScanOptions {
+    pub placeholder_policy: PlaceholderPolicy,
+    pub backend: ScanBackend,
+    pub keep_going: bool, // a walk leaves out what it can't read (ScanReport::unreadable) rather than failing
+}
+
+#[derive(Debug, Default)]
+pub struct ScanReport {
+    pub skipped_placeholders: Vec<PathBuf>,
+    pub unreadable: Vec<(PathBuf, String)>, // and why, see ScanOptions::keep_going
+}
+
+impl ScanReport {
+    // error about path is the scan's, unless options say to keep going
+    pub fn keep_going(&mut self, options: &ScanOptions, path: &Path, error: anyhow::Error) -> anyhow::Result<()> {
+        if !options.keep_going {
+            return Err(error);
+        }
+        debug!("Leaving out {}: {:#}", path.to_str().unwrap(), error);
+        self.unreadable.push((path.to_path_buf(), format!("{:#}", error)));
+        Ok(())
+    }
+}
+
+// makes the manifest of the local tree at root, blobs keys are left to default
+pub trait ScanSource {
+    fn scan(&self, root: &Path, options: &ScanOptions) -> anyhow::Result<(Manifest, ScanReport)>;
+}
+
+pub struct FsWalker;
+
+impl ScanSource for FsWalker {
+    fn scan(&self, root: &Path, options: &ScanOptions) -> anyhow::Result<(Manifest, ScanReport)> {
+        Manifest::from_fs_with_options(root, options)
+    }
+}
+
+// a listing of the files of the tree, made for example from an Everything or mlocate export
+// .csv: one path,size per line (an optional header line, paths may be quoted)
+// anything else: json lines of {"path": ..., "size": ...}
+// paths are relative to the archive root, or absolute under it. Indexes know nothing of placeholders.
+pub struct ListingFile {
+    pub path: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct ListedFile {
+    path: PathBuf,
+    size: u64,
+}
+
+impl ListingFile {
+    fn read(&self) -> anyhow::Result<Vec<(PathBuf, u64)>> {
+        let file = std::fs::File::open(&self.path).with_context(|| format!("Opening listing {}", self.path.to_str().unwrap()))?;
+        let is_csv = self.path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("csv"));
+        let mut files = Vec::new();
+        for (index, line) in std::io::BufReader::new(file).lines().enumerate() {
+            let line = line.context("Reading listing")?;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let context = || format!("Listing {} line {}", self.path.to_str().unwrap(), index + 1);
+            if is_csv {
+                let (path, size) = line.rsplit_once(',').with_context(|| format!("{}: expected path,size", context()))?;
+                let size = size.trim().trim_matches('"');
+                match size.parse() {
+                    Ok(size) => files.push((PathBuf::from(path.trim().trim_matches('"')), size)),
+                    Err(_) if index == 0 => (), // header
+                    Err(err) => return Err(err).with_context(|| format!("{}: size", context())),
+                }
+            } else {
+                let listed: ListedFile = serde_json::from_str(line).with_context(context)?;
+                files.push((listed.path, listed.size));
+            }
+        }
+        Ok(files)
+    }
+}
+
+impl ScanSource for ListingFile {
+    fn scan(&self, root: &Path, _options: &ScanOptions) -> anyhow::Result<(Manifest, ScanReport)> {
+        let files = self.read()?.into_iter()
+            .map(|(path, size)| Ok((relative_to_root(root, &path)?, size)))
+            .filter(|listed| !matches!(listed, Ok((path, _)) if path.starts_with(DOT_HAR_NAME)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok((Manifest::from_listing(files)?, ScanReport::default()))
+    }
+}
+
+fn relative_to_root(root: &Path, path: &Path) -> anyhow::Result<PathBuf> {
+    if path.is_relative() {
+        return Ok(path.to_path_buf());
+    }
+    let relative = path.strip_prefix(root).with_context(|| format!("Listed path {} is not under the archive root", path.to_str().unwrap()))?;
+    Ok(relative.to_path_buf())
+}
+
+// the tree as watchman knows it, the root (or one of its parents) gets watched if it was not already
+pub struct Watchman;
+
+#[derive(Deserialize)]
+struct WatchmanFile {
+    name: PathBuf,
+    size: u64,
+}
+
+#[derive(Deserialize)]
+struct WatchmanResponse {
+    error: Option<String>,
+    watch: Option<String>,
+    relative_path: Option<String>,
+    #[serde(default)]
+    files: Vec<WatchmanFile>,
+}
+
+fn watchman(command: &serde_json::Value) -> anyhow::Result<WatchmanResponse> {
+    let mut child = std::process::Command::new("watchman")
+        .args(["-j", "--no-pretty"])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .context("Running watchman, is it installed?")?;
+    child.stdin.take().context("Watchman stdin")?.write_all(command.to_string().as_bytes()).context("Writing watchman command")?;
+    let output = child.wait_with_output().context("Waiting for watchman")?;
+    let response: WatchmanResponse = serde_json::from_slice(&output.stdout).context("Parsing watchman response")?;
+    if let Some(error) = response.error {
+        anyhow::bail!("Watchman: {}", error);
+    }
+    Ok(response)
+}
+
+impl ScanSource for Watchman {
+    fn scan(&self, root: &Path, _options: &ScanOptions) -> anyhow::Result<(Manifest, ScanReport)> {
+        let root_str = root.to_str().context("Convert path to str")?;
+        let watched = watchman(&serde_json::json!(["watch-project", root_str]))?;
+        let watch = watched.watch.context("Watchman did not say what it watches")?;
+        let mut query = serde_json::json!({
+            "expression": ["allof", ["type", "f"], ["not", ["dirname", DOT_HAR_NAME]]],
+            "fields": ["name", "size"],
+        });
+        if let Some(relative_path) = watched.relative_path {
+            query["relative_root"] = serde_json::Value::String(relative_path);
+        }
+        let response = watchman(&serde_json::json!(["query", watch, query]))?;
+        let files = response.files.into_iter().map(|file| (file.name, file.size));
+        Ok((Manifest::from_listing(files)?, ScanReport::default()))
+    }
+}
+
+#[cfg(windows)]
+pub fn is_cloud_placeholder(metadata: &std::fs::Metadata) -> bool {
+    use std::os::windows::fs::MetadataExt;
+    const FILE_ATTRIBUTE_OFFLINE: u32 = 0x1000;
+    const FILE_ATTRIBUTE_RECALL_ON_OPEN: u32 = 0x40000;
+    const FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS: u32 = 0x400000;
+    let attributes = metadata.file_attributes();
+    attributes & (FILE_ATTRIBUTE_OFFLINE | FILE_ATTRIBUTE_RECALL_ON_OPEN | FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS) != 0
+}
+
+#[cfg(target_os = "macos")]
+pub fn is_cloud_placeholder(metadata: &std::fs::Metadata) -> bool {
+    use std::os::macos::fs::MetadataExt;
+    const SF_DATALESS: u32 = 0x40000000;
+    metadata.st_flags() & SF_DATALESS != 0
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+pub fn is_cloud_placeholder(_metadata: &std::fs::Metadata) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listed(manifest: &Manifest) -> Vec<(PathBuf, u64)> {
+        manifest.list_files().into_iter().map(|(path, _, size)| (path, size)).collect()
+    }
+
+    #[test]
+    fn listing_files() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let root = Path::new("/archive");
+        let expected = vec![
+            (PathBuf::from("dog/felt"), 42),
+            (PathBuf::from("dog/kek, too"), 7),
+            (PathBuf::from("top"), 1),
+        ];
+
+        let jsonl = dir.path().join("index.jsonl");
+        std::fs::write(&jsonl, concat!(
+            "{\"path\": \"dog/felt\", \"size\": 42}\n",
+            "{\"path\": \"/archive/top\", \"size\": 1}\n",
+            "\n",
+            "{\"path\": \".har/manifest\", \"size\": 3}\n",
+            "{\"path\": \"dog/kek, too\", \"size\": 7}\n",
+        ))?;
+        let options = ScanOptions { backend: ScanBackend::Listing(jsonl), ..Default::default() };
+        let (manifest, _) = options.backend.source().scan(root, &options)?;
+        assert_eq!(listed(&manifest), expected);
+
+        let csv = dir.path().join("export.CSV");
+        std::fs::write(&csv, "\"Filename\",\"Size\"\n\"/archive/dog/kek, too\",7\ntop,1\ndog/felt,\"42\"\n")?;
+        let (manifest, _) = ListingFile { path: csv }.scan(root, &ScanOptions::default())?;
+        assert_eq!(listed(&manifest), expected);
+
+        let outside = dir.path().join("outside.jsonl");
+        std::fs::write(&outside, "{\"path\": \"/elsewhere/top\", \"size\": 1}\n")?;
+        assert!(ListingFile { path: outside }.scan(root, &ScanOptions::default()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn keep_going_collects_errors() {
+        let mut report = ScanReport::default();
+        let options = ScanOptions { keep_going: true, ..Default::default() };
+        report.keep_going(&options, Path::new("/archive/locked"), anyhow::anyhow!("Permission denied")).unwrap();
+        assert_eq!(report.unreadable, vec![(PathBuf::from("/archive/locked"), "Permission denied".to_string())]);
+        assert!(report.keep_going(&ScanOptions::default(), Path::new("/archive/other"), anyhow::anyhow!("Permission denied")).is_err());
+        assert_eq!(report.unreadable.len(), 1);
+    }
+}
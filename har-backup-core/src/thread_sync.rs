@@ -39,6 +39,10 @@ impl<T> Receiver<T> {
     pub fn recv(&self) -> Result<T, mpsc::RecvError> {
         self.inner.recv()
     }
+
+    pub fn recv_timeout(&self, timeout: std::time::Duration) -> Result<T, mpsc::RecvTimeoutError> {
+        self.inner.recv_timeout(timeout)
+    }
 }
 
 impl<T> Sender<T> {
@@ -51,6 +55,38 @@ impl<T> Sender<T> {
     }
 }
 
+// stops push and pull between transfers (Ctrl-C), clones share it
+#[derive(Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Release);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+}
+
+// error of a transfer stopped by its CancelToken, once the transfers in flight are done
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
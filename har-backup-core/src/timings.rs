@@ -0,0 +1,85 @@
+use std::time::{Duration, Instant};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Scan, // building the manifest of the local tree
+    Diff,
+    Hashing, // rehashing local files, part of diff when checking hashes
+    Planning, // making the list of what to transfer
+    Read, // reading local files, part of transfer for push
+    Write, // writing local files, part of transfer for pull
+    Transfer,
+    ManifestUpdate,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Phase::Scan => "scan",
+            Phase::Diff => "diff",
+            Phase::Hashing => "hashing",
+            Phase::Planning => "planning",
+            Phase::Read => "read",
+            Phase::Write => "write",
+            Phase::Transfer => "transfer",
+            Phase::ManifestUpdate => "manifest update",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// total time spent in each phase, in the order phases were first seen
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Timings {
+    phases: Vec<(Phase, Duration)>,
+}
+
+impl Timings {
+    pub fn add(&mut self, phase: Phase, duration: Duration) {
+        match self.phases.iter_mut().find(|(p, _)| *p == phase) {
+            Some((_, total)) => *total += duration,
+            None => self.phases.push((phase, duration)),
+        }
+    }
+
+    pub fn time<T>(&mut self, phase: Phase, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let ret = f();
+        self.add(phase, start.elapsed());
+        ret
+    }
+
+    pub fn merge(&mut self, other: Timings) {
+        for (phase, duration) in other.phases {
+            self.add(phase, duration);
+        }
+    }
+
+    pub fn get(&self, phase: Phase) -> Option<Duration> {
+        self.phases.iter().find(|(p, _)| *p == phase).map(|(_, d)| *d)
+    }
+
+    pub fn phases(&self) -> &[(Phase, Duration)] {
+        &self.phases
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accumulate() {
+        let mut timings = Timings::default();
+        timings.add(Phase::Scan, Duration::from_millis(10));
+        timings.add(Phase::Transfer, Duration::from_millis(5));
+        timings.add(Phase::Scan, Duration::from_millis(10));
+        let value = timings.time(Phase::Diff, || 42);
+
+        assert_eq!(value, 42);
+        assert_eq!(timings.get(Phase::Scan), Some(Duration::from_millis(20)));
+        assert_eq!(timings.phases().iter().map(|(p, _)| *p).collect::<Vec<_>>(), vec![Phase::Scan, Phase::Transfer, Phase::Diff]);
+        assert_eq!(timings.get(Phase::Hashing), None);
+    }
+}
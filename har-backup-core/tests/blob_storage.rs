@@ -1,6 +1,6 @@
-use har_backup::blob_storage::{BlobStorage, EventContent};
-use har_backup::blob_storage_local_directory::BlobStorageLocalDirectory;
-use har_backup::blob_encryption::EncryptWithChacha;
+use har_backup_core::blob_storage::{BlobStorage, EventContent};
+use har_backup_core::blob_storage_local_directory::BlobStorageLocalDirectory;
+use har_backup_core::blob_encryption::EncryptWithChacha;
 use tempfile::NamedTempFile;
 use std::io::Write;
 use anyhow::Result;
@@ -31,7 +31,7 @@ fn local_directory_upload_and_download() -> Result<()> {
 
     let event = events.recv().expect("receive an event for upload");
     let blob_hash = match event.content {
-        EventContent::UploadSuccess(blob_hash) => blob_hash,
+        EventContent::UploadSuccess(info) => info.key,
         _ => anyhow::bail!("Expected UploadSuccess but got {:?}", event.content)
     };
 
@@ -39,7 +39,7 @@ fn local_directory_upload_and_download() -> Result<()> {
 
     let event = events.recv().expect("receive an event for download");
     let bytes = match event.content {
-        EventContent::DownloadSuccess(bytes) => bytes,
+        EventContent::DownloadSuccess(info) => info.data,
         _ => anyhow::bail!("Expected DownloadSuccess but got {:?}", event.content)
     };
 
@@ -67,4 +67,45 @@ fn download_blocking_twice() -> Result<()> {
     blob_storage.download_blocking("a_file")?;
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[test]
+fn blocking_results_have_checksums() -> Result<()> {
+
+    let tempdir = tempfile::tempdir().expect("create tempdir for local blob storage");
+    let mut blob_storage = make_dummy_blob_storage(tempdir.path());
+    let dummy_payload = bytes::Bytes::from("Hello I am a dummy payload");
+
+    let upload_info = blob_storage.upload_blocking(dummy_payload.clone(), None)?;
+    assert_eq!(upload_info.plaintext_size, dummy_payload.len() as u64);
+    assert!(upload_info.ciphertext_size > upload_info.plaintext_size);
+
+    let stored = std::fs::read(tempdir.path().join(&upload_info.key))?;
+    assert_eq!(upload_info.ciphertext_checksum, blake3::hash(&stored).to_hex().to_string());
+
+    let download_info = blob_storage.download_blocking(&upload_info.key)?;
+    assert_eq!(download_info.data, dummy_payload);
+    assert_eq!(download_info.ciphertext_size, upload_info.ciphertext_size);
+    assert_eq!(download_info.ciphertext_checksum, upload_info.ciphertext_checksum);
+
+    Ok(())
+}
+
+#[test]
+fn local_directory_list() -> Result<()> {
+    let tempdir = tempfile::tempdir().expect("create tempdir for local blob storage");
+    let mut blob_storage = make_dummy_blob_storage(tempdir.path());
+    let info = blob_storage.upload_blocking(bytes::Bytes::from("Hello I am a dummy payload"), None)?;
+    blob_storage.upload_blocking(bytes::Bytes::from("named"), Some("manifest"))?;
+    std::fs::create_dir(tempdir.path().join("not_a_blob"))?;
+
+    let mut objects = blob_storage.list_blocking()?;
+    objects.sort_by(|a, b| a.key.cmp(&b.key));
+    let keys: Vec<&str> = objects.iter().map(|object| object.key.as_str()).collect();
+    let mut expected = vec![info.key.as_str(), "manifest"];
+    expected.sort();
+    assert_eq!(keys, expected);
+    let listed = objects.iter().find(|object| object.key == info.key).unwrap();
+    assert_eq!(listed.size, info.ciphertext_size);
+    Ok(())
+}
@@ -0,0 +1,171 @@
+use har_backup_core::mirror::Mirror;
+use har_backup_core::archive_metadata::ArchiveMetadata;
+use har_backup_core::blob_storage::{BlobStorage, DownloadResult, Error, ErrorKind, Event, ExistsResult, ListResult, MetadataResult, TaskId, UploadResult};
+use har_backup_core::blob_storage_local_directory::BlobStorageLocalDirectory;
+use har_backup_core::thread_sync::Receiver;
+use bytes::Bytes;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use anyhow::Result;
+
+mod blob_storage;
+use blob_storage::make_dummy_blob_storage;
+
+#[test]
+fn init() -> Result<()> {
+    let tempdir = tempfile::tempdir().expect("create tempdir for local blob storage");
+    let blob_storage = make_dummy_blob_storage(tempdir.path());
+    let mut mirror = Mirror::new(Box::new(blob_storage));
+    mirror.init()?;
+    Ok(())
+}
+
+#[test]
+fn init_twice() -> Result<()> {
+    let tempdir = tempfile::tempdir().expect("create tempdir for local blob storage");
+    let blob_storage = make_dummy_blob_storage(tempdir.path());
+    let mut mirror = Mirror::new(Box::new(blob_storage));
+    mirror.init()?;
+    assert!(mirror.init().is_err());
+    Ok(())
+}
+
+#[test]
+fn init_pushes_archive_metadata() -> Result<()> {
+    let tempdir = tempfile::tempdir().expect("create tempdir for local blob storage");
+    let blob_storage = make_dummy_blob_storage(tempdir.path());
+    let mut mirror = Mirror::new(Box::new(blob_storage));
+    assert!(mirror.get_archive_metadata()?.is_none());
+
+    let metadata = ArchiveMetadata::new("kek");
+    mirror.init_with_metadata(&metadata)?;
+    assert_eq!(mirror.get_archive_metadata()?, Some(metadata));
+    mirror.check_archive_metadata()?;
+    Ok(())
+}
+
+#[test]
+fn init_is_retry_safe() -> Result<()> {
+    use har_backup_core::mirror::InitOutcome;
+    let tempdir = tempfile::tempdir().expect("create tempdir for local blob storage");
+    let blob_storage = make_dummy_blob_storage(tempdir.path());
+    let mut mirror = Mirror::new(Box::new(blob_storage));
+
+    let metadata = ArchiveMetadata::new("kek");
+    assert_eq!(mirror.init_with_metadata(&metadata)?, InitOutcome::Created);
+    assert_eq!(mirror.init_with_metadata(&metadata)?, InitOutcome::AlreadyInitialized);
+
+    let other = ArchiveMetadata::new("kek");
+    let err = mirror.init_with_metadata(&other).unwrap_err();
+    assert!(err.to_string().contains(&metadata.archive_uuid));
+    Ok(())
+}
+
+#[test]
+fn probe_reachable() -> Result<()> {
+    use har_backup_core::health::RemoteHealth;
+    let tempdir = tempfile::tempdir().expect("create tempdir for local blob storage");
+    let blob_storage = make_dummy_blob_storage(tempdir.path());
+    let mut mirror = Mirror::new(Box::new(blob_storage));
+    // before init too, the manifest not existing is an answer
+    assert_eq!(mirror.probe(), RemoteHealth::Reachable);
+    mirror.init()?;
+    assert_eq!(mirror.probe(), RemoteHealth::Reachable);
+    Ok(())
+}
+
+#[test]
+fn remote_lock() -> Result<()> {
+    use har_backup_core::remote_lock::RemoteLocked;
+    let tempdir = tempfile::tempdir().expect("create tempdir for local blob storage");
+    let mut mirror = Mirror::new(Box::new(make_dummy_blob_storage(tempdir.path())));
+    let mut other = Mirror::new(Box::new(make_dummy_blob_storage(tempdir.path())));
+    assert!(mirror.get_lock()?.is_none());
+
+    let lock = mirror.lock("this:1", 1000)?;
+    let err = other.lock("other:2", 1001).unwrap_err();
+    assert_eq!(err.downcast_ref::<RemoteLocked>().unwrap().0, lock);
+    mirror.unlock(&lock)?;
+    assert!(mirror.get_lock()?.is_none());
+
+    // other died with it
+    let other_lock = other.lock("other:2", 1002)?;
+    assert!(mirror.lock("this:1", 1003).is_err());
+    assert_eq!(mirror.force_unlock()?, Some(other_lock.clone()));
+    let lock = mirror.lock("this:1", 1004)?;
+    other.unlock(&other_lock)?;
+    assert_eq!(mirror.get_lock()?, Some(lock));
+    Ok(())
+}
+
+// a local directory with conditional uploads as s3 has them, versions count the uploads of a key
+// the manifest is replaced by the push of another machine right after the next download of it, once swap is set
+struct PushedInBetween {
+    inner: BlobStorageLocalDirectory,
+    versions: HashMap<String, u64>,
+    swap: Rc<RefCell<Option<Bytes>>>,
+}
+
+impl BlobStorage for PushedInBetween {
+    fn upload_blocking(&mut self, data: Bytes, key: Option<&str>) -> UploadResult {
+        if let Some(key) = key {
+            *self.versions.entry(key.to_string()).or_default() += 1;
+        }
+        self.inner.upload_blocking(data, key)
+    }
+
+    fn download_blocking(&mut self, key: &str) -> DownloadResult {
+        let mut info = self.inner.download_blocking(key)?;
+        info.version = Some(self.versions.get(key).copied().unwrap_or_default().to_string());
+        let swap = self.swap.borrow_mut().take();
+        if let Some(other) = swap.filter(|_| key == "manifest") {
+            self.upload_blocking(other, Some(key))?;
+        }
+        Ok(info)
+    }
+
+    fn upload_if_version_blocking(&mut self, data: Bytes, key: &str, version: &str) -> UploadResult {
+        if self.versions.get(key).copied().unwrap_or_default().to_string() != version {
+            return Err(Error::new(ErrorKind::Changed, format!("{} is not at version {} anymore", key, version)));
+        }
+        self.upload_blocking(data, Some(key))
+    }
+
+    delegate::delegate! {
+        to self.inner {
+            fn upload(&mut self, data: Bytes, key: Option<&str>) -> TaskId;
+            fn download(&mut self, key: &str) -> TaskId;
+            fn exists(&mut self, key: &str) -> TaskId;
+            fn events(&mut self) -> Receiver<Event>;
+            fn exists_blocking(&mut self, key: &str) -> ExistsResult;
+            fn list_blocking(&mut self) -> ListResult;
+            fn metadata_blocking(&mut self, key: &str) -> MetadataResult;
+        }
+    }
+}
+
+#[test]
+fn push_manifest_is_a_compare_and_swap() -> Result<()> {
+    use har_backup_core::mirror::RemoteManifestChanged;
+    let tempdir = tempfile::tempdir().expect("create tempdir for local blob storage");
+    let swap = Rc::new(RefCell::new(None));
+    let mut mirror = Mirror::new(Box::new(PushedInBetween {
+        inner: make_dummy_blob_storage(tempdir.path()),
+        versions: Default::default(),
+        swap: swap.clone(),
+    }));
+    mirror.init()?;
+    let fetched = mirror.get_manifest_blob()?;
+
+    // the manifest is still the fetched one when compared, then replaced before the upload
+    *swap.borrow_mut() = Some(Bytes::from("pushed from elsewhere"));
+    let err = mirror.push_manifest_blob_if_unchanged(Bytes::from("ours"), &fetched).unwrap_err();
+    assert!(err.downcast_ref::<RemoteManifestChanged>().is_some());
+    assert_eq!(mirror.get_manifest_blob()?, "pushed from elsewhere");
+
+    let fetched = mirror.get_manifest_blob()?;
+    mirror.push_manifest_blob_if_unchanged(Bytes::from("ours"), &fetched)?;
+    assert_eq!(mirror.get_manifest_blob()?, "ours");
+    Ok(())
+}
@@ -1,29 +1,139 @@
 use anyhow::{Result, Context};
-use crate::blob_storage_s3;
-use crate::manifest::{self, Manifest};
-use crate::mirror::TransferConfig;
-use crate::{blob_storage_local_directory::BlobStorageLocalDirectory, mirror::Mirror};
-use crate::blob_storage::{self, BlobStorage};
-use crate::dot_har::{DotHar, RemoteSpec};
+#[cfg(feature = "s3")]
+use har_backup_core::blob_storage_s3;
+#[cfg(feature = "rclone")]
+use har_backup_core::blob_storage_rclone;
+use har_backup_core::blob_storage_multi::BlobStorageMulti;
+use har_backup_core::manifest::{self, Manifest};
+use har_backup_core::mirror::{self, Dedup, InitOutcome, PullConflict, PullFile, TransferConfig, TransferProgress};
+use crate::progress::{ProgressBars, StatusLines};
+use har_backup_core::{blob_storage_local_directory::BlobStorageLocalDirectory, mirror::Mirror};
+use har_backup_core::blob_storage::{self, BlobStorage};
+use har_backup_core::blob_encryption::EncryptWithChacha;
+use har_backup_core::blob_compression::Compression;
+use crate::dot_har::{DotHar, KeySpec, ManifestBackup, RemoteSpec, DOT_HAR_NAME};
+use har_backup_core::archive_metadata::ArchiveMetadata;
+use har_backup_core::scan::{ScanOptions, ScanReport};
+use crate::settings::Settings;
+use har_backup_core::clock::{self, VirtualClock};
+use har_backup_core::health::HealthMonitor;
+use har_backup_core::timings::{Phase, Timings};
+use crate::history::{RunKind, RunRecord};
+use crate::queue::QueuedRun;
+use crate::retention::RetentionPolicy;
+use crate::path_pattern::PathPattern;
+use crate::mount;
+use crate::watch::{self, ChangeWatcher};
+use crate::schedule::{self, Schedule};
+use crate::report;
+use crate::inventory::{self, InventoryFormat};
+use crate::exit_code::{ConfigError, TransferFailed};
+use har_backup_core::journal::TransferJournal;
+use har_backup_core::thread_sync::{CancelToken, Cancelled};
+use har_backup_core::keys::{self, KeyFile};
 use std::path::{Path, PathBuf};
-use std::collections::HashMap;
-use log::debug;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+use std::io::{IsTerminal, Write};
+use tracing::debug;
+use serde::{Deserialize, Serialize};
+
+// what har remote test asks the remote for, no blob has this key
+const REMOTE_PROBE_KEY: &str = "har_remote_test_probe";
+// in .har, the config and fetched manifest of the remote copy_to copies to
+const COPY_STAGING_NAME: &str = "copy_to";
 
 pub struct WithLocal {
     local_meta: DotHar,
+    scan_options: ScanOptions,
+    report_timings: bool,
+    transfer_config: TransferConfig,
 }
 
 impl WithLocal {
     pub fn new() -> Result<Self> {
-        let local_meta = DotHar::find_cwd_or_ancestor()?;
-        let me = Self {
+        Self::new_with_settings(&Settings::default())
+    }
+
+    pub fn new_with_settings(settings: &Settings) -> Result<Self> {
+        let local_meta = DotHar::find_cwd_or_ancestor().context(ConfigError)?.with_settings(settings);
+        Ok(Self::with_dot_har(local_meta, settings))
+    }
+
+    fn with_dot_har(local_meta: DotHar, settings: &Settings) -> Self {
+        Self {
             local_meta,
+            scan_options: settings.scan.to_scan_options(),
+            report_timings: settings.timings,
+            transfer_config: TransferConfig::from_settings(&settings.transfer),
+        }
+    }
+
+    pub fn with_scan_options(mut self, scan_options: ScanOptions) -> Self {
+        self.scan_options = scan_options;
+        self
+    }
+
+    pub fn with_timings(mut self, report_timings: bool) -> Self {
+        self.report_timings = report_timings;
+        self
+    }
+
+    // what the archive is, how old the fetched manifest is, what a push or pull would transfer (without hashing) and
+    // the run that har resume would continue. What can't be read is in the report with the rest
+    pub fn status(&self) -> Result<report::StatusReport> {
+        let (remote, remote_error) = match self.local_meta.get_remote_spec() {
+            Ok(remote_spec) => (Some(remote_spec.redacted()), None),
+            Err(err) => (None, Some(err.to_string())),
         };
-        Ok(me)
+        let (key, key_error) = match self.local_meta.get_key_spec().and_then(|key_spec| Ok((key_spec, self.local_meta.get_key()?))) {
+            Ok((key_spec, key_file)) => (Some(report::KeyStatus { spec: key_spec.to_string(), fingerprint: key_file.fingerprint() }), None),
+            Err(err) => (None, Some(err.to_string())),
+        };
+        let mut skipped_placeholders = Vec::new();
+        let fetched = match self.local_meta.get_manifest_stored_time()? {
+            None => None,
+            Some(stored) => {
+                let (fetched_manifest, generation) = self.local_meta.get_manifest_with_generation().context("Reading fetched manifest")?;
+                let (local_manifest, scan_report) = scan_local_tree(&self.local_meta, &self.scan_options)?;
+                skipped_placeholders = scan_report.skipped_placeholders;
+                let extra = |manifest_a: &Manifest, manifest_b: &Manifest| -> Result<(usize, u64)> {
+                    let diff = manifest::diff_manifests(manifest_a, manifest_b);
+                    let size = manifest_a.scoped_to_paths(&diff.paths_of_top_extra_in_a)?.get_stats().total_size;
+                    Ok((diff.extra_files_in_a, size))
+                };
+                let (only_local_files, only_local_bytes) = extra(&local_manifest, &fetched_manifest)?;
+                let (only_remote_files, only_remote_bytes) = extra(&fetched_manifest, &local_manifest)?;
+                Some(report::FetchedStatus {
+                    generation,
+                    stored: clock::format_utc_timestamp(stored),
+                    only_local_files,
+                    only_local_bytes,
+                    only_remote_files,
+                    only_remote_bytes,
+                })
+            },
+        };
+        let pending = self.local_meta.get_queue()?.map(|queued| report::PendingStatus { kind: queued.kind, num_files: queued.pending.len() });
+        Ok(report::StatusReport {
+            format_version: report::FORMAT_VERSION,
+            archive_root: self.local_meta.get_archive_root().to_str().unwrap().to_string(),
+            remote,
+            remote_error,
+            key,
+            key_error,
+            fetched,
+            pending,
+            skipped_placeholders,
+        })
     }
 
-    pub fn diff(&self, remote: bool, hash_check: bool) -> Result<()> {
-        let local_manifest = Manifest::from_fs(self.local_meta.get_archive_root()).context("Making manifest from local tree")?;
+    // what the local tree has that the fetched manifest has not (the other way with remote)
+    pub fn diff(&self, remote: bool, hash_check: bool) -> Result<report::DiffReport> {
+        let _span = tracing::info_span!("diff", remote, hash_check).entered();
+        let mut timings = Timings::default();
+        let (local_manifest, scan_report) = timings.time(Phase::Scan, || scan_local_tree(&self.local_meta, &self.scan_options))?;
         let remote_manifest = self.local_meta.get_manifest().context("Reading fetched manifest")?;
 
         let (manifest_a, manifest_b) = match remote {
@@ -31,129 +141,919 @@ impl WithLocal {
             true => (&remote_manifest, &local_manifest),
         };
 
-        let mut diff = manifest::DiffManifests::default();
-        if hash_check {
-            let archive_root = self.local_meta.get_archive_root();
-            let remote_spec = self.local_meta.get_remote_spec()?;
+        let diff = match hash_check {
+            false => timings.time(Phase::Diff, || manifest::diff_manifests(manifest_a, manifest_b)),
+            true => diff_with_hash_check(&self.local_meta, manifest_a, manifest_b, &local_manifest, &mut timings)?,
+        };
+        let to_strings = |paths: &[PathBuf]| -> Vec<String> { paths.iter().map(|path| path.to_str().unwrap().to_string()).collect() };
+        Ok(report::DiffReport {
+            format_version: report::FORMAT_VERSION,
+            extra_in: if remote { report::Side::Remote } else { report::Side::Local },
+            extra_paths: to_strings(&diff.paths_of_top_extra_in_a),
+            extra_files: diff.extra_files_in_a,
+            extra_dirs: diff.extra_dirs_in_a,
+            hash_changed: hash_check.then(|| to_strings(&diff.paths_of_different_files)),
+            timings: self.report_timings.then_some(timings),
+            skipped_placeholders: scan_report.skipped_placeholders,
+        })
+    }
 
-            let bucket_name: String = match remote_spec {
-                RemoteSpec::LocalFileSystem(path) => {
-                    path.to_str().unwrap().to_string()
-                },
-                RemoteSpec::S3(spec) => {
-                    spec.bucket_name().to_string()
-                },
-            };
+    // with the generation it was stored with, for print-fetched-manifest
+    pub fn fetched_manifest(&self) -> Result<(Manifest, u64)> {
+        self.local_meta.get_manifest_with_generation().context("Reading fetched manifest")
+    }
 
-            diff = diff.with_hash_check(archive_root.to_path_buf(), bucket_name);
-        }
+    // the directory at path of the fetched manifest, see manifest::write_listing
+    pub fn write_listing<W: Write>(&self, path: &Path, long: bool, mut writer: W) -> Result<()> {
+        let fetched_manifest = self.local_meta.get_manifest().context("Reading fetched manifest")?;
+        manifest::write_listing(&fetched_manifest, path, long, &mut writer)
+    }
 
-        let diff = diff.diff_manifests(manifest_a, manifest_b);
+    // the entries under path of the fetched manifest, their number, see manifest::write_full_listing
+    pub fn export_listing<W: Write>(&self, path: &Path, format: manifest::ListingFormat, mut writer: W) -> Result<usize> {
+        let fetched_manifest = self.local_meta.get_manifest().context("Reading fetched manifest")?;
+        let num_entries = manifest::write_full_listing(&fetched_manifest, path, format, &mut writer)?;
+        writer.flush()?;
+        Ok(num_entries)
+    }
 
-        if remote {
-            println!("Remote has the additional entries:");
-        }
-        else {
-            println!("Local tree has the additional entries:");
-        }
-        for entry_path in &diff.paths_of_top_extra_in_a {
-            println!("{}", entry_path.to_str().unwrap());
+    // the files of every version of the manifest (see log), the number of rows and of versions, see inventory::collect
+    pub fn inventory<W: Write + Send>(&self, format: InventoryFormat, mut writer: W) -> Result<(usize, usize)> {
+        let versions: Vec<(u64, Manifest)> = self.manifest_versions()?.into_iter()
+            .map(|(_, generation, manifest)| (generation, manifest))
+            .collect();
+        let rows = inventory::collect(&versions);
+        inventory::write(&rows, format, &mut writer)?;
+        writer.flush()?;
+        Ok((rows.len(), versions.len()))
+    }
+
+    // size and unique size of the directories under path of the fetched manifest, see Manifest::disk_usage
+    pub fn disk_usage(&self, path: &Path, depth: usize) -> Result<Vec<manifest::DirUsage>> {
+        let fetched_manifest = self.local_meta.get_manifest().context("Reading fetched manifest")?;
+        fetched_manifest.disk_usage(path, depth)
+    }
+
+    pub fn duplicates(&self) -> Result<Vec<manifest::DuplicateGroup>> {
+        let fetched_manifest = self.local_meta.get_manifest().context("Reading fetched manifest")?;
+        Ok(fetched_manifest.duplicates())
+    }
+
+    // (path, size, blob key) of the files of the fetched manifest whose path matches pattern, in path order
+    pub fn find(&self, pattern: &PathPattern) -> Result<Vec<(PathBuf, u64, String)>> {
+        let fetched_manifest = self.local_meta.get_manifest().context("Reading fetched manifest")?;
+        Ok(fetched_manifest.walk()
+            .filter(|entry| pattern.is_match(&entry.path))
+            .filter_map(|entry| match entry.kind {
+                manifest::WalkKind::File { size, blob_key } => Some((entry.path, size, blob_key)),
+                manifest::WalkKind::Directory => None,
+            })
+            .collect())
+    }
+
+    // for shell completion: the entries of the directory of prefix starting like its last component, directories
+    // with a trailing /. Nothing rather than errors when there is no fetched manifest or no such directory
+    pub fn path_completions(&self, prefix: &str) -> Vec<String> {
+        let (dir, start) = prefix.rsplit_once('/').unwrap_or(("", prefix));
+        let Ok(fetched_manifest) = self.local_meta.get_manifest() else {
+            return Vec::new();
+        };
+        let Ok(children) = fetched_manifest.list_dir(Path::new(dir)) else {
+            return Vec::new();
+        };
+        children.into_iter()
+            .filter(|(name, _)| name.starts_with(start))
+            .map(|(name, is_dir)| {
+                let slash = if is_dir { "/" } else { "" };
+                match dir {
+                    "" => format!("{}{}", name, slash),
+                    dir => format!("{}/{}{}", dir, name, slash),
+                }
+            })
+            .collect()
+    }
+
+    // since in unix seconds, see Manifest::files_changed_since
+    pub fn changed_since(&self, since: u64) -> Result<Vec<(PathBuf, manifest::EntryTimes)>> {
+        let fetched_manifest = self.local_meta.get_manifest().context("Reading fetched manifest")?;
+        Ok(fetched_manifest.files_changed_since(since))
+    }
+
+    // fetched manifests replaced by push with their number of files, most recent first, numbered from 1 for
+    // restore_manifest
+    pub fn list_manifest_backups(&self) -> Result<Vec<(ManifestBackup, usize)>> {
+        self.local_meta.list_manifest_backups()?.into_iter()
+            .map(|backup| {
+                let manifest = Manifest::from_bytes(bytes::Bytes::from(std::fs::read(&backup.path)?))
+                    .with_context(|| format!("Reading {}", backup.path.to_str().unwrap()))?;
+                let num_files = manifest.get_stats().num_files;
+                Ok((backup, num_files))
+            })
+            .collect()
+    }
+
+    // (name in har log, generation, manifest) of the fetched manifest and its backups, most recent first
+    fn manifest_versions(&self) -> Result<Vec<(String, u64, Manifest)>> {
+        let (fetched_manifest, generation) = self.local_meta.get_manifest_with_generation().context("Reading fetched manifest")?;
+        let mut versions = vec![("fetched".to_string(), generation, fetched_manifest)];
+        for (index, backup) in self.local_meta.list_manifest_backups()?.into_iter().enumerate() {
+            let manifest = Manifest::from_bytes(bytes::Bytes::from(std::fs::read(&backup.path)?))
+                .with_context(|| format!("Reading {}", backup.path.to_str().unwrap()))?;
+            versions.push(((index + 1).to_string(), backup.generation, manifest));
         }
-        println!("Total extra files: {}, total extra dirs: {}", diff.extra_files_in_a, diff.extra_dirs_in_a);
+        Ok(versions)
+    }
 
-        if hash_check && !diff.paths_of_different_files.is_empty() {
-            println!("There are some files which hash has changed:");
-            for entry_path in &diff.paths_of_different_files {
-                println!("{}", entry_path.to_str().unwrap());
+    // the fetched manifest and its backups, numbered as restore_manifest takes them, with what changed since the one before
+    pub fn log(&self) -> Result<Vec<report::LogEntry>> {
+        let versions = self.manifest_versions()?;
+        let empty = Manifest::new();
+        let entries = versions.iter().enumerate().map(|(index, (name, generation, manifest))| {
+            let previous = versions.get(index + 1).map_or(&empty, |(_, _, previous)| previous);
+            let changes = manifest::diff_files(manifest, previous);
+            let (written, host) = match manifest.origin() {
+                Some(origin) => (Some(origin.written), Some(origin.host.clone())),
+                // older manifests: when its last files were pushed
+                None => (manifest.files_changed_since(0).last().map(|(_, times)| times.modified), None),
+            };
+            let stats = manifest.get_stats();
+            report::LogEntry {
+                name: name.clone(),
+                generation: *generation,
+                written,
+                host,
+                num_files: stats.num_files,
+                total_size: stats.total_size,
+                added: changes.added.len(),
+                changed: changes.changed.len(),
+                deleted: changes.deleted.len(),
             }
+        });
+        Ok(entries.collect())
+    }
+
+    // structure of the fetched manifest, with repair it is replaced by a repaired copy (see Manifest::repaired), and
+    // whether it was
+    pub fn fsck(&self, repair: bool) -> Result<(manifest::FsckReport, bool)> {
+        let fetched_manifest = self.local_meta.get_manifest().context("Reading fetched manifest")?;
+        let (repaired, report) = match repair {
+            true => {
+                let (repaired, report) = fetched_manifest.repaired()?;
+                (Some(repaired), report)
+            },
+            false => (None, fetched_manifest.fsck()),
+        };
+        match repaired {
+            Some(repaired) if !report.is_clean() => {
+                self.local_meta.store_manifest_with_backup(repaired.to_bytes()?)?;
+                Ok((report, true))
+            },
+            _ => Ok((report, false)),
         }
+    }
 
-        Ok(())
+    // removes the manifest backups that policy does not keep
+    pub fn prune_manifest_backups(&self, policy: &RetentionPolicy) -> Result<report::PruneReport> {
+        let backups = self.local_meta.list_manifest_backups()?;
+        let times: Vec<u64> = backups.iter().map(|backup| backup.replaced).collect();
+        let to_keep = policy.to_keep(&times);
+        let mut pruned = Vec::new();
+        for (backup, _) in backups.into_iter().zip(&to_keep).filter(|(_, &keep)| !keep) {
+            self.local_meta.remove_manifest_backup(&backup)?;
+            pruned.push(backup);
+        }
+        let num_kept = to_keep.iter().filter(|&&keep| keep).count();
+        Ok(report::PruneReport { pruned, num_kept })
+    }
+
+    pub fn restore_manifest(&self, n: usize) -> Result<ManifestBackup> {
+        self.local_meta.restore_manifest_backup(n)
     }
 
-    pub fn print_fetched_manifest(&self) -> Result<()> {
+    // stats of the fetched manifest
+    pub fn stats(&self) -> Result<manifest::Stats> {
         let fetched_manifest = self.local_meta.get_manifest().context("Reading fetched manifest")?;
-        let stats = fetched_manifest.get_stats();
-        println!("{:?}", stats);
-        manifest::print_tree(&fetched_manifest);
+        Ok(fetched_manifest.get_stats())
+    }
+
+    // how the last pushes and pulls went, for stats --graph
+    pub fn run_history(&self) -> Result<Vec<RunRecord>> {
+        self.local_meta.get_runs()
+    }
+
+    pub fn import_key_to_keychain(&self, key_path: &Path, name: &str) -> Result<()> {
+        let key_bytes = std::fs::read(key_path).with_context(|| format!("Reading {}", key_path.to_str().unwrap()))?;
+        KeyFile::from_content(&key_bytes, key_path.to_str().unwrap())?;
+        crate::keychain::store_key(name, &key_bytes)?;
+        self.local_meta.set_keychain_key(name)?;
         Ok(())
     }
+
+    pub fn remote_spec(&self) -> Result<RemoteSpec> {
+        self.local_meta.get_remote_spec()
+    }
+
+    // the fetched manifest is left as it is, fetch it from the new remote if it is another archive
+    pub fn set_remote(&self, spec: &str) -> Result<RemoteSpec> {
+        let remote_spec = RemoteSpec::parse(spec)?;
+        self.local_meta.set_remote_spec(spec).context("Write remote spec")?;
+        Ok(remote_spec)
+    }
+
+    // each remote of a multi:// spec is tried on its own, a key that no blob has then a listing
+    pub fn test_remote(&self) -> Result<Vec<report::RemoteTest>> {
+        let specs = match self.local_meta.get_remote_spec()? {
+            RemoteSpec::Multi(specs) => specs,
+            spec => vec![spec],
+        };
+        // nothing is decrypted, a throwaway key spares the passphrase prompt
+        let encrypt = EncryptWithChacha::new(&KeyFile::from_bytes(&KeyFile::create_full())?);
+        let mut tests = Vec::new();
+        for spec in specs {
+            let name = spec.redacted();
+            let probe = || -> Result<(u128, usize, u128)> {
+                let mut blob_storage = WithRemoteAndLocal::make_blob_storage(spec, &encrypt)?;
+                let start = std::time::Instant::now();
+                blob_storage.exists_blocking(REMOTE_PROBE_KEY)?;
+                let exists_ms = start.elapsed().as_millis();
+                let start = std::time::Instant::now();
+                let num_objects = blob_storage.list_blocking()?.len();
+                Ok((exists_ms, num_objects, start.elapsed().as_millis()))
+            };
+            let probe = probe().map_err(|err| format!("{:#}", err));
+            tests.push(report::RemoteTest { name, probe });
+        }
+        Ok(tests)
+    }
+
+    // the key file is loaded first, a path to something else is not written. The absolute path and the key
+    pub fn set_key_path(&self, key_path: &Path) -> Result<(PathBuf, KeyFile)> {
+        let key_path = std::path::absolute(key_path)?;
+        let key_file = KeyFile::from_file(&key_path)?;
+        self.local_meta.set_path_to_keyfile(&key_path)?;
+        Ok((key_path, key_file))
+    }
+
+    pub fn key(&self) -> Result<(KeySpec, KeyFile)> {
+        Ok((self.local_meta.get_key_spec()?, self.local_meta.get_key()?))
+    }
+
+    // None is no compression
+    pub fn set_compression(&self, level: Option<i32>) -> Result<()> {
+        match level {
+            Some(level) => self.local_meta.set_compression(Compression::with_level(level)),
+            None => self.local_meta.set_compression(Compression::off()),
+        }
+    }
+
+    // forget all recorded failures so that quarantined files are tried again, the number of files that were
+    pub fn clear_quarantine(&self) -> Result<usize> {
+        let mut journal = self.local_meta.get_journal()?;
+        let num_quarantined = journal.quarantined(self.transfer_config.max_attempts()).len();
+        journal.clear();
+        self.local_meta.store_journal(&journal)?;
+        Ok(num_quarantined)
+    }
 }
 
 pub struct WithRemoteAndLocal {
     local_meta: DotHar,
     remote: Mirror,
+    scan_options: ScanOptions,
+    report_timings: bool,
+    local_deletion: Option<LocalDeletion>, // pull removes what the remote does not have
+    progress_bars: bool, // instead of status lines during transfers
+    shrink_guard: f64, // see settings::PushSettings
+    force: bool, // push even past the shrink guard
+    pull_conflict: PullConflict, // see Mirror::set_pull_conflict
+    scope: Option<PathBuf>, // push and pull only this path of the archive
+    keep_going: bool, // files that fail are listed at the end, and the command fails then rather than on the first one
+    hash_check: bool, // push also files that differ from the remote, see with_hash_check
+    adopt: bool, // push uploads nothing, see with_adopt
+    auto_fetch: bool, // push fetches the remote manifest first
+    resuming: Option<Vec<PathBuf>>, // the pending paths of a queued run, see resume
+}
+
+// what verify downloads of the blobs it finds in the remote
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyDownload {
+    None,
+    Sample(usize), // that many blobs at random
+    All,
+}
+
+// how pull in mirror mode removes local files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LocalDeletion {
+    Trash, // to the OS trash, can be restored from there
+    Permanent,
 }
 
 impl WithRemoteAndLocal {
     pub fn new() -> Result<Self> {
-        let local_meta = DotHar::find_cwd_or_ancestor()?;
-        let remote = Self::init_mirror(&local_meta)?;
+        Self::new_with_settings(&Settings::default())
+    }
+
+    pub fn new_with_settings(settings: &Settings) -> Result<Self> {
+        let local_meta = DotHar::find_cwd_or_ancestor().context(ConfigError)?.with_settings(settings);
+        Self::connect(local_meta, settings)
+    }
+
+    // checks that the local key is the key of the archive (fingerprint) and that we can read the archive
+    fn connect(local_meta: DotHar, settings: &Settings) -> Result<Self> {
+        let mut remote = Self::init_mirror(&local_meta, TransferConfig::from_settings(&settings.transfer)).context(ConfigError)?;
+        remote.check_key_fingerprint(&local_meta.get_key().context(ConfigError)?.fingerprint())?;
+        remote.check_archive_metadata().context("Checking remote archive metadata")?;
         let me = Self {
             local_meta,
-            remote
+            remote,
+            scan_options: settings.scan.to_scan_options(),
+            report_timings: settings.timings,
+            local_deletion: None,
+            progress_bars: !settings.quiet && settings.progress.unwrap_or_else(|| std::io::stderr().is_terminal()),
+            shrink_guard: settings.push.shrink_guard(),
+            force: false,
+            pull_conflict: PullConflict::default(),
+            scope: None,
+            keep_going: false,
+            hash_check: false,
+            adopt: false,
+            auto_fetch: settings.auto_fetch,
+            resuming: None,
         };
         Ok(me)
     }
 
-    pub fn fetch_manifest(&mut self) -> Result<()> {
+    // makes archive_root an archive of the remote, .har is only written once the remote was reached with the key
+    pub fn init(archive_root: &Path, remote_spec: &str, key_spec: &KeySpec, settings: &Settings) -> Result<Self> {
+        let dot_har_path = archive_root.join(DOT_HAR_NAME);
+        if dot_har_path.exists() {
+            anyhow::bail!("It looks like this has been initialized already!")
+        }
+        // left by an init that failed, it is only ours
+        let staging_path = archive_root.join(format!("{}.init", DOT_HAR_NAME));
+        if staging_path.exists() {
+            std::fs::remove_dir_all(&staging_path).context("Removing the leftovers of a previous init")?;
+        }
+        std::fs::create_dir(&staging_path)?;
+        let connected = (|| {
+            let staging = DotHar::with_path(staging_path.clone());
+            staging.set_remote_spec(remote_spec).context("Write remote spec")?;
+            staging.set_key_spec(key_spec)?;
+            let mut me = Self::connect(staging, settings).context("Checking the remote with the key")?;
+            me.remote.get_archive_metadata()?;
+            Ok(me)
+        })();
+        let mut me = match connected {
+            Ok(connected) => connected,
+            Err(err) => {
+                std::fs::remove_dir_all(&staging_path)?;
+                return Err(err);
+            },
+        };
+        std::fs::rename(&staging_path, &dot_har_path).context("Moving .har in place")?;
+        me.local_meta = DotHar::with_path(dot_har_path).with_settings(settings);
+        Ok(me)
+    }
+
+    // dir (created, or empty) made an archive of the remote with all of its files: init, fetch and pull
+    // a pull that stops is finished with har resume in dir, as any
+    pub fn clone_into(dir: &Path, remote_spec: &str, key_spec: &KeySpec, settings: &Settings, cancel: CancelToken, keep_going: bool)
+            -> Result<report::PullReport> {
+        if dir.exists() && std::fs::read_dir(dir)?.next().is_some() {
+            anyhow::bail!("{} is not empty, har init and har pull there instead", dir.to_str().unwrap());
+        }
+        std::fs::create_dir_all(dir).with_context(|| format!("Creating {}", dir.to_str().unwrap()))?;
+        let mut me = Self::init(dir, remote_spec, key_spec, settings)?
+            .with_cancel(cancel)
+            .with_keep_going(keep_going);
+        if me.remote.get_archive_metadata()?.is_none() {
+            std::fs::remove_dir_all(dir.join(DOT_HAR_NAME))?;
+            anyhow::bail!("There is no archive to clone in the remote");
+        }
+        me.fetch_manifest()?;
+        me.pull()
+    }
+
+    pub fn with_scan_options(mut self, scan_options: ScanOptions) -> Self {
+        self.scan_options = scan_options;
+        self
+    }
+
+    pub fn with_timings(mut self, report_timings: bool) -> Self {
+        self.report_timings = report_timings;
+        self
+    }
+
+    pub fn with_local_deletion(mut self, local_deletion: Option<LocalDeletion>) -> Self {
+        self.local_deletion = local_deletion;
+        self
+    }
+
+    // Ctrl-C: push and pull stop after the transfers in flight, see Mirror::set_cancel
+    pub fn with_cancel(mut self, cancel: CancelToken) -> Self {
+        self.remote.set_cancel(cancel);
+        self
+    }
+
+    pub fn with_force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    // local files that differ from the remote are pulled again unless pull_conflict is SkipExisting
+    pub fn with_pull_conflict(mut self, pull_conflict: PullConflict) -> Self {
+        self.pull_conflict = pull_conflict;
+        self.remote.set_pull_conflict(pull_conflict);
+        self
+    }
+
+    // path relative to the archive root, the rest of the archive is left out of the diff and the transfers
+    pub fn with_scope(mut self, scope: Option<PathBuf>) -> Self {
+        self.scope = scope;
+        self
+    }
+
+    // push leaves out local files it can't read, pull keeps local files it can't hash, see report::PushReport::failures
+    pub fn with_keep_going(mut self, keep_going: bool) -> Self {
+        self.keep_going = keep_going;
+        self
+    }
+
+    // push rehashes the local files that the remote has, those with other content are pushed as new versions
+    // of the remote entries. Chunks the remote already has are not uploaded again (see Mirror::set_dedup), so
+    // a big file stored in chunks only sends the chunks that changed
+    pub fn with_hash_check(mut self, hash_check: bool) -> Self {
+        self.hash_check = hash_check;
+        self
+    }
+
+    // push records the files the remote already has a blob of (hashed locally, found in the remote manifest or with
+    // exists()) without uploading anything, for a tree copied by other means. The other files are left for a push
+    pub fn with_adopt(mut self, adopt: bool) -> Self {
+        self.adopt = adopt;
+        self
+    }
+
+    // the part of manifest in scope, None if it is not in manifest and may_be_missing
+    fn scoped(&self, manifest: Manifest, may_be_missing: bool) -> Result<Option<Manifest>> {
+        let Some(scope) = &self.scope else {
+            return Ok(Some(manifest));
+        };
+        match manifest.scoped(scope) {
+            Ok(scoped) => Ok(Some(scoped)),
+            Err(_) if may_be_missing => Ok(None),
+            Err(err) => Err(err.context(format!("{} is not in the archive", scope.to_str().unwrap()))),
+        }
+    }
+
+    pub fn with_progress_bars(mut self, progress_bars: bool) -> Self {
+        self.progress_bars = progress_bars;
+        self
+    }
+
+    // transfers retry on a virtual clock with seeded jitter, for reproducible tests
+    pub fn with_deterministic(mut self, seed: u64) -> Self {
+        self.remote.set_deterministic(seed, Arc::new(VirtualClock::new()));
+        self
+    }
+
+    // the mirror prints nothing of a transfer, this does
+    fn progress(&self, verb: &str) -> Option<Box<dyn TransferProgress>> {
+        if self.progress_bars {
+            Some(Box::new(ProgressBars::new(verb)))
+        } else {
+            Some(Box::new(StatusLines::new(verb)))
+        }
+    }
+
+    // checked against its signature when the archive has signed manifests
+    fn get_remote_manifest_blob(&mut self) -> Result<bytes::Bytes> {
         let manifest_blob = self.remote.get_manifest_blob()?;
-        self.local_meta.store_manifest(manifest_blob)?;
-        println!("Fetched manifest.");
-        Ok(())
+        if let Some(public_key) = self.signing_public_key()? {
+            let signature = self.remote.get_manifest_signature()?.context("Archive has signed manifests but the manifest signature is missing")?;
+            keys::verify_manifest(&public_key, &manifest_blob, &signature)?;
+        }
+        Ok(manifest_blob)
+    }
+
+    // the generation the fetched manifest was stored with
+    pub fn fetch_manifest(&mut self) -> Result<u64> {
+        let manifest_blob = self.get_remote_manifest_blob()?;
+        self.local_meta.store_manifest(manifest_blob)
+    }
+
+    // connecting already compared the fingerprints, the manifest is what the key has to decrypt
+    pub fn check_key(&mut self) -> Result<report::KeyCheck> {
+        let manifest_blob = self.get_remote_manifest_blob().context("Decrypting the remote manifest with the key")?;
+        let manifest = Manifest::from_bytes(manifest_blob)?;
+        let key_file = self.local_meta.get_key()?;
+        Ok(report::KeyCheck { num_files: manifest.get_stats().num_files, read_only: key_file.is_read_only() })
+    }
+
+    // compares the listing of the remote with the blobs of the remote manifest, nothing is downloaded but the manifest
+    // finds lost and truncated blobs cheaply, not corrupted ones
+    pub fn verify_remote_only(&mut self) -> Result<report::VerifyReport> {
+        let remote_manifest = Manifest::from_bytes(self.get_remote_manifest_blob()?)?;
+        let objects = self.remote.list_objects()?;
+
+        // blobs are shared by files with the same content, check each once
+        let mut checked: HashSet<String> = HashSet::new();
+        let mut problems = Vec::new();
+        let mut num_without_size = 0;
+        for (path, blob_key, ciphertext_size) in remote_manifest.list_blobs() {
+            if !checked.insert(blob_key.clone()) {
+                continue;
+            }
+            let kind = match (objects.get(&blob_key), ciphertext_size) {
+                (None, _) => report::BlobProblemKind::Missing,
+                (Some(&stored), Some(expected)) if stored != expected => report::BlobProblemKind::WrongSize { stored, expected },
+                (Some(_), None) => {
+                    num_without_size += 1;
+                    continue;
+                },
+                (Some(_), Some(_)) => continue,
+            };
+            problems.push(report::BlobProblem { blob_key, path: path.to_str().unwrap().to_string(), kind });
+        }
+        Ok(report::VerifyReport {
+            format_version: report::FORMAT_VERSION,
+            remote_only: true,
+            checked: checked.len(),
+            downloaded: 0,
+            without_size: num_without_size,
+            problems,
+        })
+    }
+
+    // checks that the remote has every blob of the fetched manifest, and downloads those of download to check that
+    // their content is the content of their key, which also checks that the key decrypts them
+    pub fn verify(&mut self, download: VerifyDownload) -> Result<report::VerifyReport> {
+        let fetched_manifest = self.local_meta.get_manifest().context("Reading fetched manifest")?;
+        // blobs are shared by files with the same content, check each once
+        let mut seen: HashSet<String> = HashSet::new();
+        let blobs: Vec<(PathBuf, String)> = fetched_manifest.list_blobs().into_iter()
+            .filter(|(_, blob_key, _)| seen.insert(blob_key.clone()))
+            .map(|(path, blob_key, _)| (path, blob_key))
+            .collect();
+        // the same sample for the same seed, see with_deterministic
+        let mut jitter = self.remote.fork_jitter();
+        let mut num_to_sample = match download {
+            VerifyDownload::None => 0,
+            VerifyDownload::Sample(num) => num,
+            VerifyDownload::All => blobs.len(),
+        };
+        // each blob with the probability of filling the sample from the remaining ones, num_to_sample of them in all
+        let keys: Vec<(String, bool)> = blobs.iter().enumerate().map(|(index, (_, blob_key))| {
+            let to_download = num_to_sample > 0 && jitter.below((blobs.len() - index) as u64) < num_to_sample as u64;
+            if to_download {
+                num_to_sample -= 1;
+            }
+            (blob_key.clone(), to_download)
+        }).collect();
+        let checks = self.remote.check_blobs(&keys)?;
+
+        let mut problems = Vec::new();
+        let mut num_downloaded = 0;
+        for ((path, blob_key), check) in blobs.iter().zip(checks) {
+            let kind = match check {
+                mirror::BlobCheck::Present => continue,
+                mirror::BlobCheck::Intact => {
+                    num_downloaded += 1;
+                    continue;
+                },
+                mirror::BlobCheck::Missing => report::BlobProblemKind::Missing,
+                mirror::BlobCheck::Damaged(reason) => {
+                    num_downloaded += 1;
+                    report::BlobProblemKind::Corrupt { reason }
+                },
+            };
+            problems.push(report::BlobProblem { blob_key: blob_key.clone(), path: path.to_str().unwrap().to_string(), kind });
+        }
+        Ok(report::VerifyReport {
+            format_version: report::FORMAT_VERSION,
+            remote_only: false,
+            checked: blobs.len(),
+            downloaded: num_downloaded,
+            without_size: 0,
+            problems,
+        })
+    }
+
+    pub fn init_remote(&mut self) -> Result<(InitOutcome, String)> {
+        self.init_remote_with_description("")
+    }
+
+    // with a full key the manifest of the new archive is signed. The uuid of the archive, new or not
+    pub fn init_remote_with_description(&mut self, description: &str) -> Result<(InitOutcome, String)> {
+        self.with_remote_lock(|me| me.init_remote_locked(description))
+    }
+
+    fn init_remote_locked(&mut self, description: &str) -> Result<(InitOutcome, String)> {
+        let key_file = self.local_meta.get_key()?;
+        // an init that died midway is retried with the same archive uuid, so that the remote recognizes it
+        let metadata = match self.local_meta.get_pending_init()? {
+            Some(metadata) if metadata.signing_public_key == key_file.public_key_hex() => metadata,
+            _ => {
+                let mut metadata = ArchiveMetadata::new(description);
+                metadata.signing_public_key = key_file.public_key_hex();
+                self.local_meta.store_pending_init(&metadata)?;
+                metadata
+            },
+        };
+        let outcome = self.remote.init_with_metadata(&metadata)?;
+        self.remote.push_key_fingerprint(&key_file.fingerprint())?;
+        if !key_file.is_read_only() {
+            self.sign_remote_manifest(&key_file)?;
+        }
+        self.local_meta.clear_pending_init()?;
+        // pinned now, see signing_public_key
+        self.signing_public_key()?;
+        Ok((outcome, metadata.archive_uuid))
+    }
+
+    // f runs with the lock of the remote, other clients can't push meanwhile (see remote_lock)
+    fn with_remote_lock<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        let holder = format!("{}:{}", host_name(), std::process::id());
+        let lock = self.remote.lock(&holder, clock::unix_now())?;
+        let result = f(self);
+        let unlocked = self.remote.unlock(&lock);
+        let value = result?;
+        unlocked?;
+        Ok(value)
+    }
+
+    // after restore_manifest, to undo a bad push: the remote manifest becomes the fetched one whatever it is now
+    // blobs that only the replaced manifest had are left in the remote
+    pub fn push_fetched_manifest(&mut self) -> Result<()> {
+        self.with_remote_lock(|me| {
+            let signer = me.manifest_signer()?;
+            let (_, manifest_blob) = me.local_meta.get_manifest_and_blob()?;
+            me.remote.push_manifest_blob(manifest_blob.clone())?;
+            if let Some(signer) = signer {
+                me.remote.push_manifest_signature(signer.sign_manifest(&manifest_blob)?)?;
+            }
+            Ok(())
+        })
+    }
+
+    // removes paths from the remote manifest, local files are left as they are (push adds them back)
+    // the blobs of removed files stay in the remote, mark_unreferenced records those no file refers to anymore
+    pub fn rm(&mut self, paths: &[PathBuf], mark_unreferenced: bool) -> Result<report::RmReport> {
+        let (removed, removed_blobs) = self.edit_remote_manifest(|remote_manifest| {
+            let mut removed = Vec::new();
+            let mut removed_blobs = HashSet::new();
+            for path in paths {
+                let removed_manifest = remote_manifest.remove(path).with_context(|| format!("Removing {}", path.to_str().unwrap()))?;
+                removed.push((path.clone(), removed_manifest.get_stats().num_files));
+                removed_blobs.extend(removed_manifest.list_blobs().into_iter().map(|(_, key, _)| key));
+            }
+            // the same content may be at paths that were not removed
+            for (_, key, _) in remote_manifest.list_blobs() {
+                removed_blobs.remove(&key);
+            }
+            Ok((removed, removed_blobs))
+        })?;
+        let mut marked_for_gc = None;
+        if mark_unreferenced {
+            let mut unreferenced: Vec<String> = removed_blobs.into_iter().collect();
+            unreferenced.sort();
+            self.local_meta.add_gc_candidates(&unreferenced)?;
+            marked_for_gc = Some(unreferenced.len());
+        }
+        Ok(report::RmReport { removed, marked_for_gc })
+    }
+
+    // renames or moves an entry of the remote manifest, nothing is transferred, and where it went
+    // the local tree is left as it is, push adds what is still at from back
+    pub fn mv(&mut self, from: &Path, to: &Path) -> Result<PathBuf> {
+        self.edit_remote_manifest(|remote_manifest| remote_manifest.rename(from, to))
+    }
+
+    // edit changes the fetched manifest, which then replaces the remote one if that is still the same
+    fn edit_remote_manifest<T>(&mut self, edit: impl FnOnce(&mut Manifest) -> Result<T>) -> Result<T> {
+        self.with_remote_lock(|me| {
+            if me.auto_fetch {
+                me.fetch_manifest()?;
+            }
+            let (mut remote_manifest, fetched_blob) = me.local_meta.get_manifest_and_blob().context("Reading fetched manifest")?;
+            let signer = me.manifest_signer()?;
+            me.remote.check_manifest_unchanged(&fetched_blob)?;
+            let value = edit(&mut remote_manifest)?;
+            me.replace_remote_manifest(&mut remote_manifest, &fetched_blob, signer)?;
+            Ok(value)
+        })
     }
 
-    pub fn init_remote(&mut self) -> Result<()> {
-        self.remote.init()?;
-        println!("Remote initialized.");
+    // if the remote manifest is still fetched_blob (see push_manifest_blob_if_unchanged), the fetched one too
+    fn replace_remote_manifest(&mut self, remote_manifest: &mut Manifest, fetched_blob: &[u8], signer: Option<KeyFile>) -> Result<()> {
+        remote_manifest.set_origin(manifest::ManifestOrigin { host: host_name(), written: clock::unix_now() });
+        let new_remote_manifest_bytes = remote_manifest.to_bytes()?;
+        self.remote.upgrade_manifest_format()?;
+        self.remote.push_manifest_blob_if_unchanged(new_remote_manifest_bytes.clone(), fetched_blob)?;
+        if let Some(signer) = signer {
+            self.remote.push_manifest_signature(signer.sign_manifest(&new_remote_manifest_bytes)?)?;
+        }
+        self.local_meta.store_manifest_with_backup(new_remote_manifest_bytes)?;
         Ok(())
     }
 
-    fn init_mirror(local_meta: &DotHar) -> Result<Mirror> {
-        let blob_storage = Self::init_blob_storage(local_meta)?;
-        let mirror = Mirror::new(blob_storage);
-        Ok(mirror)
+    // for a client that died with the lock
+    pub fn unlock(&mut self, force: bool) -> Result<report::UnlockReport> {
+        let Some(lock) = self.remote.get_lock()? else {
+            return Ok(report::UnlockReport::NotLocked);
+        };
+        if !force {
+            return Ok(report::UnlockReport::Held(lock));
+        }
+        // released in between by its holder otherwise
+        Ok(match self.remote.force_unlock()? {
+            Some(released) => report::UnlockReport::Released(released),
+            None => report::UnlockReport::NotLocked,
+        })
     }
 
-    fn init_blob_storage(local_meta: &DotHar) -> Result<Box<dyn BlobStorage>> {
+    // require manifests of an existing archive to be signed by the current (full) key
+    pub fn enable_signing(&mut self) -> Result<()> {
+        self.with_remote_lock(|me| {
+            let key_file = me.local_meta.get_key()?;
+            let public_key = key_file.public_key_hex().context("Cannot enable signing with a read only key")?;
+            me.signing_public_key()?;
+            let mut metadata = me.remote.get_archive_metadata()?.context("Remote has no archive metadata")?;
+            me.sign_remote_manifest(&key_file)?;
+            metadata.signing_public_key = Some(public_key.clone());
+            me.remote.push_archive_metadata(&metadata)?;
+            me.local_meta.set_signing_public_key(&public_key)?;
+            Ok(())
+        })
+    }
 
-        let keypath = local_meta.get_key_file()?;
+    fn sign_remote_manifest(&mut self, key_file: &KeyFile) -> Result<()> {
+        let manifest_blob = self.remote.get_manifest_blob()?;
+        self.remote.push_manifest_signature(key_file.sign_manifest(&manifest_blob)?)
+    }
+
+    // public key of the signed manifests, pinned in .har/config.toml the first time it is seen (init, fetch, clone)
+    // anyone with the read key can rewrite the archive metadata, so a remote that drops or changes it is refused
+    fn signing_public_key(&mut self) -> Result<Option<String>> {
+        let remote_key = self.remote.get_archive_metadata()?.and_then(|metadata| metadata.signing_public_key);
+        match (self.local_meta.get_signing_public_key()?, remote_key) {
+            (None, None) => Ok(None),
+            (None, Some(remote_key)) => {
+                self.local_meta.set_signing_public_key(&remote_key)?;
+                Ok(Some(remote_key))
+            },
+            (Some(pinned), Some(remote_key)) if pinned == remote_key => Ok(Some(pinned)),
+            (Some(_), None) => anyhow::bail!("Remote archive metadata lost the manifest signing key pinned in .har/config.toml, not trusting its manifests"),
+            (Some(_), Some(_)) => anyhow::bail!("Remote archive metadata has another manifest signing key than the one pinned in .har/config.toml, not trusting its manifests (remove signing_public_key from .har/config.toml if the key was changed on purpose)"),
+        }
+    }
 
-        if !keypath.exists() {
-            anyhow::bail!("Keyfile {} (as specified by .har) not found", keypath.to_str().unwrap());
+    // key to sign the manifest with, none if the archive does not have signed manifests
+    fn manifest_signer(&mut self) -> Result<Option<KeyFile>> {
+        let public_key = match self.signing_public_key()? {
+            Some(public_key) => public_key,
+            None => return Ok(None),
+        };
+        let key_file = self.local_meta.get_key()?;
+        if key_file.public_key_hex().as_deref() != Some(public_key.as_str()) {
+            anyhow::bail!("Archive has signed manifests and this key cannot sign them (read only key?)");
         }
+        Ok(Some(key_file))
+    }
+
+    // None when the remote has no archive yet
+    pub fn archive_metadata(&mut self) -> Result<Option<ArchiveMetadata>> {
+        self.remote.get_archive_metadata()
+    }
+
+    // a signing key dropped or changed by someone else is not pushed back, see signing_public_key
+    pub fn set_archive_metadata_value(&mut self, key: &str, value: &str) -> Result<()> {
+        self.with_remote_lock(|me| {
+            me.signing_public_key()?;
+            let mut metadata = me.remote.get_archive_metadata()?.context("Remote has no archive metadata")?;
+            match key {
+                "description" => metadata.description = value.to_string(),
+                _ => { metadata.extra.insert(key.to_string(), value.to_string()); },
+            }
+            me.remote.push_archive_metadata(&metadata)
+        })
+    }
+
+    fn init_mirror(local_meta: &DotHar, config: TransferConfig) -> Result<Mirror> {
+        Mirror::builder()
+            .storage(Self::init_blob_storage(local_meta)?)
+            .transfer_config(config)
+            .bucket_name(local_meta.get_remote_spec()?.bucket_name())
+            .build()
+    }
+
+    fn init_blob_storage(local_meta: &DotHar) -> Result<Box<dyn BlobStorage>> {
+
+        let key = local_meta.get_key()?;
+        let old_keys = local_meta.get_old_keys()?;
+        let encrypt = EncryptWithChacha::new_keyring(&key, &old_keys)
+            .with_compression(local_meta.get_compression()?);
 
         let remote_spec = local_meta.get_remote_spec()?;
 
-        let blob_storage: Box<dyn BlobStorage> = match remote_spec {
+        Ok(Self::make_blob_storage(remote_spec, &encrypt)?)
+    }
+
+    fn make_blob_storage(remote_spec: RemoteSpec, encrypt: &EncryptWithChacha) -> Result<Box<dyn BlobStorage + Send>> {
+        let bucket_name = remote_spec.bucket_name();
+        let blob_storage: Box<dyn BlobStorage + Send> = match remote_spec {
             RemoteSpec::LocalFileSystem(path) => {
                 debug!("fs scheme, path: {}", path.to_str().unwrap());
-                let blob_storage = BlobStorageLocalDirectory::new(&path, &keypath)?;
+                let blob_storage = BlobStorageLocalDirectory::new_with_encryption(&path, encrypt.clone())?;
                 Box::new(blob_storage)
             },
+            #[cfg(feature = "s3")]
             RemoteSpec::S3(spec) => {
-                let blob_storage = blob_storage_s3::BlobStorageS3::new(
+                let blob_storage = blob_storage_s3::BlobStorageS3::new_with_encryption(
                     spec.endpoint(),
                     spec.bucket_name(),
                     spec.key(),
                     spec.secret(),
-                    &keypath)?;
+                    encrypt.clone())?;
                 Box::new(blob_storage)
             },
+            #[cfg(not(feature = "s3"))]
+            RemoteSpec::S3(_) => anyhow::bail!("This har was built without the s3 feature"),
+            #[cfg(feature = "rclone")]
+            RemoteSpec::Rclone(remote) => {
+                debug!("rclone scheme, remote: {}", remote);
+                Box::new(blob_storage_rclone::BlobStorageRclone::new_with_encryption(&remote, encrypt.clone())?)
+            },
+            #[cfg(not(feature = "rclone"))]
+            RemoteSpec::Rclone(_) => anyhow::bail!("This har was built without the rclone feature"),
+            RemoteSpec::Multi(specs) => {
+                let children = specs.into_iter()
+                    .map(|spec| Self::make_blob_storage(spec, encrypt))
+                    .collect::<Result<Vec<_>>>()?;
+                Box::new(BlobStorageMulti::new(children, &bucket_name)?)
+            },
         };
         Ok(blob_storage)
     }
 
-    pub fn push(&mut self) -> Result<()> {
-        let local_manifest = Manifest::from_fs(self.local_meta.get_archive_root()).context("Making manifest from local tree")?;
-        let mut remote_manifest = self.local_meta.get_manifest().context("Reading fetched manifest")?;
-        let diff = manifest::diff_manifests(&local_manifest, &remote_manifest);
+    pub fn push(&mut self) -> Result<report::PushReport> {
+        let _span = tracing::info_span!("push", archive_root = %self.local_meta.get_archive_root().display()).entered();
+        self.with_remote_lock(Self::push_locked)
+    }
 
-        if diff.top_extra_ids_in_a.is_empty() {
-            println!("Nothing to push.");
-            return Ok(());
+    fn push_locked(&mut self) -> Result<report::PushReport> {
+        let fetched = match self.auto_fetch {
+            true => Some(self.fetch_manifest()?),
+            false => None,
+        };
+        let mut timings = Timings::default();
+        let scan_options = ScanOptions { keep_going: self.keep_going, ..self.scan_options.clone() };
+        let (local_manifest, scan_report) = timings.time(Phase::Scan, || scan_local_tree(&self.local_meta, &scan_options))?;
+        let archive_root = self.local_meta.get_archive_root();
+        let mut failures: Vec<(PathBuf, String)> = scan_report.unreadable.into_iter()
+            .map(|(path, reason)| (path.strip_prefix(archive_root).map_or(path.clone(), Path::to_path_buf), reason))
+            .collect();
+        let skipped_placeholders = scan_report.skipped_placeholders;
+        let mut local_manifest = self.scoped(local_manifest, false)?.unwrap();
+        let (mut remote_manifest, fetched_blob) = self.local_meta.get_manifest_and_blob().context("Reading fetched manifest")?;
+        let shrunk = match (&self.scope, &self.resuming) {
+            // the push that was queued went past it
+            (_, Some(pending)) => {
+                local_manifest = local_manifest.scoped_to_paths(pending)?;
+                None
+            },
+            (None, None) => self.check_shrink_guard(&local_manifest, &remote_manifest)?,
+            (Some(_), None) => match self.scoped(remote_manifest.clone(), true)? {
+                Some(remote_in_scope) => self.check_shrink_guard(&local_manifest, &remote_in_scope)?,
+                None => None,
+            },
+        };
+        // names of older manifests were compared exactly, which made names from macOS (NFD) new names elsewhere
+        remote_manifest.set_name_normalization(manifest::NameNormalization::Nfc);
+        let diff = match self.hash_check {
+            false => timings.time(Phase::Diff, || manifest::diff_manifests(&local_manifest, &remote_manifest)),
+            true => diff_with_hash_check(&self.local_meta, &local_manifest, &remote_manifest, &local_manifest, &mut timings)?,
+        };
+
+        if diff.top_extra_ids_in_a.is_empty() && diff.paths_of_different_files.is_empty() {
+            return Ok(report::PushReport {
+                fetched,
+                skipped_placeholders,
+                shrunk,
+                nothing_to_push: true,
+                failures,
+                timings: self.report_timings.then_some(timings),
+                ..Default::default()
+            });
         }
 
+        let signer = self.manifest_signer()?;
+        // rather than after the transfers, on the manifest update
+        self.remote.check_manifest_unchanged(&fetched_blob)?;
+
+        let planning_start = std::time::Instant::now();
         let path_getter = local_manifest.get_full_path_getter();
 
         let mut files_to_push = Vec::new();
@@ -161,48 +1061,206 @@ impl WithRemoteAndLocal {
             let extra_files = local_manifest.get_child_files_recurs(top_extra_entry);
             files_to_push.extend(extra_files);
         }
-        let paths_in_archive: Vec<PathBuf> = files_to_push.iter().map(|&id| path_getter(id)).collect();
+        let mut paths_in_archive: Vec<PathBuf> = files_to_push.iter().map(|&id| path_getter(id)).collect();
+        let num_changed = diff.paths_of_different_files.len();
+        paths_in_archive.extend(diff.paths_of_different_files.iter().cloned());
         let prefix_path = self.local_meta.get_archive_root();
+        timings.add(Phase::Planning, planning_start.elapsed());
+
+        if self.adopt {
+            let (adopted, left_out) = self.adopted_blobs(&paths_in_archive, &remote_manifest, &mut failures)?;
+            let now = clock::unix_now();
+            manifest::add_new_entries_to_manifest(&local_manifest, &mut remote_manifest, &diff, &adopted, &left_out, now)?;
+            manifest::update_changed_entries_in_manifest(&local_manifest, &mut remote_manifest, &diff, &adopted, &left_out, now)?;
+            self.replace_remote_manifest(&mut remote_manifest, &fetched_blob, signer)?;
+            return Ok(report::PushReport {
+                fetched,
+                skipped_placeholders,
+                shrunk,
+                num_changed,
+                adopted: Some((adopted.len(), left_out.len())),
+                failures,
+                timings: self.report_timings.then_some(timings),
+                ..Default::default()
+            });
+        }
 
-        println!("Starting to push {} files...", files_to_push.len());
-        let results = self.remote.push(&paths_in_archive, prefix_path, TransferConfig::default())?;
-        println!("Push done. Next is to update the remote manifest.");
+        let max_attempts = self.remote.transfer_config().max_attempts();
+        let mut journal = self.local_meta.get_journal()?;
+        let mut uploads = self.local_meta.open_upload_journal()?;
+
+        self.local_meta.store_queue(&self.queued_run(RunKind::Push, paths_in_archive.clone()))?;
+        let known_blobs = remote_manifest.list_blobs().into_iter()
+            .filter_map(|(_, key, ciphertext_size)| Some((key, ciphertext_size?)))
+            .collect();
+        self.remote.set_dedup(Some(Dedup::new(known_blobs)));
+        self.remote.set_progress(self.progress("push"));
+        let pushed = self.remote.push(&paths_in_archive, prefix_path, &mut journal, &mut uploads);
+        self.remote.set_progress(None);
+        self.remote.set_dedup(None);
+        self.local_meta.store_journal(&journal)?;
+        let (results, report) = pushed.context(TransferFailed)?;
+        timings.merge(self.remote.take_timings());
+
+        let manifest_update_start = std::time::Instant::now();
 
         // for testing
         // let results = vec![Some(UploadResult::Ok("05fd1dcbe8e3b2932f532f1c35b25607ad697b122245829b090178e645223ac1".to_string())); paths_in_archive.len()];
 
-        let mut blob_keys: HashMap<PathBuf, String> = HashMap::with_capacity(results.len());
+        let mut blob_keys: HashMap<PathBuf, manifest::StoredBlob> = HashMap::with_capacity(results.len());
+        let mut quarantined: HashSet<PathBuf> = HashSet::new();
+        let mut transferred = 0; // deduplicated blobs were not
         for (path, result) in std::iter::zip(paths_in_archive, results){
             let result = result.context("Result of upload not filled properly")?;
-            let hash_str = result.context("Result of upload is error")?;
-            blob_keys.insert(path, hash_str);
+            match result {
+                Ok(pushed) => {
+                    transferred += pushed.info.plaintext_size;
+                    blob_keys.insert(path, stored_blob(pushed));
+                },
+                Err(error) => {
+                    quarantined.insert(path.clone());
+                    if self.keep_going {
+                        failures.push((path, error.msg));
+                    }
+                },
+            }
         }
 
-        manifest::add_new_entries_to_manifest(&local_manifest, &mut remote_manifest, &diff, &blob_keys)?;
+        let now = clock::unix_now();
+        manifest::add_new_entries_to_manifest(&local_manifest, &mut remote_manifest, &diff, &blob_keys, &quarantined, now)?;
+        manifest::update_changed_entries_in_manifest(&local_manifest, &mut remote_manifest, &diff, &blob_keys, &quarantined, now)?;
         debug!("add_new_entries_to_manifest done");
 
-        let new_remote_manifest_bytes = remote_manifest.to_bytes()?;
-        self.remote.push_manifest_blob(new_remote_manifest_bytes.clone())?;
+        // what was uploaded stays in the upload journal for the push after a fetch
+        self.replace_remote_manifest(&mut remote_manifest, &fetched_blob, signer)?;
         debug!("Upload of new manifest done");
 
-        self.local_meta.store_manifest_with_backup(new_remote_manifest_bytes)?;
+        self.local_meta.clear_upload_journal()?;
+        self.local_meta.clear_queue()?;
         debug!("New manifest stored");
+        timings.add(Phase::ManifestUpdate, manifest_update_start.elapsed());
+        self.record_run(RunKind::Push, blob_keys.len(), transferred, &timings, remote_manifest.get_stats().total_size)?;
 
-        println!("Remote manifest updated.");
+        Ok(report::PushReport {
+            fetched,
+            skipped_placeholders,
+            shrunk,
+            nothing_to_push: false,
+            num_changed,
+            adopted: None,
+            transfer: Some(report),
+            quarantined: match quarantined.is_empty() || self.keep_going {
+                true => Vec::new(),
+                false => quarantined_files(&journal, max_attempts),
+            },
+            max_attempts,
+            failures,
+            timings: self.report_timings.then_some(timings),
+        })
+    }
 
-        Ok(())
+    // blobs of the local files at paths (in the archive) that the remote has and the files it has none of
+    // a file stored in chunks is only found through the remote manifest, exists() finds whole blobs
+    fn adopted_blobs(&mut self, paths: &[PathBuf], remote_manifest: &Manifest, failures: &mut Vec<(PathBuf, String)>)
+            -> Result<(HashMap<PathBuf, manifest::StoredBlob>, HashSet<PathBuf>)> {
+        let archive_root = self.local_meta.get_archive_root().to_path_buf();
+        let bucket_name = self.local_meta.get_remote_spec()?.bucket_name();
+        let mut hash_cache = self.local_meta.get_hash_cache(&bucket_name)?;
+        let known = remote_manifest.list_stored_blobs();
+        let mut adopted = HashMap::new();
+        let mut left_out = HashSet::new();
+        for path in paths {
+            if self.remote.cancel().is_cancelled() {
+                return Err(Cancelled.into());
+            }
+            let key = match hash_cache.key_of(path, &archive_root.join(path)) {
+                Ok(key) => key,
+                Err(e) if self.keep_going => {
+                    failures.push((path.clone(), format!("Hashing: {}", e)));
+                    left_out.insert(path.clone());
+                    continue;
+                },
+                Err(e) => return Err(e).with_context(|| format!("Hashing {}", path.to_str().unwrap())),
+            };
+            let stored = match known.get(&key) {
+                Some(stored) => Some(stored.clone()),
+                None if self.remote.blob_exists(&key)? => Some(manifest::StoredBlob { key, ciphertext_size: None, chunks: Vec::new() }),
+                None => None,
+            };
+            match stored {
+                Some(stored) => {
+                    adopted.insert(path.clone(), stored);
+                },
+                None => {
+                    left_out.insert(path.clone());
+                },
+            }
+        }
+        self.local_meta.store_hash_cache(&hash_cache)?;
+        Ok((adopted, left_out))
     }
 
-    pub fn pull(&mut self) -> Result<()> {
-        let local_manifest = Manifest::from_fs(self.local_meta.get_archive_root()).context("Making manifest from local tree")?;
+    // a local tree much smaller than the remote is more likely a wrong archive root than files deleted on purpose
+    // with force it is pushed anyway, and the numbers of files of both are returned to say so
+    fn check_shrink_guard(&self, local_manifest: &Manifest, remote_manifest: &Manifest) -> Result<Option<(usize, usize)>> {
+        // a walk of the local tree has .har, the remote doesn't
+        let num_files = |manifest: &Manifest| {
+            let path_getter = manifest.get_full_path_getter();
+            manifest.get_child_files_recurs(manifest.root()).into_iter()
+                .filter(|&id| !path_getter(id).starts_with(DOT_HAR_NAME))
+                .count()
+        };
+        let (num_local, num_remote) = (num_files(local_manifest), num_files(remote_manifest));
+        if (num_local as f64) >= (num_remote as f64) * self.shrink_guard {
+            return Ok(None);
+        }
+        if !self.force {
+            anyhow::bail!("Refusing to push a local tree of {} files over a remote of {} at {}, use --force if it is intended",
+                num_local, num_remote, self.local_meta.get_archive_root().to_str().unwrap());
+        }
+        Ok(Some((num_local, num_remote)))
+    }
+
+    pub fn pull(&mut self) -> Result<report::PullReport> {
+        let _span = tracing::info_span!("pull", archive_root = %self.local_meta.get_archive_root().display()).entered();
+        let mut timings = Timings::default();
+        let num_stale = mirror::remove_stale_partials(self.local_meta.get_archive_root())?;
+        if num_stale > 0 {
+            debug!("Removed {} partial files of an earlier pull", num_stale);
+        }
+        let local_manifest = timings.time(Phase::Scan, || Manifest::from_fs(self.local_meta.get_archive_root())).context("Making manifest from local tree")?;
+        let local_manifest = self.scoped(local_manifest, true)?.unwrap_or_default();
         let remote_manifest = self.local_meta.get_manifest().context("Reading fetched manifest")?;
-        let diff = manifest::diff_manifests(&remote_manifest, &local_manifest);
+        let archive_size = remote_manifest.get_stats().total_size;
+        let remote_manifest = self.scoped(remote_manifest, false)?.unwrap();
+        let diff = timings.time(Phase::Diff, || manifest::diff_manifests(&remote_manifest, &local_manifest));
+        let local_extras = match self.local_deletion {
+            Some(_) => timings.time(Phase::Diff, || manifest::diff_manifests(&local_manifest, &remote_manifest)).paths_of_top_extra_in_a,
+            None => Vec::new(),
+        };
+        let mut failures = Vec::new();
+        let changed_files = match self.pull_conflict {
+            PullConflict::SkipExisting => Vec::new(),
+            PullConflict::Overwrite | PullConflict::BackupExisting => {
+                timings.time(Phase::Hashing, || self.changed_local_files(&remote_manifest, &local_manifest, &mut failures))?
+            },
+        };
+        let is_pending = |path: &PathBuf| self.resuming.as_ref().is_none_or(|pending| pending.contains(path));
+        let changed_files: Vec<_> = changed_files.into_iter().filter(|(path, _, _)| is_pending(path)).collect();
 
-        if diff.top_extra_ids_in_a.is_empty() {
-            println!("Nothing to pull.");
-            return Ok(());
+        if diff.top_extra_ids_in_a.is_empty() && changed_files.is_empty() {
+            let mut pulled = report::PullReport {
+                nothing_to_pull: true,
+                failures,
+                timings: self.report_timings.then_some(timings),
+                ..Default::default()
+            };
+            self.delete_local_extras(&local_extras, &mut pulled)?;
+            return Ok(pulled);
         }
 
+        let planning_start = std::time::Instant::now();
+
         let remote_path_getter = remote_manifest.get_full_path_getter();
 
         let mut files_to_pull = Vec::new();
@@ -210,42 +1268,776 @@ impl WithRemoteAndLocal {
             let extra_files = remote_manifest.get_child_files_recurs(top_extra_entry);
             files_to_pull.extend(extra_files);
         }
-        let files_to_pull: Vec<_> = files_to_pull.into_iter().map(|entry_id| {
-            let path = remote_path_getter(entry_id);
-            let (key, size) = remote_manifest.get_file_key_and_size(entry_id).unwrap();
-            (path, key, size as usize)
-        }).collect();
+        let mut chunked_files = remote_manifest.list_chunked_files();
+        let files_to_pull: Vec<_> = files_to_pull.into_iter()
+            .map(|entry_id| {
+                let path = remote_path_getter(entry_id);
+                let (key, size) = remote_manifest.get_file_key_and_size(entry_id).unwrap();
+                (path, key, size)
+            })
+            .chain(changed_files.iter().cloned())
+            .filter(|(path, _, _)| is_pending(path))
+            .map(|(path, key, size)| {
+                let chunks = chunked_files.remove(&path).unwrap_or_default();
+                PullFile { path, key, size: size as usize, chunks }
+            })
+            .collect();
 
         debug!("Making sure all directories exist");
         for &top_extra_entry in &diff.top_extra_ids_in_a {
             let extra_dirs = remote_manifest.get_child_dirs_recurs(top_extra_entry);
             for &dir in &extra_dirs {
-                let dir_path = remote_path_getter(dir);
+                let dir_path = self.local_meta.get_archive_root().join(remote_path_getter(dir));
                 std::fs::create_dir_all(dir_path).context("Making sure all directories exist before pulling")?;
             }
         }
 
-        println!("Starting to pull {} files...", files_to_pull.len());
-        self.remote.pull(&files_to_pull, self.local_meta.get_archive_root(), TransferConfig::default())?;
-        println!("Pull done.");
+        timings.add(Phase::Planning, planning_start.elapsed());
+
+        let max_attempts = self.remote.transfer_config().max_attempts();
+        let mut journal = self.local_meta.get_journal()?;
+
+        let changed_paths: Vec<PathBuf> = changed_files.into_iter().map(|(path, _, _)| path).collect();
+        let (overwritten, backed_up) = match self.pull_conflict {
+            PullConflict::Overwrite => (changed_paths, Vec::new()),
+            _ => (Vec::new(), changed_paths),
+        };
+        let pending = files_to_pull.iter().map(|file| file.path.clone()).collect();
+        self.local_meta.store_queue(&self.queued_run(RunKind::Pull, pending))?;
+        self.remote.set_progress(self.progress("pull"));
+        let pulled = self.remote.pull(&files_to_pull, self.local_meta.get_archive_root(), &mut journal);
+        self.remote.set_progress(None);
+        self.local_meta.store_journal(&journal)?;
+        let (quarantined, report) = pulled.context(TransferFailed)?;
+        self.local_meta.clear_queue()?;
+        timings.merge(self.remote.take_timings());
+        let quarantined_paths: HashSet<&PathBuf> = quarantined.iter().collect();
+        let pulled: Vec<&PullFile> = files_to_pull.iter().filter(|file| !quarantined_paths.contains(&file.path)).collect();
+        self.record_run(RunKind::Pull, pulled.len(), pulled.iter().map(|file| file.size as u64).sum(), &timings, archive_size)?;
+        let mut quarantined_now = Vec::new();
+        if self.keep_going {
+            failures.extend(quarantined_with_reasons(&quarantined, &journal, max_attempts));
+        } else if !quarantined.is_empty() {
+            quarantined_now = quarantined_files(&journal, max_attempts);
+        }
+        let mut pulled = report::PullReport {
+            nothing_to_pull: false,
+            num_pulled: pulled.len(),
+            overwritten,
+            backed_up,
+            transfer: Some(report),
+            quarantined: quarantined_now,
+            max_attempts,
+            failures,
+            timings: self.report_timings.then_some(timings),
+            ..Default::default()
+        };
+        self.delete_local_extras(&local_extras, &mut pulled)?;
+        Ok(pulled)
+    }
+
+    // the files of the fetched manifest under paths (in the archive) pulled into target, at their path in the archive
+    // target does not have to be the archive root, existing files are taken as pull does (see with_pull_conflict)
+    pub fn restore(&mut self, paths: &[PathBuf], target: &Path) -> Result<report::PullReport> {
+        let fetched_manifest = self.local_meta.get_manifest().context("Reading fetched manifest")?;
+        for path in paths {
+            fetched_manifest.scoped(path).with_context(|| format!("{} is not in the archive", path.to_str().unwrap()))?;
+        }
+        let selected = fetched_manifest.scoped_to_paths(paths)?;
+        std::fs::create_dir_all(target).with_context(|| format!("Creating {}", target.to_str().unwrap()))?;
+        mirror::remove_stale_partials(target)?;
+        for dir in selected.list_dirs() {
+            std::fs::create_dir_all(target.join(dir)).context("Making sure all directories exist before restoring")?;
+        }
+        let mut chunked_files = selected.list_chunked_files();
+        let files_to_restore: Vec<PullFile> = selected.list_files().into_iter()
+            .map(|(path, key, size)| {
+                let chunks = chunked_files.remove(&path).unwrap_or_default();
+                PullFile { path, key, size: size as usize, chunks }
+            })
+            .collect();
+
+        let max_attempts = self.remote.transfer_config().max_attempts();
+        // the journal of .har is for the archive root
+        let mut journal = TransferJournal::default();
+        self.remote.set_progress(self.progress("restore"));
+        let pulled = self.remote.pull(&files_to_restore, target, &mut journal);
+        self.remote.set_progress(None);
+        let (quarantined, report) = pulled.context(TransferFailed)?;
+        Ok(report::PullReport {
+            nothing_to_pull: files_to_restore.is_empty(),
+            num_pulled: files_to_restore.len() - quarantined.len(),
+            transfer: Some(report),
+            max_attempts,
+            failures: quarantined_with_reasons(&quarantined, &journal, max_attempts),
+            ..Default::default()
+        })
+    }
+
+    fn queued_run(&self, kind: RunKind, pending: Vec<PathBuf>) -> QueuedRun {
+        QueuedRun {
+            kind,
+            scope: self.scope.clone(),
+            keep_going: self.keep_going,
+            force: self.force,
+            hash_check: self.hash_check,
+            pull_conflict: self.pull_conflict,
+            local_deletion: self.local_deletion,
+            pending,
+        }
+    }
+
+    // the push or pull queued in .har by a run that stopped before the end, with the same options
+    pub fn resume(self) -> Result<report::ResumeReport> {
+        let Some(queued) = self.local_meta.get_queue()? else {
+            return Ok(report::ResumeReport::NothingToResume);
+        };
+        let num_files = queued.pending.len();
+        let mut me = self
+            .with_scope(queued.scope)
+            .with_keep_going(queued.keep_going)
+            .with_force(queued.force)
+            .with_hash_check(queued.hash_check)
+            .with_pull_conflict(queued.pull_conflict)
+            .with_local_deletion(queued.local_deletion);
+        me.resuming = Some(queued.pending);
+        Ok(match queued.kind {
+            RunKind::Push => report::ResumeReport::Push { num_files, pushed: me.push()? },
+            RunKind::Pull => report::ResumeReport::Pull { num_files, pulled: me.pull()? },
+        })
+    }
+
+    // pushes the changes under the archive root once none came for debounce, until Ctrl-C, see watch.rs
+    // a push that fails is said and tried again after retry_after or the next change. Each one queues its files
+    // for har resume and keeps what it uploaded in the upload journal, the next one does not upload it again
+    // on_event is given what happens, to say it
+    pub fn watch(&mut self, debounce: Duration, retry_after: Duration, mut on_event: impl FnMut(report::WatchEvent)) -> Result<()> {
+        if self.local_meta.get_queue()?.is_some_and(|queued| queued.kind == RunKind::Pull) {
+            anyhow::bail!("A pull stopped before the end, finish it with har resume before watching");
+        }
+        let archive_root = self.local_meta.get_archive_root().to_path_buf();
+        let watcher = ChangeWatcher::new(&archive_root, &archive_root.join(DOT_HAR_NAME))?;
+        let cancel = self.remote.cancel().clone();
+        // another host may push while this one watches
+        self.auto_fetch = true;
+        on_event(report::WatchEvent::Watching { archive_root: &archive_root, debounce });
+        // the remote is probed every retry_after, pushes wait while it is not reachable
+        let mut health = HealthMonitor::new(retry_after);
+        // the changes made before watching
+        let mut retry = Some(Duration::ZERO);
+        loop {
+            match watcher.wait(debounce, retry, &cancel)? {
+                watch::Woken::Cancelled => break,
+                watch::Woken::Changed | watch::Woken::Retry => {},
+            }
+            let now = std::time::Instant::now();
+            if health.is_probe_due(now) {
+                if let Some(state) = health.record(self.remote.probe(), now) {
+                    on_event(report::WatchEvent::RemoteHealth(state));
+                }
+            }
+            if health.pushes_paused() {
+                // the changes are pushed after the next good probe
+                retry = Some(retry_after);
+                continue;
+            }
+            let pushed = self.push().and_then(|pushed| {
+                on_event(report::WatchEvent::Pushed(&pushed));
+                report::check_failures(&pushed.failures)
+            });
+            retry = match pushed {
+                Ok(()) => None,
+                Err(err) if err.is::<Cancelled>() => return Err(err),
+                Err(err) => {
+                    on_event(report::WatchEvent::PushFailed { error: &err, retry_after });
+                    Some(retry_after)
+                },
+            };
+        }
+        on_event(report::WatchEvent::HealthMetrics(health.metrics()));
+        on_event(report::WatchEvent::Stopped);
+        Ok(())
+    }
+
+    // fetch and push, skipped (None) when the local tree did not change since the fetched manifest and no push is
+    // left to resume
+    pub fn backup(&mut self) -> Result<Option<report::PushReport>> {
+        let started = clock::unix_now();
+        let queued_push = self.local_meta.get_queue()?.is_some_and(|queued| queued.kind == RunKind::Push);
+        let mut pushed = None;
+        let changes = self.local_changes()?;
+        if queued_push || changes.added_or_deleted || changes.modified {
+            self.auto_fetch = true;
+            // push only uploads files of the same path when it compares their content
+            let hash_check = self.hash_check;
+            self.hash_check |= changes.modified;
+            let push = self.push();
+            self.hash_check = hash_check;
+            pushed = Some(push?);
+        }
+        // a backup with failed files is not the last backup
+        if pushed.as_ref().is_none_or(|pushed: &report::PushReport| pushed.failures.is_empty()) {
+            self.local_meta.store_last_backup(started)?;
+        }
+        Ok(pushed)
+    }
+
+    // backup every interval (seconds) until Ctrl-C, see schedule.rs. A backup that fails is said, the next one is
+    // at the next interval. on_event is given what happens, to say it
+    pub fn backup_every(&mut self, every: u64, mut on_event: impl FnMut(report::BackupEvent)) -> Result<()> {
+        let cancel = self.remote.cancel().clone();
+        let mut schedule = Schedule::new(every, self.local_meta.get_last_backup()?);
+        on_event(report::BackupEvent::Scheduled { every, next: schedule.next().max(clock::unix_now()) });
+        loop {
+            while !schedule.is_due(clock::unix_now()) {
+                if cancel.is_cancelled() {
+                    on_event(report::BackupEvent::Stopped);
+                    return Ok(());
+                }
+                std::thread::sleep(schedule::POLL);
+            }
+            let started = clock::unix_now();
+            let missed = schedule.missed(started);
+            if missed > 0 {
+                on_event(report::BackupEvent::CatchingUp { missed });
+            }
+            let backed_up = self.backup().and_then(|pushed| {
+                on_event(report::BackupEvent::BackedUp(pushed.as_ref()));
+                pushed.map_or(Ok(()), |pushed| report::check_failures(&pushed.failures))
+            });
+            match backed_up {
+                Ok(()) => {},
+                Err(err) if err.is::<Cancelled>() => return Err(err),
+                Err(err) => on_event(report::BackupEvent::Failed(&err)),
+            }
+            schedule.ran(started);
+            on_event(report::BackupEvent::Next(schedule.next()));
+        }
+    }
+
+    // without hashing, as push finds what to upload: files added or deleted, or with another size or modified
+    // locally after their blob was pushed. Everything is new when the manifest was never fetched
+    fn local_changes(&self) -> Result<LocalChanges> {
+        if self.local_meta.get_manifest_stored_time()?.is_none() {
+            return Ok(LocalChanges { added_or_deleted: true, modified: false });
+        }
+        let (local_manifest, _) = scan_local_tree(&self.local_meta, &self.scan_options)?;
+        let remote_manifest = self.local_meta.get_manifest().context("Reading fetched manifest")?;
+        let changes = manifest::diff_files(&local_manifest, &remote_manifest);
+        let added_or_deleted = changes.added.iter().any(|path| !path.starts_with(DOT_HAR_NAME)) || !changes.deleted.is_empty();
+
+        let archive_root = self.local_meta.get_archive_root();
+        let local_sizes: HashMap<PathBuf, u64> = local_manifest.list_files().into_iter().map(|(path, _, size)| (path, size)).collect();
+        let pushed_at: HashMap<PathBuf, u64> = remote_manifest.files_changed_since(0).into_iter().map(|(path, times)| (path, times.modified)).collect();
+        let modified = remote_manifest.list_files().into_iter().any(|(path, _, size)| match local_sizes.get(&path) {
+            None => false, // deleted, or named otherwise by the name normalization of the remote
+            Some(&local_size) if local_size != size => true,
+            Some(_) => pushed_at.get(&path).is_some_and(|&pushed| modified_after(&archive_root.join(&path), pushed)),
+        });
+        Ok(LocalChanges { added_or_deleted, modified })
+    }
+
+    // see history, for stats --graph
+    fn record_run(&self, kind: RunKind, num_files: usize, bytes: u64, timings: &Timings, archive_size: u64) -> Result<()> {
+        let transfer_ms = timings.get(Phase::Transfer).unwrap_or_default().as_millis() as u64;
+        let run = RunRecord { kind, finished: clock::unix_now(), num_files, bytes, transfer_ms, archive_size };
+        self.local_meta.append_run(&run)
+    }
+
+    // (path, blob key, size) of the files of the remote manifest that exist locally with other content:
+    // another size, or another hash for the same size (files are read)
+    // with keep_going, a file that can't be hashed is kept as it is and added to failures
+    fn changed_local_files(&self, remote_manifest: &Manifest, local_manifest: &Manifest, failures: &mut Vec<(PathBuf, String)>)
+            -> Result<Vec<(PathBuf, String, u64)>> {
+        let archive_root = self.local_meta.get_archive_root();
+        let bucket_name = self.local_meta.get_remote_spec()?.bucket_name();
+        let local_sizes: HashMap<PathBuf, u64> = local_manifest.list_files().into_iter().map(|(path, _, size)| (path, size)).collect();
+        let mut changed = Vec::new();
+        for (path, key, size) in remote_manifest.list_files() {
+            let Some(&local_size) = local_sizes.get(&path) else {
+                continue;
+            };
+            let differs = local_size != size || match blob_storage::get_hash_name_of_file(&bucket_name, &archive_root.join(&path)) {
+                Ok(local_key) => local_key != key,
+                Err(e) if self.keep_going => {
+                    failures.push((path, format!("Hashing: {}", e)));
+                    continue;
+                },
+                Err(e) => return Err(e).with_context(|| format!("Hashing {}", path.to_str().unwrap())),
+            };
+            if differs {
+                changed.push((path, key, size));
+            }
+        }
+        Ok(changed)
+    }
+
+    // mirror mode of pull, paths are the local top entries that the remote does not have, in pulled.trashed or
+    // pulled.deleted once they are
+    fn delete_local_extras(&self, paths: &[PathBuf], pulled: &mut report::PullReport) -> Result<()> {
+        let local_deletion = match self.local_deletion {
+            Some(local_deletion) if !paths.is_empty() => local_deletion,
+            _ => return Ok(()),
+        };
+        let archive_root = self.local_meta.get_archive_root();
+        let full_paths: Vec<PathBuf> = paths.iter().map(|path| archive_root.join(path)).collect();
+        match local_deletion {
+            LocalDeletion::Trash => {
+                trash::delete_all(&full_paths).context("Moving local files to the trash")?;
+                pulled.trashed = paths.to_vec();
+            },
+            LocalDeletion::Permanent => {
+                for path in &full_paths {
+                    let removed = if path.is_dir() { std::fs::remove_dir_all(path) } else { std::fs::remove_file(path) };
+                    removed.with_context(|| format!("Deleting {}", path.to_str().unwrap()))?;
+                }
+                pulled.deleted = paths.to_vec();
+            },
+        }
+        Ok(())
+    }
+
+    // write the subtree at path (from the fetched manifest) as a tar stream, the number of files it has
+    // nothing is printed to stdout so that writer can be stdout
+    pub fn pull_to_tar<W: Write>(&mut self, path: &Path, writer: W) -> Result<usize> {
+        let remote_manifest = self.local_meta.get_manifest().context("Reading fetched manifest")?;
+        let subtree = remote_manifest.subtree(path).context("Selecting path to pull in fetched manifest")?;
+
+        let mut builder = tar::Builder::new(writer);
+
+        for dir_path in &subtree.list_dirs() {
+            append_dir_to_tar(&mut builder, dir_path)?;
+        }
+
+        let files = subtree.list_files();
+        let chunked_files = subtree.list_chunked_files();
+        let mtimes = file_mtimes(&subtree);
+        debug!("Streaming {} files as tar", files.len());
+        for (file_path, key, size) in &files {
+            let data = self.get_file_blob(file_path, key, *size, chunked_files.get(file_path))?;
+            append_file_to_tar(&mut builder, file_path, &data, mtimes.get(file_path).copied().unwrap_or(0))?;
+        }
+
+        builder.into_inner().context("Finishing tar")?.flush()?;
+        Ok(files.len())
+    }
+
+    // the files under path of the fetched manifest in a tar file that needs nothing of har to be read, compressed with
+    // zstd if compress, and the number of files it has. The tar is written next to output and renamed once complete
+    pub fn export_tar(&mut self, path: &Path, output: &Path, compress: bool) -> Result<usize> {
+        let partial_path = PathBuf::from(format!("{}.partial", output.to_str().unwrap()));
+        let mut partial = std::io::BufWriter::new(std::fs::File::create(&partial_path).context("Creating export tar")?);
+        let num_files = match self.write_tar(path, &mut partial, compress) {
+            Ok(num_files) => num_files,
+            Err(err) => {
+                drop(partial);
+                std::fs::remove_file(&partial_path).context("Removing the partial export tar")?;
+                return Err(err);
+            },
+        };
+        partial.into_inner().context("Finishing tar")?.sync_all()?;
+        std::fs::rename(&partial_path, output).context("Renaming export tar")?;
+        Ok(num_files)
+    }
+
+    // the regular files of a tar (- for stdin, zstd compressed if compress) added to the remote manifest under into,
+    // uploaded as they are read: nothing is unpacked on disk. Files the remote has at the same path are replaced,
+    // other entries (links, devices...) and empty directories are left out. The numbers of files imported and left out
+    pub fn import_tar(&mut self, tar_path: &Path, into: &Path, compress: bool) -> Result<(usize, usize)> {
+        let reader: Box<dyn std::io::Read> = match tar_path == Path::new("-") {
+            true => Box::new(std::io::stdin().lock()),
+            false => Box::new(std::io::BufReader::new(std::fs::File::open(tar_path)
+                .with_context(|| format!("Opening {}", tar_path.to_str().unwrap()))?)),
+        };
+        let reader: Box<dyn std::io::Read> = match compress {
+            true => Box::new(zstd::stream::read::Decoder::new(reader)?),
+            false => reader,
+        };
+        let into = manifest::normalize_path(into)?;
+        let known_blobs = self.local_meta.get_manifest().context("Reading fetched manifest")?.list_blobs().into_iter()
+            .filter_map(|(_, key, ciphertext_size)| Some((key, ciphertext_size?)))
+            .collect();
+        self.remote.set_dedup(Some(Dedup::new(known_blobs)));
+        let imported = self.upload_tar_entries(reader, &into);
+        self.remote.set_dedup(None);
+        let (files, num_left_out) = imported?;
+
+        let num_files = files.len();
+        self.edit_remote_manifest(|remote_manifest| add_uploaded_files(remote_manifest, files))?;
+        Ok((num_files, num_left_out))
+    }
+
+    // the files under path of the fetched manifest uploaded to the remote to (a spec as in init), at the same paths
+    // there. Blobs are downloaded and uploaded again: keys depend on the remote (see RemoteSpec::bucket_name). A remote
+    // without archive is initialized with the key, the manifest of to is fetched in .har/copy_to for the time of the copy
+    // The number of files copied
+    pub fn copy_to(&mut self, to: &str, path: &Path, settings: &Settings) -> Result<usize> {
+        if self.auto_fetch {
+            self.fetch_manifest()?;
+        }
+        let to_spec = RemoteSpec::parse(to).context(ConfigError)?;
+        if to_spec.redacted() == self.local_meta.get_remote_spec()?.redacted() {
+            anyhow::bail!("{} is the remote of this archive already", to_spec.redacted());
+        }
+        let fetched_manifest = self.local_meta.get_manifest().context("Reading fetched manifest")?;
+        let scoped = fetched_manifest.scoped(path).with_context(|| format!("{} is not in the archive", path.to_str().unwrap()))?;
+
+        let staging_path = self.local_meta.get_archive_root().join(DOT_HAR_NAME).join(COPY_STAGING_NAME);
+        if staging_path.exists() {
+            std::fs::remove_dir_all(&staging_path).context("Removing the leftovers of a previous copy")?;
+        }
+        std::fs::create_dir(&staging_path)?;
+        let copied = self.copy_to_staged(&staging_path, to, &scoped, settings);
+        std::fs::remove_dir_all(&staging_path)?;
+        copied
+    }
+
+    fn copy_to_staged(&mut self, staging_path: &Path, to: &str, scoped: &Manifest, settings: &Settings) -> Result<usize> {
+        let staging = DotHar::with_path(staging_path.to_path_buf());
+        staging.set_remote_spec(to)?;
+        staging.set_key_spec(&self.local_meta.get_key_spec()?)?;
+        staging.set_compression(self.local_meta.get_compression()?)?;
+        let mut dest = Self::connect(staging, settings).context("Connecting to the remote to copy to")?
+            .with_cancel(self.remote.cancel().clone());
+        if dest.remote.get_archive_metadata()?.is_none() {
+            let description = self.remote.get_archive_metadata()?.map(|metadata| metadata.description).unwrap_or_default();
+            dest.init_remote_with_description(&description)?;
+        }
+        dest.fetch_manifest()?;
+
+        let known_blobs = dest.local_meta.get_manifest().context("Reading fetched manifest")?.list_blobs().into_iter()
+            .filter_map(|(_, key, ciphertext_size)| Some((key, ciphertext_size?)))
+            .collect();
+        dest.remote.set_dedup(Some(Dedup::new(known_blobs)));
+        let mut chunked = scoped.list_chunked_files();
+        let mut files = HashMap::new();
+        for (file_path, key, size) in scoped.list_files() {
+            if self.remote.cancel().is_cancelled() {
+                return Err(Cancelled.into());
+            }
+            debug!("Copying {} ({} bytes)", file_path.to_str().unwrap(), size);
+            let keys = match chunked.remove(&file_path) {
+                Some(chunks) => chunks.into_iter().map(|chunk| chunk.key).collect(),
+                None => vec![key],
+            };
+            let mut reader = BlobsReader { mirror: &mut self.remote, keys: keys.into_iter(), current: bytes::Bytes::new() };
+            let pushed = dest.remote.push_reader(&mut reader, size)
+                .with_context(|| format!("Copying {}", file_path.to_str().unwrap()))
+                .context(TransferFailed)?;
+            files.insert(file_path, (size, stored_blob(pushed)));
+        }
+        dest.remote.set_dedup(None);
+
+        let num_files = files.len();
+        dest.edit_remote_manifest(|remote_manifest| add_uploaded_files(remote_manifest, files))?;
+        Ok(num_files)
+    }
+
+    // (path under into, (size, blob)) of the regular files, the last one for a path that is twice in the tar, and the
+    // number of other entries that are not directories
+    fn upload_tar_entries(&mut self, reader: impl std::io::Read, into: &Path) -> Result<(UploadedFiles, usize)> {
+        let mut files = HashMap::new();
+        let mut num_left_out = 0;
+        let mut archive = tar::Archive::new(reader);
+        for entry in archive.entries().context("Reading tar")? {
+            if self.remote.cancel().is_cancelled() {
+                return Err(Cancelled.into());
+            }
+            let mut entry = entry.context("Reading tar")?;
+            match entry.header().entry_type() {
+                tar::EntryType::Regular | tar::EntryType::Continuous => {},
+                tar::EntryType::Directory => continue,
+                _ => {
+                    num_left_out += 1;
+                    continue;
+                },
+            }
+            let path = into.join(manifest::normalize_path(&entry.path().context("Reading tar")?)?);
+            let size = entry.size();
+            debug!("Importing {} ({} bytes)", path.to_str().unwrap(), size);
+            let pushed = self.remote.push_reader(&mut entry, size)
+                .with_context(|| format!("Importing {}", path.to_str().unwrap()))
+                .context(TransferFailed)?;
+            files.insert(path, (size, stored_blob(pushed)));
+        }
+        Ok((files, num_left_out))
+    }
+
+    pub fn write_tar(&mut self, path: &Path, writer: &mut dyn Write, compress: bool) -> Result<usize> {
+        if !compress {
+            return self.pull_to_tar(path, writer);
+        }
+        // level 0 is the default of zstd
+        let mut encoder = zstd::stream::write::Encoder::new(writer, 0)?;
+        let num_files = self.pull_to_tar(path, &mut encoder)?;
+        encoder.finish().context("Finishing zstd stream")?.flush()?;
+        Ok(num_files)
+    }
 
+    // the content of the file at path of the fetched manifest, a file in chunks is written as they come
+    pub fn cat<W: Write>(&mut self, path: &Path, mut writer: W) -> Result<()> {
+        let remote_manifest = self.local_meta.get_manifest().context("Reading fetched manifest")?;
+        let scoped = remote_manifest.scoped(path).with_context(|| format!("{} is not in the archive", path.to_str().unwrap()))?;
+        let (file_path, key, size) = match scoped.list_files().as_slice() {
+            [file] if file.0 == manifest::normalize_path(path)? => file.clone(),
+            _ => anyhow::bail!("{} is not a file", path.to_str().unwrap()),
+        };
+        match scoped.list_chunked_files().remove(&file_path) {
+            Some(chunks) => {
+                let mut written = 0;
+                for chunk in &chunks {
+                    let data = self.remote.get_blob(&chunk.key).with_context(|| format!("Downloading {}", file_path.to_str().unwrap()))?;
+                    writer.write_all(&data)?;
+                    written += data.len() as u64;
+                }
+                if written != size {
+                    anyhow::bail!("Size of downloaded {} does not match manifest", file_path.to_str().unwrap());
+                }
+            },
+            None => writer.write_all(&self.get_file_blob(&file_path, &key, size, None)?)?,
+        }
+        writer.flush()?;
         Ok(())
     }
+
+    // the fetched manifest as a read-only filesystem until it is unmounted, see mount.rs
+    // on_mounted is called before it is, to say it
+    pub fn mount(&mut self, mountpoint: &Path, on_mounted: impl FnOnce()) -> Result<()> {
+        let remote_manifest = self.local_meta.get_manifest().context("Reading fetched manifest")?;
+        let tree = mount::ArchiveTree::new(&remote_manifest);
+        on_mounted();
+        let cancel = self.remote.cancel().clone();
+        let remote = &mut self.remote;
+        mount::mount(&tree, mountpoint, &cancel, |path, key| {
+            remote.get_blob(key).with_context(|| format!("Downloading {}", path.to_str().unwrap()))
+        })
+    }
+
+    // export files added or changed in the fetched manifest since the snapshot (a manifest file)
+    // deletions and the full list of changes are described in EXPORT_METADATA_NAME
+    pub fn export_since(&mut self, snapshot: &Path, output: &Path, format: ExportFormat) -> Result<manifest::FileChanges> {
+        let snapshot_bytes = std::fs::read(snapshot).with_context(|| format!("Reading snapshot {}", snapshot.to_str().unwrap()))?;
+        let snapshot_manifest = Manifest::from_bytes(bytes::Bytes::from(snapshot_bytes)).context("Decoding snapshot manifest")?;
+        let remote_manifest = self.local_meta.get_manifest().context("Reading fetched manifest")?;
+
+        let changes = manifest::diff_files(&remote_manifest, &snapshot_manifest);
+        let metadata = serde_json::to_vec_pretty(&changes)?;
+
+        let to_export: std::collections::HashSet<&PathBuf> = changes.added.iter().chain(&changes.changed).collect();
+        let files: Vec<(PathBuf, String, u64)> = remote_manifest.list_files().into_iter()
+            .filter(|(path, _, _)| to_export.contains(path))
+            .collect();
+        let chunked_files = remote_manifest.list_chunked_files();
+
+        match format {
+            ExportFormat::Tar => {
+                let out_file = std::fs::File::create(output).context("Creating export tar")?;
+                let mut builder = tar::Builder::new(std::io::BufWriter::new(out_file));
+                append_file_to_tar(&mut builder, Path::new(EXPORT_METADATA_NAME), &metadata, clock::unix_now())?;
+                let mtimes = file_mtimes(&remote_manifest);
+                for (file_path, key, size) in &files {
+                    let data = self.get_file_blob(file_path, key, *size, chunked_files.get(file_path))?;
+                    append_file_to_tar(&mut builder, file_path, &data, mtimes.get(file_path).copied().unwrap_or(0))?;
+                }
+                builder.into_inner().context("Finishing tar")?.flush()?;
+            },
+            ExportFormat::Directory => {
+                std::fs::create_dir_all(output).context("Creating export directory")?;
+                std::fs::write(output.join(EXPORT_METADATA_NAME), &metadata)?;
+                for (file_path, key, size) in &files {
+                    let data = self.get_file_blob(file_path, key, *size, chunked_files.get(file_path))?;
+                    let dest = output.join(file_path);
+                    std::fs::create_dir_all(dest.parent().unwrap())?;
+                    std::fs::write(dest, &data)?;
+                }
+            },
+        }
+
+        Ok(changes)
+    }
+
+    // chunks if the file is stored in chunks, see Manifest::list_chunked_files
+    fn get_file_blob(&mut self, file_path: &Path, key: &str, size: u64, chunks: Option<&Vec<manifest::StoredChunk>>) -> Result<bytes::Bytes> {
+        let download = |remote: &mut Mirror, key: &str| remote.get_blob(key).with_context(|| format!("Downloading {}", file_path.to_str().unwrap()));
+        let data = match chunks {
+            Some(chunks) => {
+                let mut data = Vec::with_capacity(size as usize);
+                for chunk in chunks {
+                    data.extend_from_slice(&download(&mut self.remote, &chunk.key)?);
+                }
+                bytes::Bytes::from(data)
+            },
+            None => download(&mut self.remote, key)?,
+        };
+        if data.len() as u64 != size {
+            anyhow::bail!("Size of downloaded {} does not match manifest", file_path.to_str().unwrap());
+        }
+        Ok(data)
+    }
+}
+
+// all the files of the journal that are quarantined, with their last error
+fn quarantined_files(journal: &TransferJournal, max_attempts: u32) -> Vec<report::FileFailure> {
+    journal.quarantined(max_attempts).into_iter()
+        .map(|(path, record)| (path.to_path_buf(), record.last_error.clone()))
+        .collect()
+}
+
+// paths quarantined by a transfer with their last error, for the failures of keep_going
+fn quarantined_with_reasons(paths: &[PathBuf], journal: &TransferJournal, max_attempts: u32) -> Vec<report::FileFailure> {
+    let reasons: HashMap<&Path, &str> = journal.quarantined(max_attempts).into_iter()
+        .map(|(path, record)| (path, record.last_error.as_str()))
+        .collect();
+    paths.iter().map(|path| (path.clone(), reasons.get(path.as_path()).unwrap_or(&"").to_string())).collect()
+}
+
+// rehashes the local files that both manifests have, those that did not change since the last time are taken from
+// the hash cache of .har, which is updated
+fn diff_with_hash_check(local_meta: &DotHar, manifest_a: &Manifest, manifest_b: &Manifest, local_manifest: &Manifest, timings: &mut Timings)
+        -> Result<manifest::DiffManifests> {
+    let bucket_name = local_meta.get_remote_spec()?.bucket_name();
+    let hash_cache = local_meta.get_hash_cache(&bucket_name)?;
+    let diff = manifest::DiffManifests::default()
+        .with_hash_check(local_meta.get_archive_root().to_path_buf(), bucket_name)
+        .with_hash_cache(hash_cache);
+    let mut diff = timings.time(Phase::Diff, || diff.diff_manifests(manifest_a, manifest_b));
+    timings.add(Phase::Hashing, diff.hashing_duration);
+    let mut hash_cache = diff.take_hash_cache().unwrap();
+    let (num_hashed, num_cached) = hash_cache.counts();
+    debug!("Hashed {} files, {} were cached", num_hashed, num_cached);
+    let local_paths: HashSet<PathBuf> = local_manifest.list_files().into_iter().map(|(path, _, _)| path).collect();
+    hash_cache.retain(|path| local_paths.contains(path));
+    local_meta.store_hash_cache(&hash_cache)?;
+    Ok(diff)
+}
+
+// for the holder of the remote lock, nothing depends on it
+fn host_name() -> String {
+    std::env::var("HOSTNAME").or_else(|_| std::env::var("COMPUTERNAME")).unwrap_or_else(|_| "unknown host".to_string())
+}
+
+// what backup finds changed in the local tree since the fetched manifest, see local_changes
+struct LocalChanges {
+    added_or_deleted: bool,
+    modified: bool,
+}
+
+// whether the file was modified after time (unix seconds), false when its mtime can't be read
+fn modified_after(path: &Path, time: u64) -> bool {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .is_some_and(|since_epoch| since_epoch.as_secs() > time)
+}
+
+// the placeholders the scan left out are in the reports of the commands, see report::DiffReport
+fn scan_local_tree(local_meta: &DotHar, scan_options: &ScanOptions) -> Result<(Manifest, ScanReport)> {
+    scan_options.backend.source().scan(local_meta.get_archive_root(), scan_options)
+        .context("Making manifest from local tree")
+}
+
+pub const EXPORT_METADATA_NAME: &str = ".har_export.json";
+
+pub enum ExportFormat {
+    Tar,
+    Directory,
+}
+
+fn append_dir_to_tar<W: Write>(builder: &mut tar::Builder<W>, dir_path: &Path) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Directory);
+    header.set_mode(0o755);
+    header.set_size(0);
+    builder.append_data(&mut header, dir_path, std::io::empty()).context("Writing dir to tar")
+}
+
+// path -> (size, blob) of files uploaded by copy_to and import_tar
+type UploadedFiles = HashMap<PathBuf, (u64, manifest::StoredBlob)>;
+
+// added to remote_manifest, replacing the files it has at the same paths
+fn add_uploaded_files(remote_manifest: &mut Manifest, files: UploadedFiles) -> Result<()> {
+    let remote_files: HashMap<PathBuf, String> = remote_manifest.list_files().into_iter().map(|(path, key, _)| (path, key)).collect();
+    for path in files.keys() {
+        if let Some(file_above) = path.ancestors().skip(1).find(|ancestor| remote_files.contains_key(*ancestor)) {
+            anyhow::bail!("{} is a file in the archive, {} can't be under it", file_above.to_str().unwrap(), path.to_str().unwrap());
+        }
+    }
+    let files_manifest = Manifest::from_listing(files.iter().map(|(path, (size, _))| (path.clone(), *size)))?;
+    let mut diff = manifest::diff_manifests(&files_manifest, remote_manifest);
+    diff.paths_of_different_files = files.iter()
+        .filter(|(path, (_, blob))| remote_files.get(*path).is_some_and(|key| *key != blob.key))
+        .map(|(path, _)| path.clone())
+        .collect();
+    let blob_keys: HashMap<PathBuf, manifest::StoredBlob> = files.into_iter().map(|(path, (_, blob))| (path, blob)).collect();
+    let now = clock::unix_now();
+    manifest::add_new_entries_to_manifest(&files_manifest, remote_manifest, &diff, &blob_keys, &HashSet::new(), now)?;
+    manifest::update_changed_entries_in_manifest(&files_manifest, remote_manifest, &diff, &blob_keys, &HashSet::new(), now)
+}
+
+// the content of the blobs of keys one after the other, downloaded as they are read
+struct BlobsReader<'a> {
+    mirror: &'a mut Mirror,
+    keys: std::vec::IntoIter<String>,
+    current: bytes::Bytes,
+}
+
+impl std::io::Read for BlobsReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.current.is_empty() {
+            match self.keys.next() {
+                Some(key) => self.current = self.mirror.get_blob(&key).map_err(std::io::Error::other)?,
+                None => return Ok(0),
+            }
+        }
+        let read = self.current.split_to(buf.len().min(self.current.len()));
+        buf[..read.len()].copy_from_slice(&read);
+        Ok(read.len())
+    }
+}
+
+fn stored_blob(pushed: mirror::PushedFile) -> manifest::StoredBlob {
+    // 0 for blobs deduplicated by exists(), see Mirror::push
+    let known_size = |size: u64| (size > 0).then_some(size);
+    let chunks = pushed.chunks.into_iter()
+        .map(|chunk| manifest::StoredChunk { key: chunk.key, size: chunk.plaintext_size, ciphertext_size: chunk.ciphertext_size })
+        .collect::<Vec<_>>();
+    // unknown if any chunk's is
+    let ciphertext_size = known_size(pushed.info.ciphertext_size).filter(|_| chunks.iter().all(|chunk| chunk.ciphertext_size > 0));
+    manifest::StoredBlob { key: pushed.info.key, ciphertext_size, chunks }
+}
+
+// when the blob of each file last changed, files pushed before times were recorded have none
+fn file_mtimes(manifest: &Manifest) -> HashMap<PathBuf, u64> {
+    manifest.files_changed_since(0).into_iter().map(|(path, times)| (path, times.modified)).collect()
+}
+
+fn append_file_to_tar<W: Write>(builder: &mut tar::Builder<W>, file_path: &Path, data: &[u8], mtime: u64) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Regular);
+    header.set_mode(0o644);
+    header.set_size(data.len() as u64);
+    header.set_mtime(mtime);
+    builder.append_data(&mut header, file_path, data).context("Writing file to tar")
 }
 
 pub mod for_integ_test {
     use std::path::Path;
     use super::{WithLocal, WithRemoteAndLocal};
-    use super::DotHar;
+    use super::{DotHar, Settings};
     pub fn with_local(dot_har_path: &Path) -> WithLocal {
-        WithLocal { local_meta: DotHar::with_path(dot_har_path.to_path_buf()) }
+        WithLocal::with_dot_har(DotHar::with_path(dot_har_path.to_path_buf()), &Settings::default())
     }
     pub fn with_remote_and_local(dot_har_path: &Path) -> WithRemoteAndLocal {
-        let local_meta = DotHar::with_path(dot_har_path.to_path_buf());
-        let remote = WithRemoteAndLocal::init_mirror(&local_meta).unwrap();
-        WithRemoteAndLocal {
-            local_meta,
-            remote
-        }
+        try_with_remote_and_local(dot_har_path).unwrap()
+    }
+    pub fn try_with_remote_and_local(dot_har_path: &Path) -> anyhow::Result<WithRemoteAndLocal> {
+        with_remote_and_local_and_settings(dot_har_path, &Settings::default())
+    }
+    pub fn with_remote_and_local_and_settings(dot_har_path: &Path, settings: &Settings) -> anyhow::Result<WithRemoteAndLocal> {
+        Ok(WithRemoteAndLocal::connect(DotHar::with_path(dot_har_path.to_path_buf()), settings)?
+            .with_deterministic(seed()))
+    }
+    // the same seed for every run, HAR_BACKUP_TEST_SEED to try others
+    fn seed() -> u64 {
+        std::env::var("HAR_BACKUP_TEST_SEED").ok().and_then(|seed| seed.parse().ok()).unwrap_or(0)
     }
 }
\ No newline at end of file
@@ -0,0 +1,59 @@
+// completions SHELL: the script clap_complete generates for the subcommands and flags, with for bash and fish
+// the paths of the archive completed from the fetched manifest (complete-path) for the commands taking them
+// zsh only has the generated part, its autoloaded completion functions leave no place to hook in
+
+use clap_complete::Shell;
+use std::io::Write;
+
+const BASH_ARCHIVE_PATHS: &str = r#"
+_BIN_archive_paths() {
+    local cur="${COMP_WORDS[COMP_CWORD]}" prev="${COMP_WORDS[COMP_CWORD-1]}"
+    case "${COMP_WORDS[1]}" in
+        ls|cat|du|rm|mv|restore|export-tar|export-listing|copy)
+            if [[ "$cur" != -* && "$prev" != --target && "$prev" != --to && "$prev" != -o && "$prev" != --output ]]; then
+                local IFS=$'\n'
+                COMPREPLY=($(BIN complete-path -- "$cur" 2>/dev/null))
+                if [[ ${#COMPREPLY[@]} -eq 1 && "${COMPREPLY[0]}" == */ ]]; then
+                    compopt -o nospace
+                fi
+                return 0
+            fi
+            ;;
+    esac
+    _BIN "$@"
+}
+complete -F _BIN_archive_paths -o bashdefault -o default BIN
+"#;
+
+const FISH_ARCHIVE_PATHS: &str = r#"
+complete -c BIN -n "__fish_seen_subcommand_from ls cat du rm mv restore export-tar export-listing copy; and not __fish_prev_arg_in --target --to -o --output" -f -a "(BIN complete-path -- (commandline -ct) 2>/dev/null)"
+"#;
+
+// BIN in the scripts above is the name of the binary, as the function clap_complete names _BIN for bash
+pub fn write_completions(command: &mut clap::Command, bin_name: &str, shell: Shell, out: &mut impl Write) -> anyhow::Result<()> {
+    clap_complete::generate(shell, command, bin_name, out);
+    let archive_paths = match shell {
+        Shell::Bash => BASH_ARCHIVE_PATHS,
+        Shell::Fish => FISH_ARCHIVE_PATHS,
+        _ => return Ok(()),
+    };
+    out.write_all(archive_paths.replace("BIN", bin_name).as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bash_wraps_the_generated_function() -> anyhow::Result<()> {
+        let mut command = clap::Command::new("har_backup").subcommand(clap::Command::new("ls").arg(clap::Arg::new("path")));
+        let mut script = Vec::new();
+        write_completions(&mut command, "har", Shell::Bash, &mut script)?;
+        let script = String::from_utf8(script)?;
+        assert!(script.starts_with("_har() {"));
+        assert!(script.contains("    _har \"$@\""));
+        assert!(script.ends_with("complete -F _har_archive_paths -o bashdefault -o default har\n"));
+        Ok(())
+    }
+}
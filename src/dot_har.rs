@@ -1,23 +1,105 @@
 
 use std::path::{Path, PathBuf};
 use anyhow::{Result, Context, anyhow};
-use super::manifest::Manifest;
+use har_backup_core::manifest::Manifest;
+use har_backup_core::journal::{TransferJournal, UploadJournal};
+use har_backup_core::hash_cache::HashCache;
+use har_backup_core::keys::KeyFile;
+use har_backup_core::blob_compression::Compression;
+use har_backup_core::archive_metadata::ArchiveMetadata;
+use super::settings::Settings;
+use super::history::{self, RunRecord};
+use super::queue::QueuedRun;
 use std::ops::Range;
+use tracing::debug;
 
-pub const DOT_HAR_NAME: &str = ".har";
-const KEYPATH_FILE: &str = "keypath";
-const REMOTE_FILE: &str = "remote";
+pub use har_backup_core::scan::DOT_HAR_NAME;
 const FETCHED_MANIFEST: &str = "fetched_manifest";
 const FETCHED_MANIFEST_BACKUP: &str = "fetched_manifest.backup";
+const JOURNAL_FILE: &str = "journal";
+const OLD_KEYS_FILE: &str = "old_keys";
+const PENDING_INIT_FILE: &str = "pending_init";
+const UPLOAD_JOURNAL_FILE: &str = "upload_journal";
+const MANIFEST_GENERATION_FILE: &str = "manifest_generation";
+const CONFIG_FILE: &str = "config.toml";
+// before config.toml: settings in config, remote, key and compression in a file each
+const LEGACY_CONFIG_FILE: &str = "config";
+const LEGACY_VALUE_FILES: [(&str, &str); 3] = [("remote", REMOTE_KEY), ("keypath", KEY_KEY), ("compression", COMPRESSION_KEY)];
+// keys of config.toml, the same as in the settings
+const REMOTE_KEY: &str = "remote";
+const KEY_KEY: &str = "key";
+const COMPRESSION_KEY: &str = "compression";
+// not a setting: the public key of the signed manifests as first seen, see cmd_impl signing_public_key
+const SIGNING_PUBLIC_KEY_KEY: &str = "signing_public_key";
+const HISTORY_FILE: &str = "history";
+const QUEUE_FILE: &str = "queue";
+const LAST_BACKUP_FILE: &str = "last_backup";
+const HASH_CACHE_FILE: &str = "hash_cache";
+const GC_CANDIDATES_FILE: &str = "gc_candidates";
+const MANIFEST_BACKUPS_DIR: &str = "manifest_backups";
+pub const DEFAULT_MANIFEST_BACKUPS: usize = 10;
+
+// a manifest that doesn't decode may be from a writer that doesn't rename (older har), read again a few times
+const MANIFEST_READ_ATTEMPTS: u32 = 5;
+const MANIFEST_READ_RETRY_DELAY: std::time::Duration = std::time::Duration::from_millis(50);
 
 #[derive(Clone)]
 pub struct DotHar {
-    path: PathBuf
+    path: PathBuf,
+    overrides: Overrides,
+    manifest_backups: usize, // kept in MANIFEST_BACKUPS_DIR, see store_manifest_with_backup
+}
+
+// a fetched manifest that a push replaced, restore_manifest_backup puts it back
+#[derive(Debug, Clone, PartialEq)]
+pub struct ManifestBackup {
+    pub path: PathBuf,
+    pub generation: u64, // it was the fetched manifest of
+    pub replaced: u64, // unix seconds
+}
+
+// taken instead of what the .har files have, see settings
+#[derive(Clone, Default)]
+struct Overrides {
+    remote: Option<String>,
+    key: Option<String>,
+    compression: Option<String>,
 }
 
 pub enum RemoteSpec {
     LocalFileSystem(PathBuf),
     S3(S3Spec),
+    Multi(Vec<RemoteSpec>), // write to all, read from the first available
+    Rclone(String), // REMOTE:PATH as rclone takes it, its config has the credentials
+}
+
+// separates the remotes of a multi:// spec, one per block of lines
+const MULTI_SEPARATOR: &str = "\n---\n";
+
+// key in config.toml: a path to a key file or keychain://<name>
+pub enum KeySpec {
+    File(PathBuf),
+    Keychain(String),
+}
+
+const KEYCHAIN_SCHEME: &str = "keychain://";
+
+impl KeySpec {
+    pub fn parse(spec_str: &str) -> Self {
+        match spec_str.strip_prefix(KEYCHAIN_SCHEME) {
+            Some(name) => KeySpec::Keychain(name.to_string()),
+            None => KeySpec::File(PathBuf::from(spec_str)),
+        }
+    }
+}
+
+impl std::fmt::Display for KeySpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeySpec::File(path) => write!(f, "{}", path.to_str().unwrap()),
+            KeySpec::Keychain(name) => write!(f, "{}{}", KEYCHAIN_SCHEME, name),
+        }
+    }
 }
 
 pub struct S3Spec {
@@ -44,7 +126,7 @@ impl S3Spec {
 }
 
 impl RemoteSpec {
-    fn parse(spec_str: &str) -> Result<Self> {
+    pub fn parse(spec_str: &str) -> Result<Self> {
         let (scheme, the_rest) = spec_str.split_once("://").context("Remote spec (as specified by .har) does not have format A://B")?;
         let ret = match scheme {
             "fs" => {
@@ -75,17 +157,54 @@ impl RemoteSpec {
                 };
                 RemoteSpec::S3(s3_spec)
             },
+            "multi" => {
+                let specs = the_rest.trim_matches('\n').split(MULTI_SEPARATOR)
+                    .map(|spec_str| RemoteSpec::parse(spec_str.trim_matches('\n')))
+                    .collect::<Result<Vec<_>>>()?;
+                RemoteSpec::Multi(specs)
+            },
+            "rclone" => RemoteSpec::Rclone(the_rest.to_string()),
             _ => anyhow::bail!("Unknown scheme {}", scheme)
         };
         Ok(ret)
     }
+
+    // what blob keys are derived from (see blob_storage::get_hash_name)
+    pub fn bucket_name(&self) -> String {
+        match self {
+            RemoteSpec::LocalFileSystem(path) => path.to_str().unwrap().to_string(),
+            RemoteSpec::S3(spec) => spec.bucket_name().to_string(),
+            RemoteSpec::Multi(specs) => specs[0].bucket_name(),
+            RemoteSpec::Rclone(remote) => remote.clone(),
+        }
+    }
+
+    // for display, without the s3 credentials
+    pub fn redacted(&self) -> String {
+        match self {
+            RemoteSpec::LocalFileSystem(path) => format!("fs://{}", path.to_str().unwrap()),
+            RemoteSpec::S3(spec) => format!("s3://{} bucket {}", spec.endpoint(), spec.bucket_name()),
+            RemoteSpec::Multi(specs) => format!("multi://{}", specs.iter().map(RemoteSpec::redacted).collect::<Vec<_>>().join(", ")),
+            RemoteSpec::Rclone(remote) => format!("rclone://{}", remote),
+        }
+    }
 }
 
 impl DotHar {
 
     // should be used for testing only
     pub fn with_path(path: PathBuf) -> Self {
-        Self { path }
+        Self { path, overrides: Overrides::default(), manifest_backups: DEFAULT_MANIFEST_BACKUPS }
+    }
+
+    pub fn with_settings(mut self, settings: &Settings) -> Self {
+        self.overrides = Overrides {
+            remote: settings.remote.clone(),
+            key: settings.key.clone(),
+            compression: settings.compression.clone(),
+        };
+        self.manifest_backups = settings.manifest_backups.unwrap_or(DEFAULT_MANIFEST_BACKUPS);
+        self
     }
 
     pub fn find_cwd_or_ancestor() -> Result<Self> {
@@ -93,7 +212,10 @@ impl DotHar {
         for dir in cwd.ancestors() {
             let maybe_exists = dir.join(DOT_HAR_NAME);
             if maybe_exists.exists() {
-                return Ok(Self{path: maybe_exists});
+                let dot_har = Self::with_path(maybe_exists);
+                // before the settings are loaded from config.toml
+                dot_har.migrate_legacy_config()?;
+                return Ok(dot_har);
             }
         }
         anyhow::bail!("Did not find {} in cwd or any ancestor dir", DOT_HAR_NAME)
@@ -103,23 +225,171 @@ impl DotHar {
         self.path.parent().unwrap()
     }
 
+    // settings of this archive, in the format of the --config file (see settings), with its remote and key
+    pub fn config_path(&self) -> PathBuf {
+        self.path.join(CONFIG_FILE)
+    }
+
+    // the values of the legacy files go in config.toml unless it has them already, the files are then removed
+    fn migrate_legacy_config(&self) -> Result<()> {
+        let legacy_files: Vec<&str> = std::iter::once(LEGACY_CONFIG_FILE).chain(LEGACY_VALUE_FILES.map(|(name, _)| name))
+            .filter(|name| self.path.join(name).exists())
+            .collect();
+        if legacy_files.is_empty() {
+            return Ok(());
+        }
+        let mut config = self.read_config()?;
+        if self.path.join(LEGACY_CONFIG_FILE).exists() {
+            let legacy_config = String::from_utf8(self.read_file(LEGACY_CONFIG_FILE)?)?;
+            let legacy_config: toml_edit::DocumentMut = legacy_config.parse().context("Parsing legacy .har/config")?;
+            if config.is_empty() {
+                // as it is, comments included
+                config = legacy_config;
+            } else {
+                for (key, item) in legacy_config.iter() {
+                    if !config.contains_key(key) {
+                        config.insert(key, item.clone());
+                    }
+                }
+            }
+        }
+        for (name, key) in LEGACY_VALUE_FILES {
+            if self.path.join(name).exists() && !config.contains_key(key) {
+                config[key] = toml_edit::value(String::from_utf8(self.read_file(name)?)?.trim_end_matches('\n'));
+            }
+        }
+        self.write_file_atomic(CONFIG_FILE, config.to_string().as_bytes()).context("Storing CONFIG_FILE")?;
+        for name in legacy_files {
+            std::fs::remove_file(self.path.join(name))?;
+        }
+        debug!("Migrated {} to {}", LEGACY_CONFIG_FILE, CONFIG_FILE);
+        Ok(())
+    }
+
+    fn read_config(&self) -> Result<toml_edit::DocumentMut> {
+        if !self.path.join(CONFIG_FILE).exists() {
+            return Ok(toml_edit::DocumentMut::new());
+        }
+        let content = String::from_utf8(self.read_file(CONFIG_FILE)?)?;
+        content.parse().context("Parsing .har/config.toml")
+    }
+
+    // compression = 3 is read as "3"
+    fn get_config_value(&self, key: &str) -> Result<Option<String>> {
+        self.migrate_legacy_config()?;
+        let config = self.read_config()?;
+        let value = match config.get(key).and_then(|item| item.as_value()) {
+            None => None,
+            Some(toml_edit::Value::String(value)) => Some(value.value().clone()),
+            Some(toml_edit::Value::Integer(value)) => Some(value.value().to_string()),
+            Some(_) => anyhow::bail!("{} in .har/config.toml is neither a string nor an integer", key),
+        };
+        Ok(value)
+    }
+
+    // the rest of config.toml is kept as it is, comments included
+    fn set_config_value(&self, key: &str, value: impl Into<toml_edit::Value>) -> Result<()> {
+        self.migrate_legacy_config()?;
+        let mut config = self.read_config()?;
+        config[key] = toml_edit::value(value);
+        self.write_file_atomic(CONFIG_FILE, config.to_string().as_bytes()).context("Storing CONFIG_FILE")
+    }
+
     pub fn get_manifest(&self) -> Result<Manifest> {
-        let file_content = self.read_file(FETCHED_MANIFEST)?;
-        let manifest = Manifest::from_bytes(bytes::Bytes::from(file_content))?;
-        Ok(manifest)
+        Ok(self.get_manifest_with_generation()?.0)
+    }
+
+    // the fetched manifest and the generation it was stored with, see store_manifest
+    // read again if a fetch-manifest or push stored another one meanwhile
+    pub fn get_manifest_with_generation(&self) -> Result<(Manifest, u64)> {
+        let (manifest, _, generation) = self.read_manifest()?;
+        Ok((manifest, generation))
+    }
+
+    // the fetched manifest and its bytes as they were downloaded, see mirror::Mirror::push_manifest_blob_if_unchanged
+    pub fn get_manifest_and_blob(&self) -> Result<(Manifest, bytes::Bytes)> {
+        let (manifest, manifest_blob, _) = self.read_manifest()?;
+        Ok((manifest, manifest_blob))
+    }
+
+    fn read_manifest(&self) -> Result<(Manifest, bytes::Bytes, u64)> {
+        let mut attempt = 1;
+        loop {
+            let generation = self.get_manifest_generation()?;
+            let manifest_blob = self.read_file(FETCHED_MANIFEST).map(bytes::Bytes::from);
+            let manifest = manifest_blob.and_then(|manifest_blob| Ok((Manifest::from_bytes(manifest_blob.clone())?, manifest_blob)));
+            let stable = self.get_manifest_generation()? == generation;
+            match manifest {
+                Ok((manifest, manifest_blob)) if stable => return Ok((manifest, manifest_blob, generation)),
+                Err(err) if attempt >= MANIFEST_READ_ATTEMPTS => return Err(err),
+                _ => {},
+            }
+            attempt += 1;
+            std::thread::sleep(MANIFEST_READ_RETRY_DELAY);
+        }
+    }
+
+    // when the fetched manifest was last stored (by fetch-manifest or push), None if it never was
+    pub fn get_manifest_stored_time(&self) -> Result<Option<u64>> {
+        let path = self.path.join(FETCHED_MANIFEST);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let modified = std::fs::metadata(&path)?.modified()?;
+        Ok(Some(modified.duration_since(std::time::UNIX_EPOCH).map_or(0, |since_epoch| since_epoch.as_secs())))
+    }
+
+    // bumped every time the fetched manifest is stored, 0 if it never was (or by an older har)
+    pub fn get_manifest_generation(&self) -> Result<u64> {
+        if !self.path.join(MANIFEST_GENERATION_FILE).exists() {
+            return Ok(0);
+        }
+        let file_content = String::from_utf8(self.read_file(MANIFEST_GENERATION_FILE)?)?;
+        file_content.trim().parse().context("Parsing manifest generation")
+    }
+
+    pub fn get_key_spec(&self) -> Result<KeySpec> {
+        if let Some(key_spec) = &self.overrides.key {
+            return Ok(KeySpec::parse(key_spec));
+        }
+        let key_spec = self.get_config_value(KEY_KEY)?.context("No key in .har/config.toml (see har key set-path)")?;
+        Ok(KeySpec::parse(&key_spec))
     }
 
-    pub fn get_key_file(&self) -> Result<PathBuf> {
-        let file_content = self.read_file(KEYPATH_FILE)?;
-        let keypath_str = String::from_utf8(file_content)?;
-        Ok(PathBuf::from(&keypath_str))
+    pub fn get_key(&self) -> Result<KeyFile> {
+        Self::load_key(self.get_key_spec()?)
+    }
+
+    // keys blobs were encrypted with before the current one, one key spec per line of OLD_KEYS_FILE
+    pub fn get_old_keys(&self) -> Result<Vec<KeyFile>> {
+        if !self.path.join(OLD_KEYS_FILE).exists() {
+            return Ok(Vec::new());
+        }
+        let file_content = String::from_utf8(self.read_file(OLD_KEYS_FILE)?)?;
+        file_content.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| Self::load_key(KeySpec::parse(line.trim())).with_context(|| format!("Loading old key {}", line)))
+            .collect()
+    }
+
+    fn load_key(key_spec: KeySpec) -> Result<KeyFile> {
+        match key_spec {
+            KeySpec::File(keypath) => {
+                if !keypath.exists() {
+                    anyhow::bail!("Keyfile {} (as specified by .har) not found", keypath.to_str().unwrap());
+                }
+                KeyFile::from_file(&keypath)
+            },
+            KeySpec::Keychain(name) => KeyFile::from_content(&crate::keychain::load_key(&name)?, &name),
+        }
     }
 
     pub fn get_remote_spec(&self) -> Result<RemoteSpec> {
-        let file_content = self.read_file(REMOTE_FILE)?;
-        let remote_spec = String::from_utf8(file_content)?;
-        let remote_spec = RemoteSpec::parse(&remote_spec)?;
-        Ok(remote_spec)
+        if let Some(remote_spec) = &self.overrides.remote {
+            return RemoteSpec::parse(remote_spec);
+        }
+        let remote_spec = self.get_config_value(REMOTE_KEY)?.context("No remote in .har/config.toml (see har remote set)")?;
+        RemoteSpec::parse(&remote_spec)
     }
 
     fn read_file(&self, name: &str) -> Result<Vec<u8>> {
@@ -128,24 +398,338 @@ impl DotHar {
         Ok(file_content)
     }
 
-    pub fn store_manifest(&self, manifest_blob: bytes::Bytes) -> Result<()> {
-        std::fs::write(self.path.join(FETCHED_MANIFEST), &manifest_blob).context("Storing fetched manifest")?;
-        Ok(())
+    // readers see either the previous manifest or this one, never a part of it
+    pub fn store_manifest(&self, manifest_blob: bytes::Bytes) -> Result<u64> {
+        self.write_file_atomic(FETCHED_MANIFEST, &manifest_blob).context("Storing fetched manifest")?;
+        self.bump_manifest_generation()
     }
 
-    pub fn store_manifest_with_backup(&self, manifest_blob: bytes::Bytes) -> Result<()> {
+    // the manifest replaced is kept as FETCHED_MANIFEST_BACKUP, and with the ones replaced before it in
+    // MANIFEST_BACKUPS_DIR, the oldest are removed past manifest_backups of them
+    pub fn store_manifest_with_backup(&self, manifest_blob: bytes::Bytes) -> Result<u64> {
         let path = self.path.join(FETCHED_MANIFEST);
         let backup_path = self.path.join(FETCHED_MANIFEST_BACKUP);
         std::fs::copy(&path, backup_path).context("Backup of fetched manifest")?;
-        std::fs::write(path, &manifest_blob).context("Storing fetched manifest")?;
+        if self.manifest_backups > 0 {
+            let backups_dir = self.path.join(MANIFEST_BACKUPS_DIR);
+            std::fs::create_dir_all(&backups_dir).context("Creating MANIFEST_BACKUPS_DIR")?;
+            let name = format!("{}-{}", self.get_manifest_generation()?, har_backup_core::clock::unix_now());
+            std::fs::copy(&path, backups_dir.join(name)).context("Backup of fetched manifest")?;
+        }
+        for backup in self.list_manifest_backups()?.into_iter().skip(self.manifest_backups) {
+            std::fs::remove_file(&backup.path).with_context(|| format!("Removing {}", backup.path.to_str().unwrap()))?;
+        }
+        self.store_manifest(manifest_blob)
+    }
+
+    // most recent first
+    pub fn list_manifest_backups(&self) -> Result<Vec<ManifestBackup>> {
+        let backups_dir = self.path.join(MANIFEST_BACKUPS_DIR);
+        if !backups_dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut backups = Vec::new();
+        for dir_entry in std::fs::read_dir(&backups_dir).context("Listing MANIFEST_BACKUPS_DIR")? {
+            let path = dir_entry?.path();
+            let name = path.file_name().unwrap().to_string_lossy().into_owned();
+            // the temporary files of other tools and such
+            let Some((Ok(generation), Ok(replaced))) = name.split_once('-').map(|(generation, replaced)| (generation.parse(), replaced.parse())) else {
+                continue;
+            };
+            backups.push(ManifestBackup { path, generation, replaced });
+        }
+        backups.sort_by_key(|backup| std::cmp::Reverse(backup.generation));
+        Ok(backups)
+    }
+
+    pub fn remove_manifest_backup(&self, backup: &ManifestBackup) -> Result<()> {
+        std::fs::remove_file(&backup.path).with_context(|| format!("Removing {}", backup.path.to_str().unwrap()))
+    }
+
+    // the fetched manifest becomes the nth most recent backup (from 1), it is itself backed up so that this can be undone
+    pub fn restore_manifest_backup(&self, n: usize) -> Result<ManifestBackup> {
+        let backup = self.list_manifest_backups()?.into_iter().nth(n.saturating_sub(1))
+            .with_context(|| format!("There is no manifest backup {}", n))?;
+        let manifest_blob = bytes::Bytes::from(std::fs::read(&backup.path).with_context(|| format!("Read {}", backup.path.to_str().unwrap()))?);
+        Manifest::from_bytes(manifest_blob.clone()).context("Reading the manifest backup")?;
+        self.store_manifest_with_backup(manifest_blob)?;
+        Ok(backup)
+    }
+
+    // after the manifest is renamed in place, so that a reader seeing the same generation before and after its read
+    // has read the manifest of that generation
+    fn bump_manifest_generation(&self) -> Result<u64> {
+        let generation = self.get_manifest_generation()? + 1;
+        self.write_file_atomic(MANIFEST_GENERATION_FILE, generation.to_string().as_bytes()).context("Storing manifest generation")?;
+        Ok(generation)
+    }
+
+    // written next to name then renamed over it, the temporary name is per process so that concurrent writers don't mix
+    fn write_file_atomic(&self, name: &str, content: &[u8]) -> Result<()> {
+        let path = self.path.join(name);
+        let tmp_path = self.path.join(format!("{}.tmp.{}", name, std::process::id()));
+        let mut file = std::fs::File::create(&tmp_path).with_context(|| format!("Create {}", tmp_path.to_str().unwrap()))?;
+        std::io::Write::write_all(&mut file, content)?;
+        file.sync_all()?;
+        drop(file);
+        std::fs::rename(&tmp_path, &path).with_context(|| format!("Rename to {}", path.to_str().unwrap()))?;
+        Ok(())
+    }
+
+    // empty journal if there was never any failure
+    pub fn get_journal(&self) -> Result<TransferJournal> {
+        if !self.path.join(JOURNAL_FILE).exists() {
+            return Ok(TransferJournal::default());
+        }
+        let file_content = self.read_file(JOURNAL_FILE)?;
+        TransferJournal::from_bytes(&file_content)
+    }
+
+    pub fn store_journal(&self, journal: &TransferJournal) -> Result<()> {
+        std::fs::write(self.path.join(JOURNAL_FILE), journal.to_bytes()?).context("Storing transfer journal")
+    }
+
+    // metadata of an init-remote that has not completed yet, reused when it is retried
+    pub fn get_pending_init(&self) -> Result<Option<ArchiveMetadata>> {
+        if !self.path.join(PENDING_INIT_FILE).exists() {
+            return Ok(None);
+        }
+        let file_content = self.read_file(PENDING_INIT_FILE)?;
+        Ok(Some(ArchiveMetadata::from_bytes(bytes::Bytes::from(file_content))?))
+    }
+
+    pub fn store_pending_init(&self, metadata: &ArchiveMetadata) -> Result<()> {
+        std::fs::write(self.path.join(PENDING_INIT_FILE), metadata.to_bytes()?).context("Write PENDING_INIT_FILE")
+    }
+
+    pub fn clear_pending_init(&self) -> Result<()> {
+        let path = self.path.join(PENDING_INIT_FILE);
+        if path.exists() {
+            std::fs::remove_file(path).context("Remove PENDING_INIT_FILE")?;
+        }
+        Ok(())
+    }
+
+    // uploads of a push that has not updated the remote manifest yet
+    pub fn open_upload_journal(&self) -> Result<UploadJournal> {
+        UploadJournal::open(&self.path.join(UPLOAD_JOURNAL_FILE))
+    }
+
+    // once the remote manifest refers to the uploaded blobs
+    pub fn clear_upload_journal(&self) -> Result<()> {
+        let path = self.path.join(UPLOAD_JOURNAL_FILE);
+        if path.exists() {
+            std::fs::remove_file(path).context("Remove UPLOAD_JOURNAL_FILE")?;
+        }
         Ok(())
     }
 
+    pub fn append_run(&self, run: &RunRecord) -> Result<()> {
+        use std::io::Write;
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(self.path.join(HISTORY_FILE)).context("Opening HISTORY_FILE")?;
+        file.write_all(history::run_line(run)?.as_bytes()).context("Appending to HISTORY_FILE")
+    }
+
+    // oldest first, empty if no push or pull transferred anything yet
+    pub fn get_runs(&self) -> Result<Vec<RunRecord>> {
+        if !self.path.join(HISTORY_FILE).exists() {
+            return Ok(Vec::new());
+        }
+        let content = self.read_file(HISTORY_FILE)?;
+        Ok(history::parse_runs(&String::from_utf8_lossy(&content)))
+    }
+
+    // empty if there is none yet
+    pub fn get_hash_cache(&self, bucket_name: &str) -> Result<HashCache> {
+        if !self.path.join(HASH_CACHE_FILE).exists() {
+            return Ok(HashCache::new(bucket_name));
+        }
+        Ok(HashCache::from_bytes(&self.read_file(HASH_CACHE_FILE)?, bucket_name))
+    }
+
+    pub fn store_hash_cache(&self, hash_cache: &HashCache) -> Result<()> {
+        self.write_file_atomic(HASH_CACHE_FILE, &hash_cache.to_bytes()?).context("Storing hash cache")
+    }
+
+    // transfers of a push or pull that stopped before the end, for har resume
+    pub fn get_queue(&self) -> Result<Option<QueuedRun>> {
+        if !self.path.join(QUEUE_FILE).exists() {
+            return Ok(None);
+        }
+        Ok(Some(QueuedRun::from_bytes(&self.read_file(QUEUE_FILE)?).context("Reading QUEUE_FILE")?))
+    }
+
+    pub fn store_queue(&self, queued: &QueuedRun) -> Result<()> {
+        self.write_file_atomic(QUEUE_FILE, &queued.to_bytes()?).context("Storing QUEUE_FILE")
+    }
+
+    pub fn clear_queue(&self) -> Result<()> {
+        let path = self.path.join(QUEUE_FILE);
+        if path.exists() {
+            std::fs::remove_file(path).context("Remove QUEUE_FILE")?;
+        }
+        Ok(())
+    }
+
+    // unix seconds when the last har backup that went through started, see schedule::Schedule
+    pub fn get_last_backup(&self) -> Result<Option<u64>> {
+        if !self.path.join(LAST_BACKUP_FILE).exists() {
+            return Ok(None);
+        }
+        let file_content = String::from_utf8(self.read_file(LAST_BACKUP_FILE)?)?;
+        Ok(Some(file_content.trim().parse().context("Parsing LAST_BACKUP_FILE")?))
+    }
+
+    pub fn store_last_backup(&self, started: u64) -> Result<()> {
+        self.write_file_atomic(LAST_BACKUP_FILE, started.to_string().as_bytes()).context("Storing LAST_BACKUP_FILE")
+    }
+
+    // blob keys the remote manifest stopped referring to (see har rm), one per line, sorted
+    // blobs are never deleted from the remote yet, this is what a gc would start from
+    pub fn get_gc_candidates(&self) -> Result<Vec<String>> {
+        if !self.path.join(GC_CANDIDATES_FILE).exists() {
+            return Ok(Vec::new());
+        }
+        let content = self.read_file(GC_CANDIDATES_FILE)?;
+        Ok(String::from_utf8_lossy(&content).lines().map(str::to_string).collect())
+    }
+
+    pub fn add_gc_candidates(&self, keys: &[String]) -> Result<()> {
+        let mut candidates: std::collections::BTreeSet<String> = self.get_gc_candidates()?.into_iter().collect();
+        candidates.extend(keys.iter().cloned());
+        let content: String = candidates.into_iter().map(|key| key + "\n").collect();
+        self.write_file_atomic(GC_CANDIDATES_FILE, content.as_bytes()).context("Storing GC_CANDIDATES_FILE")
+    }
+
+    // compression in config.toml: a zstd level or "off", default level if missing
+    pub fn get_compression(&self) -> Result<Compression> {
+        let compression = match &self.overrides.compression {
+            Some(compression) => compression.clone(),
+            None => match self.get_config_value(COMPRESSION_KEY)? {
+                Some(compression) => compression,
+                None => return Ok(Compression::default()),
+            },
+        };
+        match compression.trim() {
+            "off" => Ok(Compression::off()),
+            level => Ok(Compression::with_level(level.parse().with_context(|| format!("Compression level {}", level))?)),
+        }
+    }
+
+    pub fn set_compression(&self, compression: Compression) -> Result<()> {
+        match compression.level() {
+            Some(level) => self.set_config_value(COMPRESSION_KEY, level as i64),
+            None => self.set_config_value(COMPRESSION_KEY, "off"),
+        }
+    }
+
     pub fn set_path_to_keyfile(&self, path: &Path) -> Result<()> {
-        std::fs::write(self.path.join(KEYPATH_FILE), path.to_str().context("Path to str")?).context("Write KEYPATH_FILE")
+        self.set_config_value(KEY_KEY, path.to_str().context("Path to str")?)
+    }
+
+    pub fn set_keychain_key(&self, name: &str) -> Result<()> {
+        self.set_key_spec(&KeySpec::Keychain(name.to_string()))
+    }
+
+    pub fn set_key_spec(&self, key_spec: &KeySpec) -> Result<()> {
+        self.set_config_value(KEY_KEY, key_spec.to_string())
+    }
+
+    pub fn set_remote_spec(&self, spec: &str) -> Result<()> {
+        self.set_config_value(REMOTE_KEY, spec)
+    }
+
+    pub fn get_signing_public_key(&self) -> Result<Option<String>> {
+        self.get_config_value(SIGNING_PUBLIC_KEY_KEY)
     }
 
-    pub fn set_remote_spec(&self, spec: &str) -> std::io::Result<()> {
-        std::fs::write(self.path.join(REMOTE_FILE), spec)
+    pub fn set_signing_public_key(&self, public_key: &str) -> Result<()> {
+        self.set_config_value(SIGNING_PUBLIC_KEY_KEY, public_key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manifest_reads_are_not_torn() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let dot_har = DotHar::with_path(dir.path().to_path_buf());
+        assert_eq!(dot_har.get_manifest_generation()?, 0);
+        let manifest_bytes = Manifest::new().to_bytes()?;
+        assert_eq!(dot_har.store_manifest(manifest_bytes.clone())?, 1);
+
+        // manifests of growing sizes so that a torn read would not decode
+        let bigger = Manifest::from_listing((0..200).map(|i| (PathBuf::from(format!("dir/file{}", i)), i)))?;
+        let bigger_bytes = bigger.to_bytes()?;
+        let writer = {
+            let dot_har = dot_har.clone();
+            std::thread::spawn(move || -> Result<()> {
+                for i in 0..50 {
+                    let blob = if i % 2 == 0 { bigger_bytes.clone() } else { manifest_bytes.clone() };
+                    dot_har.store_manifest(blob)?;
+                }
+                Ok(())
+            })
+        };
+        let mut last_generation = 0;
+        while !writer.is_finished() {
+            let (_, generation) = dot_har.get_manifest_with_generation()?;
+            assert!(generation >= last_generation);
+            last_generation = generation;
+        }
+        writer.join().unwrap()?;
+        assert_eq!(dot_har.get_manifest_generation()?, 51);
+        // the last one stored is the empty one
+        assert!(dot_har.get_manifest()?.list_dirs().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn legacy_config_is_migrated() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        std::fs::write(dir.path().join("config"), "# slow link\nauto_fetch = true\n[transfer]\nmax_attempts = 4\n")?;
+        std::fs::write(dir.path().join("remote"), "fs:///backup\n")?;
+        std::fs::write(dir.path().join("keypath"), "keychain://kek")?;
+        std::fs::write(dir.path().join("compression"), "off")?;
+        let dot_har = DotHar::with_path(dir.path().to_path_buf());
+        assert_eq!(dot_har.get_remote_spec()?.bucket_name(), "/backup");
+        for name in ["config", "remote", "keypath", "compression"] {
+            assert!(!dir.path().join(name).exists());
+        }
+        assert_eq!(dot_har.get_key_spec()?.to_string(), "keychain://kek");
+        assert_eq!(dot_har.get_compression()?.level(), None);
+
+        dot_har.set_compression(Compression::with_level(9))?;
+        let settings = Settings::load(Some(&dot_har.config_path()), None)?;
+        assert_eq!((settings.remote.as_deref(), settings.compression.as_deref()), (Some("fs:///backup"), Some("9")));
+        assert!(settings.auto_fetch);
+        assert_eq!(settings.transfer.max_attempts, Some(4));
+        assert!(std::fs::read_to_string(dot_har.config_path())?.contains("# slow link"));
+        Ok(())
+    }
+
+    #[test]
+    fn manifest_backups_rotate() -> Result<()> {
+        let dir = tempfile::TempDir::new()?;
+        let mut dot_har = DotHar::with_path(dir.path().to_path_buf());
+        dot_har.manifest_backups = 2;
+        let manifest_of = |num_files: u64| Manifest::from_listing((0..num_files).map(|i| (PathBuf::from(format!("file{}", i)), i)))?.to_bytes();
+        let num_files = |dot_har: &DotHar| -> Result<usize> { Ok(dot_har.get_manifest()?.get_stats().num_files) };
+        dot_har.store_manifest(manifest_of(0)?)?;
+        for i in 1..4 {
+            dot_har.store_manifest_with_backup(manifest_of(i)?)?;
+        }
+        let generations: Vec<u64> = dot_har.list_manifest_backups()?.iter().map(|backup| backup.generation).collect();
+        assert_eq!(generations, vec![3, 2]);
+
+        assert_eq!(dot_har.restore_manifest_backup(2)?.generation, 2);
+        assert_eq!(num_files(&dot_har)?, 1);
+        // undone with the backup of the manifest it replaced
+        dot_har.restore_manifest_backup(1)?;
+        assert_eq!(num_files(&dot_har)?, 3);
+        assert!(dot_har.restore_manifest_backup(3).is_err());
+        Ok(())
     }
 }
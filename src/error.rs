@@ -0,0 +1,102 @@
+// har_backup::Error, what went wrong sorted for library callers to match on: cmd_impl returns anyhow errors with
+// their context, Error::from finds in them the markers of har and har-backup-core (as exit_code does) and the kind
+// of blob storage errors. The message is the whole chain, as {:#} prints it
+
+use har_backup_core::blob_storage::{self, ErrorKind};
+use har_backup_core::keys::SignatureMismatch;
+use har_backup_core::manifest::UndecodableManifest;
+use har_backup_core::mirror::RemoteManifestChanged;
+use har_backup_core::remote_lock::RemoteLocked;
+use har_backup_core::thread_sync::Cancelled;
+use crate::exit_code::{ConfigError, TransferFailed};
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("{0}")]
+    NotFound(String), // a blob, a file or the archive
+    #[error("{0}")]
+    Auth(String), // credentials refused or missing
+    #[error("{0}")]
+    Network(String), // the remote could not be reached or failed
+    #[error("{0}")]
+    Crypto(String), // a blob that does not decrypt, a manifest signature that does not match
+    #[error("{0}")]
+    Corrupt(String), // a manifest that does not decode
+    #[error("{0}")]
+    Conflict(String), // the remote is locked, or its manifest changed since the fetch
+    #[error("{0}")]
+    Io(String), // of local files
+    #[error("{0}")]
+    Config(String), // see exit_code::ConfigError
+    #[error("{0}")]
+    Transfer(String), // files that could not be uploaded or downloaded, see exit_code::TransferFailed
+    #[error("Interrupted")]
+    Cancelled,
+    #[error("{0}")]
+    Other(String),
+}
+
+// a context of err (see anyhow::Error::downcast_ref) or one of the errors it was made from
+fn find<T: std::error::Error + Send + Sync + 'static>(err: &anyhow::Error) -> Option<&T> {
+    err.downcast_ref::<T>().or_else(|| err.chain().find_map(|cause| cause.downcast_ref::<T>()))
+}
+
+impl From<anyhow::Error> for Error {
+    fn from(err: anyhow::Error) -> Self {
+        let msg = format!("{:#}", err);
+        if find::<Cancelled>(&err).is_some() {
+            Error::Cancelled
+        } else if find::<RemoteLocked>(&err).is_some() || find::<RemoteManifestChanged>(&err).is_some() {
+            Error::Conflict(msg)
+        } else if find::<ConfigError>(&err).is_some() {
+            Error::Config(msg)
+        } else if find::<SignatureMismatch>(&err).is_some() {
+            Error::Crypto(msg)
+        } else if find::<UndecodableManifest>(&err).is_some() {
+            Error::Corrupt(msg)
+        } else if let Some(storage_error) = find::<blob_storage::Error>(&err) {
+            match storage_error.kind {
+                ErrorKind::NotFound => Error::NotFound(msg),
+                ErrorKind::Auth => Error::Auth(msg),
+                ErrorKind::Network => Error::Network(msg),
+                ErrorKind::Crypto => Error::Crypto(msg),
+                ErrorKind::Io => Error::Io(msg),
+                ErrorKind::Changed => Error::Conflict(msg),
+                ErrorKind::Other => Error::Other(msg),
+            }
+        } else if let Some(io_error) = find::<std::io::Error>(&err) {
+            match io_error.kind() {
+                std::io::ErrorKind::NotFound => Error::NotFound(msg),
+                _ => Error::Io(msg),
+            }
+        } else if find::<TransferFailed>(&err).is_some() {
+            Error::Transfer(msg)
+        } else {
+            Error::Other(msg)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn kinds_are_found_under_context() {
+        let storage_error = blob_storage::Error::new(ErrorKind::Auth, "Error while downloading (status code 403)".to_string());
+        let err = anyhow::Error::from(storage_error).context("Fetching manifest");
+        let Error::Auth(msg) = Error::from(err) else { panic!("Expected Auth") };
+        assert_eq!(msg, "Fetching manifest: Error while downloading (status code 403)");
+
+        let err: anyhow::Result<()> = Err(std::io::Error::from(std::io::ErrorKind::NotFound)).context("Reading fetched manifest");
+        assert!(matches!(Error::from(err.unwrap_err()), Error::NotFound(_)));
+        let err = anyhow::anyhow!("Decoding").context(UndecodableManifest).context("Reading fetched manifest");
+        assert!(matches!(Error::from(err), Error::Corrupt(_)));
+        let err = anyhow::Error::from(RemoteManifestChanged).context("Pushing");
+        assert!(matches!(Error::from(err), Error::Conflict(_)));
+        assert!(matches!(Error::from(anyhow::Error::from(Cancelled).context(TransferFailed)), Error::Cancelled));
+        assert!(matches!(Error::from(anyhow::anyhow!("Anything else")), Error::Other(_)));
+    }
+}
@@ -0,0 +1,63 @@
+// what har exits with, so that cron jobs and scripts can tell what went wrong without reading the output
+// errors are sorted by the markers below, found anywhere in their context chain
+
+use har_backup_core::thread_sync::Cancelled;
+
+pub const CLEAN: i32 = 0;
+pub const DIFFERENCES: i32 = 1; // diff found some
+pub const TRANSFER_ERRORS: i32 = 2;
+pub const CONFIG_ERROR: i32 = 3; // and wrong arguments
+pub const OTHER_ERROR: i32 = 4;
+pub const INTERRUPTED: i32 = 130; // as a shell reports SIGINT
+
+// context of errors that a change of the settings, .har or the key fixes
+#[derive(Debug)]
+pub struct ConfigError;
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid configuration")
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+// context of errors of files that could not be uploaded or downloaded
+#[derive(Debug)]
+pub struct TransferFailed;
+
+impl std::fmt::Display for TransferFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Transfer failed")
+    }
+}
+
+impl std::error::Error for TransferFailed {}
+
+pub fn of_error(err: &anyhow::Error) -> i32 {
+    if err.is::<Cancelled>() {
+        INTERRUPTED
+    } else if err.is::<ConfigError>() {
+        CONFIG_ERROR
+    } else if err.is::<TransferFailed>() {
+        TRANSFER_ERRORS
+    } else {
+        OTHER_ERROR
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn markers_are_found_under_other_context() {
+        let err = anyhow::anyhow!("No remote in .har/config.toml").context(ConfigError).context("Connecting");
+        assert_eq!(of_error(&err), CONFIG_ERROR);
+        let err: anyhow::Result<()> = Err(anyhow::anyhow!("1 files failed")).context(TransferFailed);
+        assert_eq!(of_error(&err.unwrap_err()), TRANSFER_ERRORS);
+        assert_eq!(of_error(&anyhow::Error::from(Cancelled).context(TransferFailed)), INTERRUPTED);
+        assert_eq!(of_error(&anyhow::anyhow!("Anything else")), OTHER_ERROR);
+    }
+}
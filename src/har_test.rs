@@ -1,8 +1,8 @@
 use clap::{Parser, Args, Subcommand};
 use anyhow::{Result, Context};
-use har_backup::blob_storage::{EventContent, BlobStorage};
-use har_backup::blob_storage_local_directory::BlobStorageLocalDirectory;
-use har_backup::manifest::{Manifest, print_tree};
+use har_backup_core::blob_storage::{EventContent, BlobStorage};
+use har_backup_core::blob_storage_local_directory::BlobStorageLocalDirectory;
+use har_backup_core::manifest::{Manifest, print_tree};
 use std::path::PathBuf;
 
 #[derive(Parser)]
@@ -50,7 +50,7 @@ struct BlobStorageArgs {
 }
 
 fn main() -> Result<()> {
-    env_logger::init();
+    har_backup::logging::init(None, har_backup::logging::LogFormat::Text)?;
     let cli = Cli::parse();
     match cli.command {
         Command::MakeManifestFromFs(sub_cli) => {
@@ -74,7 +74,7 @@ fn main() -> Result<()> {
             blob_storage.upload(bytes::Bytes::from(sub_cli.data), None);
             let event = events.recv().expect("receive an event for upload");
             let blob_hash = match event.content {
-                EventContent::UploadSuccess(blob_hash) => blob_hash,
+                EventContent::UploadSuccess(info) => info.key,
                 _ => anyhow::bail!("Expected UploadSuccess but got {:?}", event.content)
             };
             println!("Upload success. Blob name: {}", blob_hash);
@@ -87,7 +87,7 @@ fn main() -> Result<()> {
             blob_storage.download(&sub_cli.blob_key);
             let event = events.recv().expect("receive an event for download");
             let bytes = match event.content {
-                EventContent::DownloadSuccess(bytes) => bytes,
+                EventContent::DownloadSuccess(info) => info.data,
                 _ => anyhow::bail!("Expected DownloadSuccess but got {:?}", event.content)
             };
             println!("Download success. Data: {:?}", bytes);
@@ -0,0 +1,96 @@
+use std::io::Write;
+use indicatif::HumanBytes;
+use serde::{Deserialize, Serialize};
+
+// pushes and pulls that transferred something, one json line each in .har (see dot_har::DotHar::append_run)
+// for stats --graph, nothing reads it back to decide what to transfer
+
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+pub const DEFAULT_GRAPH_WIDTH: usize = 60; // runs, the most recent ones
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RunKind {
+    Push,
+    Pull,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunRecord {
+    pub kind: RunKind,
+    pub finished: u64, // unix seconds
+    pub num_files: usize,
+    pub bytes: u64, // of the files transferred, before encryption
+    pub transfer_ms: u64,
+    pub archive_size: u64, // sum of the file sizes of the remote manifest after the run
+}
+
+impl RunRecord {
+    // bytes per second of the transfer phase
+    pub fn throughput(&self) -> f64 {
+        self.bytes as f64 * 1000.0 / self.transfer_ms.max(1) as f64
+    }
+}
+
+// lines that don't parse are skipped, like the last one of a run that crashed while appending it
+pub fn parse_runs(content: &str) -> Vec<RunRecord> {
+    content.lines().filter_map(|line| serde_json::from_str(line).ok()).collect()
+}
+
+pub fn run_line(run: &RunRecord) -> anyhow::Result<String> {
+    Ok(serde_json::to_string(run)? + "\n")
+}
+
+// one char per value, scaled from 0 to the max of values
+pub fn sparkline(values: &[f64]) -> String {
+    let max = values.iter().cloned().fold(0.0, f64::max);
+    values.iter().map(|&value| {
+        let level = if max > 0.0 { (value / max * (SPARKS.len() - 1) as f64).round() as usize } else { 0 };
+        SPARKS[level.min(SPARKS.len() - 1)]
+    }).collect()
+}
+
+// throughput, bytes transferred and archive size of the last width runs, oldest first
+pub fn write_graphs<W: Write>(runs: &[RunRecord], width: usize, out: &mut W) -> std::io::Result<()> {
+    let runs = &runs[runs.len().saturating_sub(width)..];
+    let (Some(first), Some(last)) = (runs.first(), runs.last()) else {
+        return Ok(());
+    };
+    let graph = |value: fn(&RunRecord) -> f64| sparkline(&runs.iter().map(value).collect::<Vec<_>>());
+    let max_throughput = runs.iter().map(RunRecord::throughput).fold(0.0, f64::max);
+    let total_bytes: u64 = runs.iter().map(|run| run.bytes).sum();
+
+    writeln!(out, "Last {} runs, {} to {}", runs.len(), har_backup_core::clock::format_utc_timestamp(first.finished), har_backup_core::clock::format_utc_timestamp(last.finished))?;
+    writeln!(out, "kind        {}", runs.iter().map(|run| if run.kind == RunKind::Push { '^' } else { 'v' }).collect::<String>())?;
+    writeln!(out, "throughput  {} max {}/s", graph(RunRecord::throughput), HumanBytes(max_throughput as u64))?;
+    writeln!(out, "transferred {} total {}", graph(|run| run.bytes as f64), HumanBytes(total_bytes))?;
+    writeln!(out, "archive     {} {} -> {}", graph(|run| run.archive_size as f64), HumanBytes(first.archive_size), HumanBytes(last.archive_size))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(kind: RunKind, bytes: u64, archive_size: u64) -> RunRecord {
+        RunRecord { kind, finished: 0, num_files: 1, bytes, transfer_ms: 1000, archive_size }
+    }
+
+    #[test]
+    fn graphs_of_runs() -> anyhow::Result<()> {
+        assert_eq!(sparkline(&[0.0, 7.0, 14.0, 3.5]), "▁▅█▃");
+        assert_eq!(sparkline(&[0.0, 0.0]), "▁▁");
+
+        let runs = [run(RunKind::Push, 100, 100), run(RunKind::Pull, 400, 100), run(RunKind::Push, 700, 800)];
+        let content = runs.iter().map(run_line).collect::<anyhow::Result<String>>()? + "{\"kind\":\"pu";
+        assert_eq!(parse_runs(&content), runs);
+
+        let mut out = Vec::new();
+        write_graphs(&runs, 2, &mut out)?;
+        let out = String::from_utf8(out)?;
+        assert!(out.contains("kind        v^\n"));
+        assert!(out.contains("transferred ▅█ total 1.07 KiB\n"));
+        assert!(out.contains("archive     ▂█ 100 B -> 800 B\n"));
+        Ok(())
+    }
+}
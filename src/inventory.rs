@@ -0,0 +1,135 @@
+// har inventory: every file of the versions of the manifest kept in .har (the fetched one and its backups, see
+// har log) with the generations that have it, for spreadsheets and query engines rather than people
+// a file with another blob in some versions has a row per blob
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::PathBuf;
+use har_backup_core::manifest::{self, Manifest};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InventoryRow {
+    pub path: PathBuf,
+    pub size: u64,
+    pub blob_key: String,
+    pub snapshots: Vec<u64>, // generations, most recent first
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InventoryFormat {
+    Csv,
+    Parquet,
+}
+
+// versions as (generation, manifest), most recent first, rows sorted by path
+pub fn collect(versions: &[(u64, Manifest)]) -> Vec<InventoryRow> {
+    let mut rows: BTreeMap<(PathBuf, String), InventoryRow> = BTreeMap::new();
+    for (generation, manifest) in versions {
+        for (path, blob_key, size) in manifest.list_files() {
+            rows.entry((path.clone(), blob_key.clone()))
+                .or_insert_with(|| InventoryRow { path, size, blob_key, snapshots: Vec::new() })
+                .snapshots.push(*generation);
+        }
+    }
+    rows.into_values().collect()
+}
+
+// the generations space separated, parquet has the same columns as csv
+fn snapshots_field(row: &InventoryRow) -> String {
+    row.snapshots.iter().map(u64::to_string).collect::<Vec<_>>().join(" ")
+}
+
+pub fn write(rows: &[InventoryRow], format: InventoryFormat, out: impl Write + Send) -> anyhow::Result<()> {
+    match format {
+        InventoryFormat::Csv => write_csv(rows, out),
+        InventoryFormat::Parquet => write_parquet(rows, out),
+    }
+}
+
+fn write_csv(rows: &[InventoryRow], mut out: impl Write) -> anyhow::Result<()> {
+    writeln!(out, "path,size,blob_key,snapshots")?;
+    for row in rows {
+        writeln!(out, "{},{},{},{}", manifest::csv_field(row.path.to_str().unwrap()), row.size, row.blob_key, snapshots_field(row))?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "parquet")]
+fn write_parquet(rows: &[InventoryRow], out: impl Write + Send) -> anyhow::Result<()> {
+    use std::sync::Arc;
+    use parquet::data_type::{ByteArray, ByteArrayType, Int64Type};
+    use parquet::file::properties::WriterProperties;
+    use parquet::file::writer::SerializedFileWriter;
+    use parquet::schema::parser::parse_message_type;
+
+    let schema = parse_message_type("
+        message inventory {
+            required binary path (STRING);
+            required int64 size;
+            required binary blob_key (STRING);
+            required binary snapshots (STRING);
+        }")?;
+    let strings = |field: &dyn Fn(&InventoryRow) -> String| -> Vec<ByteArray> {
+        rows.iter().map(|row| ByteArray::from(field(row).into_bytes())).collect()
+    };
+    let paths = strings(&|row| row.path.to_str().unwrap().to_string());
+    let sizes: Vec<i64> = rows.iter().map(|row| row.size as i64).collect();
+    let blob_keys = strings(&|row| row.blob_key.clone());
+    let snapshots = strings(&snapshots_field);
+
+    let mut writer = SerializedFileWriter::new(out, Arc::new(schema), Arc::new(WriterProperties::builder().build()))?;
+    let mut row_group = writer.next_row_group()?;
+    for column in [Some(&paths), None, Some(&blob_keys), Some(&snapshots)] {
+        let mut column_writer = row_group.next_column()?.unwrap();
+        match column {
+            Some(values) => column_writer.typed::<ByteArrayType>().write_batch(values, None, None)?,
+            None => column_writer.typed::<Int64Type>().write_batch(&sizes, None, None)?,
+        };
+        column_writer.close()?;
+    }
+    row_group.close()?;
+    writer.close()?;
+    Ok(())
+}
+
+#[cfg(not(feature = "parquet"))]
+fn write_parquet(_rows: &[InventoryRow], _out: impl Write + Send) -> anyhow::Result<()> {
+    anyhow::bail!("This har was built without the parquet feature")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_of_the_versions() -> anyhow::Result<()> {
+        let older = Manifest::from_listing([(PathBuf::from("a/kek"), 3), (PathBuf::from("b,c"), 5)])?;
+        let newer = Manifest::from_listing([(PathBuf::from("a/kek"), 3)])?;
+        let rows = collect(&[(4, newer), (2, older)]);
+        assert_eq!(rows.iter().map(|row| (row.path.to_str().unwrap(), row.snapshots.clone())).collect::<Vec<_>>(),
+            vec![("a/kek", vec![4, 2]), ("b,c", vec![2])]);
+
+        let mut csv = Vec::new();
+        write(&rows, InventoryFormat::Csv, &mut csv)?;
+        let csv = String::from_utf8(csv)?;
+        assert_eq!(csv.lines().collect::<Vec<_>>(), vec![
+            "path,size,blob_key,snapshots".to_string(),
+            format!("a/kek,3,{},4 2", rows[0].blob_key),
+            format!("\"b,c\",5,{},2", rows[1].blob_key),
+        ]);
+        Ok(())
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn parquet_has_the_rows_and_columns() -> anyhow::Result<()> {
+        use parquet::file::reader::{FileReader, SerializedFileReader};
+        let manifest = Manifest::from_listing([(PathBuf::from("a/kek"), 3), (PathBuf::from("chuchu"), 5)])?;
+        let mut written = Vec::new();
+        write(&collect(&[(1, manifest)]), InventoryFormat::Parquet, &mut written)?;
+        let reader = SerializedFileReader::new(bytes::Bytes::from(written))?;
+        assert_eq!(reader.metadata().file_metadata().num_rows(), 2);
+        assert_eq!(reader.metadata().file_metadata().schema_descr().num_columns(), 4);
+        Ok(())
+    }
+}
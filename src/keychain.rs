@@ -0,0 +1,15 @@
+use anyhow::Context;
+
+// keys stored in the platform keychain (Secret Service, macOS Keychain, Windows Credential Manager)
+// entries are (SERVICE, name), the secret is the content of a key file
+const SERVICE: &str = "har_backup";
+
+pub fn store_key(name: &str, key_bytes: &[u8]) -> anyhow::Result<()> {
+    let entry = keyring::Entry::new(SERVICE, name).context("Opening keychain entry")?;
+    entry.set_secret(key_bytes).with_context(|| format!("Storing key {} in keychain", name))
+}
+
+pub fn load_key(name: &str) -> anyhow::Result<Vec<u8>> {
+    let entry = keyring::Entry::new(SERVICE, name).context("Opening keychain entry")?;
+    entry.get_secret().with_context(|| format!("Reading key {} from keychain", name))
+}
@@ -1,10 +1,19 @@
-pub mod blob_storage;
-pub mod blob_storage_local_directory;
-pub mod blob_encryption;
-pub mod manifest;
-pub mod thread_sync;
-pub mod mirror;
 pub mod dot_har;
 pub mod cmd_impl;
-pub mod blob_storage_tasks;
-pub mod blob_storage_s3;
\ No newline at end of file
+pub mod keychain;
+pub mod progress;
+pub mod settings;
+pub mod history;
+pub mod queue;
+pub mod retention;
+pub mod path_pattern;
+pub mod mount;
+pub mod report;
+pub mod completions;
+pub mod exit_code;
+pub mod watch;
+pub mod schedule;
+pub mod inventory;
+pub mod logging;
+pub mod error;
+pub use error::Error;
@@ -0,0 +1,52 @@
+// logs of har on stderr through tracing, as text or as a json object per line (--log-format json) for systemd, CI
+// and log collectors. The log records of har-backup-core get there too (tracing-log), in the spans of push, pull
+// and diff. What har prints for people (say!, listings) is not logs and stays on stdout
+
+use std::io::IsTerminal;
+use anyhow::Context;
+use tracing_subscriber::filter::{EnvFilter, LevelFilter};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> anyhow::Result<Self> {
+        match text {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => anyhow::bail!("Log format {} is not text or json", text),
+        }
+    }
+}
+
+// level (off, error... trace) is over RUST_LOG, which can still set levels per module, errors only by default
+pub fn init(level: Option<&str>, format: LogFormat) -> anyhow::Result<()> {
+    let mut filter = EnvFilter::builder().with_default_directive(LevelFilter::ERROR.into()).from_env_lossy();
+    if let Some(level) = level {
+        filter = filter.add_directive(level.parse::<LevelFilter>().with_context(|| format!("Log level {}", level))?.into());
+    }
+    let builder = tracing_subscriber::fmt().with_env_filter(filter).with_writer(std::io::stderr);
+    let initialized = match format {
+        LogFormat::Text => builder.with_ansi(std::io::stderr().is_terminal()).try_init(),
+        LogFormat::Json => builder.json().try_init(),
+    };
+    initialized.map_err(|err| anyhow::anyhow!(err)).context("Starting the logger")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats() {
+        assert_eq!("json".parse::<LogFormat>().unwrap(), LogFormat::Json);
+        assert_eq!("text".parse::<LogFormat>().unwrap(), LogFormat::Text);
+        assert!("yaml".parse::<LogFormat>().is_err());
+    }
+}
@@ -1,36 +1,186 @@
-use clap::{Parser, Args, Subcommand};
+use clap::{CommandFactory, Parser, Args, Subcommand};
 use anyhow::{Result, Context};
 use std::path::{Path, PathBuf};
-use log::debug;
+use har_backup_core::say;
+use har_backup::settings::Settings;
+use har_backup::report::{self, check_failures, DiffReport, PullReport, PushReport, ResumeReport};
+use har_backup_core::thread_sync::{CancelToken, Cancelled};
 
 #[derive(Parser)]
 struct Cli {
     #[command(subcommand)]
     command: Command,
+    #[arg(long, global=true, value_name="FILE",
+        help="Settings file (toml) over .har/config.toml, values can also be set with HAR_BACKUP__<KEY> environment variables")]
+    config: Option<PathBuf>,
+    #[arg(long, global=true, help="Print how long each phase took (push, pull and diff)")]
+    timings: bool,
+    #[arg(long, global=true, help="Print status lines instead of progress bars during push and pull")]
+    no_progress: bool,
+    #[arg(short, long, global=true, help="Only print errors, warnings and the output of inspection commands (diff, dupes...)")]
+    quiet: bool,
+    #[arg(short, long, global=true, action=clap::ArgAction::Count, conflicts_with="quiet",
+        help="Print everything (over quiet in the settings) and log more, -v info, -vv debug, -vvv trace")]
+    verbose: u8,
+    #[arg(long, global=true, value_name="LEVEL", value_parser=["off", "error", "warn", "info", "debug", "trace"],
+        help="Log level, over RUST_LOG (which can still set levels per module)")]
+    log_level: Option<String>,
+    #[arg(long, global=true, value_name="FORMAT", value_parser=["text", "json"],
+        help="Logs (on stderr) as text or as a json object per line, for systemd, CI and log collectors")]
+    log_format: Option<String>,
+}
+
+impl Cli {
+    // flags have the last word over the config file and the environment
+    fn apply_to(&self, settings: &mut Settings) {
+        if self.timings {
+            settings.timings = true;
+        }
+        if self.no_progress {
+            settings.progress = Some(false);
+        }
+        if self.quiet {
+            settings.quiet = true;
+        }
+        if self.verbose > 0 {
+            settings.quiet = false;
+        }
+        if let Some(log_level) = &self.log_level {
+            settings.log_level = Some(log_level.clone());
+        } else if self.verbose > 0 {
+            let level = ["info", "debug", "trace"][(self.verbose as usize).min(3) - 1];
+            settings.log_level = Some(level.to_string());
+        }
+        if let Some(log_format) = &self.log_format {
+            settings.log_format = Some(log_format.clone());
+        }
+    }
 }
 
 #[derive(Subcommand)]
 enum Command {
     #[command(
         about="Create an encryption key",
-        after_help="The key is used to encrypt/decrypt blobs. It is up to you to store it safely.",
+        after_help="The key is used to encrypt/decrypt blobs and to sign manifests. It is up to you to store it safely.",
     )]
     CreateKey(CreateKey),
+    #[command(
+        about="Write the read only part of a key",
+        after_help="A read only key can fetch and pull (restore) but cannot push to an archive with signed manifests.",
+    )]
+    DeriveReadKey(DeriveReadKey),
+    #[command(
+        about="Salvage what can be decoded from a damaged manifest file",
+        after_help="For example on .har/fetched_manifest after fetching a damaged remote manifest.\n\
+                    Entries whose parent directory was lost are put in lost+found.",
+    )]
+    RecoverManifest(RecoverManifest),
+    #[command(
+        about="Store a key in the OS keychain and use it for this archive",
+        after_help="It replaces the key path in .har/config.toml by keychain://NAME. The key file can then be deleted.",
+    )]
+    KeychainImport(KeychainImport),
+    #[command(
+        about="Print the shell completion script",
+        after_help="For example har completions bash > /etc/bash_completion.d/har.\n\
+                    With bash and fish, paths of the archive are completed from the fetched manifest too.",
+    )]
+    Completions(Completions),
+    #[command(hide=true, about="Print the paths of the fetched manifest starting with PREFIX, for the completion scripts")]
+    CompletePath(CompletePath),
     #[command(
         about="Initialize the local archive directory",
         after_help="It makes the current working directory the archive root.\n\
                     It creates a .har directory containing config/metadata",
     )]
     InitLocal,
+    #[command(
+        about="Make the current working directory an archive of a remote, asking for what is not given",
+        after_help="It writes the remote and the key in .har once the remote could be reached with the key.\n\
+                    REMOTE is fs://PATH, s3://ENDPOINT (the bucket and credentials are then asked for) or\n\
+                    rclone://REMOTE:PATH (a remote of the rclone config, the rclone binary is run for transfers).\n\
+                    A key file that does not exist can be created, KEY can also be keychain://NAME.",
+    )]
+    Init(Init),
+    #[command(
+        about="Make a new directory an archive of a remote and pull all of its files",
+        after_help="REMOTE is as in init, its archive has to exist. DIR is created if needed and has to be empty.\n\
+                    A pull that stops before the end is finished with har resume in DIR.",
+    )]
+    Clone(CloneRemote),
     #[command(
         about="Fetch the remote manifest",
         after_help="It stores the manifest in .har",
     )]
     FetchManifest,
     #[command(about="Print the fetched manifest")]
-    PrintFetchedManifest,
-    #[command(about="Push an empty manifest")]
-    InitRemote,
+    PrintFetchedManifest(PrintFetchedManifest),
+    #[command(
+        about="List a directory of the fetched manifest",
+        after_help="With -l each line has the type (d or -), the size (of everything in it for a directory)\n\
+                    and the blob key of files before the name.",
+    )]
+    Ls(Ls),
+    #[command(
+        about="Write the content of a file of the fetched manifest to stdout",
+        after_help="It is downloaded from the remote and decrypted, nothing is written in the archive.",
+    )]
+    Cat(Cat),
+    #[command(
+        about="Print the size of directories of the fetched manifest",
+        after_help="Each line has the size of the files in the directory (recursively), their unique size (content\n\
+                    stored once in the remote counted once) and the path. Directories down to --depth under PATH are listed.",
+    )]
+    Du(Du),
+    #[command(
+        about="Mount the fetched manifest as a read-only filesystem",
+        after_help="Files are downloaded and decrypted when they are read, chunks of big files only when the part\n\
+                    read is in them. It runs until the mount point is unmounted (fusermount -u) or Ctrl-C.\n\
+                    Needs har built with the mount feature and FUSE.",
+    )]
+    Mount(Mount),
+    #[command(
+        about="Push an empty manifest",
+        after_help="It also pushes the archive metadata (uuid, format versions, description...)",
+    )]
+    InitRemote(InitRemote),
+    #[command(subcommand, about="Inspect/configure the remote")]
+    Remote(RemoteCommand),
+    #[command(subcommand, about="Inspect/configure the key of the archive")]
+    Key(KeyCommand),
+    #[command(
+        about="List files of the fetched manifest that have the same content",
+        after_help="Blobs are stored once in the remote, the wasted space is local only.",
+    )]
+    Dupes,
+    #[command(
+        about="List files of the fetched manifest matching a pattern or pushed since a date",
+        after_help="PATTERN is a glob, matched against names when it has no / (*.jpg) and against paths\n\
+                    otherwise (photos/**/*.jpg). With --regex it is a regex searched for in paths.\n\
+                    Files pushed before the manifest recorded times are not listed by --changed-since.",
+    )]
+    Find(Find),
+    #[command(
+        about="Print statistics of the fetched manifest",
+        after_help="With --graph, also charts the throughput, bytes transferred and archive size of the last pushes and pulls.",
+    )]
+    Stats(StatsArgs),
+    #[command(
+        about="Print the state of the archive on one screen",
+        after_help="The archive root, the remote (without credentials), the key fingerprint, when the manifest was\n\
+                    last fetched, what push and pull would transfer (without hashing) and the run har resume continues.",
+    )]
+    Status(StatusArgs),
+    #[command(
+        about="Retry files that failed too many times on previous push/pull",
+        after_help="Files failing every attempt are quarantined and left out of push/pull until this is run.",
+    )]
+    ClearQuarantine,
+    #[command(
+        about="Set how blobs are compressed before encryption",
+        after_help="Applies to blobs pushed from now on. Formats that are already compressed (images, videos, archives...) are stored as is.",
+    )]
+    Compression(CompressionArgs),
     #[command(
         about="Compare local tree with fetched manifest",
         after_help="Do not forget to fetch before.",
@@ -41,16 +191,618 @@ enum Command {
         after_help="It diffs local tree with fetched remote manifest.\n\
                     It uploads new files, directories and uploads the updated manifest.",
     )]
-    Push,
+    Push(Push),
+    #[command(
+        about="Push the changes of the local tree as they happen, until Ctrl-C",
+        after_help="Changes are pushed once none came for --debounce seconds, the remote manifest is fetched before\n\
+                    each push. A push that fails is tried again after --retry-after seconds or the next change.",
+    )]
+    Watch(Watch),
+    #[command(
+        about="Fetch then push, once or on a schedule",
+        after_help="With --every (or schedule in the settings) it keeps running and backs up at each interval, by the\n\
+                    wall clock: intervals missed while the machine slept make one backup when it wakes up. A backup is\n\
+                    skipped when the local tree has nothing the fetched manifest lacks.",
+    )]
+    Backup(Backup),
+    #[command(
+        about="Write the files of the fetched manifest to a tar file",
+        after_help="Blobs are downloaded and decrypted, the tar needs nothing of har to be read. Paths in the tar are\n\
+                    relative to PATH. It is compressed with zstd with --zstd or when OUTPUT ends with .zst or .tzst.",
+    )]
+    ExportTar(ExportTar),
+    #[command(
+        about="Write the entries of the fetched manifest with their size, hash, mode and time",
+        after_help="For audits and comparisons with other backup tools, nothing is downloaded. The hash is the blob\n\
+                    key (keyed by the bucket name, as diff --hash computes it), under the harkey keyword in mtree.\n\
+                    The manifest has no modes, they are those export-tar gives (0644 files, 0755 directories).",
+    )]
+    ExportListing(ExportListing),
+    #[command(
+        about="Write every file of the manifest versions kept in .har with its blob key and generations",
+        after_help="A row per file and blob: path,size,blob_key,snapshots where snapshots are the generations (as in\n\
+                    har log) of the versions that have it, space separated. Nothing is downloaded. --format parquet\n\
+                    writes the same columns, when har is built with the parquet feature.",
+    )]
+    Inventory(Inventory),
+    #[command(
+        about="Add the files of a tar file to the archive, without unpacking it",
+        after_help="Files are uploaded as they are read from the tar, then added to the remote manifest under --into\n\
+                    (replacing the files it has at the same paths). Links and other special entries are left out.\n\
+                    The tar is read as zstd with --zstd or when TAR ends with .zst or .tzst.",
+    )]
+    ImportTar(ImportTar),
+    #[command(
+        about="Copy files of the archive to another remote",
+        after_help="REMOTE is as in init, a remote without archive is initialized with the key of this one. Blobs are\n\
+                    downloaded and uploaded again, the files keep their paths in the archive of REMOTE (replacing\n\
+                    the files it has there). The remote of this archive stays the same.",
+    )]
+    Copy(CopyTo),
     #[command(
         about="Pull files from remote",
     )]
-    Pull,
+    Pull(Pull),
+    #[command(
+        about="Restore paths of the archive into a directory",
+        after_help="Files are restored at their path in the archive under TARGET, which does not have to be the\n\
+                    archive root. Files that exist in TARGET are kept unless --overwrite or --backup-existing.",
+    )]
+    Restore(Restore),
+    #[command(
+        about="Remove paths from the archive",
+        after_help="They are removed from the remote manifest, local files are left as they are and a push adds\n\
+                    them back. Blobs stay in the remote, --mark-unreferenced lists those no file refers to anymore\n\
+                    in .har/gc_candidates.",
+    )]
+    Rm(Rm),
+    #[command(
+        about="Rename or move a path inside the archive",
+        after_help="Only the remote manifest changes, nothing is transferred. Like mv, FROM goes into TO when TO is\n\
+                    a directory, missing parent directories are added. Move the local files too, or push adds them\n\
+                    back at FROM.",
+    )]
+    Mv(Mv),
+    #[command(
+        about="Resume the push or pull that stopped before the end",
+        after_help="Push and pull keep the files left to transfer in .har/queue until they complete.\n\
+                    Resume transfers these files only, with the options of the run that stopped.",
+    )]
+    Resume(Resume),
+    #[command(
+        about="Roll the fetched manifest back to one that push replaced",
+        after_help="Without N it lists the backups kept in .har (manifest_backups in the settings, 10 by default).\n\
+                    With --push the remote manifest is rolled back too, to undo a bad push.",
+    )]
+    RestoreManifest(RestoreManifest),
+    #[command(
+        about="List the versions of the manifest kept in .har",
+        after_help="The fetched manifest and the ones push replaced, with when and from which host they were written\n\
+                    and the files added, changed and deleted since the version before. Roll back to one with\n\
+                    restore-manifest N.",
+    )]
+    Log,
+    #[command(
+        about="Remove the manifest backups that a retention policy does not keep",
+        after_help="The most recent backup of each of the last N days, weeks (from monday) and months is kept,\n\
+                    a backup can be kept for several of them. Backups are listed by restore-manifest.",
+    )]
+    Prune(Prune),
+    #[command(
+        about="Release the lock that push and init-remote take on the remote",
+        after_help="For a push that died with it. Without --force it only prints who has the lock.",
+    )]
+    Unlock(Unlock),
+    #[command(
+        about="Export files added/changed since a snapshot",
+        after_help="The snapshot is a manifest file, for example .har/fetched_manifest.backup.\n\
+                    Deleted paths are listed in the .har_export.json metadata file of the export.",
+    )]
+    Export(Export),
+    #[command(
+        about="Check that the remote still has the blobs of the fetched manifest",
+        after_help="With --sample N or --all blobs are also downloaded, decrypted and hashed, corrupt ones are listed.\n\
+                    With --remote-only the remote listing is compared with the sizes recorded in the remote manifest,\n\
+                    missing and truncated blobs are found without downloading them.",
+    )]
+    Verify(Verify),
+    #[command(
+        about="Check the structure of the fetched manifest",
+        after_help="Finds entries listed twice or nowhere, listings of entries that do not exist, names that are the\n\
+                    same once normalized and files without a blob. With --repair the fetched manifest is replaced by\n\
+                    a repaired one (see restore-manifest to go back), with --push the remote manifest is replaced too.",
+    )]
+    Fsck(Fsck),
+}
+
+#[derive(Subcommand)]
+enum RemoteCommand {
+    #[command(about="Print the remote of this archive, without credentials")]
+    Show,
+    #[command(
+        about="Replace the remote of this archive",
+        after_help="REMOTE is as in init: fs://PATH, s3://ENDPOINT (the bucket and credentials are then asked for) or\n\
+                    rclone://REMOTE:PATH.\n\
+                    Nothing is transferred, fetch the manifest again if the new remote is another archive.",
+    )]
+    Set(SetRemote),
+    #[command(
+        about="Check that the remote can be reached",
+        after_help="Each remote (each one of a multi:// remote) is asked for a key that does not exist and listed,\n\
+                    with how long it took. The key is not needed.",
+    )]
+    Test,
+    #[command(about="Print the archive metadata stored in the remote")]
+    Info,
+    #[command(
+        about="Set a value in the archive metadata stored in the remote",
+        after_help="KEY is either description or a free form key.",
+    )]
+    SetMeta(SetMeta),
+    #[command(
+        about="Sign the manifest and require it to be signed from now on",
+        after_help="Needs a full key (see create-key), read only keys are then unable to push.",
+    )]
+    EnableSigning,
+}
+
+#[derive(Subcommand)]
+enum KeyCommand {
+    #[command(
+        about="Use another key file for this archive",
+        after_help="The file is loaded before .har refers to it. See keychain-import for keys in the OS keychain.",
+    )]
+    SetPath(SetKeyPath),
+    #[command(about="Print the fingerprint of the key, the remote keeps the fingerprint of the archive key")]
+    Fingerprint,
+    #[command(
+        about="Check that the key is the key of the archive",
+        after_help="The fingerprints are compared and the remote manifest is downloaded and decrypted.",
+    )]
+    Check,
+}
+
+#[derive(Args, Debug)]
+struct SetKeyPath {
+    path: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct SetRemote {
+    remote: String,
+}
+
+#[derive(Args, Debug)]
+struct InitRemote {
+    #[arg(long, default_value="", help="Description stored in the archive metadata")]
+    description: String,
+}
+
+#[derive(Args, Debug)]
+struct SetMeta {
+    key: String,
+    value: String,
 }
 
 #[derive(Args, Debug)]
 struct CreateKey {
     path: PathBuf,
+    #[arg(long, help="Protect the key file with a passphrase, asked for whenever the key is loaded")]
+    passphrase: bool,
+}
+
+#[derive(Args, Debug)]
+struct Completions {
+    shell: clap_complete::Shell,
+}
+
+#[derive(Args, Debug)]
+struct CompletePath {
+    #[arg(default_value="")]
+    prefix: String,
+}
+
+#[derive(Args, Debug)]
+struct Init {
+    #[arg(long, value_name="REMOTE", help="Remote spec (fs://PATH, s3://ENDPOINT, rclone://REMOTE:PATH...), asked for if not given")]
+    remote: Option<String>,
+    #[arg(long, value_name="KEY", help="Key file or keychain://NAME, asked for if not given")]
+    key: Option<String>,
+    #[arg(long, help="Create the key file at KEY")]
+    create_key: bool,
+    #[arg(long, help="Protect the created key file with a passphrase")]
+    passphrase: bool,
+}
+
+#[derive(Args, Debug)]
+struct CloneRemote {
+    remote: String,
+    dir: PathBuf,
+    #[arg(long, value_name="KEY", help="Key file or keychain://NAME, asked for if not given")]
+    key: Option<String>,
+    #[arg(long, help="Leave out files that can't be downloaded, list them at the end and fail then")]
+    keep_going: bool,
+    #[command(flatten)]
+    transfer: TransferArgs,
+}
+
+#[derive(Args, Debug)]
+struct KeychainImport {
+    key_path: PathBuf,
+    #[arg(long, default_value="default", help="Name of the keychain entry")]
+    name: String,
+}
+
+#[derive(Args, Debug)]
+struct CompressionArgs {
+    #[arg(help="zstd level (1 to 22)", required_unless_present="off", value_parser=clap::value_parser!(i32).range(1..=22))]
+    level: Option<i32>,
+    #[arg(long, conflicts_with="level", help="Store blobs uncompressed")]
+    off: bool,
+}
+
+#[derive(Args, Debug)]
+struct RecoverManifest {
+    input_path: PathBuf,
+    output_path: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct DeriveReadKey {
+    key_path: PathBuf,
+    output_path: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct PrintFetchedManifest {
+    #[arg(long, required=false, help="Print the manifest as json instead of a tree")]
+    json: bool,
+    #[arg(long, help="Do not print entries deeper than this (1 is the content of the archive root)")]
+    depth: Option<usize>,
+    #[arg(long, required=false, help="Print the blob key of files")]
+    hashes: bool,
+    #[arg(long, required=false, help="Do not print the size of files")]
+    no_sizes: bool,
+}
+
+#[derive(Args, Debug)]
+struct Ls {
+    #[arg(default_value="", hide_default_value=true, help="Directory to list (relative to the archive root), the root by default")]
+    path: PathBuf,
+    #[arg(short, long, help="Print the type, size and blob key of entries")]
+    long: bool,
+}
+
+#[derive(Args, Debug)]
+struct Cat {
+    #[arg(help="File to write (relative to the archive root)")]
+    path: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct Rm {
+    #[arg(required=true, help="Files or directories to remove (relative to the archive root)")]
+    paths: Vec<PathBuf>,
+    #[arg(long, help="Record the blobs that no file refers to anymore for garbage collection")]
+    mark_unreferenced: bool,
+}
+
+#[derive(Args, Debug)]
+struct Mv {
+    #[arg(help="File or directory to move (relative to the archive root)")]
+    from: PathBuf,
+    #[arg(help="New path, or a directory to move it into")]
+    to: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct Du {
+    #[arg(default_value="", hide_default_value=true, help="Directory (relative to the archive root), the root by default")]
+    path: PathBuf,
+    #[arg(long, default_value_t=1, help="How many levels of directories under PATH to list (0 for PATH only)")]
+    depth: usize,
+}
+
+#[derive(Args, Debug)]
+struct Mount {
+    mountpoint: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct StatusArgs {
+    #[arg(long, help="Print one JSON object instead, its fields are only ever added to (format_version changes otherwise)")]
+    json: bool,
+}
+
+#[derive(Args, Debug)]
+struct StatsArgs {
+    #[arg(long, help="Chart the last pushes and pulls (kept in .har/history)")]
+    graph: bool,
+}
+
+#[derive(Args, Debug)]
+struct Find {
+    #[arg(required_unless_present="changed_since", conflicts_with="changed_since", help="Glob (or regex) of the files to list")]
+    pattern: Option<String>,
+    #[arg(long, requires="pattern", help="PATTERN is a regex")]
+    regex: bool,
+    #[arg(long, value_name="DATE", help="UTC date (2024-05-01) or time (2024-05-01T12:00:00Z), files added or changed at or after it are listed")]
+    changed_since: Option<String>,
+}
+
+#[derive(Args, Debug)]
+struct ScanArgs {
+    #[arg(long, value_enum,
+        help="What to do with cloud placeholder files (OneDrive/Dropbox/iCloud files not downloaded locally) [default: skip]")]
+    placeholders: Option<PlaceholderPolicyArg>,
+    #[arg(long, value_name="PATH", conflicts_with="watchman",
+        help="Take the local tree from a listing instead of walking it (.csv of path,size or json lines of {\"path\", \"size\"})")]
+    listing: Option<PathBuf>,
+    #[arg(long, help="Take the local tree from a watchman query instead of walking it")]
+    watchman: bool,
+}
+
+// over the [transfer] table of the settings
+#[derive(Args, Debug)]
+struct TransferArgs {
+    #[arg(long, value_name="N", value_parser=clap::value_parser!(u64).range(1..),
+        help="Transfers in flight at once [default: 32, transfer.active_tasks_limit in the settings]")]
+    jobs: Option<u64>,
+    #[arg(long, value_name="BYTES",
+        help="Bytes in flight before waiting for transfers to finish [default: 10000000, transfer.active_size_limit in the settings]")]
+    max_inflight_bytes: Option<usize>,
+}
+
+impl TransferArgs {
+    fn apply_to(&self, settings: &mut Settings) {
+        if let Some(jobs) = self.jobs {
+            settings.transfer.active_tasks_limit = Some(jobs as usize);
+        }
+        if let Some(max_inflight_bytes) = self.max_inflight_bytes {
+            settings.transfer.active_size_limit = Some(max_inflight_bytes);
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+enum PlaceholderPolicyArg {
+    Skip,
+    Hydrate,
+    Error,
+}
+
+impl ScanArgs {
+    fn apply_to(&self, settings: &mut Settings) {
+        use har_backup_core::scan::PlaceholderPolicy;
+        if let Some(placeholders) = &self.placeholders {
+            settings.scan.placeholders = match placeholders {
+                PlaceholderPolicyArg::Skip => PlaceholderPolicy::Skip,
+                PlaceholderPolicyArg::Hydrate => PlaceholderPolicy::Hydrate,
+                PlaceholderPolicyArg::Error => PlaceholderPolicy::Error,
+            };
+        }
+        if let Some(listing) = &self.listing {
+            settings.scan.listing = Some(listing.clone());
+            settings.scan.watchman = false;
+        }
+        if self.watchman {
+            settings.scan.listing = None;
+            settings.scan.watchman = true;
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+struct Push {
+    #[arg(help="Only push this path (relative to the archive root)")]
+    path: Option<PathBuf>,
+    #[command(flatten)]
+    scan: ScanArgs,
+    #[command(flatten)]
+    transfer: TransferArgs,
+    #[arg(long, help="Push even if the local tree is much smaller than the remote (see push.shrink_guard in the settings)")]
+    force: bool,
+    #[arg(long, help="Leave out files that can't be read or uploaded, list them at the end and fail then")]
+    keep_going: bool,
+    #[arg(long, help="Also push files that the remote has with other content (they are hashed to tell)")]
+    hash: bool,
+    #[arg(long, help="Upload nothing, record the files whose content the remote already has (they are hashed to tell)")]
+    adopt: bool,
+    #[arg(long, help="Fetch the remote manifest first (auto_fetch in the settings)")]
+    fetch: bool,
+}
+
+#[derive(Args, Debug)]
+struct Pull {
+    #[arg(conflicts_with="to_stdout_tar", help="Only pull this path (relative to the archive root)")]
+    path: Option<PathBuf>,
+    #[arg(long, value_name="PATH", num_args=0..=1, default_missing_value="",
+        help="Write the files under PATH (default: everything) to stdout as a tar stream instead of the archive root")]
+    to_stdout_tar: Option<PathBuf>,
+    #[arg(long, conflicts_with="to_stdout_tar",
+        help="Mirror the remote: local files and dirs that the remote does not have are moved to the trash")]
+    delete: bool,
+    #[arg(long, requires="delete", help="With --delete, delete for good instead of moving to the trash")]
+    permanent: bool,
+    #[arg(long, group="existing", help="Keep local files that exist in the remote with other content (default)")]
+    skip_existing: bool,
+    #[arg(long, group="existing", conflicts_with="to_stdout_tar", help="Replace local files that differ from the remote (they are hashed to tell)")]
+    overwrite: bool,
+    #[arg(long, group="existing", conflicts_with="to_stdout_tar",
+        help="Like --overwrite, but rename the local files to NAME.har-backup first")]
+    backup_existing: bool,
+    #[arg(long, conflicts_with="to_stdout_tar", help="Keep pulling past files that fail, list them at the end and fail then")]
+    keep_going: bool,
+    #[command(flatten)]
+    transfer: TransferArgs,
+}
+
+#[derive(Args, Debug)]
+struct Restore {
+    #[arg(required=true, help="Paths to restore (relative to the archive root)")]
+    paths: Vec<PathBuf>,
+    #[arg(long, help="Directory to restore into")]
+    target: PathBuf,
+    #[arg(long, group="existing", help="Replace files that exist in the target")]
+    overwrite: bool,
+    #[arg(long, group="existing", help="Like --overwrite, but rename the existing files to NAME.har-backup first")]
+    backup_existing: bool,
+    #[command(flatten)]
+    transfer: TransferArgs,
+}
+
+#[derive(Args, Debug)]
+struct RestoreManifest {
+    #[arg(value_parser=clap::value_parser!(u64).range(1..), help="Backup to restore, 1 is the most recent")]
+    n: Option<u64>,
+    #[arg(long, requires="n", help="Also replace the remote manifest with it")]
+    push: bool,
+}
+
+#[derive(Args, Debug)]
+#[command(group(clap::ArgGroup::new("keep").required(true).multiple(true)))]
+struct Prune {
+    #[arg(long, value_name="N", default_value_t=0, group="keep", help="Keep the last backup of each of the last N days")]
+    keep_daily: usize,
+    #[arg(long, value_name="N", default_value_t=0, group="keep", help="Keep the last backup of each of the last N weeks")]
+    keep_weekly: usize,
+    #[arg(long, value_name="N", default_value_t=0, group="keep", help="Keep the last backup of each of the last N months")]
+    keep_monthly: usize,
+}
+
+#[derive(Args, Debug)]
+struct Unlock {
+    #[arg(long, help="Release the lock even though its holder may still be pushing")]
+    force: bool,
+}
+
+#[derive(Args, Debug)]
+struct Watch {
+    #[arg(long, value_name="SECONDS", default_value_t=10, help="Wait for changes to stop for this long before pushing")]
+    debounce: u64,
+    #[arg(long, value_name="SECONDS", default_value_t=300, help="Try a failed push again after this long, and probe the remote this often")]
+    retry_after: u64,
+    #[arg(long, help="Leave out files that can't be read or uploaded, they are tried again by the next push")]
+    keep_going: bool,
+    #[command(flatten)]
+    scan: ScanArgs,
+    #[command(flatten)]
+    transfer: TransferArgs,
+}
+
+#[derive(Args, Debug)]
+struct Backup {
+    #[arg(long, value_name="INTERVAL", help="Back up at this interval until Ctrl-C, like 6h, 30m or 1d12h (over schedule in the settings)")]
+    every: Option<String>,
+    #[arg(long, help="Leave out files that can't be read or uploaded, they are tried again by the next backup")]
+    keep_going: bool,
+    #[command(flatten)]
+    scan: ScanArgs,
+    #[command(flatten)]
+    transfer: TransferArgs,
+}
+
+#[derive(Args, Debug)]
+struct ExportTar {
+    #[arg(help="Only export this path (relative to the archive root)")]
+    path: Option<PathBuf>,
+    #[arg(short, long, help="Tar file to write, - for stdout")]
+    output: PathBuf,
+    #[arg(long, help="Compress with zstd")]
+    zstd: bool,
+}
+
+#[derive(Args, Debug)]
+struct ExportListing {
+    #[arg(help="Only list this path (relative to the archive root)")]
+    path: Option<PathBuf>,
+    #[arg(long, value_enum, default_value_t=ListingFormatArg::Mtree)]
+    format: ListingFormatArg,
+    #[arg(short, long, help="File to write instead of stdout")]
+    output: Option<PathBuf>,
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+enum ListingFormatArg {
+    Mtree,
+    Csv,
+}
+
+#[derive(Args, Debug)]
+struct Inventory {
+    #[arg(long, value_enum, default_value_t=InventoryFormatArg::Csv)]
+    format: InventoryFormatArg,
+    #[arg(short, long, help="File to write instead of stdout")]
+    output: Option<PathBuf>,
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+enum InventoryFormatArg {
+    Csv,
+    Parquet,
+}
+
+#[derive(Args, Debug)]
+struct CopyTo {
+    #[arg(help="Only copy this path (relative to the archive root)")]
+    path: Option<PathBuf>,
+    #[arg(long, value_name="REMOTE", help="Remote spec to copy to (fs://PATH, s3://ENDPOINT, rclone://REMOTE:PATH...)")]
+    to: String,
+    #[arg(long, help="Fetch the remote manifest first (auto_fetch in the settings)")]
+    fetch: bool,
+}
+
+#[derive(Args, Debug)]
+struct ImportTar {
+    #[arg(help="Tar file to read, - for stdin")]
+    tar: PathBuf,
+    #[arg(long, value_name="PATH", help="Directory of the archive to add the files to (default: the archive root)")]
+    into: Option<PathBuf>,
+    #[arg(long, help="Decompress with zstd")]
+    zstd: bool,
+    #[arg(long, help="Fetch the remote manifest first (auto_fetch in the settings)")]
+    fetch: bool,
+}
+
+#[derive(Args, Debug)]
+struct Resume {
+    #[command(flatten)]
+    transfer: TransferArgs,
+}
+
+#[derive(Args, Debug)]
+struct Verify {
+    #[arg(long, conflicts_with_all=["sample", "all"], help="Compare object sizes listed by the remote with the manifest instead of downloading blobs")]
+    remote_only: bool,
+    #[arg(long, value_name="N", conflicts_with="all", help="Also download N blobs at random and check their content")]
+    sample: Option<usize>,
+    #[arg(long, help="Also download every blob and check its content")]
+    all: bool,
+    #[arg(long, help="Print one JSON object instead, its fields are only ever added to (format_version changes otherwise)")]
+    json: bool,
+}
+
+#[derive(Args, Debug)]
+struct Fsck {
+    #[arg(long, help="Replace the fetched manifest with a repaired one")]
+    repair: bool,
+    #[arg(long, requires="repair", help="Also replace the remote manifest with it")]
+    push: bool,
+}
+
+#[derive(Args, Debug)]
+struct Export {
+    #[arg(long, help="Manifest file to compare the fetched manifest with")]
+    since: PathBuf,
+    #[arg(long, value_enum, default_value_t=ExportFormatArg::Tar)]
+    format: ExportFormatArg,
+    output: PathBuf,
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+enum ExportFormatArg {
+    Tar,
+    Dir,
 }
 
 #[derive(Args, Debug)]
@@ -59,26 +811,833 @@ struct Diff {
     remote: bool,
     #[arg(long, required=false, help="Rehash local files to check if they are same as in remote")]
     hash: bool,
+    #[arg(long, help="Fetch the remote manifest first (auto_fetch in the settings)")]
+    fetch: bool,
+    #[arg(long, help="Print one JSON object instead, its fields are only ever added to (format_version changes otherwise)")]
+    json: bool,
+    #[command(flatten)]
+    scan: ScanArgs,
+}
+
+// RUST_LOG as before, the log level setting replaces its default level
+fn init_logger(settings: &Settings) -> Result<()> {
+    use har_backup::logging::{self, LogFormat};
+    let level = match &settings.log_level {
+        Some(level) => Some(level.as_str()),
+        None if settings.quiet => Some("error"),
+        None => None,
+    };
+    let format = settings.log_format.as_deref().map(str::parse::<LogFormat>).transpose()?.unwrap_or_default();
+    logging::init(level, format)
+}
+
+// the error is printed as returning it from main would, the exit code is by what went wrong (see exit_code)
+fn main() {
+    use har_backup::exit_code;
+    let code = match run() {
+        Ok(code) => code,
+        Err(err) => {
+            if err.is::<Cancelled>() {
+                say!(Interrupted);
+            } else {
+                eprintln!("Error: {:?}", err);
+            }
+            exit_code::of_error(&err)
+        },
+    };
+    std::process::exit(code);
 }
 
-fn main() -> Result<()> {
+fn run() -> Result<i32> {
 
-    use har_backup::cmd_impl::{WithLocal, WithRemoteAndLocal};
+    use har_backup::cmd_impl::{LocalDeletion, VerifyDownload, WithLocal, WithRemoteAndLocal};
+    use har_backup::retention::RetentionPolicy;
+    use har_backup::path_pattern::PathPattern;
+    use har_backup::exit_code::{self, ConfigError};
 
-    env_logger::init();
-    let cli = Cli::parse();
-    match cli.command {
-        Command::CreateKey(sub_cli) => create_key(&sub_cli.path),
+    // clap would exit with 2 on a usage error, which is TRANSFER_ERRORS here
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(err) => {
+            err.print()?;
+            return Ok(if err.use_stderr() { exit_code::CONFIG_ERROR } else { exit_code::CLEAN });
+        },
+    };
+    // outside of an archive (create-key, init-local...) there is only the --config file
+    let archive_config = har_backup::dot_har::DotHar::find_cwd_or_ancestor().ok().map(|dot_har| dot_har.config_path());
+    let mut settings = Settings::load(archive_config.as_deref(), cli.config.as_deref()).context(ConfigError)?;
+    cli.apply_to(&mut settings);
+    init_logger(&settings).context(ConfigError)?;
+    har_backup_core::messages::set_quiet(settings.quiet);
+    let cancel = cancel_on_ctrl_c()?;
+    let mut differs = false;
+    let result = match cli.command {
+        Command::CreateKey(sub_cli) => create_key(&sub_cli.path, sub_cli.passphrase),
+        Command::DeriveReadKey(sub_cli) => derive_read_key(&sub_cli.key_path, &sub_cli.output_path),
+        Command::RecoverManifest(sub_cli) => recover_manifest(&sub_cli.input_path, &sub_cli.output_path),
+        Command::Completions(sub_cli) => har_backup::completions::write_completions(&mut Cli::command(), env!("CARGO_BIN_NAME"), sub_cli.shell, &mut std::io::stdout().lock()),
+        Command::CompletePath(sub_cli) => {
+            if har_backup::dot_har::DotHar::find_cwd_or_ancestor().is_ok() {
+                for completion in WithLocal::new_with_settings(&settings)?.path_completions(&sub_cli.prefix) {
+                    println!("{}", completion);
+                }
+            }
+            Ok(())
+        },
         Command::InitLocal => init_local(),
-        Command::FetchManifest => WithRemoteAndLocal::new()?.fetch_manifest(),
-        Command::InitRemote => WithRemoteAndLocal::new()?.init_remote(),
-        Command::PrintFetchedManifest => WithLocal::new()?.print_fetched_manifest(),
-        Command::Diff(sub_cli) => WithLocal::new()?.diff(sub_cli.remote, sub_cli.hash),
-        Command::Push => WithRemoteAndLocal::new()?.push(),
-        Command::Pull => WithRemoteAndLocal::new()?.pull(),
+        Command::Init(sub_cli) => init(sub_cli, &settings),
+        Command::Clone(sub_cli) => {
+            sub_cli.transfer.apply_to(&mut settings);
+            clone(sub_cli, &settings, cancel.clone())
+        },
+        Command::KeychainImport(sub_cli) => {
+            WithLocal::new_with_settings(&settings)?.import_key_to_keychain(&sub_cli.key_path, &sub_cli.name)?;
+            say!(KeyStoredInKeychain, sub_cli.name);
+            Ok(())
+        },
+        Command::FetchManifest => {
+            let generation = WithRemoteAndLocal::new_with_settings(&settings)?.fetch_manifest()?;
+            say!(ManifestFetched, generation);
+            Ok(())
+        },
+        Command::InitRemote(sub_cli) => {
+            use har_backup_core::mirror::InitOutcome;
+            match WithRemoteAndLocal::new_with_settings(&settings)?.init_remote_with_description(&sub_cli.description)? {
+                (InitOutcome::Created, archive_uuid) => say!(RemoteInitialized, archive_uuid),
+                (InitOutcome::AlreadyInitialized, archive_uuid) => say!(RemoteAlreadyInitialized, archive_uuid),
+            }
+            Ok(())
+        },
+        Command::Remote(RemoteCommand::Show) => {
+            say!(RemoteSpecLine, WithLocal::new_with_settings(&settings)?.remote_spec()?.redacted());
+            Ok(())
+        },
+        Command::Remote(RemoteCommand::Set(sub_cli)) => {
+            let remote_spec = WithLocal::new_with_settings(&settings)?.set_remote(&complete_remote_spec(sub_cli.remote)?)?;
+            say!(RemoteSet, remote_spec.redacted());
+            Ok(())
+        },
+        Command::Remote(RemoteCommand::Test) => {
+            let tests = WithLocal::new_with_settings(&settings)?.test_remote()?;
+            for test in &tests {
+                match &test.probe {
+                    Ok((exists_ms, num_objects, list_ms)) => say!(RemoteTestOk, test.name, exists_ms, num_objects, list_ms),
+                    Err(reason) => say!(RemoteTestFailed, test.name, reason),
+                }
+            }
+            match tests.iter().filter(|test| test.probe.is_err()).count() {
+                0 => Ok(()),
+                num_failed => anyhow::bail!("{} remotes could not be reached", num_failed),
+            }
+        },
+        Command::Key(KeyCommand::SetPath(sub_cli)) => {
+            let (key_path, key_file) = WithLocal::new_with_settings(&settings)?.set_key_path(&sub_cli.path)?;
+            say!(KeyPathSet, key_path.to_str().unwrap(), &key_file.fingerprint()[..16]);
+            Ok(())
+        },
+        Command::Key(KeyCommand::Fingerprint) => {
+            let (key_spec, key_file) = WithLocal::new_with_settings(&settings)?.key()?;
+            say!(KeyFingerprint, key_spec, key_file.fingerprint(), if key_file.is_read_only() { "read only" } else { "full" });
+            Ok(())
+        },
+        Command::Key(KeyCommand::Check) => {
+            let checked = WithRemoteAndLocal::new_with_settings(&settings)?.check_key()?;
+            say!(KeyCheckOk, checked.num_files, if checked.read_only { "read only" } else { "full" });
+            Ok(())
+        },
+        Command::Remote(RemoteCommand::Info) => {
+            match WithRemoteAndLocal::new_with_settings(&settings)?.archive_metadata()? {
+                Some(metadata) => print!("{}", metadata),
+                None => say!(NoArchiveMetadata),
+            }
+            Ok(())
+        },
+        Command::Remote(RemoteCommand::SetMeta(sub_cli)) => {
+            WithRemoteAndLocal::new_with_settings(&settings)?.set_archive_metadata_value(&sub_cli.key, &sub_cli.value)?;
+            say!(ArchiveMetadataUpdated);
+            Ok(())
+        },
+        Command::Remote(RemoteCommand::EnableSigning) => {
+            WithRemoteAndLocal::new_with_settings(&settings)?.enable_signing()?;
+            say!(SigningEnabled);
+            Ok(())
+        },
+        Command::PrintFetchedManifest(sub_cli) => {
+            let tree_format = har_backup_core::manifest::TreeFormat {
+                show_size: !sub_cli.no_sizes,
+                show_hash: sub_cli.hashes,
+                max_depth: sub_cli.depth,
+            };
+            let (fetched_manifest, generation) = WithLocal::new_with_settings(&settings)?.fetched_manifest()?;
+            if sub_cli.json {
+                println!("{}", fetched_manifest.to_json()?);
+            } else {
+                print!("{}", fetched_manifest.get_stats());
+                say!(ManifestGeneration, generation);
+                har_backup_core::manifest::print_tree_with_format(&fetched_manifest, &tree_format);
+            }
+            Ok(())
+        },
+        Command::Ls(sub_cli) => WithLocal::new_with_settings(&settings)?.write_listing(&sub_cli.path, sub_cli.long, std::io::stdout().lock()),
+        Command::Cat(sub_cli) => WithRemoteAndLocal::new_with_settings(&settings)?.cat(&sub_cli.path, std::io::stdout().lock()),
+        Command::Dupes => {
+            let groups = WithLocal::new_with_settings(&settings)?.duplicates()?;
+            for group in &groups {
+                say!(DuplicateGroup, group.size, group.paths.len(), group.blob_key);
+                for path in &group.paths {
+                    println!("  {}", path.to_str().unwrap());
+                }
+            }
+            say!(DuplicatesSummary, groups.len(), groups.iter().map(|group| group.wasted_size()).sum::<u64>());
+            Ok(())
+        },
+        Command::Status(sub_cli) => {
+            set_quiet_for_json(sub_cli.json);
+            let status = WithLocal::new_with_settings(&settings)?.status()?;
+            match sub_cli.json {
+                true => report::print(&status),
+                false => {
+                    say_status_report(&status);
+                    Ok(())
+                },
+            }
+        },
+        Command::Stats(sub_cli) => {
+            let cmd = WithLocal::new_with_settings(&settings)?;
+            print!("{}", cmd.stats()?);
+            if sub_cli.graph {
+                let runs = cmd.run_history()?;
+                if runs.is_empty() {
+                    say!(NoRunHistory);
+                }
+                har_backup::history::write_graphs(&runs, har_backup::history::DEFAULT_GRAPH_WIDTH, &mut std::io::stdout().lock())?;
+            }
+            Ok(())
+        },
+        Command::Find(sub_cli) => match (sub_cli.pattern, sub_cli.changed_since) {
+            (Some(pattern), _) => {
+                let pattern = match sub_cli.regex {
+                    true => PathPattern::regex(&pattern)?,
+                    false => PathPattern::glob(&pattern)?,
+                };
+                let found = WithLocal::new_with_settings(&settings)?.find(&pattern)?;
+                for (path, size, blob_key) in &found {
+                    say!(FoundFile, path.to_str().unwrap(), size, blob_key);
+                }
+                say!(FoundFilesSummary, found.len(), indicatif::HumanBytes(found.iter().map(|(_, size, _)| size).sum()));
+                Ok(())
+            },
+            (None, Some(changed_since)) => {
+                use har_backup_core::clock::format_utc_timestamp;
+                let since = har_backup_core::clock::parse_utc_timestamp(&changed_since)?;
+                let since = since.duration_since(std::time::UNIX_EPOCH).map_or(0, |since_epoch| since_epoch.as_secs());
+                let files = WithLocal::new_with_settings(&settings)?.changed_since(since)?;
+                for (path, times) in &files {
+                    say!(ChangedFile, format_utc_timestamp(times.modified), path.to_str().unwrap());
+                }
+                say!(ChangedFilesSummary, files.len(), format_utc_timestamp(since));
+                Ok(())
+            },
+            (None, None) => unreachable!("clap requires one"),
+        },
+        Command::ClearQuarantine => {
+            say!(QuarantineCleared, WithLocal::new_with_settings(&settings)?.clear_quarantine()?);
+            Ok(())
+        },
+        Command::Compression(sub_cli) => {
+            WithLocal::new_with_settings(&settings)?.set_compression(sub_cli.level)?;
+            match sub_cli.level {
+                Some(level) => say!(CompressionSet, level),
+                None => say!(CompressionOff),
+            }
+            Ok(())
+        },
+        Command::Diff(sub_cli) => {
+            sub_cli.scan.apply_to(&mut settings);
+            set_quiet_for_json(sub_cli.json);
+            if sub_cli.fetch || settings.auto_fetch {
+                let generation = WithRemoteAndLocal::new_with_settings(&settings)?.fetch_manifest()?;
+                say!(ManifestFetched, generation);
+            }
+            let diff = WithLocal::new_with_settings(&settings)?.diff(sub_cli.remote, sub_cli.hash)?;
+            differs = diff.differs();
+            say_diff_report(&diff, sub_cli.json)
+        },
+        Command::Push(sub_cli) => {
+            sub_cli.scan.apply_to(&mut settings);
+            settings.auto_fetch |= sub_cli.fetch;
+            sub_cli.transfer.apply_to(&mut settings);
+            WithRemoteAndLocal::new_with_settings(&settings)?
+                .with_scope(sub_cli.path)
+                .with_force(sub_cli.force)
+                .with_keep_going(sub_cli.keep_going)
+                .with_hash_check(sub_cli.hash)
+                .with_adopt(sub_cli.adopt)
+                .with_cancel(cancel.clone())
+                .push()
+                .and_then(|pushed| {
+                    say_push_report(&pushed);
+                    check_failures(&pushed.failures)
+                })
+        },
+        Command::Watch(sub_cli) => {
+            use std::time::Duration;
+            sub_cli.scan.apply_to(&mut settings);
+            sub_cli.transfer.apply_to(&mut settings);
+            WithRemoteAndLocal::new_with_settings(&settings)?
+                .with_keep_going(sub_cli.keep_going)
+                .with_cancel(cancel.clone())
+                .watch(Duration::from_secs(sub_cli.debounce), Duration::from_secs(sub_cli.retry_after), say_watch_event)
+        },
+        Command::Backup(sub_cli) => {
+            sub_cli.scan.apply_to(&mut settings);
+            sub_cli.transfer.apply_to(&mut settings);
+            let every = match sub_cli.every.or(settings.schedule.clone()) {
+                Some(every) => Some(har_backup::schedule::parse_interval(&every).context(ConfigError)?),
+                None => None,
+            };
+            let mut cmd = WithRemoteAndLocal::new_with_settings(&settings)?
+                .with_keep_going(sub_cli.keep_going)
+                .with_cancel(cancel.clone());
+            match every {
+                Some(every) => cmd.backup_every(every, say_backup_event),
+                None => cmd.backup().and_then(|pushed| {
+                    say_backup_report(pushed.as_ref());
+                    pushed.map_or(Ok(()), |pushed| check_failures(&pushed.failures))
+                }),
+            }
+        },
+        Command::Copy(sub_cli) => {
+            settings.auto_fetch |= sub_cli.fetch;
+            let to = complete_remote_spec(sub_cli.to)?;
+            let num_files = WithRemoteAndLocal::new_with_settings(&settings)?.with_cancel(cancel.clone()).copy_to(&to, &sub_cli.path.unwrap_or_default(), &settings)?;
+            say!(CopyDone, num_files, har_backup::dot_har::RemoteSpec::parse(&to)?.redacted());
+            Ok(())
+        },
+        Command::ImportTar(sub_cli) => {
+            let compress = sub_cli.zstd || is_zstd_path(&sub_cli.tar);
+            settings.auto_fetch |= sub_cli.fetch;
+            let into = sub_cli.into.unwrap_or_default();
+            let (num_files, num_left_out) = WithRemoteAndLocal::new_with_settings(&settings)?.with_cancel(cancel.clone()).import_tar(&sub_cli.tar, &into, compress)?;
+            say!(RemoteManifestUpdated);
+            say!(TarImported, num_files, into.to_str().unwrap(), num_left_out);
+            Ok(())
+        },
+        Command::ExportTar(sub_cli) => {
+            let compress = sub_cli.zstd || is_zstd_path(&sub_cli.output);
+            let path = sub_cli.path.unwrap_or_default();
+            let mut cmd = WithRemoteAndLocal::new_with_settings(&settings)?;
+            if sub_cli.output == Path::new("-") {
+                cmd.write_tar(&path, &mut std::io::stdout().lock(), compress)?;
+            } else {
+                let num_files = cmd.export_tar(&path, &sub_cli.output, compress)?;
+                say!(TarExported, num_files, sub_cli.output.to_str().unwrap());
+            }
+            Ok(())
+        },
+        Command::ExportListing(sub_cli) => {
+            use har_backup_core::manifest::ListingFormat;
+            let format = match sub_cli.format {
+                ListingFormatArg::Mtree => ListingFormat::Mtree,
+                ListingFormatArg::Csv => ListingFormat::Csv,
+            };
+            let cmd = WithLocal::new_with_settings(&settings)?;
+            let path = sub_cli.path.unwrap_or_default();
+            match sub_cli.output {
+                None => cmd.export_listing(&path, format, std::io::stdout().lock()).map(|_| ()),
+                Some(output) => {
+                    let file = std::io::BufWriter::new(std::fs::File::create(&output).context("Creating listing file")?);
+                    let num_entries = cmd.export_listing(&path, format, file)?;
+                    say!(ListingExported, num_entries, output.to_str().unwrap());
+                    Ok(())
+                },
+            }
+        },
+        Command::Inventory(sub_cli) => {
+            use har_backup::inventory::InventoryFormat;
+            let format = match sub_cli.format {
+                InventoryFormatArg::Csv => InventoryFormat::Csv,
+                InventoryFormatArg::Parquet => InventoryFormat::Parquet,
+            };
+            let cmd = WithLocal::new_with_settings(&settings)?;
+            match sub_cli.output {
+                None => cmd.inventory(format, std::io::stdout()).map(|_| ()),
+                Some(output) => {
+                    let file = std::io::BufWriter::new(std::fs::File::create(&output).context("Creating inventory file")?);
+                    let (num_rows, num_versions) = cmd.inventory(format, file)?;
+                    say!(InventoryExported, num_rows, num_versions, output.to_str().unwrap());
+                    Ok(())
+                },
+            }
+        },
+        Command::Export(sub_cli) => {
+            use har_backup::cmd_impl::ExportFormat;
+            let format = match sub_cli.format {
+                ExportFormatArg::Tar => ExportFormat::Tar,
+                ExportFormatArg::Dir => ExportFormat::Directory,
+            };
+            let changes = WithRemoteAndLocal::new_with_settings(&settings)?.export_since(&sub_cli.since, &sub_cli.output, format)?;
+            say!(Exported, changes.added.len(), changes.changed.len(), changes.deleted.len());
+            Ok(())
+        },
+        Command::Pull(sub_cli) => {
+            sub_cli.transfer.apply_to(&mut settings);
+            match sub_cli.to_stdout_tar {
+                Some(path) => WithRemoteAndLocal::new_with_settings(&settings)?.pull_to_tar(&path, std::io::stdout().lock()).map(|_| ()),
+                None => {
+                    use har_backup_core::mirror::PullConflict;
+                    let local_deletion = match (sub_cli.delete, sub_cli.permanent) {
+                        (false, _) => None,
+                        (true, false) => Some(LocalDeletion::Trash),
+                        (true, true) => Some(LocalDeletion::Permanent),
+                    };
+                    let pull_conflict = match (sub_cli.overwrite, sub_cli.backup_existing) {
+                        (true, _) => PullConflict::Overwrite,
+                        (_, true) => PullConflict::BackupExisting,
+                        _ => PullConflict::SkipExisting,
+                    };
+                    WithRemoteAndLocal::new_with_settings(&settings)?
+                        .with_scope(sub_cli.path)
+                        .with_local_deletion(local_deletion)
+                        .with_pull_conflict(pull_conflict)
+                        .with_keep_going(sub_cli.keep_going)
+                        .with_cancel(cancel.clone())
+                        .pull()
+                        .and_then(|pulled| {
+                            say_pull_report(&pulled);
+                            check_failures(&pulled.failures)
+                        })
+                },
+            }
+        },
+        Command::Restore(sub_cli) => {
+            use har_backup_core::mirror::PullConflict;
+            sub_cli.transfer.apply_to(&mut settings);
+            let pull_conflict = match (sub_cli.overwrite, sub_cli.backup_existing) {
+                (true, _) => PullConflict::Overwrite,
+                (_, true) => PullConflict::BackupExisting,
+                _ => PullConflict::SkipExisting,
+            };
+            WithRemoteAndLocal::new_with_settings(&settings)?
+                .with_pull_conflict(pull_conflict)
+                .with_cancel(cancel.clone())
+                .restore(&sub_cli.paths, &sub_cli.target)
+                .and_then(|restored| {
+                    say!(RestoreDone, restored.num_pulled);
+                    say_transfer(&restored);
+                    say_failures(&restored.failures);
+                    check_failures(&restored.failures)
+                })
+        },
+        Command::Rm(sub_cli) => {
+            let removed = WithRemoteAndLocal::new_with_settings(&settings)?.rm(&sub_cli.paths, sub_cli.mark_unreferenced)?;
+            for (path, num_files) in &removed.removed {
+                say!(RmRemoved, path.to_str().unwrap(), num_files);
+            }
+            say!(RemoteManifestUpdated);
+            if let Some(num_blobs) = removed.marked_for_gc {
+                say!(RmMarkedForGc, num_blobs);
+            }
+            Ok(())
+        },
+        Command::Du(sub_cli) => {
+            for usage in WithLocal::new_with_settings(&settings)?.disk_usage(&sub_cli.path, sub_cli.depth)? {
+                let size = format!("{:>10}", indicatif::HumanBytes(usage.size).to_string());
+                let unique_size = format!("{:>10}", indicatif::HumanBytes(usage.unique_size).to_string());
+                let path = match usage.path.to_str().unwrap() {
+                    "" => ".".to_string(),
+                    path => format!("{}/", path),
+                };
+                say!(DuLine, size, unique_size, path);
+            }
+            Ok(())
+        },
+        Command::Mount(sub_cli) => WithRemoteAndLocal::new_with_settings(&settings)?
+            .with_cancel(cancel.clone())
+            .mount(&sub_cli.mountpoint, || say!(Mounted, sub_cli.mountpoint.to_str().unwrap())),
+        Command::Mv(sub_cli) => {
+            let moved_to = WithRemoteAndLocal::new_with_settings(&settings)?.mv(&sub_cli.from, &sub_cli.to)?;
+            say!(MvMoved, sub_cli.from.to_str().unwrap(), moved_to.to_str().unwrap());
+            say!(RemoteManifestUpdated);
+            Ok(())
+        },
+        Command::RestoreManifest(sub_cli) => match sub_cli.n {
+            None => {
+                use har_backup_core::clock::format_utc_timestamp;
+                let backups = WithLocal::new_with_settings(&settings)?.list_manifest_backups()?;
+                if backups.is_empty() {
+                    say!(NoManifestBackups);
+                }
+                for (index, (backup, num_files)) in backups.iter().enumerate() {
+                    say!(ManifestBackupLine, index + 1, backup.generation, format_utc_timestamp(backup.replaced), num_files);
+                }
+                Ok(())
+            },
+            Some(n) => {
+                let backup = WithLocal::new_with_settings(&settings)?.restore_manifest(n as usize)?;
+                say!(ManifestRestored, n, backup.generation);
+                if sub_cli.push {
+                    WithRemoteAndLocal::new_with_settings(&settings)?.push_fetched_manifest()?;
+                    say!(RemoteManifestRestored);
+                }
+                Ok(())
+            },
+        },
+        Command::Log => {
+            use har_backup_core::clock::format_utc_timestamp;
+            for entry in WithLocal::new_with_settings(&settings)?.log()? {
+                let written = entry.written.map_or("at an unknown time".to_string(), format_utc_timestamp);
+                let host = entry.host.unwrap_or_else(|| "an unknown host".to_string());
+                say!(LogVersion, entry.name, entry.generation, written, host, entry.num_files, indicatif::HumanBytes(entry.total_size),
+                    entry.added, entry.changed, entry.deleted);
+            }
+            Ok(())
+        },
+        Command::Prune(sub_cli) => {
+            use har_backup_core::clock::format_utc_timestamp;
+            let policy = RetentionPolicy { keep_daily: sub_cli.keep_daily, keep_weekly: sub_cli.keep_weekly, keep_monthly: sub_cli.keep_monthly };
+            let pruned = WithLocal::new_with_settings(&settings)?.prune_manifest_backups(&policy)?;
+            for backup in &pruned.pruned {
+                say!(ManifestBackupPruned, backup.generation, format_utc_timestamp(backup.replaced));
+            }
+            say!(PruneSummary, pruned.num_kept, pruned.pruned.len());
+            Ok(())
+        },
+        Command::Unlock(sub_cli) => {
+            use har_backup::report::UnlockReport;
+            use har_backup_core::clock::format_utc_timestamp;
+            match WithRemoteAndLocal::new_with_settings(&settings)?.unlock(sub_cli.force)? {
+                UnlockReport::NotLocked => say!(RemoteNotLocked),
+                UnlockReport::Held(lock) => {
+                    say!(RemoteLockInfo, &lock.holder, format_utc_timestamp(lock.acquired));
+                    anyhow::bail!("Not releasing the lock without --force, make sure that {} is not pushing anymore", lock.holder);
+                },
+                UnlockReport::Released(lock) => {
+                    say!(RemoteLockInfo, &lock.holder, format_utc_timestamp(lock.acquired));
+                    say!(RemoteUnlocked, lock.holder);
+                },
+            }
+            Ok(())
+        },
+        Command::Resume(sub_cli) => {
+            sub_cli.transfer.apply_to(&mut settings);
+            match WithRemoteAndLocal::new_with_settings(&settings)?.with_cancel(cancel.clone()).resume()? {
+                ResumeReport::NothingToResume => {
+                    say!(NothingToResume);
+                    Ok(())
+                },
+                ResumeReport::Push { num_files, pushed } => {
+                    say!(Resuming, "push", num_files);
+                    say_push_report(&pushed);
+                    check_failures(&pushed.failures)
+                },
+                ResumeReport::Pull { num_files, pulled } => {
+                    say!(Resuming, "pull", num_files);
+                    say_pull_report(&pulled);
+                    check_failures(&pulled.failures)
+                },
+            }
+        },
+        Command::Verify(sub_cli) => {
+            set_quiet_for_json(sub_cli.json);
+            let mut cmd = WithRemoteAndLocal::new_with_settings(&settings)?.with_cancel(cancel.clone());
+            let verified = match (sub_cli.remote_only, sub_cli.sample, sub_cli.all) {
+                (true, _, _) => cmd.verify_remote_only()?,
+                (false, _, true) => cmd.verify(VerifyDownload::All)?,
+                (false, Some(num), false) => cmd.verify(VerifyDownload::Sample(num))?,
+                (false, None, false) => cmd.verify(VerifyDownload::None)?,
+            };
+            match sub_cli.json {
+                true => report::print(&verified)?,
+                false => say_verify_report(&verified),
+            }
+            verified.check()
+        },
+        Command::Fsck(sub_cli) => {
+            let (checked, repaired) = WithLocal::new_with_settings(&settings)?.fsck(sub_cli.repair)?;
+            say_fsck_report(&checked);
+            if repaired {
+                say!(FsckRepaired);
+            } else if !checked.is_clean() {
+                anyhow::bail!("The fetched manifest has {} issues, repair them with --repair", checked.issues.len());
+            }
+            if sub_cli.push {
+                WithRemoteAndLocal::new_with_settings(&settings)?.push_fetched_manifest()?;
+                say!(RemoteManifestRestored);
+            }
+            Ok(())
+        },
+    };
+    result.map(|()| if differs { exit_code::DIFFERENCES } else { exit_code::CLEAN })
+}
+
+// what diff found, the report as is with --json
+fn say_diff_report(diff: &DiffReport, json: bool) -> Result<()> {
+    if json {
+        return report::print(diff);
+    }
+    say_placeholders(&diff.skipped_placeholders);
+    match diff.extra_in {
+        report::Side::Remote => say!(DiffRemoteHasExtra),
+        report::Side::Local => say!(DiffLocalHasExtra),
+    }
+    for path in &diff.extra_paths {
+        println!("{}", path);
+    }
+    say!(DiffTotals, diff.extra_files, diff.extra_dirs);
+    if let Some(changed) = diff.hash_changed.as_ref().filter(|changed| !changed.is_empty()) {
+        say!(DiffHashChanged);
+        for path in changed {
+            println!("{}", path);
+        }
+    }
+    say_timings(diff.timings.as_ref());
+    Ok(())
+}
+
+fn say_push_report(pushed: &PushReport) {
+    if let Some(generation) = pushed.fetched {
+        say!(ManifestFetched, generation);
+    }
+    say_placeholders(&pushed.skipped_placeholders);
+    if let Some((num_local, num_remote)) = pushed.shrunk {
+        say!(PushShrinkGuard, num_local, num_remote);
+    }
+    if pushed.num_changed > 0 && pushed.adopted.is_none() {
+        say!(PushChangedFiles, pushed.num_changed);
+    }
+    match (pushed.nothing_to_push, pushed.adopted) {
+        (true, _) => say!(NothingToPush),
+        (false, Some((num_adopted, num_left_out))) => {
+            say!(RemoteManifestUpdated);
+            say!(PushAdopted, num_adopted, num_left_out);
+        },
+        (false, None) => say!(RemoteManifestUpdated),
+    }
+    if let Some(transfer) = &pushed.transfer {
+        say_transfer_summary(transfer);
+    }
+    say_quarantined(&pushed.quarantined, pushed.max_attempts);
+    say_timings(pushed.timings.as_ref());
+    say_failures(&pushed.failures);
+}
+
+// what a backup pushed, None when it was skipped
+fn say_backup_report(pushed: Option<&PushReport>) {
+    match pushed {
+        Some(pushed) => say_push_report(pushed),
+        None => say!(BackupNothingChanged),
+    }
+}
+
+fn say_pull_report(pulled: &PullReport) {
+    if !pulled.overwritten.is_empty() {
+        say!(PullOverwrote, pulled.overwritten.len());
+        say_paths(&pulled.overwritten);
+    }
+    if !pulled.backed_up.is_empty() {
+        say!(PullBackedUp, pulled.backed_up.len(), har_backup_core::mirror::BACKUP_SUFFIX);
+        say_paths(&pulled.backed_up);
+    }
+    match pulled.nothing_to_pull {
+        true => say!(NothingToPull),
+        false => say!(PullDone),
+    }
+    if !pulled.trashed.is_empty() {
+        say!(LocalTrashed, pulled.trashed.len());
+        say_paths(&pulled.trashed);
+    }
+    if !pulled.deleted.is_empty() {
+        say!(LocalDeleted, pulled.deleted.len());
+        say_paths(&pulled.deleted);
+    }
+    say_transfer(pulled);
+    say_timings(pulled.timings.as_ref());
+    say_failures(&pulled.failures);
+}
+
+// indented under the message that counts them, not when quiet
+fn say_paths(paths: &[PathBuf]) {
+    if har_backup_core::messages::is_quiet() {
+        return;
+    }
+    for path in paths {
+        println!("  {}", path.to_str().unwrap());
+    }
+}
+
+// cloud files the scan left out, see ScanReport
+fn say_placeholders(paths: &[PathBuf]) {
+    if paths.is_empty() {
+        return;
+    }
+    say!(PlaceholdersSkipped, paths.len());
+    if !har_backup_core::messages::is_quiet() {
+        for path in paths {
+            println!("{}", path.to_str().unwrap());
+        }
+    }
+}
+
+// what can't be read is said with the rest
+fn say_status_report(status: &report::StatusReport) {
+    say!(StatusArchiveRoot, status.archive_root);
+    match (&status.remote, &status.remote_error) {
+        (Some(remote), _) => say!(StatusRemote, remote),
+        (None, error) => say!(StatusNoRemote, error.clone().unwrap_or_default()),
+    }
+    match (&status.key, &status.key_error) {
+        (Some(key), _) => say!(StatusKey, key.spec, &key.fingerprint[..16]),
+        (None, error) => say!(StatusNoKey, error.clone().unwrap_or_default()),
+    }
+    match &status.fetched {
+        None => say!(StatusNeverFetched),
+        Some(fetched) => {
+            say_placeholders(&status.skipped_placeholders);
+            say!(StatusFetched, fetched.generation, fetched.stored);
+            say!(StatusDelta, fetched.only_local_files, indicatif::HumanBytes(fetched.only_local_bytes),
+                fetched.only_remote_files, indicatif::HumanBytes(fetched.only_remote_bytes));
+        },
+    }
+    match &status.pending {
+        Some(pending) => say!(StatusPendingRun, if pending.kind == har_backup::history::RunKind::Push { "push" } else { "pull" }, pending.num_files),
+        None => say!(StatusNothingPending),
+    }
+}
+
+// the problems found then the totals, the command fails after with VerifyReport::check
+fn say_verify_report(verified: &report::VerifyReport) {
+    use report::BlobProblemKind;
+    for problem in &verified.problems {
+        match &problem.kind {
+            BlobProblemKind::Missing => say!(VerifyMissingBlob, problem.blob_key, problem.path),
+            BlobProblemKind::WrongSize { stored, expected } => say!(VerifySizeMismatch, problem.blob_key, problem.path, stored, expected),
+            BlobProblemKind::Corrupt { reason } => say!(VerifyCorruptBlob, problem.blob_key, problem.path, reason),
+        }
+    }
+    let num_missing = verified.count(|kind| matches!(kind, BlobProblemKind::Missing));
+    match verified.remote_only {
+        true => {
+            let num_wrong_size = verified.count(|kind| matches!(kind, BlobProblemKind::WrongSize { .. }));
+            say!(VerifyRemoteSummary, verified.checked, num_missing, num_wrong_size, verified.without_size);
+        },
+        false => {
+            let num_damaged = verified.count(|kind| matches!(kind, BlobProblemKind::Corrupt { .. }));
+            say!(VerifySummary, verified.checked, num_missing, verified.downloaded, num_damaged);
+        },
+    }
+}
+
+fn say_fsck_report(checked: &har_backup_core::manifest::FsckReport) {
+    use har_backup_core::manifest::FsckIssue;
+    let path = |path: &Path| path.to_str().unwrap().to_string();
+    for issue in &checked.issues {
+        match issue {
+            FsckIssue::RootNotDirectory => say!(FsckRootNotDirectory),
+            FsckIssue::DanglingChild(at) => say!(FsckDanglingChild, path(at)),
+            FsckIssue::NameMismatch(at, name) => say!(FsckNameMismatch, path(at), name),
+            FsckIssue::SharedEntry(at, first) => say!(FsckSharedEntry, path(at), path(first)),
+            FsckIssue::DuplicateName(at, other) => say!(FsckDuplicateName, path(at), path(other)),
+            FsckIssue::NoBlobKey(at) => say!(FsckNoBlobKey, path(at)),
+            FsckIssue::Orphan(at) => say!(FsckOrphan, path(at)),
+        }
+    }
+    say!(FsckSummary, checked.num_entries, checked.issues.len());
+}
+
+fn say_watch_event(event: report::WatchEvent) {
+    use har_backup_core::health::RemoteHealth;
+    use report::WatchEvent;
+    match event {
+        WatchEvent::Watching { archive_root, debounce } => say!(Watching, archive_root.to_str().unwrap(), debounce.as_secs()),
+        WatchEvent::Pushed(pushed) => say_push_report(pushed),
+        WatchEvent::PushFailed { error, retry_after } => say!(WatchPushFailed, format!("{:#}", error), retry_after.as_secs()),
+        WatchEvent::RemoteHealth(RemoteHealth::Reachable) => say!(RemoteReachable),
+        WatchEvent::RemoteHealth(RemoteHealth::Unreachable(reason)) => say!(RemoteUnreachable, reason),
+        WatchEvent::RemoteHealth(RemoteHealth::AuthExpired(reason)) => say!(RemoteAuthExpired, reason),
+        WatchEvent::HealthMetrics(metrics) => say!(WatchHealthMetrics, metrics.probes, metrics.failed_probes,
+            metrics.transitions, metrics.unreachable_time.as_secs()),
+        WatchEvent::Stopped => say!(WatchStopped),
+    }
+}
+
+fn say_backup_event(event: report::BackupEvent) {
+    use har_backup_core::clock::format_utc_timestamp;
+    use report::BackupEvent;
+    match event {
+        BackupEvent::Scheduled { every, next } => say!(BackupScheduled, har_backup::schedule::format_interval(every), format_utc_timestamp(next)),
+        BackupEvent::CatchingUp { missed } => say!(BackupCatchingUp, missed),
+        BackupEvent::BackedUp(pushed) => say_backup_report(pushed),
+        BackupEvent::Failed(error) => say!(BackupFailed, format!("{:#}", error)),
+        BackupEvent::Next(next) => say!(BackupNext, format_utc_timestamp(next)),
+        BackupEvent::Stopped => say!(BackupStopped),
+    }
+}
+
+fn say_transfer(pulled: &PullReport) {
+    if let Some(transfer) = &pulled.transfer {
+        say_transfer_summary(transfer);
+    }
+    say_quarantined(&pulled.quarantined, pulled.max_attempts);
+}
+
+fn say_transfer_summary(transfer: &har_backup_core::mirror::TransferReport) {
+    say!(TransferSummary, transfer.num_files, transfer.plaintext_bytes, transfer.ciphertext_bytes, format!("{:.3}", transfer.elapsed.as_secs_f64()),
+        transfer.throughput().round(), transfer.num_retried, transfer.num_skipped);
+}
+
+fn say_quarantined(quarantined: &[report::FileFailure], max_attempts: u32) {
+    if quarantined.is_empty() {
+        return;
+    }
+    say!(Quarantined, quarantined.len(), max_attempts);
+    for (path, reason) in quarantined {
+        println!("{}: {}", path.to_str().unwrap(), reason);
+    }
+}
+
+// the files that failed with --keep-going, the command fails after
+fn say_failures(failures: &[report::FileFailure]) {
+    if failures.is_empty() {
+        return;
+    }
+    say!(FilesFailed, failures.len());
+    for (path, reason) in failures {
+        println!("{}: {}", path.to_str().unwrap(), reason);
     }
 }
 
+fn say_timings(timings: Option<&har_backup_core::timings::Timings>) {
+    for (phase, duration) in timings.iter().flat_map(|timings| timings.phases()) {
+        say!(PhaseTiming, phase, format!("{:.3}", duration.as_secs_f64()));
+    }
+}
+
+fn is_zstd_path(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "zst" || extension == "tzst")
+}
+
+// the JSON is all there is on stdout, but for warnings and errors
+fn set_quiet_for_json(json: bool) {
+    if json {
+        har_backup_core::messages::set_quiet(true);
+    }
+}
+
+// the first Ctrl-C lets push and pull finish the transfers in flight and store their journals, the second one quits
+fn cancel_on_ctrl_c() -> Result<CancelToken> {
+    let cancel = CancelToken::new();
+    let handler_cancel = cancel.clone();
+    ctrlc::set_handler(move || {
+        if handler_cancel.is_cancelled() {
+            std::process::exit(har_backup::exit_code::INTERRUPTED);
+        }
+        say!(Interrupting);
+        handler_cancel.cancel();
+    }).context("Setting Ctrl-C handler")?;
+    Ok(cancel)
+}
+
 fn write_file_without_overwrite(path: &Path, content: &[u8]) -> Result<()> {
     if path.exists() {
         anyhow::bail!("{} already exists", path.to_str().unwrap());
@@ -87,12 +1646,43 @@ fn write_file_without_overwrite(path: &Path, content: &[u8]) -> Result<()> {
     Ok(())
 }
 
-fn create_key(path: &Path) -> Result<()> {
+fn create_key(path: &Path, with_passphrase: bool) -> Result<()> {
     let path_str = path.to_str().context("Convert path to str")?;
-    println!("Creating key");
-    let key = har_backup::blob_encryption::create_key();
+    if path.exists() {
+        anyhow::bail!("{} already exists", path_str);
+    }
+    let passphrase = with_passphrase.then(har_backup_core::keys::prompt_new_passphrase).transpose()?;
+    say!(CreatingKey);
+    let mut key = har_backup_core::keys::KeyFile::create_full();
+    if let Some(passphrase) = passphrase {
+        key = har_backup_core::keys::wrap_with_passphrase(&key, &passphrase)?;
+    }
     write_file_without_overwrite(path, key.as_slice()).context("Writing key to file")?;
-    println!("key stored at {}", path_str);
+    say!(KeyStored, path_str);
+    Ok(())
+}
+
+fn derive_read_key(key_path: &Path, output_path: &Path) -> Result<()> {
+    let key_file = har_backup_core::keys::KeyFile::from_file(key_path)?;
+    write_file_without_overwrite(output_path, key_file.read_key()).context("Writing read only key to file")?;
+    say!(ReadKeyStored, output_path.to_str().context("Convert path to str")?);
+    Ok(())
+}
+
+fn recover_manifest(input_path: &Path, output_path: &Path) -> Result<()> {
+    let bytes = std::fs::read(input_path).context("Reading manifest to recover")?;
+    let (manifest, report) = har_backup_core::manifest::Manifest::from_bytes_lenient(&bytes)?;
+    write_file_without_overwrite(output_path, &manifest.to_bytes()?).context("Writing recovered manifest")?;
+    for region in &report.damaged_regions {
+        say!(ManifestDamagedRegion, region.start, region.end);
+    }
+    for path in &report.lost_paths {
+        say!(ManifestLostPath, path.to_str().unwrap());
+    }
+    for path in &report.orphans {
+        say!(ManifestOrphan, path.to_str().unwrap());
+    }
+    say!(ManifestRecovered, report.recovered_entries, report.expected_entries, report.damaged_regions.len(), output_path.to_str().unwrap());
     Ok(())
 }
 
@@ -102,6 +1692,101 @@ fn init_local() -> Result<()> {
         anyhow::bail!("It looks like this has been initialized already!")
     }
     std::fs::create_dir(DOT_HAR_NAME)?;
-    println!("Archive initialized.");
+    say!(ArchiveInitialized);
     Ok(())
 }
+
+fn init(args: Init, settings: &Settings) -> Result<()> {
+    use har_backup::dot_har::{KeySpec, DOT_HAR_NAME};
+    use har_backup_core::messages::MessageKey;
+    if Path::new(DOT_HAR_NAME).exists() {
+        anyhow::bail!("It looks like this has been initialized already!")
+    }
+    let remote_spec = match args.remote {
+        Some(remote_spec) => remote_spec,
+        None => prompt_line(MessageKey::InitRemotePrompt, &[])?,
+    };
+    let remote_spec = complete_remote_spec(remote_spec)?;
+    let key = match args.key {
+        Some(key) => key,
+        None => prompt_line(MessageKey::InitKeyPrompt, &[])?,
+    };
+    let key_spec = match KeySpec::parse(&key) {
+        KeySpec::File(path) => {
+            let path = std::path::absolute(path)?;
+            let path_str = path.to_str().context("Convert path to str")?;
+            if args.create_key {
+                create_key(&path, args.passphrase)?;
+            } else if !path.exists() {
+                if !prompt_line(MessageKey::InitCreateKeyPrompt, &[path_str.to_string()])?.eq_ignore_ascii_case("y") {
+                    anyhow::bail!("Key file {} does not exist, --create-key creates it", path_str);
+                }
+                create_key(&path, args.passphrase)?;
+            }
+            KeySpec::File(path)
+        },
+        keychain => keychain,
+    };
+    let mut cmd = har_backup::cmd_impl::WithRemoteAndLocal::init(&std::env::current_dir()?, &remote_spec, &key_spec, settings)?;
+    say!(ArchiveInitialized);
+    match cmd.archive_metadata()? {
+        Some(metadata) => say!(InitRemoteHasArchive, metadata.archive_uuid),
+        None => say!(InitRemoteEmpty),
+    }
+    Ok(())
+}
+
+fn clone(args: CloneRemote, settings: &Settings, cancel: CancelToken) -> Result<()> {
+    use har_backup::dot_har::KeySpec;
+    use har_backup_core::messages::MessageKey;
+    let remote_spec = complete_remote_spec(args.remote)?;
+    let key = match args.key {
+        Some(key) => key,
+        None => prompt_line(MessageKey::InitKeyPrompt, &[])?,
+    };
+    let key_spec = match KeySpec::parse(&key) {
+        KeySpec::File(path) => {
+            let path = std::path::absolute(path)?;
+            if !path.exists() {
+                anyhow::bail!("Key file {} does not exist", path.to_str().context("Convert path to str")?);
+            }
+            KeySpec::File(path)
+        },
+        keychain => keychain,
+    };
+    let dir = std::path::absolute(&args.dir)?;
+    let pulled = har_backup::cmd_impl::WithRemoteAndLocal::clone_into(&dir, &remote_spec, &key_spec, settings, cancel, args.keep_going)?;
+    say!(ArchiveInitialized);
+    say_pull_report(&pulled);
+    check_failures(&pulled.failures)
+}
+
+// fs paths made absolute, the bucket and credentials of an s3 endpoint asked for
+fn complete_remote_spec(remote_spec: String) -> Result<String> {
+    use har_backup_core::messages::{render, MessageKey};
+    let completed = if let Some(path) = remote_spec.strip_prefix("fs://") {
+        format!("fs://{}", std::path::absolute(path)?.to_str().context("Convert path to str")?)
+    } else if let Some(endpoint) = remote_spec.strip_prefix("s3://").filter(|endpoint| !endpoint.contains('\n')) {
+        // only the endpoint, the rest of the spec is asked for
+        let bucket_name = prompt_line(MessageKey::InitS3BucketPrompt, &[])?;
+        let access_key = prompt_line(MessageKey::InitS3AccessKeyPrompt, &[])?;
+        let secret = har_backup_core::keys::prompt_passphrase(&render(MessageKey::InitS3SecretPrompt, &[]))?;
+        format!("s3://{}\n{}\n{}\n{}", endpoint, bucket_name, access_key, secret)
+    } else {
+        remote_spec
+    };
+    Ok(completed)
+}
+
+// a line typed by the user, for init and remote set: without a terminal the values have to be given as flags
+fn prompt_line(prompt: har_backup_core::messages::MessageKey, args: &[String]) -> Result<String> {
+    use std::io::{BufRead, IsTerminal, Write};
+    if !std::io::stdin().is_terminal() {
+        anyhow::bail!("Missing values and no terminal to ask for them, see --help for the flags");
+    }
+    print!("{}", har_backup_core::messages::render(prompt, args));
+    std::io::stdout().flush()?;
+    let mut line = String::new();
+    std::io::stdin().lock().read_line(&mut line).context("Reading answer")?;
+    Ok(line.trim().to_string())
+}
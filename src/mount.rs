@@ -0,0 +1,307 @@
+// the fetched manifest as a read-only filesystem, for har mount
+// inodes are given in the order of Manifest::walk, nothing is downloaded until a file is read. Then only the blobs
+// (the chunks of a big file) that the read needs are, recently read ones are kept in a ContentCache
+
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use bytes::Bytes;
+use har_backup_core::manifest::{Manifest, StoredChunk, WalkKind};
+use har_backup_core::thread_sync::CancelToken;
+
+pub const ROOT_INO: u64 = 1;
+// decrypted blobs kept for reads that come next, fuse reads files in pieces of 128 KiB or so
+pub const CACHE_BYTES: u64 = 256 * 1024 * 1024;
+
+pub struct ArchiveTree {
+    nodes: Vec<Node>, // inode - 1
+    written: u64, // unix seconds, the time of every entry
+}
+
+pub struct Node {
+    pub path: PathBuf,
+    pub parent: u64,
+    pub kind: NodeKind,
+}
+
+pub enum NodeKind {
+    Directory(Vec<(String, u64)>), // children, sorted by name
+    File(FileNode),
+}
+
+pub struct FileNode {
+    pub size: u64,
+    pub key: String,
+    pub chunks: Option<Vec<StoredChunk>>,
+}
+
+impl ArchiveTree {
+    pub fn new(manifest: &Manifest) -> Self {
+        let mut chunked = manifest.list_chunked_files();
+        let mut nodes = vec![Node { path: PathBuf::new(), parent: ROOT_INO, kind: NodeKind::Directory(Vec::new()) }];
+        let mut inodes: HashMap<PathBuf, u64> = HashMap::from([(PathBuf::new(), ROOT_INO)]);
+        // parents are walked before their children, children by name
+        for entry in manifest.walk() {
+            let ino = nodes.len() as u64 + 1;
+            let parent = inodes[entry.path.parent().unwrap()];
+            if let NodeKind::Directory(children) = &mut nodes[parent as usize - 1].kind {
+                children.push((entry.path.file_name().unwrap().to_str().unwrap().to_string(), ino));
+            }
+            let kind = match entry.kind {
+                WalkKind::Directory => {
+                    inodes.insert(entry.path.clone(), ino);
+                    NodeKind::Directory(Vec::new())
+                },
+                WalkKind::File { size, blob_key } => NodeKind::File(FileNode { size, key: blob_key, chunks: chunked.remove(&entry.path) }),
+            };
+            nodes.push(Node { path: entry.path, parent, kind });
+        }
+        let written = manifest.origin().map_or(0, |origin| origin.written);
+        Self { nodes, written }
+    }
+
+    pub fn get(&self, ino: u64) -> Option<&Node> {
+        self.nodes.get((ino as usize).checked_sub(1)?)
+    }
+
+    pub fn lookup(&self, parent: u64, name: &str) -> Option<u64> {
+        let NodeKind::Directory(children) = &self.get(parent)?.kind else {
+            return None;
+        };
+        children.binary_search_by(|(child_name, _)| child_name.as_str().cmp(name)).ok().map(|index| children[index].1)
+    }
+
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+}
+
+// decrypted blobs by key, the most recently read first, up to max_bytes in total (the last one whatever its size)
+pub struct ContentCache {
+    max_bytes: u64,
+    blobs: VecDeque<(String, Bytes)>,
+}
+
+impl ContentCache {
+    pub fn new(max_bytes: u64) -> Self {
+        Self { max_bytes, blobs: VecDeque::new() }
+    }
+
+    pub fn get_or_fetch(&mut self, key: &str, fetch: impl FnOnce() -> anyhow::Result<Bytes>) -> anyhow::Result<Bytes> {
+        if let Some(index) = self.blobs.iter().position(|(cached_key, _)| cached_key == key) {
+            let blob = self.blobs.remove(index).unwrap();
+            self.blobs.push_front(blob);
+            return Ok(self.blobs[0].1.clone());
+        }
+        let data = fetch()?;
+        self.blobs.push_front((key.to_string(), data.clone()));
+        let mut total: u64 = self.blobs.iter().map(|(_, data)| data.len() as u64).sum();
+        while total > self.max_bytes && self.blobs.len() > 1 {
+            total -= self.blobs.pop_back().unwrap().1.len() as u64;
+        }
+        Ok(data)
+    }
+}
+
+// size bytes of file from offset (less at the end of the file), from the blobs the range is in
+// fetch_blob gets a blob by key with the size it has in the manifest
+pub fn read_range(file: &FileNode, offset: u64, size: u64, mut fetch_blob: impl FnMut(&str, u64) -> anyhow::Result<Bytes>) -> anyhow::Result<Vec<u8>> {
+    let blobs: Vec<(&str, u64)> = match &file.chunks {
+        Some(chunks) => chunks.iter().map(|chunk| (chunk.key.as_str(), chunk.size)).collect(),
+        None => vec![(file.key.as_str(), file.size)],
+    };
+    let end = offset.saturating_add(size).min(file.size);
+    let mut data = Vec::with_capacity(end.saturating_sub(offset) as usize);
+    let mut blob_start = 0;
+    for (key, blob_size) in blobs {
+        let blob_end = blob_start + blob_size;
+        if blob_end > offset && blob_start < end {
+            let blob = fetch_blob(key, blob_size)?;
+            if blob.len() as u64 != blob_size {
+                anyhow::bail!("Size of blob {} does not match manifest", key);
+            }
+            data.extend_from_slice(&blob[(offset.max(blob_start) - blob_start) as usize..(end.min(blob_end) - blob_start) as usize]);
+        }
+        blob_start = blob_end;
+    }
+    Ok(data)
+}
+
+// serves tree on mountpoint until it is unmounted (fusermount -u) or cancel is
+#[cfg(feature = "mount")]
+pub fn mount(tree: &ArchiveTree, mountpoint: &Path, cancel: &CancelToken, fetch_blob: impl FnMut(&Path, &str) -> anyhow::Result<Bytes>) -> anyhow::Result<()> {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use anyhow::Context;
+    let options = [fuser::MountOption::RO, fuser::MountOption::FSName("har".to_string()), fuser::MountOption::Subtype("har".to_string())];
+    let filesystem = fuse::ArchiveFs { tree, cache: ContentCache::new(CACHE_BYTES), fetch_blob };
+    let mut session = fuser::Session::new(filesystem, mountpoint, &options)
+        .with_context(|| format!("Mounting on {}", mountpoint.to_str().unwrap()))?;
+    let mut unmounter = session.unmount_callable();
+    let unmounted = AtomicBool::new(false);
+    std::thread::scope(|scope| {
+        scope.spawn(|| {
+            while !unmounted.load(Ordering::Relaxed) {
+                if cancel.is_cancelled() {
+                    let _ = unmounter.unmount();
+                    return;
+                }
+                std::thread::sleep(std::time::Duration::from_millis(100));
+            }
+        });
+        let served = session.run();
+        unmounted.store(true, Ordering::Relaxed);
+        served
+    }).context("Serving the mount")
+}
+
+#[cfg(not(feature = "mount"))]
+pub fn mount(_tree: &ArchiveTree, _mountpoint: &Path, _cancel: &CancelToken, _fetch_blob: impl FnMut(&Path, &str) -> anyhow::Result<Bytes>) -> anyhow::Result<()> {
+    anyhow::bail!("This har was built without the mount feature")
+}
+
+#[cfg(feature = "mount")]
+mod fuse {
+    use std::ffi::OsStr;
+    use std::path::Path;
+    use std::time::{Duration, UNIX_EPOCH};
+    use bytes::Bytes;
+    use fuser::{FileAttr, FileType, Filesystem, ReplyAttr, ReplyData, ReplyDirectory, ReplyEntry, ReplyOpen, Request};
+    use tracing::warn;
+    use super::{ArchiveTree, ContentCache, NodeKind, read_range};
+
+    // the tree does not change while mounted
+    const TTL: Duration = Duration::from_secs(3600);
+    const BLOCK_SIZE: u32 = 4096;
+
+    pub(super) struct ArchiveFs<'a, F> {
+        pub tree: &'a ArchiveTree,
+        pub cache: ContentCache,
+        pub fetch_blob: F,
+    }
+
+    impl<F> ArchiveFs<'_, F> {
+        fn attr(&self, ino: u64) -> Option<FileAttr> {
+            let (kind, size, perm) = match &self.tree.get(ino)?.kind {
+                NodeKind::Directory(_) => (FileType::Directory, 0, 0o555),
+                NodeKind::File(file) => (FileType::RegularFile, file.size, 0o444),
+            };
+            let time = UNIX_EPOCH + Duration::from_secs(self.tree.written());
+            Some(FileAttr {
+                ino, size, blocks: size.div_ceil(512), atime: time, mtime: time, ctime: time, crtime: time, kind, perm,
+                nlink: 1, uid: unsafe { libc::getuid() }, gid: unsafe { libc::getgid() }, rdev: 0, blksize: BLOCK_SIZE, flags: 0,
+            })
+        }
+    }
+
+    impl<F: FnMut(&Path, &str) -> anyhow::Result<Bytes>> Filesystem for ArchiveFs<'_, F> {
+        fn lookup(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
+            match name.to_str().and_then(|name| self.tree.lookup(parent, name)).and_then(|ino| self.attr(ino)) {
+                Some(attr) => reply.entry(&TTL, &attr, 0),
+                None => reply.error(libc::ENOENT),
+            }
+        }
+
+        fn getattr(&mut self, _req: &Request<'_>, ino: u64, _fh: Option<u64>, reply: ReplyAttr) {
+            match self.attr(ino) {
+                Some(attr) => reply.attr(&TTL, &attr),
+                None => reply.error(libc::ENOENT),
+            }
+        }
+
+        fn open(&mut self, _req: &Request<'_>, ino: u64, flags: i32, reply: ReplyOpen) {
+            match self.tree.get(ino) {
+                None => reply.error(libc::ENOENT),
+                Some(_) if flags & libc::O_ACCMODE != libc::O_RDONLY => reply.error(libc::EROFS),
+                Some(_) => reply.opened(0, 0),
+            }
+        }
+
+        fn read(&mut self, _req: &Request<'_>, ino: u64, _fh: u64, offset: i64, size: u32, _flags: i32, _lock_owner: Option<u64>, reply: ReplyData) {
+            let Some(node) = self.tree.get(ino) else {
+                return reply.error(libc::ENOENT);
+            };
+            let NodeKind::File(file) = &node.kind else {
+                return reply.error(libc::EISDIR);
+            };
+            let (cache, fetch_blob) = (&mut self.cache, &mut self.fetch_blob);
+            let read = read_range(file, offset.max(0) as u64, size as u64, |key, _| cache.get_or_fetch(key, || fetch_blob(&node.path, key)));
+            match read {
+                Ok(data) => reply.data(&data),
+                Err(error) => {
+                    warn!("Reading {}: {:#}", node.path.to_str().unwrap(), error);
+                    reply.error(libc::EIO);
+                },
+            }
+        }
+
+        fn readdir(&mut self, _req: &Request<'_>, ino: u64, _fh: u64, offset: i64, mut reply: ReplyDirectory) {
+            let Some(node) = self.tree.get(ino) else {
+                return reply.error(libc::ENOENT);
+            };
+            let NodeKind::Directory(children) = &node.kind else {
+                return reply.error(libc::ENOTDIR);
+            };
+            let kind = |ino| match self.tree.get(ino).map(|node| &node.kind) {
+                Some(NodeKind::File(_)) => FileType::RegularFile,
+                _ => FileType::Directory,
+            };
+            let entries = [(".", ino), ("..", node.parent)].into_iter().chain(children.iter().map(|(name, child_ino)| (name.as_str(), *child_ino)));
+            // offset is that of the last entry the kernel got, 0 at first
+            for (index, (name, child_ino)) in entries.enumerate().skip(offset as usize) {
+                if reply.add(child_ino, index as i64 + 1, kind(child_ino), name) {
+                    break;
+                }
+            }
+            reply.ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    fn chunk(key: &str, size: u64) -> StoredChunk {
+        StoredChunk { key: key.to_string(), size, ciphertext_size: size + 40 }
+    }
+
+    #[test]
+    fn inodes_of_the_manifest() -> anyhow::Result<()> {
+        let manifest = Manifest::from_listing([(PathBuf::from("dog/fault"), 3), (PathBuf::from("felt"), 4), (PathBuf::from("dog/deal/fetch"), 5)])?;
+        let tree = ArchiveTree::new(&manifest);
+        let dog = tree.lookup(ROOT_INO, "dog").unwrap();
+        let fetch = tree.lookup(tree.lookup(dog, "deal").unwrap(), "fetch").unwrap();
+        assert_eq!(tree.get(fetch).unwrap().path, PathBuf::from("dog/deal/fetch"));
+        assert!(matches!(&tree.get(fetch).unwrap().kind, NodeKind::File(file) if file.size == 5));
+        let NodeKind::Directory(children) = &tree.get(dog).unwrap().kind else {
+            panic!("dog is not a directory");
+        };
+        assert_eq!(children.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>(), vec!["deal", "fault"]);
+        assert_eq!(tree.get(dog).unwrap().parent, ROOT_INO);
+        assert_eq!(tree.lookup(ROOT_INO, "nope"), None);
+        assert_eq!(tree.lookup(fetch, "nope"), None);
+        assert!(tree.get(0).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn reads_only_the_blobs_of_the_range() -> anyhow::Result<()> {
+        let file = FileNode { size: 10, key: "whole".to_string(), chunks: Some(vec![chunk("a", 4), chunk("b", 4), chunk("c", 2)]) };
+        let fetched = RefCell::new(Vec::new());
+        let mut cache = ContentCache::new(8);
+        let mut read = |offset, size| read_range(&file, offset, size, |key, _| cache.get_or_fetch(key, || {
+            fetched.borrow_mut().push(key.to_string());
+            Ok(Bytes::from(match key { "a" => "0123", "b" => "4567", _ => "89" }))
+        }));
+        assert_eq!(read(2, 4)?, b"2345");
+        assert_eq!(read(5, 100)?, b"56789");
+        assert_eq!(read(0, 1)?, b"0");
+        assert_eq!(read(10, 4)?, b"");
+        // a and b were cached, c pushed a out
+        assert_eq!(*fetched.borrow(), vec!["a", "b", "c", "a"]);
+
+        let truncated = FileNode { size: 5, key: "whole".to_string(), chunks: None };
+        assert!(read_range(&truncated, 0, 5, |_, _| Ok(Bytes::from("0123"))).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,85 @@
+use std::path::Path;
+use anyhow::Context;
+use regex::Regex;
+
+// paths of the archive looked for by har find
+// a glob without / is matched against the name of entries (like find -name), one with / against the whole path.
+// * and ? don't go past a /, ** does. A regex is searched for anywhere in the path
+#[derive(Debug, Clone)]
+pub struct PathPattern {
+    regex: Regex,
+    name_only: bool,
+}
+
+impl PathPattern {
+    pub fn glob(glob: &str) -> anyhow::Result<Self> {
+        let mut regex = String::from("^");
+        let mut chars = glob.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '*' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    // a/**/b is also a/b
+                    if chars.peek() == Some(&'/') {
+                        chars.next();
+                        regex.push_str("(?:.*/)?");
+                    } else {
+                        regex.push_str(".*");
+                    }
+                },
+                '*' => regex.push_str("[^/]*"),
+                '?' => regex.push_str("[^/]"),
+                '[' => {
+                    let class: String = chars.by_ref().take_while(|&c| c != ']').collect();
+                    let class = class.strip_prefix('!').map_or(class.clone(), |negated| format!("^{}", negated));
+                    regex.push_str(&format!("[{}]", class.replace('\\', "\\\\")));
+                },
+                c => regex.push_str(&regex::escape(&c.to_string())),
+            }
+        }
+        regex.push('$');
+        let regex = Regex::new(&regex).with_context(|| format!("Invalid glob {}", glob))?;
+        Ok(Self { regex, name_only: !glob.contains('/') })
+    }
+
+    pub fn regex(regex: &str) -> anyhow::Result<Self> {
+        Ok(Self { regex: Regex::new(regex).with_context(|| format!("Invalid regex {}", regex))?, name_only: false })
+    }
+
+    // path relative to the archive root
+    pub fn is_match(&self, path: &Path) -> bool {
+        let matched = match self.name_only {
+            true => path.file_name().unwrap_or_default(),
+            false => path.as_os_str(),
+        };
+        self.regex.is_match(matched.to_str().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn globs_and_regexes() -> anyhow::Result<()> {
+        let matches = |pattern: &PathPattern, path: &str| pattern.is_match(Path::new(path));
+
+        let names = PathPattern::glob("*.jp?g")?;
+        assert!(matches(&names, "photos/2024/cat.jpeg"));
+        assert!(!matches(&names, "cat.jpg") && !matches(&names, "cat.pngg"));
+
+        let paths = PathPattern::glob("photos/*/c[a-d]t.*")?;
+        assert!(matches(&paths, "photos/2024/cat.jpg"));
+        assert!(!matches(&paths, "photos/2024/dec/cat.jpg"));
+        assert!(!matches(&paths, "photos/2024/cut.jpg"));
+        let deep = PathPattern::glob("photos/**/cat.jpg")?;
+        assert!(matches(&deep, "photos/cat.jpg") && matches(&deep, "photos/2024/dec/cat.jpg"));
+        assert!(matches(&PathPattern::glob("[!.]*")?, "docs") && !matches(&PathPattern::glob("[!.]*")?, ".git"));
+
+        let regex = PathPattern::regex(r"20\d\d/.*\.(jpg|png)$")?;
+        assert!(matches(&regex, "photos/2024/cat.png"));
+        assert!(!matches(&regex, "photos/cat.png"));
+        assert!(PathPattern::regex("(").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use indicatif::{HumanBytes, MultiProgress, ProgressBar, ProgressStyle};
+use har_backup_core::mirror::{TransferEvent, TransferProgress};
+use har_backup_core::say;
+
+// smaller files come and go too fast for a bar of their own
+const BIG_FILE_SIZE: u64 = 32 * 1024 * 1024;
+const TICK_INTERVAL: Duration = Duration::from_millis(200);
+
+// progress bars of a push or pull on stderr: overall bytes, files done and one spinner per big file in flight
+// storages only tell when a transfer is over, so big files get a spinner and not a bar
+pub struct ProgressBars {
+    multi: MultiProgress,
+    bytes: ProgressBar,
+    files: ProgressBar,
+    big_files: HashMap<usize, ProgressBar>,
+}
+
+impl ProgressBars {
+    pub fn new(verb: &str) -> Self {
+        let multi = MultiProgress::new();
+        let bytes = multi.add(ProgressBar::new(0).with_style(
+            ProgressStyle::with_template("{prefix} [{bar:40}] {bytes}/{total_bytes} {bytes_per_sec} eta {eta}")
+                .unwrap()
+                .progress_chars("=> "),
+        ).with_prefix(verb.to_string()));
+        let files = multi.add(ProgressBar::new(0).with_style(
+            ProgressStyle::with_template("files {pos}/{len} {msg}").unwrap(),
+        ));
+        Self { multi, bytes, files, big_files: HashMap::new() }
+    }
+
+    fn big_file_style() -> ProgressStyle {
+        ProgressStyle::with_template("  {spinner} {msg} {elapsed}").unwrap()
+    }
+}
+
+impl TransferProgress for ProgressBars {
+    fn on_event(&mut self, event: &TransferEvent) {
+        match event {
+            TransferEvent::Started { num_files, num_bytes } => {
+                self.bytes.set_length(*num_bytes);
+                self.files.set_length(*num_files as u64);
+            },
+            TransferEvent::FileStarted { index, path, size } => {
+                if *size >= BIG_FILE_SIZE {
+                    let bar = self.multi.add(ProgressBar::new_spinner().with_style(Self::big_file_style())
+                        .with_message(format!("{} ({})", path.to_str().unwrap(), HumanBytes(*size))));
+                    bar.enable_steady_tick(TICK_INTERVAL);
+                    self.big_files.insert(*index, bar);
+                }
+            },
+            TransferEvent::ChunkDone { size, .. } => {
+                self.bytes.inc(*size);
+            },
+            TransferEvent::FileDone { index, size } => {
+                self.bytes.inc(*size);
+                self.files.inc(1);
+                if let Some(bar) = self.big_files.remove(index) {
+                    bar.finish_and_clear();
+                }
+            },
+            TransferEvent::FileFailed { index, size, retrying, .. } => {
+                if let Some(bar) = self.big_files.remove(index) {
+                    bar.finish_and_clear();
+                }
+                if !retrying {
+                    // quarantined, it won't be transferred
+                    self.bytes.set_length(self.bytes.length().unwrap_or(0).saturating_sub(*size));
+                    self.files.inc(1);
+                    self.files.set_message("(some quarantined)");
+                }
+            },
+            TransferEvent::Finished => {
+                for (_, bar) in self.big_files.drain() {
+                    bar.finish_and_clear();
+                }
+                self.bytes.finish();
+                self.files.finish();
+            },
+            TransferEvent::Status { .. } => {},
+            event => say_outcome(event),
+        }
+    }
+}
+
+// what a push did besides transferring, printed by both observers
+fn say_outcome(event: &TransferEvent) {
+    match event {
+        TransferEvent::Resumed { num_files } => say!(PushResumed, num_files),
+        TransferEvent::Deduplicated { num_files } => say!(PushDeduplicated, num_files),
+        _ => {},
+    }
+}
+
+// a status line every TransferConfig::time_between_prints, when there are no progress bars
+pub struct StatusLines {
+    pushing: bool,
+}
+
+impl StatusLines {
+    pub fn new(verb: &str) -> Self {
+        Self { pushing: verb == "push" }
+    }
+}
+
+impl TransferProgress for StatusLines {
+    fn on_event(&mut self, event: &TransferEvent) {
+        match event {
+            TransferEvent::Status { num_done, num_files, num_active, transferred_bytes } if self.pushing => {
+                say!(PushStatus, num_done, num_files, num_active, transferred_bytes);
+            },
+            TransferEvent::Status { num_done, num_files, num_active, transferred_bytes } => {
+                say!(PullStatus, num_done, num_files, num_active, transferred_bytes);
+            },
+            event => say_outcome(event),
+        }
+    }
+}
@@ -0,0 +1,31 @@
+use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use har_backup_core::mirror::PullConflict;
+use crate::cmd_impl::LocalDeletion;
+use crate::history::RunKind;
+
+// the transfers of a push or pull that has not completed, in .har (see dot_har::DotHar::store_queue)
+// har resume runs it again with the same options, only for these paths. What was transferred before it stopped is
+// not again: push finds it in the upload journal, pull in place (and the same as the remote for --overwrite)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueuedRun {
+    pub kind: RunKind,
+    pub scope: Option<PathBuf>,
+    pub keep_going: bool,
+    pub force: bool, // push
+    #[serde(default)]
+    pub hash_check: bool, // push, queues written before it was recorded don't have it
+    pub pull_conflict: PullConflict,
+    pub local_deletion: Option<LocalDeletion>, // pull, done once the files are pulled
+    pub pending: Vec<PathBuf>, // in the archive
+}
+
+impl QueuedRun {
+    pub fn to_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        Ok(serde_json::to_vec(self)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+}
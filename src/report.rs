@@ -0,0 +1,296 @@
+// what diff, status and verify print with --json, for scripts and monitoring rather than people
+// fields are only ever added, FORMAT_VERSION changes if one is removed or changes meaning
+// and what the commands did, which main.rs says: cmd_impl prints nothing
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use serde::Serialize;
+use har_backup_core::health::{HealthMetrics, RemoteHealth};
+use har_backup_core::mirror::TransferReport;
+use har_backup_core::remote_lock::RemoteLock;
+use har_backup_core::timings::Timings;
+use crate::dot_har::ManifestBackup;
+use crate::exit_code::TransferFailed;
+
+pub const FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DiffReport {
+    pub format_version: u32,
+    pub extra_in: Side, // what has the entries the other side has not
+    pub extra_paths: Vec<String>, // top most, a directory stands for everything in it
+    pub extra_files: usize,
+    pub extra_dirs: usize,
+    pub hash_changed: Option<Vec<String>>, // with --hash only
+    #[serde(skip)]
+    pub timings: Option<Timings>, // with --timings only
+    #[serde(skip)]
+    pub skipped_placeholders: Vec<PathBuf>, // cloud files the scan left out, see ScanReport
+}
+
+impl DiffReport {
+    // for the exit code
+    pub fn differs(&self) -> bool {
+        !self.extra_paths.is_empty() || self.hash_changed.as_ref().is_some_and(|paths| !paths.is_empty())
+    }
+}
+
+// a file left out of a push or pull, and why
+pub type FileFailure = (PathBuf, String);
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PushReport {
+    pub fetched: Option<u64>, // generation of the manifest fetched before, with auto_fetch
+    pub skipped_placeholders: Vec<PathBuf>,
+    pub shrunk: Option<(usize, usize)>, // files of the local tree and of the remote, pushed anyway with --force
+    pub nothing_to_push: bool, // and the remote manifest was not updated
+    pub num_changed: usize, // with --hash: files of the remote with another content, pushed again
+    pub adopted: Option<(usize, usize)>, // with --adopt: files adopted, files the remote had no blob of
+    pub transfer: Option<TransferReport>, // None when nothing was uploaded
+    pub quarantined: Vec<FileFailure>, // by this push or earlier ones, in failures with --keep-going
+    pub max_attempts: u32, // before a file is quarantined
+    pub failures: Vec<FileFailure>, // with --keep-going, the push fails on the first one otherwise
+    pub timings: Option<Timings>, // with --timings only
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PullReport {
+    pub nothing_to_pull: bool,
+    pub num_pulled: usize, // quarantined files are not
+    pub overwritten: Vec<PathBuf>, // local files that differed from the remote, see PullConflict
+    pub backed_up: Vec<PathBuf>,
+    pub trashed: Vec<PathBuf>, // local top entries the remote does not have, see LocalDeletion
+    pub deleted: Vec<PathBuf>,
+    pub transfer: Option<TransferReport>,
+    pub quarantined: Vec<FileFailure>,
+    pub max_attempts: u32,
+    pub failures: Vec<FileFailure>,
+    pub timings: Option<Timings>,
+}
+
+// what har resume continued, num_files were left to transfer
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResumeReport {
+    NothingToResume,
+    Push { num_files: usize, pushed: PushReport },
+    Pull { num_files: usize, pulled: PullReport },
+}
+
+// what watch does as it goes, given to its on_event
+pub enum WatchEvent<'a> {
+    Watching { archive_root: &'a Path, debounce: Duration },
+    Pushed(&'a PushReport), // a push that went to the end
+    PushFailed { error: &'a anyhow::Error, retry_after: Duration },
+    RemoteHealth(&'a RemoteHealth), // the remote went reachable, unreachable or refused the credentials
+    HealthMetrics(&'a HealthMetrics), // when stopping
+    Stopped,
+}
+
+// what backup --every does as it goes, times in unix seconds
+pub enum BackupEvent<'a> {
+    Scheduled { every: u64, next: u64 },
+    CatchingUp { missed: u64 },
+    BackedUp(Option<&'a PushReport>), // None when nothing changed
+    Failed(&'a anyhow::Error),
+    Next(u64),
+    Stopped,
+}
+
+// the command fails when some files did, after they were said
+pub fn check_failures(failures: &[FileFailure]) -> anyhow::Result<()> {
+    if failures.is_empty() {
+        return Ok(());
+    }
+    Err(anyhow::anyhow!("{} files failed", failures.len()).context(TransferFailed))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Local,
+    Remote,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StatusReport {
+    pub format_version: u32,
+    pub archive_root: String,
+    pub remote: Option<String>, // without credentials
+    pub remote_error: Option<String>, // why there is no remote
+    pub key: Option<KeyStatus>,
+    pub key_error: Option<String>,
+    pub fetched: Option<FetchedStatus>, // None when the manifest was never fetched
+    pub pending: Option<PendingStatus>,
+    #[serde(skip)]
+    pub skipped_placeholders: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct KeyStatus {
+    pub spec: String,
+    pub fingerprint: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FetchedStatus {
+    pub generation: u64,
+    pub stored: String, // utc timestamp
+    pub only_local_files: usize,
+    pub only_local_bytes: u64,
+    pub only_remote_files: usize,
+    pub only_remote_bytes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PendingStatus {
+    pub kind: crate::history::RunKind,
+    pub num_files: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VerifyReport {
+    pub format_version: u32,
+    pub remote_only: bool, // sizes listed by the remote were compared rather than blobs downloaded
+    pub checked: usize, // blobs, each once however many files have it
+    pub downloaded: usize,
+    pub without_size: usize, // remote_only: without a recorded size, only checked for presence
+    pub problems: Vec<BlobProblem>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BlobProblem {
+    pub blob_key: String,
+    pub path: String, // one of the files with this blob
+    #[serde(flatten)]
+    pub kind: BlobProblemKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "problem", rename_all = "snake_case")]
+pub enum BlobProblemKind {
+    Missing,
+    WrongSize { stored: u64, expected: u64 },
+    Corrupt { reason: String },
+}
+
+impl VerifyReport {
+    pub fn count(&self, is_kind: impl Fn(&BlobProblemKind) -> bool) -> usize {
+        self.problems.iter().filter(|problem| is_kind(&problem.kind)).count()
+    }
+
+    // the command fails when blobs are missing or damaged, after they were said
+    pub fn check(&self) -> anyhow::Result<()> {
+        let num_missing = self.count(|kind| matches!(kind, BlobProblemKind::Missing));
+        let num_damaged = self.problems.len() - num_missing;
+        match (num_missing + num_damaged, self.remote_only) {
+            (0, _) => Ok(()),
+            (_, true) => anyhow::bail!("Remote verification found {} missing and {} damaged blobs", num_missing, num_damaged),
+            (_, false) => anyhow::bail!("Verification found {} missing and {} corrupt blobs", num_missing, num_damaged),
+        }
+    }
+}
+
+// a version of the manifest in har log, with what changed since the one before
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogEntry {
+    pub name: String, // "fetched" or the number of the backup
+    pub generation: u64,
+    pub written: Option<u64>, // older manifests: when their last files were pushed, if they have times
+    pub host: Option<String>, // None for older manifests
+    pub num_files: usize,
+    pub total_size: u64,
+    pub added: usize,
+    pub changed: usize,
+    pub deleted: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PruneReport {
+    pub pruned: Vec<ManifestBackup>,
+    pub num_kept: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RmReport {
+    pub removed: Vec<(PathBuf, usize)>, // with the number of files under each path
+    pub marked_for_gc: Option<usize>, // blobs no file refers to anymore, with --mark-unreferenced
+}
+
+// a remote of har remote test
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemoteTest {
+    pub name: String, // the spec without credentials
+    pub probe: Result<(u128, usize, u128), String>, // exists ms, number of objects, list ms, or why it failed
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyCheck {
+    pub num_files: usize, // of the remote manifest the key decrypted
+    pub read_only: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UnlockReport {
+    NotLocked,
+    Held(RemoteLock), // not released without --force
+    Released(RemoteLock),
+}
+
+pub fn print<T: Serialize>(report: &T) -> anyhow::Result<()> {
+    println!("{}", serde_json::to_string(report)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_report_differs() -> anyhow::Result<()> {
+        let mut report = DiffReport {
+            format_version: FORMAT_VERSION,
+            extra_in: Side::Local,
+            extra_paths: Vec::new(),
+            extra_files: 0,
+            extra_dirs: 0,
+            hash_changed: Some(Vec::new()),
+            timings: Some(Timings::default()),
+            skipped_placeholders: Vec::new(),
+        };
+        assert!(!report.differs());
+        // timings are for people
+        assert!(serde_json::to_value(&report)?.get("timings").is_none());
+        report.hash_changed = Some(vec!["a/b".to_string()]);
+        assert!(report.differs());
+        Ok(())
+    }
+
+    // scripts depend on these names, changing one is a new FORMAT_VERSION
+    #[test]
+    fn verify_report_schema() -> anyhow::Result<()> {
+        let report = VerifyReport {
+            format_version: FORMAT_VERSION,
+            remote_only: true,
+            checked: 3,
+            downloaded: 0,
+            without_size: 1,
+            problems: vec![
+                BlobProblem { blob_key: "k1".to_string(), path: "a/b".to_string(), kind: BlobProblemKind::Missing },
+                BlobProblem { blob_key: "k2".to_string(), path: "c".to_string(), kind: BlobProblemKind::WrongSize { stored: 4, expected: 5 } },
+            ],
+        };
+        assert_eq!(serde_json::to_value(&report)?, serde_json::json!({
+            "format_version": 1,
+            "remote_only": true,
+            "checked": 3,
+            "downloaded": 0,
+            "without_size": 1,
+            "problems": [
+                { "blob_key": "k1", "path": "a/b", "problem": "missing" },
+                { "blob_key": "k2", "path": "c", "problem": "wrong_size", "stored": 4, "expected": 5 },
+            ],
+        }));
+        assert_eq!(report.count(|kind| matches!(kind, BlobProblemKind::Missing)), 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,72 @@
+// which versions of the manifest to keep, by age, like the forget policies of other backup tools
+// the most recent version of each of the last keep_daily days (that have one) is kept, the same for weeks and months,
+// a version can be kept for several of them. For har prune, on the manifest backups of .har (see dot_har::DotHar)
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    pub keep_daily: usize,
+    pub keep_weekly: usize,
+    pub keep_monthly: usize,
+}
+
+impl RetentionPolicy {
+    // times are unix seconds, most recent first, as dot_har::DotHar::list_manifest_backups
+    pub fn to_keep(&self, times: &[u64]) -> Vec<bool> {
+        let mut keep = vec![false; times.len()];
+        let day = |time: u64| (time / 86400).to_string();
+        // weeks from monday, the epoch is a thursday
+        let week = |time: u64| ((time / 86400 + 3) / 7).to_string();
+        let month = |time: u64| har_backup_core::clock::format_utc_timestamp(time)[..7].to_string();
+        let periods: [(usize, &dyn Fn(u64) -> String); 3] = [(self.keep_daily, &day), (self.keep_weekly, &week), (self.keep_monthly, &month)];
+        for (num_periods, period_of) in periods {
+            let mut last_period = None;
+            let mut num_kept = 0;
+            for (index, &time) in times.iter().enumerate() {
+                if num_kept == num_periods {
+                    break;
+                }
+                let period = period_of(time);
+                if last_period.as_ref() != Some(&period) {
+                    keep[index] = true;
+                    num_kept += 1;
+                    last_period = Some(period);
+                }
+            }
+        }
+        keep
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use har_backup_core::clock::parse_utc_timestamp;
+
+    fn unix(timestamp: &str) -> u64 {
+        parse_utc_timestamp(timestamp).unwrap().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs()
+    }
+
+    #[test]
+    fn keeps_the_last_of_each_period() {
+        let times: Vec<u64> = [
+            "2024-05-20T18:00:00Z", // monday
+            "2024-05-20T09:00:00Z",
+            "2024-05-19T09:00:00Z", // sunday
+            "2024-05-17T09:00:00Z",
+            "2024-05-02T09:00:00Z",
+            "2024-04-30T09:00:00Z",
+            "2024-03-01T09:00:00Z",
+        ].into_iter().map(unix).collect();
+
+        let daily = RetentionPolicy { keep_daily: 2, ..Default::default() };
+        assert_eq!(daily.to_keep(&times), vec![true, false, true, false, false, false, false]);
+        let weekly = RetentionPolicy { keep_weekly: 3, ..Default::default() };
+        assert_eq!(weekly.to_keep(&times), vec![true, false, true, false, true, false, false]);
+        let monthly = RetentionPolicy { keep_monthly: 10, ..Default::default() };
+        assert_eq!(monthly.to_keep(&times), vec![true, false, false, false, false, true, true]);
+
+        let all = RetentionPolicy { keep_daily: 1, keep_weekly: 2, keep_monthly: 2 };
+        assert_eq!(all.to_keep(&times), vec![true, false, true, false, false, true, false]);
+        assert_eq!(RetentionPolicy::default().to_keep(&times), vec![false; 7]);
+    }
+}
@@ -0,0 +1,118 @@
+// when har backup --every runs, by the wall clock (unix seconds) rather than a monotonic clock that stops while the
+// machine sleeps. Runs missed asleep or switched off make one run as soon as possible rather than one per interval,
+// a backup pushes the local tree as it is whatever the number of runs it stands for
+
+use std::time::Duration;
+use anyhow::Context;
+
+// how often the clock is looked at between runs, and Ctrl-C
+pub const POLL: Duration = Duration::from_secs(1);
+
+// 6h, 30m, 1d12h... with the units s, m, h, d and w, in seconds
+pub fn parse_interval(text: &str) -> anyhow::Result<u64> {
+    let invalid = || format!("Invalid interval {} (like 6h, 30m or 1d12h)", text);
+    let mut seconds: u64 = 0;
+    let mut rest = text.trim();
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).with_context(invalid)?;
+        let number: u64 = rest[..digits].parse().with_context(invalid)?;
+        let unit_char = rest[digits..].chars().next().unwrap();
+        let unit = match unit_char {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86400,
+            'w' => 7 * 86400,
+            _ => anyhow::bail!(invalid()),
+        };
+        seconds = number.checked_mul(unit).and_then(|part| seconds.checked_add(part)).with_context(invalid)?;
+        rest = &rest[digits + unit_char.len_utf8()..];
+    }
+    if seconds == 0 {
+        anyhow::bail!(invalid());
+    }
+    Ok(seconds)
+}
+
+// as parse_interval reads it, largest units first
+pub fn format_interval(seconds: u64) -> String {
+    let units = [('w', 7 * 86400), ('d', 86400), ('h', 3600), ('m', 60), ('s', 1)];
+    let mut rest = seconds;
+    let mut text = String::new();
+    for (unit_char, unit) in units {
+        if rest >= unit {
+            text += &format!("{}{}", rest / unit, unit_char);
+            rest %= unit;
+        }
+    }
+    text
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Schedule {
+    every: u64,
+    next: u64,
+}
+
+impl Schedule {
+    // last_run is the start of the last backup that went through (see dot_har::DotHar::get_last_backup), due now without
+    pub fn new(every: u64, last_run: Option<u64>) -> Self {
+        Self { every, next: last_run.map_or(0, |last_run| last_run.saturating_add(every)) }
+    }
+
+    pub fn next(&self) -> u64 {
+        self.next
+    }
+
+    pub fn is_due(&self, now: u64) -> bool {
+        now >= self.next
+    }
+
+    // whole intervals that passed without a run, besides the run due now
+    pub fn missed(&self, now: u64) -> u64 {
+        match self.next {
+            0 => 0,
+            next => now.saturating_sub(next) / self.every,
+        }
+    }
+
+    // the next run is every after this one started, right away if it took longer than that
+    pub fn ran(&mut self, started: u64) {
+        self.next = started.saturating_add(self.every);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intervals() -> anyhow::Result<()> {
+        assert_eq!(parse_interval("6h")?, 6 * 3600);
+        assert_eq!(parse_interval("1d12h")?, 36 * 3600);
+        assert_eq!(parse_interval("90s")?, 90);
+        assert_eq!(parse_interval("2w")?, 14 * 86400);
+        assert_eq!(format_interval(parse_interval("1d12h")?), "1d12h");
+        assert_eq!(format_interval(90), "1m30s");
+        for invalid in ["", "6", "h", "6x", "6é", "0m", "1h30"] {
+            assert!(parse_interval(invalid).is_err(), "{}", invalid);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn catches_up_once_after_sleeping() {
+        let hour = 3600;
+        assert!(Schedule::new(6 * hour, None).is_due(1000));
+
+        let mut schedule = Schedule::new(6 * hour, Some(100 * hour));
+        assert!(!schedule.is_due(105 * hour));
+        assert!(schedule.is_due(106 * hour));
+        assert_eq!(schedule.missed(106 * hour), 0);
+        // asleep for a day
+        assert_eq!(schedule.missed(130 * hour), 4);
+        schedule.ran(130 * hour);
+        assert!(!schedule.is_due(131 * hour));
+        assert_eq!(schedule.next(), 136 * hour);
+    }
+}
@@ -0,0 +1,202 @@
+// every option of har in one place, layered:
+// defaults < .har/config.toml of the archive < config file (--config) < HAR_BACKUP__* environment < flags
+//
+// the environment names the keys of the config file, nested tables separated by a double underscore:
+// HAR_BACKUP__REMOTE="fs:///backup" or HAR_BACKUP__TRANSFER__MAX_ATTEMPTS=5
+// remote, key and compression of the archive are in .har/config.toml, the layers above override them so that
+// containers don't need to edit it.
+
+use std::path::{Path, PathBuf};
+use anyhow::Context;
+use serde::{Deserialize, Deserializer};
+use har_backup_core::mirror::TransferSettings;
+use har_backup_core::scan::{PlaceholderPolicy, ScanBackend, ScanOptions};
+
+pub const ENV_PREFIX: &str = "HAR_BACKUP__";
+const ENV_SEPARATOR: &str = "__";
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub remote: Option<String>, // remote spec, see dot_har::RemoteSpec
+    pub key: Option<String>, // key file path or keychain://<name>
+    #[serde(deserialize_with = "level_or_off")]
+    pub compression: Option<String>, // zstd level or "off"
+    pub timings: bool,
+    pub progress: Option<bool>, // progress bars, by default when stderr is a terminal
+    pub quiet: bool, // see messages::set_quiet, no progress bars either
+    pub log_level: Option<String>, // off, error, warn, info, debug or trace
+    pub log_format: Option<String>, // text or json, see logging::LogFormat
+    pub auto_fetch: bool, // push and diff fetch the remote manifest first rather than use the one fetched last
+    pub manifest_backups: Option<usize>, // fetched manifests replaced by push kept in .har, see dot_har::DEFAULT_MANIFEST_BACKUPS
+    pub schedule: Option<String>, // interval of har backup without --every, like 6h, see schedule::parse_interval
+    pub scan: ScanSettings,
+    pub transfer: TransferSettings,
+    pub push: PushSettings,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScanSettings {
+    pub placeholders: PlaceholderPolicy,
+    pub listing: Option<PathBuf>,
+    pub watchman: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PushSettings {
+    // push refuses (without --force) when the local tree has less than this fraction of the files of the remote,
+    // like an empty archive root on a mount that failed. 0 to disable, DEFAULT_SHRINK_GUARD if unset
+    pub shrink_guard: Option<f64>,
+}
+
+pub const DEFAULT_SHRINK_GUARD: f64 = 0.1;
+
+impl PushSettings {
+    pub fn shrink_guard(&self) -> f64 {
+        self.shrink_guard.unwrap_or(DEFAULT_SHRINK_GUARD)
+    }
+}
+
+impl ScanSettings {
+    pub fn to_scan_options(&self) -> ScanOptions {
+        let backend = match (&self.listing, self.watchman) {
+            (Some(path), _) => ScanBackend::Listing(path.clone()),
+            (None, true) => ScanBackend::Watchman,
+            (None, false) => ScanBackend::Walk,
+        };
+        ScanOptions { placeholder_policy: self.placeholders, backend, keep_going: false }
+    }
+}
+
+impl Settings {
+    // from the config files and the environment of the process, flags are for the caller to apply
+    // the archive config (see dot_har::DotHar::config_path) is optional, the --config one is not
+    pub fn load(archive_config_path: Option<&Path>, config_path: Option<&Path>) -> anyhow::Result<Self> {
+        let archive_config_path = archive_config_path.filter(|path| path.exists());
+        let config_paths: Vec<&Path> = archive_config_path.into_iter().chain(config_path).collect();
+        Self::load_with_env(&config_paths, std::env::vars())
+    }
+
+    // later config files override the values of earlier ones
+    pub fn load_with_env(config_paths: &[&Path], env: impl IntoIterator<Item = (String, String)>) -> anyhow::Result<Self> {
+        let mut table = toml::Table::new();
+        for path in config_paths {
+            let content = std::fs::read_to_string(path).with_context(|| format!("Reading config file {}", path.to_str().unwrap()))?;
+            let file_table = content.parse::<toml::Table>().with_context(|| format!("Parsing config file {}", path.to_str().unwrap()))?;
+            merge_tables(&mut table, file_table);
+        }
+        for (name, value) in env {
+            if let Some(key_path) = name.strip_prefix(ENV_PREFIX) {
+                set_env_value(&mut table, key_path, &value).with_context(|| format!("Environment variable {}", name))?;
+            }
+        }
+        toml::Value::Table(table).try_into().context("Reading settings")
+    }
+}
+
+// compression = 3 and compression = "3" alike
+fn level_or_off<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum LevelOrOff {
+        Level(i64),
+        Text(String),
+    }
+    Ok(Option::<LevelOrOff>::deserialize(deserializer)?.map(|value| match value {
+        LevelOrOff::Level(level) => level.to_string(),
+        LevelOrOff::Text(text) => text,
+    }))
+}
+
+fn merge_tables(table: &mut toml::Table, other: toml::Table) {
+    for (key, value) in other {
+        match (table.get_mut(&key), value) {
+            (Some(toml::Value::Table(table)), toml::Value::Table(other)) => merge_tables(table, other),
+            (_, value) => { table.insert(key, value); },
+        }
+    }
+}
+
+// values are typed from their look, strings otherwise
+fn env_value(value: &str) -> toml::Value {
+    if let Ok(integer) = value.parse::<i64>() {
+        return toml::Value::Integer(integer);
+    }
+    match value {
+        "true" => toml::Value::Boolean(true),
+        "false" => toml::Value::Boolean(false),
+        _ => toml::Value::String(value.to_string()),
+    }
+}
+
+fn set_env_value(table: &mut toml::Table, key_path: &str, value: &str) -> anyhow::Result<()> {
+    let key_path = key_path.to_lowercase();
+    let mut keys: Vec<&str> = key_path.split(ENV_SEPARATOR).collect();
+    let last = keys.pop().filter(|key| !key.is_empty()).context("Empty settings key")?;
+    let mut table = table;
+    for key in keys {
+        let entry = table.entry(key).or_insert_with(|| toml::Value::Table(toml::Table::new()));
+        table = entry.as_table_mut().with_context(|| format!("Settings key {} is not a table", key))?;
+    }
+    table.insert(last.to_string(), env_value(value));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn layers() -> anyhow::Result<()> {
+        assert_eq!(Settings::load_with_env(&[], Vec::new())?, Settings::default());
+
+        let archive_config = tempfile::NamedTempFile::new()?;
+        std::fs::write(archive_config.path(), concat!(
+            "remote = \"fs:///from/archive\"\n",
+            "[transfer]\n",
+            "upload_rate_limit = 1000000\n",
+            "max_attempts = 4\n",
+        ))?;
+
+        let config = tempfile::NamedTempFile::new()?;
+        std::fs::write(config.path(), concat!(
+            "remote = \"fs:///from/file\"\n",
+            "compression = \"off\"\n",
+            "[transfer]\n",
+            "max_attempts = 5\n",
+            "active_tasks_limit = 8\n",
+            "[scan]\n",
+            "placeholders = \"hydrate\"\n",
+        ))?;
+        let settings = Settings::load_with_env(&[archive_config.path(), config.path()], env(&[
+            ("HAR_BACKUP__REMOTE", "fs:///from/env"),
+            ("HAR_BACKUP__TRANSFER__MAX_ATTEMPTS", "7"),
+            ("HAR_BACKUP__TRANSFER__STATUS_INTERVAL_MS", "2000"),
+            ("HAR_BACKUP__PROGRESS", "false"),
+            ("HAR_BACKUP__AUTO_FETCH", "true"),
+            ("HAR_BACKUP__KEY", "keychain://kek"),
+            ("HAR_BACKUP__COMPRESSION", "9"),
+            ("OTHER__REMOTE", "ignored"),
+        ]))?;
+        assert_eq!(settings.remote.as_deref(), Some("fs:///from/env"));
+        assert_eq!(settings.compression.as_deref(), Some("9"));
+        assert_eq!(settings.key.as_deref(), Some("keychain://kek"));
+        assert_eq!(settings.progress, Some(false));
+        assert!(settings.auto_fetch);
+        assert_eq!(settings.transfer.max_attempts, Some(7));
+        assert_eq!(settings.transfer.active_tasks_limit, Some(8));
+        assert_eq!(settings.transfer.upload_rate_limit, Some(1000000));
+        assert_eq!(settings.transfer.status_interval_ms, Some(2000));
+        assert_eq!(settings.scan.placeholders, PlaceholderPolicy::Hydrate);
+
+        // typos are errors rather than silently ignored
+        Settings::load_with_env(&[], env(&[("HAR_BACKUP__TRANSFER__MAX_ATEMPTS", "7")])).unwrap_err();
+        Ok(())
+    }
+}
@@ -0,0 +1,91 @@
+// filesystem notifications of the archive root, for har watch
+// what happens in .har (journals, manifests written by the push itself) and reads are not changes
+
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+use anyhow::{Context, Result};
+use tracing::warn;
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use har_backup_core::thread_sync::CancelToken;
+
+// how often a wait looks at the cancel token
+const POLL: Duration = Duration::from_millis(200);
+
+pub enum Woken {
+    Changed,
+    Retry,
+    Cancelled,
+}
+
+pub struct ChangeWatcher {
+    _watcher: notify::RecommendedWatcher, // events stop when it is dropped
+    events: mpsc::Receiver<notify::Result<Event>>,
+    dot_har: PathBuf,
+}
+
+impl ChangeWatcher {
+    pub fn new(archive_root: &Path, dot_har: &Path) -> Result<Self> {
+        let (sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender).context("Starting the file watcher")?;
+        watcher.watch(archive_root, RecursiveMode::Recursive)
+            .with_context(|| format!("Watching {}", archive_root.to_str().unwrap()))?;
+        Ok(Self { _watcher: watcher, events, dot_har: dot_har.to_path_buf() })
+    }
+
+    // blocks until changes came and then none for debounce, retry (from now) passed or cancel was called
+    // changes made while the caller was busy (pushing) are in the channel already and count
+    pub fn wait(&self, debounce: Duration, retry: Option<Duration>, cancel: &CancelToken) -> Result<Woken> {
+        let start = Instant::now();
+        let mut last_change: Option<Instant> = None;
+        loop {
+            if cancel.is_cancelled() {
+                return Ok(Woken::Cancelled);
+            }
+            match last_change {
+                Some(last_change) if last_change.elapsed() >= debounce => return Ok(Woken::Changed),
+                None if retry.is_some_and(|retry| start.elapsed() >= retry) => return Ok(Woken::Retry),
+                _ => {},
+            }
+            match self.events.recv_timeout(POLL) {
+                Ok(Ok(event)) => if self.is_change(&event) {
+                    last_change = Some(Instant::now());
+                },
+                // events were lost (queue overflow...), the push that follows scans everything anyway
+                Ok(Err(error)) => {
+                    warn!("File watcher: {}", error);
+                    last_change = Some(Instant::now());
+                },
+                Err(RecvTimeoutError::Timeout) => {},
+                Err(RecvTimeoutError::Disconnected) => anyhow::bail!("The file watcher stopped"),
+            }
+        }
+    }
+
+    fn is_change(&self, event: &Event) -> bool {
+        !matches!(event.kind, EventKind::Access(_)) && event.paths.iter().any(|path| !path.starts_with(&self.dot_har))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes_in_dot_har_are_ignored() -> Result<()> {
+        let archive_root = tempfile::tempdir()?;
+        let dot_har = archive_root.path().join(".har");
+        std::fs::create_dir(&dot_har)?;
+        let watcher = ChangeWatcher::new(archive_root.path(), &dot_har)?;
+        let cancel = CancelToken::new();
+        let debounce = Duration::from_millis(100);
+
+        std::fs::write(dot_har.join("journal"), "kek")?;
+        assert!(matches!(watcher.wait(debounce, Some(Duration::from_millis(500)), &cancel)?, Woken::Retry));
+        std::fs::write(archive_root.path().join("chuchu"), "tamtam")?;
+        assert!(matches!(watcher.wait(debounce, None, &cancel)?, Woken::Changed));
+        cancel.cancel();
+        assert!(matches!(watcher.wait(debounce, None, &cancel)?, Woken::Cancelled));
+        Ok(())
+    }
+}
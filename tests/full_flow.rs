@@ -2,11 +2,13 @@ use anyhow::{Result, Context};
 use tempfile::TempDir;
 use std::path::{Path, PathBuf};
 
-use har_backup::cmd_impl::{WithLocal, WithRemoteAndLocal};
+use har_backup::cmd_impl::{VerifyDownload, WithLocal, WithRemoteAndLocal};
 use har_backup::dot_har::{DotHar, DOT_HAR_NAME};
+use har_backup::report::BlobProblemKind;
+use har_backup_core::messages::{self, MessageKey};
 
 fn create_key(path: &Path) -> Result<()> {
-    let key = har_backup::blob_encryption::create_key();
+    let key = har_backup_core::blob_encryption::create_key();
     std::fs::write(path, key.as_slice()).context("Writing key to file")?;
     Ok(())
 }
@@ -36,12 +38,1053 @@ fn fetch_diff_push() -> Result<()> {
 
     with_remote_and_local.init_remote()?;
     with_remote_and_local.fetch_manifest()?;
-    with_local.diff(false, false)?;
+    assert!(!with_local.diff(false, false)?.differs());
 
     let new_file_path = archive_root.path().join("chuchu");
     std::fs::write(&new_file_path, "tamtam").unwrap();
+    assert_eq!(with_local.diff(false, false)?.extra_paths, vec!["chuchu".to_string()]);
 
+    // cmd_impl prints nothing, main.rs says the report
+    messages::start_recording();
+    let pushed = with_remote_and_local.push()?;
+    assert!(messages::take_recorded().into_iter().all(|message| message.key == MessageKey::PushStatus));
+    assert_eq!((pushed.nothing_to_push, pushed.fetched, pushed.num_changed), (false, None, 0));
+    // one file of 6 bytes, no retries and nothing skipped
+    let transfer = pushed.transfer.unwrap();
+    assert_eq!((transfer.num_files, transfer.plaintext_bytes, transfer.num_retried, transfer.num_skipped), (1, 6, 0, 0));
+
+    messages::start_recording();
+    assert!(with_remote_and_local.push()?.nothing_to_push);
+    assert!(messages::take_recorded().is_empty());
+    // stored by fetch-manifest then by the push that updated the remote
+    assert_eq!(DotHar::with_path(dot_har_path.clone()).get_manifest_generation()?, 2);
+
+    Ok(())
+}
+
+#[test]
+fn history_of_pushes() -> Result<()> {
+    let (archive_root, _storage, dot_har_path) = make_dummy_archive();
+    let mut with_remote_and_local = har_backup::cmd_impl::for_integ_test::with_remote_and_local(&dot_har_path);
+    with_remote_and_local.init_remote()?;
+    with_remote_and_local.fetch_manifest()?;
+    std::fs::write(archive_root.path().join("chuchu"), "tamtam")?;
+    with_remote_and_local.push()?;
+    with_remote_and_local.push()?;
+
+    // only the push that transferred something is in the history
+    let runs = DotHar::with_path(dot_har_path).get_runs()?;
+    assert_eq!(runs.len(), 1);
+    assert_eq!((runs[0].kind, runs[0].num_files, runs[0].bytes), (har_backup::history::RunKind::Push, 1, 6));
+    Ok(())
+}
+
+#[test]
+fn find_changed_since() -> Result<()> {
+    let (archive_root, _storage, dot_har_path) = make_dummy_archive();
+    let mut with_remote_and_local = har_backup::cmd_impl::for_integ_test::with_remote_and_local(&dot_har_path);
+    let with_local = har_backup::cmd_impl::for_integ_test::with_local(&dot_har_path);
+    with_remote_and_local.init_remote()?;
+    with_remote_and_local.fetch_manifest()?;
+    std::fs::write(archive_root.path().join("chuchu"), "tamtam")?;
+    with_remote_and_local.push()?;
+
+    // pushed files have a modification time
+    let hour = std::time::Duration::from_secs(3600);
+    let since = |time: std::time::SystemTime| time.duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+    let changed = with_local.changed_since(since(std::time::SystemTime::now() - hour))?;
+    assert_eq!(changed.iter().map(|(path, _)| path.clone()).collect::<Vec<_>>(), vec![PathBuf::from("chuchu")]);
+    assert!(with_local.changed_since(since(std::time::SystemTime::now() + hour))?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn push_then_pull_to_tar() -> Result<()> {
+    let (archive_root, _storage, dot_har_path) = make_dummy_archive();
+    let mut with_remote_and_local = har_backup::cmd_impl::for_integ_test::with_remote_and_local(&dot_har_path);
+
+    with_remote_and_local.init_remote()?;
+    with_remote_and_local.fetch_manifest()?;
+
+    std::fs::create_dir_all(archive_root.path().join("docs/taxes"))?;
+    std::fs::write(archive_root.path().join("docs/taxes/2023"), "paid")?;
+    std::fs::write(archive_root.path().join("chuchu"), "tamtam")?;
+
+    with_remote_and_local.push()?;
+
+    let mut tar_bytes = Vec::new();
+    with_remote_and_local.pull_to_tar(Path::new("docs"), &mut tar_bytes)?;
+
+    let mut archive = tar::Archive::new(tar_bytes.as_slice());
+    let mut found = Vec::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.to_path_buf();
+        let mut content = String::new();
+        std::io::Read::read_to_string(&mut entry, &mut content)?;
+        found.push((path, content));
+    }
+
+    assert_eq!(found, vec![
+        (PathBuf::from("taxes"), String::new()),
+        (PathBuf::from("taxes/2023"), "paid".to_string()),
+    ]);
+
+    let export_dir = TempDir::new()?;
+    let output = export_dir.path().join("docs.tar.zst");
+    assert_eq!(with_remote_and_local.export_tar(Path::new("docs"), &output, true)?, 1);
+    let tar_bytes = zstd::decode_all(std::fs::File::open(&output)?)?;
+    let mut archive = tar::Archive::new(tar_bytes.as_slice());
+    let entry = archive.entries()?.map(|entry| entry.unwrap()).find(|entry| entry.path().unwrap() == Path::new("taxes/2023")).unwrap();
+    // when it was pushed
+    assert!(entry.header().mtime()? > 0);
+    assert_eq!(std::fs::read_dir(export_dir.path())?.count(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn import_tar_without_unpacking() -> Result<()> {
+    let (archive_root, _storage, dot_har_path) = make_dummy_archive();
+    let mut with_remote_and_local = har_backup::cmd_impl::for_integ_test::with_remote_and_local(&dot_har_path);
+    with_remote_and_local.init_remote()?;
+    with_remote_and_local.fetch_manifest()?;
+    std::fs::create_dir_all(archive_root.path().join("old/docs"))?;
+    std::fs::write(archive_root.path().join("old/docs/taxes"), "unpaid")?;
+    with_remote_and_local.push()?;
+
+    let tar_dir = TempDir::new()?;
+    let tar_path = tar_dir.path().join("old.tar");
+    let mut builder = tar::Builder::new(std::fs::File::create(&tar_path)?);
+    for (path, content) in [("docs/taxes", "paid"), ("docs/2023/receipt", "kek")] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, path, content.as_bytes())?;
+    }
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Symlink);
+    header.set_size(0);
+    builder.append_link(&mut header, "docs/link", "taxes")?;
+    builder.into_inner()?;
+
+    // two files, the link is left out
+    assert_eq!(with_remote_and_local.import_tar(&tar_path, Path::new("old"), false)?, (2, 1));
+
+    let mut content = Vec::new();
+    with_remote_and_local.cat(Path::new("old/docs/taxes"), &mut content)?;
+    assert_eq!(content, b"paid");
+    content.clear();
+    with_remote_and_local.cat(Path::new("old/docs/2023/receipt"), &mut content)?;
+    assert_eq!(content, b"kek");
+    // nothing was unpacked
+    assert!(!archive_root.path().join("old/docs/2023").exists());
+    assert!(with_remote_and_local.import_tar(&tar_path, Path::new("old/docs/taxes"), false).is_err());
+    Ok(())
+}
+
+#[test]
+fn export_since_snapshot() -> Result<()> {
+    let (archive_root, _storage, dot_har_path) = make_dummy_archive();
+    let mut with_remote_and_local = har_backup::cmd_impl::for_integ_test::with_remote_and_local(&dot_har_path);
+
+    with_remote_and_local.init_remote()?;
+    with_remote_and_local.fetch_manifest()?;
+
+    std::fs::write(archive_root.path().join("chuchu"), "tamtam")?;
+    with_remote_and_local.push()?;
+
+    // out of the archive root, the next push would upload it
+    let snapshot_dir = TempDir::new()?;
+    let snapshot = dot_har_path.join("fetched_manifest");
+    let snapshot_copy = snapshot_dir.path().join("snapshot");
+    std::fs::copy(snapshot, &snapshot_copy)?;
+
+    std::fs::create_dir(archive_root.path().join("docs"))?;
+    std::fs::write(archive_root.path().join("docs/new"), "news")?;
+    with_remote_and_local.push()?;
+
+    let export_dir = TempDir::new()?;
+    let changes = with_remote_and_local.export_since(&snapshot_copy, export_dir.path(), har_backup::cmd_impl::ExportFormat::Directory)?;
+
+    assert_eq!(changes.added, vec![PathBuf::from("docs/new")]);
+    assert!(changes.changed.is_empty());
+    assert!(changes.deleted.is_empty());
+    assert_eq!(std::fs::read_to_string(export_dir.path().join("docs/new"))?, "news");
+    assert!(!export_dir.path().join("chuchu").exists());
+    assert!(export_dir.path().join(har_backup::cmd_impl::EXPORT_METADATA_NAME).exists());
+
+    Ok(())
+}
+
+#[test]
+fn read_only_key_cannot_push_signed_archive() -> Result<()> {
+    use har_backup_core::keys::KeyFile;
+    let (archive_root, _storage, dot_har_path) = make_dummy_archive();
+    let key_path = dot_har_path.join("kek_keyfile");
+    let full_key = KeyFile::create_full();
+    std::fs::write(&key_path, &full_key)?;
+
+    let mut with_remote_and_local = har_backup::cmd_impl::for_integ_test::with_remote_and_local(&dot_har_path);
+    with_remote_and_local.init_remote()?;
+    with_remote_and_local.fetch_manifest()?;
+    std::fs::write(archive_root.path().join("chuchu"), "tamtam")?;
+    with_remote_and_local.push()?;
+
+    std::fs::write(&key_path, KeyFile::from_bytes(&full_key)?.read_key())?;
+    let mut with_remote_and_local = har_backup::cmd_impl::for_integ_test::with_remote_and_local(&dot_har_path);
+    with_remote_and_local.fetch_manifest()?;
+    std::fs::write(archive_root.path().join("kek"), "kek")?;
+    assert!(with_remote_and_local.push().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn signing_key_is_pinned() -> Result<()> {
+    let (_archive_root, storage, dot_har_path) = make_dummy_archive();
+    std::fs::write(dot_har_path.join("kek_keyfile"), har_backup_core::keys::KeyFile::create_full())?;
+    let mut with_remote_and_local = har_backup::cmd_impl::for_integ_test::with_remote_and_local(&dot_har_path);
+    with_remote_and_local.init_remote()?;
+    let dot_har = DotHar::with_path(dot_har_path.clone());
+    let pinned = dot_har.get_signing_public_key()?.unwrap();
+
+    // as if the archive metadata was rewritten with the read key, without the signing key
+    let encrypt = har_backup_core::blob_encryption::EncryptWithChacha::new_with_key_from_file(&dot_har_path.join("kek_keyfile"))?;
+    let blob_storage = har_backup_core::blob_storage_local_directory::BlobStorageLocalDirectory::new_with_encryption(storage.path(), encrypt)?;
+    let mut remote = har_backup_core::mirror::Mirror::builder().storage(Box::new(blob_storage)).build()?;
+    let mut metadata = remote.get_archive_metadata()?.unwrap();
+    metadata.signing_public_key = None;
+    remote.push_archive_metadata(&metadata)?;
+    let err = with_remote_and_local.fetch_manifest().unwrap_err();
+    assert!(format!("{:#}", err).contains("lost the manifest signing key"));
+    assert!(with_remote_and_local.set_archive_metadata_value("description", "kek").is_err());
+    assert_eq!(remote.get_archive_metadata()?.unwrap().signing_public_key, None);
+
+    metadata.signing_public_key = Some("00".repeat(32));
+    remote.push_archive_metadata(&metadata)?;
+    let err = with_remote_and_local.fetch_manifest().unwrap_err();
+    assert!(format!("{:#}", err).contains("another manifest signing key"));
+
+    metadata.signing_public_key = Some(pinned);
+    remote.push_archive_metadata(&metadata)?;
+    with_remote_and_local.fetch_manifest()?;
+    Ok(())
+}
+
+#[test]
+fn connect_with_wrong_key_fails() -> Result<()> {
+    let (_archive_root, _storage, dot_har_path) = make_dummy_archive();
+    let mut with_remote_and_local = har_backup::cmd_impl::for_integ_test::with_remote_and_local(&dot_har_path);
+    with_remote_and_local.init_remote()?;
+    with_remote_and_local.fetch_manifest()?;
+
+    create_key(&dot_har_path.join("other_keyfile"))?;
+    DotHar::with_path(dot_har_path.clone()).set_path_to_keyfile(&dot_har_path.join("other_keyfile"))?;
+    let err = har_backup::cmd_impl::for_integ_test::try_with_remote_and_local(&dot_har_path).err().unwrap();
+    assert!(err.to_string().contains("archive key"));
+
+    Ok(())
+}
+
+#[test]
+fn init_checks_the_remote_before_writing_dot_har() -> Result<()> {
+    use har_backup::dot_har::KeySpec;
+    let archive_root = TempDir::new()?;
+    let storage = TempDir::new()?;
+    let key_path = storage.path().join("kek_keyfile");
+    create_key(&key_path)?;
+    let key_spec = KeySpec::File(key_path);
+    let settings = har_backup::settings::Settings::default();
+
+    let missing_remote = format!("fs://{}", storage.path().join("missing").to_str().unwrap());
+    assert!(WithRemoteAndLocal::init(archive_root.path(), &missing_remote, &key_spec, &settings).is_err());
+    assert_eq!(std::fs::read_dir(archive_root.path())?.count(), 0);
+
+    let remote_spec = format!("fs://{}", storage.path().to_str().unwrap());
+    let mut with_remote_and_local = WithRemoteAndLocal::init(archive_root.path(), &remote_spec, &key_spec, &settings)?;
+    assert!(with_remote_and_local.archive_metadata()?.is_none());
+    let (_, archive_uuid) = with_remote_and_local.init_remote()?;
+    with_remote_and_local.fetch_manifest()?;
+    assert!(WithRemoteAndLocal::init(archive_root.path(), &remote_spec, &key_spec, &settings).is_err());
+
+    // another archive root of the same remote
+    let other_root = TempDir::new()?;
+    let mut other = WithRemoteAndLocal::init(other_root.path(), &remote_spec, &key_spec, &settings)?;
+    assert_eq!(other.archive_metadata()?.map(|metadata| metadata.archive_uuid), Some(archive_uuid));
+    let dot_har = DotHar::with_path(other_root.path().join(DOT_HAR_NAME));
+    assert_eq!(dot_har.get_remote_spec()?.bucket_name(), storage.path().to_str().unwrap());
+    assert_eq!(dot_har.get_key_spec()?.to_string(), key_spec.to_string());
+    Ok(())
+}
+
+#[test]
+fn remote_show_set_and_test() -> Result<()> {
+    let (_archive_root, storage, dot_har_path) = make_dummy_archive();
+    har_backup::cmd_impl::for_integ_test::with_remote_and_local(&dot_har_path).init_remote()?;
+    let with_local = har_backup::cmd_impl::for_integ_test::with_local(&dot_har_path);
+    let remote_spec = format!("fs://{}", storage.path().to_str().unwrap());
+
+    assert_eq!(with_local.remote_spec()?.redacted(), remote_spec);
+    let tests = with_local.test_remote()?;
+    assert_eq!(tests.len(), 1);
+    let (_, num_objects, _) = tests[0].probe.clone().unwrap();
+    assert_ne!(num_objects, 0);
+
+    assert!(with_local.set_remote("nope").is_err());
+    let missing_remote = format!("fs://{}", storage.path().join("missing").to_str().unwrap());
+    with_local.set_remote(&format!("multi://{}\n---\n{}", remote_spec, missing_remote))?;
+    let reached: Vec<bool> = with_local.test_remote()?.iter().map(|test| test.probe.is_ok()).collect();
+    assert_eq!(reached, vec![true, false]);
+    Ok(())
+}
+
+#[test]
+fn key_set_path_fingerprint_and_check() -> Result<()> {
+    let (_archive_root, _storage, dot_har_path) = make_dummy_archive();
+    let mut with_remote_and_local = har_backup::cmd_impl::for_integ_test::with_remote_and_local(&dot_har_path);
+    with_remote_and_local.init_remote()?;
+    let with_local = har_backup::cmd_impl::for_integ_test::with_local(&dot_har_path);
+
+    assert_eq!(with_remote_and_local.check_key()?.num_files, 0);
+    let fingerprint = DotHar::with_path(dot_har_path.clone()).get_key()?.fingerprint();
+    assert_eq!(with_local.key()?.1.fingerprint(), fingerprint);
+
+    let not_a_key = dot_har_path.join("not_a_key");
+    std::fs::write(&not_a_key, "kek")?;
+    assert!(with_local.set_key_path(&not_a_key).is_err());
+    create_key(&dot_har_path.join("other_keyfile"))?;
+    with_local.set_key_path(&dot_har_path.join("other_keyfile"))?;
+    assert_ne!(DotHar::with_path(dot_har_path.clone()).get_key()?.fingerprint(), fingerprint);
+    assert!(har_backup::cmd_impl::for_integ_test::try_with_remote_and_local(&dot_har_path).is_err());
+    Ok(())
+}
+
+#[test]
+fn init_remote_retry() -> Result<()> {
+    let (_archive_root, _storage, dot_har_path) = make_dummy_archive();
+    let dot_har = DotHar::with_path(dot_har_path.clone());
+    let mut with_remote_and_local = har_backup::cmd_impl::for_integ_test::with_remote_and_local(&dot_har_path);
+
+    // as if a previous init-remote died before completing
+    let metadata = har_backup_core::archive_metadata::ArchiveMetadata::new("");
+    dot_har.store_pending_init(&metadata)?;
+    with_remote_and_local.init_remote()?;
+    assert!(dot_har.get_pending_init()?.is_none());
+
+    dot_har.store_pending_init(&metadata)?;
+    let (outcome, archive_uuid) = with_remote_and_local.init_remote()?;
+    assert_eq!((outcome, archive_uuid), (har_backup_core::mirror::InitOutcome::AlreadyInitialized, metadata.archive_uuid.clone()));
+
+    let err = with_remote_and_local.init_remote().unwrap_err();
+    assert!(err.to_string().contains("use fetch"));
+    Ok(())
+}
+
+#[test]
+fn pull_delete_permanent() -> Result<()> {
+    use har_backup::cmd_impl::LocalDeletion;
+    let (archive_root, _storage, dot_har_path) = make_dummy_archive();
+    let mut with_remote_and_local = har_backup::cmd_impl::for_integ_test::with_remote_and_local(&dot_har_path);
+    with_remote_and_local.init_remote()?;
+    with_remote_and_local.fetch_manifest()?;
+
+    std::fs::write(archive_root.path().join("chuchu"), "tamtam")?;
+    with_remote_and_local.push()?;
+
+    std::fs::create_dir_all(archive_root.path().join("local/only"))?;
+    std::fs::write(archive_root.path().join("local/only/file"), "kek")?;
+    std::fs::write(archive_root.path().join("stray"), "kek")?;
+
+    // without deletion local only files are left alone
+    with_remote_and_local.pull()?;
+    assert!(archive_root.path().join("stray").exists());
+
+    let mut with_remote_and_local = with_remote_and_local.with_local_deletion(Some(LocalDeletion::Permanent));
+    let pulled = with_remote_and_local.pull()?;
+    assert!(pulled.nothing_to_pull);
+    assert_eq!(pulled.deleted.len(), 2);
+    assert!(!archive_root.path().join("stray").exists());
+    assert!(!archive_root.path().join("local").exists());
+    assert!(archive_root.path().join("chuchu").exists());
+    assert!(dot_har_path.exists());
+    Ok(())
+}
+
+#[test]
+fn pull_backup_existing() -> Result<()> {
+    use har_backup_core::mirror::PullConflict;
+    let (archive_root, _storage, dot_har_path) = make_dummy_archive();
+    let mut with_remote_and_local = har_backup::cmd_impl::for_integ_test::with_remote_and_local(&dot_har_path);
+    with_remote_and_local.init_remote()?;
+    with_remote_and_local.fetch_manifest()?;
+
+    std::fs::write(archive_root.path().join("chuchu"), "tamtam")?;
+    std::fs::write(archive_root.path().join("same"), "kek")?;
+    with_remote_and_local.push()?;
+    // same size, other content
+    std::fs::write(archive_root.path().join("chuchu"), "tamtom")?;
+
+    // by default local files are kept
+    assert!(with_remote_and_local.pull()?.nothing_to_pull);
+
+    let mut with_remote_and_local = with_remote_and_local.with_pull_conflict(PullConflict::BackupExisting);
+    let pulled = with_remote_and_local.pull()?;
+    assert_eq!((pulled.backed_up, pulled.overwritten), (vec![PathBuf::from("chuchu")], Vec::new()));
+    assert_eq!(std::fs::read_to_string(archive_root.path().join("chuchu"))?, "tamtam");
+    assert_eq!(std::fs::read_to_string(archive_root.path().join("chuchu.har-backup"))?, "tamtom");
+    assert!(!archive_root.path().join("same.har-backup").exists());
+    Ok(())
+}
+
+#[test]
+fn push_and_pull_a_path() -> Result<()> {
+    let (archive_root, _storage, dot_har_path) = make_dummy_archive();
+    let with_remote_and_local = har_backup::cmd_impl::for_integ_test::with_remote_and_local(&dot_har_path);
+    let mut with_remote_and_local = with_remote_and_local.with_scope(Some(PathBuf::from("photos")));
+    with_remote_and_local.init_remote()?;
+    with_remote_and_local.fetch_manifest()?;
+
+    std::fs::create_dir_all(archive_root.path().join("photos/2024"))?;
+    std::fs::create_dir(archive_root.path().join("docs"))?;
+    std::fs::write(archive_root.path().join("photos/2024/cat"), "meow")?;
+    std::fs::write(archive_root.path().join("docs/taxes"), "owed")?;
+    with_remote_and_local.push()?;
+
+    // only photos went, and a path that isn't there is an error
+    std::fs::remove_dir_all(archive_root.path().join("photos"))?;
+    std::fs::remove_dir_all(archive_root.path().join("docs"))?;
+    let mut with_remote_and_local = with_remote_and_local.with_scope(None);
+    with_remote_and_local.pull()?;
+    assert_eq!(std::fs::read_to_string(archive_root.path().join("photos/2024/cat"))?, "meow");
+    assert!(!archive_root.path().join("docs").exists());
+    let mut with_remote_and_local = with_remote_and_local.with_scope(Some(PathBuf::from("docs")));
+    assert!(with_remote_and_local.push().is_err());
+    assert!(with_remote_and_local.pull().is_err());
+
+    std::fs::create_dir(archive_root.path().join("docs"))?;
+    std::fs::write(archive_root.path().join("docs/taxes"), "owed")?;
+    with_remote_and_local.push()?;
+    std::fs::remove_dir_all(archive_root.path().join("photos"))?;
+    std::fs::remove_dir_all(archive_root.path().join("docs"))?;
+    let mut with_remote_and_local = with_remote_and_local.with_scope(Some(PathBuf::from("docs/taxes")));
+    with_remote_and_local.pull()?;
+    assert_eq!(std::fs::read_to_string(archive_root.path().join("docs/taxes"))?, "owed");
+    assert!(!archive_root.path().join("photos").exists());
+    Ok(())
+}
+
+#[test]
+fn restore_paths_into_a_directory() -> Result<()> {
+    let (archive_root, _storage, dot_har_path) = make_dummy_archive();
+    let mut with_remote_and_local = har_backup::cmd_impl::for_integ_test::with_remote_and_local(&dot_har_path);
+    with_remote_and_local.init_remote()?;
+    with_remote_and_local.fetch_manifest()?;
+
+    std::fs::create_dir_all(archive_root.path().join("photos/2024/empty"))?;
+    std::fs::create_dir(archive_root.path().join("docs"))?;
+    std::fs::write(archive_root.path().join("photos/2024/cat"), "meow")?;
+    std::fs::write(archive_root.path().join("docs/taxes"), "owed")?;
+    std::fs::write(archive_root.path().join("docs/rent"), "paid")?;
+    with_remote_and_local.push()?;
+
+    // anywhere, the archive root is left alone
+    let target = TempDir::new()?;
+    let restore_dir = target.path().join("restored");
+    with_remote_and_local.restore(&[PathBuf::from("photos"), PathBuf::from("docs/taxes")], &restore_dir)?;
+    assert_eq!(std::fs::read_to_string(restore_dir.join("photos/2024/cat"))?, "meow");
+    assert!(restore_dir.join("photos/2024/empty").is_dir());
+    assert_eq!(std::fs::read_to_string(restore_dir.join("docs/taxes"))?, "owed");
+    assert!(!restore_dir.join("docs/rent").exists());
+
+    // existing files are kept unless overwritten
+    std::fs::write(restore_dir.join("docs/taxes"), "paid")?;
+    with_remote_and_local.restore(&[PathBuf::from("docs")], &restore_dir)?;
+    assert_eq!(std::fs::read_to_string(restore_dir.join("docs/taxes"))?, "paid");
+    assert_eq!(std::fs::read_to_string(restore_dir.join("docs/rent"))?, "paid");
+    let mut with_remote_and_local = with_remote_and_local.with_pull_conflict(har_backup_core::mirror::PullConflict::Overwrite);
+    with_remote_and_local.restore(&[PathBuf::from("docs")], &restore_dir)?;
+    assert_eq!(std::fs::read_to_string(restore_dir.join("docs/taxes"))?, "owed");
+
+    assert!(with_remote_and_local.restore(&[PathBuf::from("videos")], &restore_dir).is_err());
+    Ok(())
+}
+
+#[test]
+fn rm_paths_from_the_archive() -> Result<()> {
+    let (archive_root, _storage, dot_har_path) = make_dummy_archive();
+    let mut with_remote_and_local = har_backup::cmd_impl::for_integ_test::with_remote_and_local(&dot_har_path);
+    with_remote_and_local.init_remote()?;
+    with_remote_and_local.fetch_manifest()?;
+
+    std::fs::create_dir(archive_root.path().join("docs"))?;
+    std::fs::write(archive_root.path().join("docs/taxes"), "owed")?;
+    std::fs::write(archive_root.path().join("docs/rent"), "paid")?;
+    std::fs::write(archive_root.path().join("receipt"), "paid")?;
+    with_remote_and_local.push()?;
+
+    with_remote_and_local.rm(&[PathBuf::from("docs")], true)?;
+    with_remote_and_local.fetch_manifest()?;
+    let dot_har = DotHar::with_path(dot_har_path.clone());
+    let files: Vec<PathBuf> = dot_har.get_manifest()?.list_files().into_iter().map(|(path, _, _)| path).collect();
+    assert_eq!(files, vec![PathBuf::from("receipt")]);
+    // the content of docs/rent is still that of receipt
+    assert_eq!(dot_har.get_gc_candidates()?.len(), 1);
+    assert!(archive_root.path().join("docs/taxes").exists());
+
+    assert!(with_remote_and_local.rm(&[PathBuf::from("docs")], false).is_err());
+    Ok(())
+}
+
+#[test]
+fn mv_without_transfer() -> Result<()> {
+    let (archive_root, _storage, dot_har_path) = make_dummy_archive();
+    let mut with_remote_and_local = har_backup::cmd_impl::for_integ_test::with_remote_and_local(&dot_har_path);
+    with_remote_and_local.init_remote()?;
+    with_remote_and_local.fetch_manifest()?;
+    std::fs::create_dir(archive_root.path().join("docs"))?;
+    std::fs::write(archive_root.path().join("docs/taxes"), "owed")?;
+    with_remote_and_local.push()?;
+
+    with_remote_and_local.mv(Path::new("docs"), Path::new("archive/2024"))?;
+    std::fs::create_dir(archive_root.path().join("archive"))?;
+    std::fs::rename(archive_root.path().join("docs"), archive_root.path().join("archive/2024"))?;
+    with_remote_and_local.fetch_manifest()?;
+    let files: Vec<PathBuf> = DotHar::with_path(dot_har_path.clone()).get_manifest()?.list_files().into_iter().map(|(path, _, _)| path).collect();
+    assert_eq!(files, vec![PathBuf::from("archive/2024/taxes")]);
+
+    assert!(with_remote_and_local.push()?.nothing_to_push);
+    Ok(())
+}
+
+#[test]
+fn log_of_manifest_versions() -> Result<()> {
+    let (archive_root, _storage, dot_har_path) = make_dummy_archive();
+    let mut with_remote_and_local = har_backup::cmd_impl::for_integ_test::with_remote_and_local(&dot_har_path);
+    with_remote_and_local.init_remote()?;
+    with_remote_and_local.fetch_manifest()?;
+    std::fs::write(archive_root.path().join("felt"), "kek")?;
+    std::fs::write(archive_root.path().join("dog"), "woof")?;
+    with_remote_and_local.push()?;
+    with_remote_and_local.rm(&[PathBuf::from("dog")], false)?;
+
+    let versions = har_backup::cmd_impl::for_integ_test::with_local(&dot_har_path).log()?;
+    assert_eq!(versions.len(), 3);
+    let counts = |entry: &har_backup::report::LogEntry| (entry.name.clone(), entry.num_files, entry.added, entry.deleted);
+    assert_eq!(counts(&versions[0]), ("fetched".to_string(), 1, 0, 1));
+    assert_eq!(counts(&versions[1]), ("1".to_string(), 2, 2, 0));
+    assert_eq!(counts(&versions[2]), ("2".to_string(), 0, 0, 0));
+    // the manifest of init-remote does not record where it was written
+    assert!(versions[1].host.is_some());
+    assert!(versions[2].host.is_none());
+    Ok(())
+}
+
+#[test]
+fn cat_a_file() -> Result<()> {
+    let (archive_root, _storage, dot_har_path) = make_dummy_archive();
+    let mut settings = har_backup::settings::Settings::default();
+    settings.transfer.chunk_threshold = Some(1000);
+    settings.transfer.chunk_size = Some(1024);
+    let mut with_remote_and_local = har_backup::cmd_impl::for_integ_test::with_remote_and_local_and_settings(&dot_har_path, &settings)?;
+    with_remote_and_local.init_remote()?;
+    with_remote_and_local.fetch_manifest()?;
+
+    let big: Vec<u8> = (0..5000u32).map(|i| (i * 7 % 251) as u8).collect();
+    std::fs::create_dir(archive_root.path().join("etc"))?;
+    std::fs::write(archive_root.path().join("etc/config"), "kek = 1")?;
+    std::fs::write(archive_root.path().join("big"), &big)?;
+    with_remote_and_local.push()?;
+
+    let mut out = Vec::new();
+    with_remote_and_local.cat(Path::new("etc/config"), &mut out)?;
+    assert_eq!(out, b"kek = 1");
+    let mut out = Vec::new();
+    with_remote_and_local.cat(Path::new("big"), &mut out)?;
+    assert_eq!(out, big);
+
+    assert!(with_remote_and_local.cat(Path::new("etc"), Vec::new()).is_err());
+    assert!(with_remote_and_local.cat(Path::new("etc/other"), Vec::new()).is_err());
+    Ok(())
+}
+
+#[test]
+fn status_on_one_screen() -> Result<()> {
+    let (archive_root, _storage, dot_har_path) = make_dummy_archive();
+    let with_local = har_backup::cmd_impl::for_integ_test::with_local(&dot_har_path);
+    let status = with_local.status()?;
+    assert!(status.remote.is_some() && status.key.is_some());
+    assert!(status.fetched.is_none() && status.pending.is_none());
+
+    let mut with_remote_and_local = har_backup::cmd_impl::for_integ_test::with_remote_and_local(&dot_har_path);
+    with_remote_and_local.init_remote()?;
+    with_remote_and_local.fetch_manifest()?;
+    std::fs::write(archive_root.path().join("felt"), "kek")?;
+    with_remote_and_local.push()?;
+    std::fs::remove_file(archive_root.path().join("felt"))?;
+    std::fs::write(archive_root.path().join("dog"), "woof")?;
+
+    let status = with_local.status()?;
+    let fetched = status.fetched.unwrap();
+    let delta = (fetched.only_local_files, fetched.only_local_bytes, fetched.only_remote_files, fetched.only_remote_bytes);
+    assert_eq!(delta, (1, 4, 1, 3));
+    assert!(status.remote.unwrap().starts_with("fs://"));
+    Ok(())
+}
+
+#[test]
+fn push_keep_going_lists_failures() -> Result<()> {
+    let (archive_root, _storage, dot_har_path) = make_dummy_archive();
+    let mut with_remote_and_local = har_backup::cmd_impl::for_integ_test::with_remote_and_local(&dot_har_path);
+    with_remote_and_local.init_remote()?;
+    with_remote_and_local.fetch_manifest()?;
+
+    // quarantined by earlier pushes
+    let mut journal = har_backup_core::journal::TransferJournal::default();
+    for _ in 0..3 {
+        journal.record_failure(Path::new("stuck"), "Reading stuck: permission denied");
+    }
+    DotHar::with_path(dot_har_path.clone()).store_journal(&journal)?;
+    std::fs::write(archive_root.path().join("stuck"), "kek")?;
+    std::fs::write(archive_root.path().join("chuchu"), "tamtam")?;
+
+    let mut with_remote_and_local = with_remote_and_local.with_keep_going(true);
+    messages::start_recording();
+    let pushed = with_remote_and_local.push()?;
+    assert_eq!(pushed.failures.iter().map(|(path, _)| path.clone()).collect::<Vec<_>>(), vec![PathBuf::from("stuck")]);
+    assert!(har_backup::report::check_failures(&pushed.failures).is_err());
+    // the others went on
+    let mut with_remote_and_local = with_remote_and_local.with_keep_going(false);
+    std::fs::remove_file(archive_root.path().join("stuck"))?;
+    assert!(with_remote_and_local.push()?.nothing_to_push);
+    Ok(())
+}
+
+#[test]
+fn verify_remote_only() -> Result<()> {
+    let (archive_root, storage, dot_har_path) = make_dummy_archive();
+    let mut with_remote_and_local = har_backup::cmd_impl::for_integ_test::with_remote_and_local(&dot_har_path);
+    with_remote_and_local.init_remote()?;
+    with_remote_and_local.fetch_manifest()?;
+
+    std::fs::write(archive_root.path().join("chuchu"), "tamtam")?;
+    std::fs::write(archive_root.path().join("same"), "tamtam")?;
+    std::fs::write(archive_root.path().join("felt"), "kek")?;
+    std::fs::write(archive_root.path().join("dog"), "woof")?;
+    with_remote_and_local.push()?;
+
+    let verified = with_remote_and_local.verify_remote_only()?;
+    assert!(verified.problems.is_empty());
+    assert_eq!((verified.checked, verified.without_size), (3, 0));
+    verified.check()?;
+
+    let manifest = DotHar::with_path(dot_har_path.clone()).get_manifest()?;
+    let blob_of = |name: &str| manifest.list_blobs().into_iter()
+        .find(|(path, _, _)| path == Path::new(name))
+        .map(|(_, blob_key, _)| storage.path().join(blob_key))
+        .unwrap();
+    std::fs::remove_file(blob_of("felt"))?;
+    let truncated = std::fs::read(blob_of("dog"))?;
+    std::fs::write(blob_of("dog"), &truncated[..truncated.len() - 1])?;
+
+    let verified = with_remote_and_local.verify_remote_only()?;
+    assert!(verified.check().is_err());
+    assert_eq!(verified.count(|kind| matches!(kind, BlobProblemKind::Missing)), 1);
+    assert_eq!(verified.count(|kind| matches!(kind, BlobProblemKind::WrongSize { .. })), 1);
+    Ok(())
+}
+
+#[test]
+fn verify_downloads_blobs() -> Result<()> {
+    let (archive_root, storage, dot_har_path) = make_dummy_archive();
+    let mut with_remote_and_local = har_backup::cmd_impl::for_integ_test::with_remote_and_local(&dot_har_path);
+    with_remote_and_local.init_remote()?;
+    with_remote_and_local.fetch_manifest()?;
+
+    std::fs::write(archive_root.path().join("chuchu"), "tamtam")?;
+    std::fs::write(archive_root.path().join("same"), "tamtam")?;
+    std::fs::write(archive_root.path().join("felt"), "kek")?;
+    std::fs::write(archive_root.path().join("dog"), "woof")?;
+    with_remote_and_local.push()?;
+
+    for (download, num_downloaded) in [(VerifyDownload::All, 3), (VerifyDownload::Sample(2), 2), (VerifyDownload::None, 0)] {
+        let verified = with_remote_and_local.verify(download)?;
+        assert_eq!((verified.checked, verified.downloaded, verified.problems.len()), (3, num_downloaded, 0));
+    }
+
+    let manifest = DotHar::with_path(dot_har_path.clone()).get_manifest()?;
+    let blob_of = |name: &str| manifest.list_blobs().into_iter()
+        .find(|(path, _, _)| path == Path::new(name))
+        .map(|(_, blob_key, _)| storage.path().join(blob_key))
+        .unwrap();
+    std::fs::remove_file(blob_of("felt"))?;
+    let mut flipped = std::fs::read(blob_of("dog"))?;
+    let last = flipped.len() - 1;
+    flipped[last] ^= 1;
+    std::fs::write(blob_of("dog"), &flipped)?;
+
+    // without downloading, only the missing one is found
+    let verified = with_remote_and_local.verify(VerifyDownload::None)?;
+    assert!(verified.check().is_err());
+    let kinds: Vec<BlobProblemKind> = verified.problems.into_iter().map(|problem| problem.kind).collect();
+    assert_eq!(kinds, vec![BlobProblemKind::Missing]);
+
+    let verified = with_remote_and_local.verify(VerifyDownload::All)?;
+    assert!(verified.check().is_err());
+    assert_eq!(verified.count(|kind| matches!(kind, BlobProblemKind::Missing)), 1);
+    assert_eq!(verified.count(|kind| matches!(kind, BlobProblemKind::Corrupt { .. })), 1);
+    assert_eq!(verified.downloaded, 2);
+    Ok(())
+}
+
+#[test]
+fn push_and_pull_in_chunks() -> Result<()> {
+    let (archive_root, _storage, dot_har_path) = make_dummy_archive();
+    let mut settings = har_backup::settings::Settings::default();
+    settings.transfer.chunk_threshold = Some(1000);
+    settings.transfer.chunk_size = Some(1024);
+    let mut with_remote_and_local = har_backup::cmd_impl::for_integ_test::with_remote_and_local_and_settings(&dot_har_path, &settings)?;
+    with_remote_and_local.init_remote()?;
+    with_remote_and_local.fetch_manifest()?;
+
+    let content: Vec<u8> = (0..5000u32).map(|i| (i % 251) as u8).collect();
+    std::fs::create_dir_all(archive_root.path().join("videos"))?;
+    std::fs::write(archive_root.path().join("videos/big"), &content)?;
+    std::fs::write(archive_root.path().join("small"), "tamtam")?;
+    with_remote_and_local.push()?;
+    // every chunk is a blob
+    with_remote_and_local.verify_remote_only()?.check()?;
+
+    let mut tar_bytes = Vec::new();
+    with_remote_and_local.pull_to_tar(Path::new("videos"), &mut tar_bytes)?;
+    let mut archive = tar::Archive::new(tar_bytes.as_slice());
+    let mut entry = archive.entries()?.map(|entry| entry.unwrap()).find(|entry| entry.path().unwrap() == Path::new("big")).unwrap();
+    let mut from_tar = Vec::new();
+    std::io::Read::read_to_end(&mut entry, &mut from_tar)?;
+    assert_eq!(from_tar, content);
+
+    std::fs::remove_dir_all(archive_root.path().join("videos"))?;
+    with_remote_and_local.pull()?;
+    assert_eq!(std::fs::read(archive_root.path().join("videos/big"))?, content);
+    Ok(())
+}
+
+#[test]
+fn push_refuses_empty_local_tree() -> Result<()> {
+    let (archive_root, _storage, dot_har_path) = make_dummy_archive();
+    let mut with_remote_and_local = har_backup::cmd_impl::for_integ_test::with_remote_and_local(&dot_har_path);
+
+    with_remote_and_local.init_remote()?;
+    with_remote_and_local.fetch_manifest()?;
+    std::fs::write(archive_root.path().join("a"), "a")?;
+    std::fs::write(archive_root.path().join("b"), "b")?;
+    with_remote_and_local.push()?;
+
+    // as if the archive root was a mount point that failed
+    std::fs::remove_file(archive_root.path().join("a"))?;
+    std::fs::remove_file(archive_root.path().join("b"))?;
+    assert!(with_remote_and_local.push().is_err());
+
+    let mut with_remote_and_local = with_remote_and_local.with_force(true);
+    assert_eq!(with_remote_and_local.push()?.shrunk, Some((0, 2)));
+
+    Ok(())
+}
+
+#[test]
+fn resume_interrupted_push() -> Result<()> {
+    use har_backup_core::thread_sync::CancelToken;
+    let (archive_root, _storage, dot_har_path) = make_dummy_archive();
+    let mut with_remote_and_local = har_backup::cmd_impl::for_integ_test::with_remote_and_local(&dot_har_path);
+    with_remote_and_local.init_remote()?;
+    with_remote_and_local.fetch_manifest()?;
+    let dot_har = DotHar::with_path(dot_har_path.clone());
+
+    let resumed = har_backup::cmd_impl::for_integ_test::with_remote_and_local(&dot_har_path).resume()?;
+    assert_eq!(resumed, har_backup::report::ResumeReport::NothingToResume);
+
+    // Ctrl-C before anything was transferred
+    std::fs::write(archive_root.path().join("chuchu"), "tamtam")?;
+    let cancel = CancelToken::new();
+    cancel.cancel();
+    assert!(with_remote_and_local.with_cancel(cancel).push().is_err());
+    let queued = dot_har.get_queue()?.unwrap();
+    assert_eq!(queued.pending, vec![PathBuf::from("chuchu")]);
+
+    // only what was queued
+    std::fs::write(archive_root.path().join("felt"), "kek")?;
+    let resumed = har_backup::cmd_impl::for_integ_test::with_remote_and_local(&dot_har_path).with_cancel(CancelToken::new()).resume()?;
+    assert!(matches!(resumed, har_backup::report::ResumeReport::Push { num_files: 1, .. }));
+    assert!(dot_har.get_queue()?.is_none());
+    let paths: Vec<PathBuf> = dot_har.get_manifest()?.list_files().into_iter().map(|(path, _, _)| path).collect();
+    assert_eq!(paths, vec![PathBuf::from("chuchu")]);
+    Ok(())
+}
+
+#[test]
+fn backup_skips_when_nothing_changed() -> Result<()> {
+    let (archive_root, _storage, dot_har_path) = make_dummy_archive();
+    let mut with_remote_and_local = har_backup::cmd_impl::for_integ_test::with_remote_and_local(&dot_har_path);
+    with_remote_and_local.init_remote()?;
+    with_remote_and_local.fetch_manifest()?;
+    let dot_har = DotHar::with_path(dot_har_path.clone());
+    assert_eq!(dot_har.get_last_backup()?, None);
+
+    std::fs::write(archive_root.path().join("chuchu"), "tamtam")?;
+    let pushed = with_remote_and_local.backup()?.unwrap();
+    assert!(pushed.fetched.is_some());
+    assert_eq!(pushed.transfer.map(|transfer| transfer.num_files), Some(1));
+    assert!(dot_har.get_last_backup()?.is_some());
+
+    assert_eq!(with_remote_and_local.backup()?, None);
+    Ok(())
+}
+
+#[test]
+fn backup_runs_when_files_are_modified_or_deleted() -> Result<()> {
+    let (archive_root, _storage, dot_har_path) = make_dummy_archive();
+    let mut with_remote_and_local = har_backup::cmd_impl::for_integ_test::with_remote_and_local(&dot_har_path);
+    with_remote_and_local.init_remote()?;
+    with_remote_and_local.fetch_manifest()?;
+    std::fs::write(archive_root.path().join("chuchu"), "tamtam")?;
+    std::fs::write(archive_root.path().join("bidule"), "truc")?;
+    with_remote_and_local.backup()?.unwrap();
+
+    // same size, only the mtime tells, later than the push of the same second
+    let chuchu = std::fs::File::options().write(true).open(archive_root.path().join("chuchu"))?;
+    std::io::Write::write_all(&mut &chuchu, b"tamtom")?;
+    chuchu.set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(10))?;
+    let pushed = with_remote_and_local.backup()?.unwrap();
+    assert_eq!(pushed.transfer.map(|transfer| transfer.num_files), Some(1));
+    chuchu.set_modified(std::time::UNIX_EPOCH)?;
+    assert_eq!(with_remote_and_local.backup()?, None);
+
+    std::fs::remove_file(archive_root.path().join("bidule"))?;
+    assert!(with_remote_and_local.backup()?.is_some());
+    Ok(())
+}
+
+#[test]
+fn push_changed_files() -> Result<()> {
+    let (archive_root, _storage, dot_har_path) = make_dummy_archive();
+    let mut settings = har_backup::settings::Settings::default();
+    settings.transfer.chunk_threshold = Some(1000);
+    settings.transfer.chunk_size = Some(1024);
+    let mut with_remote_and_local = har_backup::cmd_impl::for_integ_test::with_remote_and_local_and_settings(&dot_har_path, &settings)?;
+    with_remote_and_local.init_remote()?;
+    with_remote_and_local.fetch_manifest()?;
+
+    let mut content: Vec<u8> = (0..5000u32).map(|i| (i % 251) as u8).collect();
+    std::fs::write(archive_root.path().join("big"), &content)?;
+    std::fs::write(archive_root.path().join("small"), "tamtam")?;
+    with_remote_and_local.push()?;
+
+    // same sizes, only the content tells
+    content[4500] ^= 1;
+    std::fs::write(archive_root.path().join("big"), &content)?;
+    std::fs::write(archive_root.path().join("small"), "tamtom")?;
+    assert!(with_remote_and_local.push()?.nothing_to_push);
+
+    let mut with_remote_and_local = with_remote_and_local.with_hash_check(true);
+    let pushed = with_remote_and_local.push()?;
+    assert_eq!(pushed.num_changed, 2);
+    // the last chunk of big and small
+    assert_eq!(pushed.transfer.unwrap().plaintext_bytes, 5000 - 4 * 1024 + 6);
+
+    assert!(with_remote_and_local.push()?.nothing_to_push);
+
+    std::fs::remove_file(archive_root.path().join("big"))?;
+    std::fs::remove_file(archive_root.path().join("small"))?;
+    with_remote_and_local.pull()?;
+    assert_eq!(std::fs::read(archive_root.path().join("big"))?, content);
+    assert_eq!(std::fs::read_to_string(archive_root.path().join("small"))?, "tamtom");
+    Ok(())
+}
+
+#[test]
+fn push_adopt_without_upload() -> Result<()> {
+    let (archive_root, _storage, dot_har_path) = make_dummy_archive();
+    let mut settings = har_backup::settings::Settings::default();
+    settings.transfer.chunk_threshold = Some(1000);
+    settings.transfer.chunk_size = Some(1024);
+    let mut with_remote_and_local = har_backup::cmd_impl::for_integ_test::with_remote_and_local_and_settings(&dot_har_path, &settings)?;
+    with_remote_and_local.init_remote()?;
+    with_remote_and_local.fetch_manifest()?;
+
+    let content: Vec<u8> = (0..5000u32).map(|i| (i % 251) as u8).collect();
+    std::fs::create_dir(archive_root.path().join("docs"))?;
+    std::fs::write(archive_root.path().join("docs/big"), &content)?;
+    std::fs::write(archive_root.path().join("docs/taxes"), "owed")?;
+    std::fs::write(archive_root.path().join("docs/rent"), "late")?;
     with_remote_and_local.push()?;
+    // its blob stays in the remote, only exists() finds it
+    with_remote_and_local.rm(&[PathBuf::from("docs/rent")], false)?;
+    std::fs::remove_file(archive_root.path().join("docs/rent"))?;
 
+    // copied by other means
+    std::fs::create_dir(archive_root.path().join("copy"))?;
+    std::fs::write(archive_root.path().join("copy/big"), &content)?;
+    std::fs::write(archive_root.path().join("copy/taxes"), "owed")?;
+    std::fs::write(archive_root.path().join("copy/rent"), "late")?;
+    std::fs::write(archive_root.path().join("copy/new"), "fresh")?;
+    let mut with_remote_and_local = with_remote_and_local.with_adopt(true);
+    let pushed = with_remote_and_local.push()?;
+    assert_eq!(pushed.adopted, Some((3, 1)));
+    assert_eq!(pushed.transfer, None);
+    let files: Vec<PathBuf> = DotHar::with_path(dot_har_path.clone()).get_manifest()?.list_files().into_iter().map(|(path, _, _)| path).collect();
+    assert_eq!(files, ["copy/big", "copy/rent", "copy/taxes", "docs/big", "docs/taxes"].map(PathBuf::from));
+
+    let mut with_remote_and_local = with_remote_and_local.with_adopt(false);
+    with_remote_and_local.push()?;
+    std::fs::remove_dir_all(archive_root.path().join("copy"))?;
+    with_remote_and_local.pull()?;
+    assert_eq!(std::fs::read(archive_root.path().join("copy/big"))?, content);
+    assert_eq!(std::fs::read_to_string(archive_root.path().join("copy/rent"))?, "late");
+    assert_eq!(std::fs::read_to_string(archive_root.path().join("copy/new"))?, "fresh");
+    Ok(())
+}
+
+#[test]
+fn copy_a_path_to_another_remote() -> Result<()> {
+    let (archive_root, _storage, dot_har_path) = make_dummy_archive();
+    let mut settings = har_backup::settings::Settings::default();
+    settings.transfer.chunk_threshold = Some(1000);
+    settings.transfer.chunk_size = Some(1024);
+    let mut with_remote_and_local = har_backup::cmd_impl::for_integ_test::with_remote_and_local_and_settings(&dot_har_path, &settings)?;
+    with_remote_and_local.init_remote()?;
+    with_remote_and_local.fetch_manifest()?;
+    let content: Vec<u8> = (0..5000u32).map(|i| (i % 251) as u8).collect();
+    std::fs::create_dir(archive_root.path().join("docs"))?;
+    std::fs::write(archive_root.path().join("docs/big"), &content)?;
+    std::fs::write(archive_root.path().join("docs/taxes"), "owed")?;
+    std::fs::write(archive_root.path().join("receipt"), "paid")?;
+    with_remote_and_local.push()?;
+
+    let cold_storage = TempDir::new()?;
+    let cold_spec = format!("fs://{}", cold_storage.path().to_str().unwrap());
+    with_remote_and_local.copy_to(&cold_spec, Path::new("docs"), &settings)?;
+    assert!(!dot_har_path.join("copy_to").exists());
+    // the archive of the other remote, with the same key
+    let cold_root = TempDir::new()?;
+    let cold_dot_har = DotHar::with_path(cold_root.path().join(DOT_HAR_NAME));
+    std::fs::create_dir(cold_root.path().join(DOT_HAR_NAME))?;
+    cold_dot_har.set_remote_spec(&cold_spec)?;
+    cold_dot_har.set_path_to_keyfile(&dot_har_path.join("kek_keyfile"))?;
+    let mut cold = har_backup::cmd_impl::for_integ_test::with_remote_and_local(&cold_root.path().join(DOT_HAR_NAME));
+    cold.fetch_manifest()?;
+    let files: Vec<PathBuf> = cold_dot_har.get_manifest()?.list_files().into_iter().map(|(path, _, _)| path).collect();
+    assert_eq!(files, ["docs/big", "docs/taxes"].map(PathBuf::from));
+    cold.pull()?;
+    assert_eq!(std::fs::read(cold_root.path().join("docs/big"))?, content);
+    assert_eq!(std::fs::read_to_string(cold_root.path().join("docs/taxes"))?, "owed");
+
+    // again, with another file: the copied ones are there already
+    std::fs::write(archive_root.path().join("docs/rent"), "late")?;
+    with_remote_and_local.push()?;
+    with_remote_and_local.copy_to(&cold_spec, Path::new(""), &settings)?;
+    cold.fetch_manifest()?;
+    assert_eq!(cold_dot_har.get_manifest()?.list_files().len(), 4);
+    assert!(with_remote_and_local.copy_to(&cold_spec, Path::new("nothing"), &settings).is_err());
     Ok(())
-}
\ No newline at end of file
+}
+
+#[test]
+fn clone_a_remote_into_a_new_dir() -> Result<()> {
+    use har_backup_core::thread_sync::CancelToken;
+    let (archive_root, storage, dot_har_path) = make_dummy_archive();
+    let mut with_remote_and_local = har_backup::cmd_impl::for_integ_test::with_remote_and_local(&dot_har_path);
+    let remote_spec = format!("fs://{}", storage.path().to_str().unwrap());
+    let key_spec = har_backup::dot_har::KeySpec::File(dot_har_path.join("kek_keyfile"));
+    let settings = har_backup::settings::Settings::default();
+    let new_machine = TempDir::new()?;
+    let clone_dir = new_machine.path().join("archive");
+    // nothing to clone yet
+    assert!(WithRemoteAndLocal::clone_into(&clone_dir, &remote_spec, &key_spec, &settings, CancelToken::new(), false).is_err());
+    assert!(!clone_dir.join(DOT_HAR_NAME).exists());
+
+    with_remote_and_local.init_remote()?;
+    with_remote_and_local.fetch_manifest()?;
+    std::fs::create_dir(archive_root.path().join("docs"))?;
+    std::fs::write(archive_root.path().join("docs/taxes"), "owed")?;
+    std::fs::write(archive_root.path().join("receipt"), "paid")?;
+    with_remote_and_local.push()?;
+
+    WithRemoteAndLocal::clone_into(&clone_dir, &remote_spec, &key_spec, &settings, CancelToken::new(), false)?;
+    assert_eq!(std::fs::read_to_string(clone_dir.join("docs/taxes"))?, "owed");
+    assert_eq!(std::fs::read_to_string(clone_dir.join("receipt"))?, "paid");
+    assert_eq!(DotHar::with_path(clone_dir.join(DOT_HAR_NAME)).get_manifest()?.list_files().len(), 2);
+    assert!(WithRemoteAndLocal::clone_into(&clone_dir, &remote_spec, &key_spec, &settings, CancelToken::new(), false).is_err());
+    Ok(())
+}
+
+#[test]
+fn push_over_a_changed_remote_manifest() -> Result<()> {
+    let (archive_root, _storage, dot_har_path) = make_dummy_archive();
+    let mut with_remote_and_local = har_backup::cmd_impl::for_integ_test::with_remote_and_local(&dot_har_path);
+    with_remote_and_local.init_remote()?;
+    with_remote_and_local.fetch_manifest()?;
+    let dot_har = DotHar::with_path(dot_har_path.clone());
+
+    // as if another machine pushed chuchu after this one fetched
+    std::fs::write(archive_root.path().join("chuchu"), "tamtam")?;
+    with_remote_and_local.push()?;
+    dot_har.store_manifest(bytes::Bytes::from(std::fs::read(dot_har_path.join("fetched_manifest.backup"))?))?;
+    std::fs::remove_file(archive_root.path().join("chuchu"))?;
+
+    std::fs::write(archive_root.path().join("felt"), "kek")?;
+    let err = with_remote_and_local.push().unwrap_err();
+    assert!(err.downcast_ref::<har_backup_core::mirror::RemoteManifestChanged>().is_some());
+    // before transferring anything, the queue is stored right before
+    assert!(dot_har.get_queue()?.is_none());
+
+    let settings = har_backup::settings::Settings { auto_fetch: true, ..Default::default() }; // instead of fetch-manifest
+    let mut with_remote_and_local = har_backup::cmd_impl::for_integ_test::with_remote_and_local_and_settings(&dot_har_path, &settings)?;
+    with_remote_and_local.push()?;
+    let mut paths: Vec<PathBuf> = dot_har.get_manifest()?.list_files().into_iter().map(|(path, _, _)| path).collect();
+    paths.sort();
+    assert_eq!(paths, vec![PathBuf::from("chuchu"), PathBuf::from("felt")]);
+    Ok(())
+}
+
+#[test]
+fn push_takes_the_remote_lock() -> Result<()> {
+    let (archive_root, _storage, dot_har_path) = make_dummy_archive();
+    let mut with_remote_and_local = har_backup::cmd_impl::for_integ_test::with_remote_and_local(&dot_har_path);
+    with_remote_and_local.init_remote()?;
+    with_remote_and_local.fetch_manifest()?;
+    std::fs::write(archive_root.path().join("chuchu"), "tamtam")?;
+    with_remote_and_local.push()?;
+
+    assert_eq!(with_remote_and_local.unlock(false)?, har_backup::report::UnlockReport::NotLocked);
+
+    // released by a push that fails too
+    std::fs::remove_file(archive_root.path().join("chuchu"))?;
+    assert!(with_remote_and_local.push().is_err());
+    let mut with_remote_and_local = with_remote_and_local.with_force(true);
+    with_remote_and_local.push()?;
+    Ok(())
+}
+
+#[test]
+fn restore_manifest_after_a_bad_push() -> Result<()> {
+    let (archive_root, _storage, dot_har_path) = make_dummy_archive();
+    let mut with_remote_and_local = har_backup::cmd_impl::for_integ_test::with_remote_and_local(&dot_har_path);
+    let with_local = har_backup::cmd_impl::for_integ_test::with_local(&dot_har_path);
+    with_remote_and_local.init_remote()?;
+    with_remote_and_local.fetch_manifest()?;
+    std::fs::write(archive_root.path().join("chuchu"), "tamtam")?;
+    with_remote_and_local.push()?;
+    std::fs::write(archive_root.path().join("oops"), "kek")?;
+    with_remote_and_local.push()?;
+
+    let backups = with_local.list_manifest_backups()?;
+    assert_eq!(backups.len(), 2);
+    assert_eq!(backups[0].1, 1);
+
+    with_local.restore_manifest(1)?;
+    with_remote_and_local.push_fetched_manifest()?;
+    with_remote_and_local.fetch_manifest()?;
+    let paths: Vec<PathBuf> = DotHar::with_path(dot_har_path.clone()).get_manifest()?.list_files().into_iter().map(|(path, _, _)| path).collect();
+    assert_eq!(paths, vec![PathBuf::from("chuchu")]);
+    Ok(())
+}